@@ -0,0 +1,130 @@
+//! Preview of the unsaved changes as a line diff against the files on disk
+
+use std::fs;
+
+use anyhow::Result;
+use crossterm::event::KeyCode;
+use similar::{ChangeTag, TextDiff};
+
+use crate::app::types::{DiffLine, DiffLineKind};
+use crate::app::App;
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Line diff from `old` to `new`, with a gap line between separate hunks
+pub(crate) fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = Vec::new();
+    for group in diff.grouped_ops(CONTEXT_LINES) {
+        let first_line = group.first().map_or(0, |op| op.new_range().start) + 1;
+        lines.push(DiffLine {
+            kind: DiffLineKind::Gap,
+            text: format!("@@ line {} @@", first_line),
+        });
+        for op in &group {
+            for change in diff.iter_changes(op) {
+                let kind = match change.tag() {
+                    ChangeTag::Equal => DiffLineKind::Context,
+                    ChangeTag::Insert => DiffLineKind::Added,
+                    ChangeTag::Delete => DiffLineKind::Removed,
+                };
+                lines.push(DiffLine {
+                    kind,
+                    text: change.value().trim_end_matches(['\n', '\r']).to_string(),
+                });
+            }
+        }
+    }
+    lines
+}
+
+impl App {
+    /// Show what saving would change in each file. With `save_after`,
+    /// confirming the preview saves.
+    pub fn open_diff_preview(&mut self, save_after: bool) {
+        let mut lines = Vec::new();
+        for module in self.config.modules() {
+            // A file that doesn't exist yet is all new
+            let on_disk = fs::read_to_string(&module.path).unwrap_or_default();
+            if on_disk == module.content {
+                continue;
+            }
+            lines.push(DiffLine {
+                kind: DiffLineKind::File,
+                text: self.display_path(&module.path),
+            });
+            lines.extend(diff_lines(&on_disk, &module.content));
+        }
+
+        if lines.is_empty() {
+            self.set_status("No unsaved changes".to_string());
+            return;
+        }
+        self.diff_popup.lines = lines;
+        self.diff_popup.scroll_offset = 0;
+        self.diff_popup.save_after = save_after;
+        self.diff_popup.show = true;
+    }
+
+    pub(crate) fn handle_diff_popup_input(&mut self, code: KeyCode) -> Result<()> {
+        let max_scroll =
+            (self.diff_popup.lines.len() as u16).saturating_sub(self.diff_popup.visible_lines);
+        let page = self.diff_popup.visible_lines.saturating_sub(1);
+        let scroll = &mut self.diff_popup.scroll_offset;
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => *scroll = (*scroll + 1).min(max_scroll),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(page),
+            KeyCode::PageDown => *scroll = (*scroll + page).min(max_scroll),
+            KeyCode::Home => *scroll = 0,
+            KeyCode::End => *scroll = max_scroll,
+            KeyCode::Enter | KeyCode::Char('y') if self.diff_popup.save_after => {
+                self.diff_popup.show = false;
+                self.saving = true;
+                self.set_status("Saving...".to_string());
+            }
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('n') | KeyCode::Char('V') => {
+                self.diff_popup.show = false;
+                if self.diff_popup.save_after {
+                    self.set_status("Not saved".to_string());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let lines = diff_lines(old, new);
+        let kinds: Vec<_> = lines.iter().map(|l| l.kind).collect();
+
+        // Two hunks, far enough apart to be shown separately
+        assert_eq!(kinds.iter().filter(|k| **k == DiffLineKind::Gap).count(), 2);
+        assert_eq!(lines[0].text, "@@ line 1 @@");
+        assert!(lines.contains(&DiffLine {
+            kind: DiffLineKind::Removed,
+            text: "b".to_string()
+        }));
+        assert!(lines.contains(&DiffLine {
+            kind: DiffLineKind::Added,
+            text: "B".to_string()
+        }));
+        assert_eq!(
+            lines.last(),
+            Some(&DiffLine {
+                kind: DiffLineKind::Added,
+                text: "l".to_string()
+            })
+        );
+        assert!(diff_lines(old, old).is_empty());
+    }
+}
@@ -1,12 +1,29 @@
 use anyhow::Result;
-use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind};
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEventKind,
+};
+use ratatui::layout::Rect;
 
+use crate::app::text_input;
 use crate::app::types::{Focus, ListType};
 use crate::app::ui::widgets::apply_look_ahead_scroll;
 use crate::app::App;
+use crate::config_parser::{EntryType, LIST_OPTIONS};
 
 impl App {
     pub fn handle_event(&mut self, event: Event) -> Result<()> {
+        let focus_before = self.focus.clone();
+        let result = self.dispatch_event(event);
+
+        // Remember where focus came from, for Ctrl+O
+        let is_main_area = |focus: &Focus| focus != &Focus::PropertyEditor;
+        if self.focus != focus_before && is_main_area(&self.focus) && is_main_area(&focus_before) {
+            self.previous_focus = Some(focus_before);
+        }
+        result
+    }
+
+    fn dispatch_event(&mut self, event: Event) -> Result<()> {
         if let Event::Key(key) = event {
             if key.kind != KeyEventKind::Press {
                 return Ok(());
@@ -20,21 +37,37 @@ impl App {
                         self.should_quit = true;
                         return Ok(());
                     }
-                    KeyCode::Char('s') if !self.is_searching => {
-                        self.save_config()?;
+                    KeyCode::Char('s') if !self.is_searching && !self.saving => {
+                        // Show what's about to be written first, if asked to
+                        let preview = self.settings.preview_before_save
+                            && self.is_dirty
+                            && !self.diff_popup.show;
+                        if preview {
+                            self.open_diff_preview(true);
+                            return Ok(());
+                        }
+                        // The main loop saves once "Saving..." is on screen
+                        self.diff_popup.show = false;
+                        self.saving = true;
+                        self.set_status("Saving...".to_string());
                         return Ok(());
                     }
                     _ => {}
                 }
             }
 
+            // Nothing may edit the config while it's being saved
+            if self.saving {
+                return Ok(());
+            }
+
             // Block most input during search
             if self.is_searching {
                 // Only allow Escape to cancel search
                 if key.code == KeyCode::Esc {
                     self.searcher.cancel();
                     self.is_searching = false;
-                    self.status_message = Some("Search cancelled".to_string());
+                    self.set_status("Search cancelled".to_string());
                 }
                 return Ok(());
             }
@@ -44,6 +77,11 @@ impl App {
                 return Ok(());
             }
 
+            if key.code == KeyCode::F(3) {
+                self.toggle_mouse_capture();
+                return Ok(());
+            }
+
             if self.show_help {
                 // Any key closes help
                 self.show_help = false;
@@ -100,6 +138,49 @@ impl App {
                 return Ok(());
             }
 
+            // Handle the diff preview
+            if self.diff_popup.show {
+                return self.handle_diff_popup_input(key.code);
+            }
+
+            // Handle confirmation prompt if it's open (drawn above everything else)
+            if self.confirm_prompt.show {
+                self.handle_confirm_prompt_input(key.code)?;
+                return Ok(());
+            }
+
+            // Handle the note input
+            if self.text_prompt.show {
+                return self.handle_text_prompt_input(key.code);
+            }
+
+            // Handle the list options popup
+            if self.list_options.show {
+                self.handle_list_options_input(key.code);
+                return Ok(());
+            }
+
+            // Handle the choice of file to move an entry to
+            if self.move_prompt.show {
+                return self.handle_move_prompt_input(key.code);
+            }
+
+            // Handle the choice of template in the property editor
+            if self.template_prompt.show {
+                return self.handle_template_prompt_input(key.code);
+            }
+
+            // Handle the cleanup view of disabled blocks
+            if self.cleanup.show {
+                return self.handle_cleanup_input(key.code);
+            }
+
+            // Handle the "add as" choice for a search result
+            if self.add_as_prompt.show {
+                self.handle_add_as_prompt_input(key.code)?;
+                return Ok(());
+            }
+
             // Handle rebuild prompt if it's open
             if self.rebuild_prompt.show {
                 self.handle_rebuild_prompt_input(key.code)?;
@@ -112,15 +193,53 @@ impl App {
                 return Ok(());
             }
 
+            if key.code == KeyCode::Char('o') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                // Jump back to the previously focused column or search bar
+                if let Some(previous) = self.previous_focus.take() {
+                    self.focus = previous;
+                }
+                return Ok(());
+            }
+
             match self.focus {
                 Focus::SearchBar => self.handle_search_input(key.code)?,
                 Focus::Programs => self.handle_list_input(key.code, ListType::Programs)?,
                 Focus::Services => self.handle_list_input(key.code, ListType::Services)?,
                 Focus::Packages => self.handle_list_input(key.code, ListType::Packages)?,
+                Focus::Users => self.handle_list_input(key.code, ListType::Users)?,
                 Focus::PropertyEditor => self.handle_property_editor_input(key.code)?,
             }
+        } else if let Event::Paste(text) = event {
+            let text = text.replace("\r\n", "\n").replace('\r', "\n");
+            if self.text_prompt.show {
+                // Inserted in one go, so pasted line breaks separate words instead of submitting
+                let text = text.replace('\n', " ");
+                self.text_prompt
+                    .buffer
+                    .insert_str(self.text_prompt.cursor, &text);
+                self.text_prompt.cursor += text.len();
+            } else {
+                // Elsewhere a paste behaves as if the text was typed
+                for c in text.chars() {
+                    let code = if c == '\n' {
+                        KeyCode::Enter
+                    } else {
+                        KeyCode::Char(c)
+                    };
+                    self.handle_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)))?;
+                }
+            }
         } else if let Event::Mouse(mouse) = event {
-            if !self.is_searching {
+            if !self.is_searching
+                && !self.confirm_prompt.show
+                && !self.add_as_prompt.show
+                && !self.text_prompt.show
+                && !self.list_options.show
+                && !self.cleanup.show
+                && !self.move_prompt.show
+                && !self.template_prompt.show
+                && !self.diff_popup.show
+            {
                 if self.prop_editor.show {
                     self.handle_property_editor_mouse(mouse)?;
                 } else {
@@ -153,16 +272,7 @@ impl App {
                     let relative_y = (y - self.property_list_area.y) as usize;
                     let scroll_offset = self.prop_editor.list_state.offset();
                     let clicked_idx = scroll_offset + relative_y;
-                    let len = if self.prop_editor.showing_available {
-                        self.prop_editor.available_options.len()
-                    } else {
-                        self.prop_editor
-                            .entry
-                            .as_ref()
-                            .and_then(|(name, entry_type)| self.config.get_entry(name, entry_type))
-                            .map(|e| e.properties.len())
-                            .unwrap_or(0)
-                    };
+                    let len = self.visible_property_indices().len();
                     if clicked_idx < len {
                         self.prop_editor.list_state.select(Some(clicked_idx));
                     }
@@ -186,74 +296,93 @@ impl App {
                 } else if self.programs_area.contains((x, y).into()) {
                     self.focus = Focus::Programs;
                     // Calculate which item was clicked (accounting for border and scroll offset)
-                    if y > self.programs_area.y
-                        && y < self.programs_area.y + self.programs_area.height - 1
+                    if let Some(clicked_idx) =
+                        self.clicked_list_index(self.programs_area, self.program_state.offset(), y)
                     {
-                        let scroll_offset = self.program_state.offset();
-                        let clicked_idx = scroll_offset + (y - self.programs_area.y - 1) as usize;
                         if clicked_idx < self.programs.len() {
                             self.program_state.select(Some(clicked_idx));
                         }
                     }
                 } else if self.services_area.contains((x, y).into()) {
                     self.focus = Focus::Services;
-                    if y > self.services_area.y
-                        && y < self.services_area.y + self.services_area.height - 1
+                    if let Some(clicked_idx) =
+                        self.clicked_list_index(self.services_area, self.service_state.offset(), y)
                     {
-                        let scroll_offset = self.service_state.offset();
-                        let clicked_idx = scroll_offset + (y - self.services_area.y - 1) as usize;
                         if clicked_idx < self.services.len() {
                             self.service_state.select(Some(clicked_idx));
+                            // Clicking a group header collapses/expands it
+                            if self.services[clicked_idx].header.is_some() {
+                                self.toggle_service_group_at(clicked_idx);
+                            }
                         }
                     }
                 } else if self.packages_area.contains((x, y).into()) {
                     self.focus = Focus::Packages;
-                    if y > self.packages_area.y
-                        && y < self.packages_area.y + self.packages_area.height - 1
+                    if let Some(clicked_idx) =
+                        self.clicked_list_index(self.packages_area, self.package_state.offset(), y)
                     {
-                        let scroll_offset = self.package_state.offset();
-                        let clicked_idx = scroll_offset + (y - self.packages_area.y - 1) as usize;
-                        if clicked_idx < self.packages.len() {
+                        // Section dividers can't be selected
+                        if self
+                            .packages
+                            .get(clicked_idx)
+                            .is_some_and(|e| e.header.is_none())
+                        {
                             self.package_state.select(Some(clicked_idx));
                         }
                     }
+                } else if self.users_area.contains((x, y).into()) {
+                    self.focus = Focus::Users;
+                    if let Some(clicked_idx) =
+                        self.clicked_list_index(self.users_area, self.user_state.offset(), y)
+                    {
+                        if clicked_idx < self.users.len() {
+                            self.user_state.select(Some(clicked_idx));
+                        }
+                    }
                 }
             }
             MouseEventKind::Down(MouseButton::Right) => {
                 // Right click toggles the item under cursor
                 if self.programs_area.contains((x, y).into()) {
-                    if y > self.programs_area.y
-                        && y < self.programs_area.y + self.programs_area.height - 1
+                    if let Some(clicked_idx) =
+                        self.clicked_list_index(self.programs_area, self.program_state.offset(), y)
                     {
-                        let scroll_offset = self.program_state.offset();
-                        let clicked_idx = scroll_offset + (y - self.programs_area.y - 1) as usize;
                         if clicked_idx < self.programs.len() {
                             self.program_state.select(Some(clicked_idx));
                             self.toggle_selected(&ListType::Programs)?;
                         }
                     }
                 } else if self.services_area.contains((x, y).into()) {
-                    if y > self.services_area.y
-                        && y < self.services_area.y + self.services_area.height - 1
+                    if let Some(clicked_idx) =
+                        self.clicked_list_index(self.services_area, self.service_state.offset(), y)
                     {
-                        let scroll_offset = self.service_state.offset();
-                        let clicked_idx = scroll_offset + (y - self.services_area.y - 1) as usize;
                         if clicked_idx < self.services.len() {
                             self.service_state.select(Some(clicked_idx));
                             self.toggle_selected(&ListType::Services)?;
                         }
                     }
                 } else if self.packages_area.contains((x, y).into()) {
-                    if y > self.packages_area.y
-                        && y < self.packages_area.y + self.packages_area.height - 1
+                    if let Some(clicked_idx) =
+                        self.clicked_list_index(self.packages_area, self.package_state.offset(), y)
                     {
-                        let scroll_offset = self.package_state.offset();
-                        let clicked_idx = scroll_offset + (y - self.packages_area.y - 1) as usize;
-                        if clicked_idx < self.packages.len() {
+                        if self
+                            .packages
+                            .get(clicked_idx)
+                            .is_some_and(|e| e.header.is_none())
+                        {
                             self.package_state.select(Some(clicked_idx));
                             self.toggle_selected(&ListType::Packages)?;
                         }
                     }
+                } else if self.users_area.contains((x, y).into()) {
+                    if let Some(clicked_idx) =
+                        self.clicked_list_index(self.users_area, self.user_state.offset(), y)
+                    {
+                        if clicked_idx < self.users.len() {
+                            self.user_state.select(Some(clicked_idx));
+                            self.toggle_selected(&ListType::Users)?;
+                        }
+                    }
                 }
             }
             MouseEventKind::ScrollUp => {
@@ -262,6 +391,7 @@ impl App {
                     Focus::Programs => self.move_selection(-3, &ListType::Programs),
                     Focus::Services => self.move_selection(-3, &ListType::Services),
                     Focus::Packages => self.move_selection(-3, &ListType::Packages),
+                    Focus::Users => self.move_selection(-3, &ListType::Users),
                     _ => {}
                 }
             }
@@ -271,6 +401,7 @@ impl App {
                     Focus::Programs => self.move_selection(3, &ListType::Programs),
                     Focus::Services => self.move_selection(3, &ListType::Services),
                     Focus::Packages => self.move_selection(3, &ListType::Packages),
+                    Focus::Users => self.move_selection(3, &ListType::Users),
                     _ => {}
                 }
             }
@@ -281,49 +412,54 @@ impl App {
     }
 
     pub(crate) fn handle_search_input(&mut self, code: KeyCode) -> Result<()> {
+        // Anything but Up/Down keeps the recalled query and stops recalling
+        if !matches!(code, KeyCode::Up | KeyCode::Down) {
+            self.search_recall = None;
+        }
+
         match code {
-            KeyCode::Char(c) => {
-                self.search_query.insert(self.search_cursor, c);
-                self.search_cursor += 1;
-            }
-            KeyCode::Backspace => {
-                if self.search_cursor > 0 {
-                    self.search_cursor -= 1;
-                    self.search_query.remove(self.search_cursor);
-                }
-            }
-            KeyCode::Delete => {
-                if self.search_cursor < self.search_query.len() {
-                    self.search_query.remove(self.search_cursor);
-                }
-            }
-            KeyCode::Left => {
-                self.search_cursor = self.search_cursor.saturating_sub(1);
-            }
-            KeyCode::Right => {
-                self.search_cursor = (self.search_cursor + 1).min(self.search_query.len());
-            }
-            KeyCode::Home => {
-                self.search_cursor = 0;
-            }
-            KeyCode::End => {
-                self.search_cursor = self.search_query.len();
-            }
             KeyCode::Enter => {
                 self.perform_search()?;
             }
+            KeyCode::F(5) => {
+                // Search again without using cached results
+                self.refresh_search();
+            }
+            KeyCode::F(2) => {
+                // Switch between searching packages and NixOS options
+                self.search_mode = self.search_mode.toggled();
+                self.set_status(format!("Searching {}", self.search_mode.label()));
+                if !self.search_query.is_empty() {
+                    self.perform_search()?;
+                }
+            }
             KeyCode::Tab => {
                 self.focus = Focus::Programs;
             }
+            KeyCode::BackTab => {
+                // Wrap around to the last column, mirroring Tab from it
+                self.focus = if self.show_users {
+                    Focus::Users
+                } else {
+                    Focus::Packages
+                };
+            }
+            KeyCode::Up => {
+                // Recall earlier searches
+                self.recall_search(true);
+            }
             KeyCode::Down => {
-                self.focus = Focus::Programs;
+                // Newer searches while recalling, otherwise on to the lists
+                if !self.recall_search(false) {
+                    self.focus = Focus::Programs;
+                }
             }
             KeyCode::Esc => {
                 self.search_query.clear();
                 self.search_cursor = 0;
                 self.load_from_config(); // Reset to config entries
             }
-            _ => {}
+            code => text_input::edit(&mut self.search_query, &mut self.search_cursor, code),
         }
 
         Ok(())
@@ -344,7 +480,8 @@ impl App {
                 self.focus = match list_type {
                     ListType::Programs => Focus::Services,
                     ListType::Services => Focus::Packages,
-                    ListType::Packages => Focus::SearchBar,
+                    ListType::Packages if self.show_users => Focus::Users,
+                    ListType::Packages | ListType::Users => Focus::SearchBar,
                 };
             }
             KeyCode::BackTab => {
@@ -352,6 +489,7 @@ impl App {
                     ListType::Programs => Focus::SearchBar,
                     ListType::Services => Focus::Programs,
                     ListType::Packages => Focus::Services,
+                    ListType::Users => Focus::Packages,
                 };
             }
             KeyCode::Left => {
@@ -359,15 +497,38 @@ impl App {
                     ListType::Programs => Focus::SearchBar,
                     ListType::Services => Focus::Programs,
                     ListType::Packages => Focus::Services,
+                    ListType::Users => Focus::Packages,
                 };
             }
             KeyCode::Right => {
                 self.focus = match list_type {
                     ListType::Programs => Focus::Services,
                     ListType::Services => Focus::Packages,
+                    ListType::Packages if self.show_users => Focus::Users,
                     ListType::Packages => Focus::Packages,
+                    ListType::Users => Focus::Users,
                 };
             }
+            KeyCode::Char('[') => {
+                // Previous column, keeping the relative vertical position
+                match list_type {
+                    ListType::Programs => {}
+                    ListType::Services => self.jump_to_column(&list_type, ListType::Programs),
+                    ListType::Packages => self.jump_to_column(&list_type, ListType::Services),
+                    ListType::Users => self.jump_to_column(&list_type, ListType::Packages),
+                }
+            }
+            KeyCode::Char(']') => {
+                // Next column, keeping the relative vertical position
+                match list_type {
+                    ListType::Programs => self.jump_to_column(&list_type, ListType::Services),
+                    ListType::Services => self.jump_to_column(&list_type, ListType::Packages),
+                    ListType::Packages if self.show_users => {
+                        self.jump_to_column(&list_type, ListType::Users)
+                    }
+                    ListType::Packages | ListType::Users => {}
+                }
+            }
             KeyCode::Char('/') | KeyCode::Esc => {
                 self.focus = Focus::SearchBar;
             }
@@ -379,6 +540,126 @@ impl App {
                 // Show description popup for the selected entry
                 self.show_description_popup(&list_type);
             }
+            KeyCode::Char('r') => {
+                // Show where else the selected entry is referenced
+                self.show_references(&list_type);
+            }
+            KeyCode::Char('n') => {
+                // Jump to the next place that mentions the selected entry
+                self.jump_to_reference(&list_type, true);
+            }
+            KeyCode::Char('N') => {
+                // Jump to the previous place that mentions the selected entry
+                self.jump_to_reference(&list_type, false);
+            }
+            KeyCode::Char('A') => {
+                // Enable/disable every visible entry in this column (asks first)
+                self.confirm_toggle_all_visible(&list_type)?;
+            }
+            KeyCode::Char('G') => {
+                // Show the uncommitted git diff of the config (with --git)
+                self.show_git_diff();
+            }
+            KeyCode::Char('m') => {
+                // Show the history of status messages
+                self.show_status_history();
+            }
+            KeyCode::Char('C') => {
+                // Copy a commit-ready summary of this session's changes
+                self.copy_change_summary();
+            }
+            KeyCode::Char('J') => {
+                // Copy the selected entry as parsed, for bug reports and scripts
+                self.copy_entry_json(&list_type);
+            }
+            KeyCode::Char('P') => {
+                // Add a whole list of packages, e.g. pasted from another config
+                self.open_packages_prompt();
+            }
+            KeyCode::Char('u') => {
+                // Take back the last package list, template or cleanup
+                self.undo_snapshot();
+            }
+            KeyCode::Char('L') if list_type == ListType::Packages => {
+                // Pick which package list new packages go to
+                self.cycle_package_list_target();
+            }
+            KeyCode::Char('i') => {
+                // Show descriptions under each entry, or only in the d popup
+                self.toggle_inline_descriptions();
+            }
+            KeyCode::Char('#') => {
+                // Add or edit a note, kept as a trailing comment on the entry's line
+                self.open_note_prompt(&list_type);
+            }
+            KeyCode::Char('O') => {
+                // Edit options that are lists (firewall ports, kernel modules, ...)
+                self.list_options.show = true;
+                self.list_options.member = None;
+                self.list_options.property = None;
+            }
+            KeyCode::Char('F') => {
+                // Move between this host's file and a shared module
+                self.open_move_prompt(&list_type)?;
+            }
+            KeyCode::Char('X') => {
+                // List disabled programs/services whose blocks could be removed
+                self.open_cleanup();
+            }
+            KeyCode::Char('R') => {
+                // Rename a program/service, keeping its properties
+                self.open_rename_prompt(&list_type);
+            }
+            KeyCode::F(5) => {
+                // Search again without using cached results
+                self.refresh_search();
+            }
+            KeyCode::Char('E') => {
+                // Set enable to an arbitrary expression instead of true/false
+                self.open_enable_prompt(&list_type);
+            }
+            KeyCode::Char('a') => {
+                // Choose whether a search result is added as a package, program or service
+                self.open_add_as_prompt(&list_type);
+            }
+            KeyCode::Char('b') => {
+                // Collapse the search bar to one line when it isn't in use
+                self.toggle_compact_search();
+            }
+            KeyCode::Char('o') => {
+                // Order search results by relevance or by name
+                self.cycle_result_sort();
+            }
+            KeyCode::Char('H') => {
+                // Hide/show disabled programs and services (saved as a setting)
+                self.toggle_hide_disabled();
+            }
+            KeyCode::Char('U') => {
+                // Show/hide the Users column
+                self.toggle_users_column();
+            }
+            KeyCode::Char('V') => {
+                // Diff of the unsaved changes against the files on disk
+                self.open_diff_preview(false);
+            }
+            KeyCode::Char('S') => {
+                // Preview the diff on Ctrl+S before saving (saved as a setting)
+                self.toggle_preview_before_save();
+            }
+            KeyCode::Char('M') if list_type == ListType::Services => {
+                // Hide service results that aren't NixOS service modules
+                self.toggle_service_modules_only();
+            }
+            KeyCode::Char('g') if list_type == ListType::Services => {
+                // Group services under collapsible prefix headers
+                self.toggle_service_grouping();
+            }
+            KeyCode::Char('z') if list_type == ListType::Services => {
+                // Collapse/expand the group of the selected service
+                if let Some(idx) = self.service_state.selected() {
+                    self.toggle_service_group_at(idx);
+                }
+            }
             _ => {}
         }
 
@@ -391,9 +672,30 @@ impl App {
             ListType::Programs => self.programs_area,
             ListType::Services => self.services_area,
             ListType::Packages => self.packages_area,
+            ListType::Users => self.users_area,
         };
         // Subtract 2 for top and bottom borders
-        area.height.saturating_sub(2) as usize
+        area.height.saturating_sub(2) as usize / self.list_row_height()
+    }
+
+    /// Entry index under screen row `y` of a bordered list area, given its scroll
+    /// offset. `None` on the borders or if the area has no room for rows at all.
+    pub(crate) fn clicked_list_index(&self, area: Rect, offset: usize, y: u16) -> Option<usize> {
+        let first_row = area.y.checked_add(1)?;
+        let last_row = area.y.saturating_add(area.height).checked_sub(2)?;
+        if y < first_row || y > last_row {
+            return None;
+        }
+        Some(offset + (y - first_row) as usize / self.list_row_height())
+    }
+
+    /// Screen rows per list entry: 2 while descriptions are shown inline
+    pub(crate) fn list_row_height(&self) -> usize {
+        if self.settings.inline_descriptions {
+            2
+        } else {
+            1
+        }
     }
 
     pub(crate) fn move_selection(&mut self, delta: i32, list_type: &ListType) {
@@ -404,6 +706,7 @@ impl App {
             ListType::Programs => (&mut self.program_state, self.programs.len()),
             ListType::Services => (&mut self.service_state, self.services.len()),
             ListType::Packages => (&mut self.package_state, self.packages.len()),
+            ListType::Users => (&mut self.user_state, self.users.len()),
         };
 
         if len == 0 {
@@ -411,12 +714,25 @@ impl App {
         }
 
         let current = state.selected().unwrap_or(0);
-        let new = if delta > 0 {
+        let mut new = if delta > 0 {
             (current + delta as usize).min(len - 1)
         } else {
             current.saturating_sub((-delta) as usize)
         };
 
+        // Expanded group headers and package section dividers can't be selected
+        match list_type {
+            ListType::Services => new = self.next_selectable_service(new, delta),
+            ListType::Packages => new = self.next_selectable_package(new, delta),
+            ListType::Programs | ListType::Users => {}
+        }
+        let state = match list_type {
+            ListType::Programs => &mut self.program_state,
+            ListType::Services => &mut self.service_state,
+            ListType::Packages => &mut self.package_state,
+            ListType::Users => &mut self.user_state,
+        };
+
         state.select(Some(new));
 
         // Apply look-ahead scrolling
@@ -430,13 +746,112 @@ impl App {
         apply_look_ahead_scroll(new, len, viewport_height, state, direction);
     }
 
+    /// Focus another column, selecting the row at the same relative position
+    /// (e.g. 75% down the source list selects 75% down the target list)
+    pub(crate) fn jump_to_column(&mut self, from: &ListType, to: ListType) {
+        let (source_selected, source_len) = match from {
+            ListType::Programs => (self.program_state.selected(), self.programs.len()),
+            ListType::Services => (self.service_state.selected(), self.services.len()),
+            ListType::Packages => (self.package_state.selected(), self.packages.len()),
+            ListType::Users => (self.user_state.selected(), self.users.len()),
+        };
+        let target_len = match to {
+            ListType::Programs => self.programs.len(),
+            ListType::Services => self.services.len(),
+            ListType::Packages => self.packages.len(),
+            ListType::Users => self.users.len(),
+        };
+
+        self.focus = match to {
+            ListType::Programs => Focus::Programs,
+            ListType::Services => Focus::Services,
+            ListType::Packages => Focus::Packages,
+            ListType::Users => Focus::Users,
+        };
+
+        if target_len == 0 {
+            return;
+        }
+
+        let source_idx = source_selected.unwrap_or(0);
+        let mut target = if source_len > 1 {
+            // Round to the nearest row
+            (source_idx * (target_len - 1) + (source_len - 1) / 2) / (source_len - 1)
+        } else {
+            0
+        }
+        .min(target_len - 1);
+
+        match to {
+            ListType::Services => target = self.next_selectable_service(target, 1),
+            ListType::Packages => target = self.next_selectable_package(target, 1),
+            ListType::Programs | ListType::Users => {}
+        }
+
+        let viewport_height = self.get_list_viewport_height(&to);
+        let state = match to {
+            ListType::Programs => &mut self.program_state,
+            ListType::Services => &mut self.service_state,
+            ListType::Packages => &mut self.package_state,
+            ListType::Users => &mut self.user_state,
+        };
+        state.select(Some(target));
+        apply_look_ahead_scroll(target, target_len, viewport_height, state, 0);
+    }
+
+    /// Select and focus the list row for a config entry. Returns where it
+    /// landed, or `None` if the entry isn't currently shown.
+    pub(crate) fn select_entry(
+        &mut self,
+        name: &str,
+        entry_type: &EntryType,
+    ) -> Option<(ListType, usize)> {
+        let list_type = match entry_type {
+            EntryType::Program => ListType::Programs,
+            EntryType::Service => ListType::Services,
+            EntryType::Package => ListType::Packages,
+            EntryType::User => ListType::Users,
+        };
+        if list_type == ListType::Users {
+            self.show_users = true;
+        }
+        let entries = match list_type {
+            ListType::Programs => &self.programs,
+            ListType::Services => &self.services,
+            ListType::Packages => &self.packages,
+            ListType::Users => &self.users,
+        };
+        let index = entries
+            .iter()
+            .position(|e| e.name == name && e.header.is_none())?;
+        let len = entries.len();
+
+        self.focus = match list_type {
+            ListType::Programs => Focus::Programs,
+            ListType::Services => Focus::Services,
+            ListType::Packages => Focus::Packages,
+            ListType::Users => Focus::Users,
+        };
+        let viewport_height = self.get_list_viewport_height(&list_type);
+        let state = match list_type {
+            ListType::Programs => &mut self.program_state,
+            ListType::Services => &mut self.service_state,
+            ListType::Packages => &mut self.package_state,
+            ListType::Users => &mut self.user_state,
+        };
+        state.select(Some(index));
+        apply_look_ahead_scroll(index, len, viewport_height, state, 0);
+
+        Some((list_type, index))
+    }
+
     fn handle_rebuild_prompt_input(&mut self, code: KeyCode) -> Result<()> {
         match code {
             KeyCode::Left | KeyCode::Char('h') => {
-                self.rebuild_prompt.selected = 0;
+                self.rebuild_prompt.mode = self.rebuild_prompt.mode.cycle(false);
             }
             KeyCode::Right | KeyCode::Char('l') => {
-                self.rebuild_prompt.selected = 1;
+                self.rebuild_prompt.mode = self.rebuild_prompt.mode.cycle(true);
             }
             KeyCode::Char('y') | KeyCode::Char('Y') => {
                 self.rebuild_prompt.selected = 0;
@@ -445,6 +860,9 @@ impl App {
             KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
                 self.rebuild_prompt.show = false;
             }
+            KeyCode::Char('v') => {
+                self.cycle_rebuild_verbosity();
+            }
             KeyCode::Enter => {
                 if self.rebuild_prompt.selected == 0 {
                     self.rebuild_prompt.pending_rebuild = true;
@@ -464,6 +882,182 @@ impl App {
         Ok(())
     }
 
+    fn handle_text_prompt_input(&mut self, code: KeyCode) -> Result<()> {
+        let prompt = &mut self.text_prompt;
+        match code {
+            KeyCode::Enter => return self.submit_text_prompt(),
+            KeyCode::Esc => {
+                prompt.show = false;
+                self.set_status("Cancelled".to_string());
+            }
+            code => text_input::edit(&mut prompt.buffer, &mut prompt.cursor, code),
+        }
+        Ok(())
+    }
+
+    fn handle_list_options_input(&mut self, code: KeyCode) {
+        let option_count = LIST_OPTIONS.len();
+        let member_count = self
+            .list_options_path()
+            .and_then(|path| self.config.list_option_members(&path))
+            .map_or(0, |members| members.len());
+        let state = &mut self.list_options;
+
+        match (state.member, code) {
+            // Choosing an option
+            (None, KeyCode::Up | KeyCode::Char('k')) => {
+                state.option = state.option.saturating_sub(1);
+            }
+            (None, KeyCode::Down | KeyCode::Char('j')) => {
+                state.option = (state.option + 1).min(option_count - 1);
+            }
+            (None, KeyCode::Enter | KeyCode::Right | KeyCode::Char('l')) => {
+                state.member = Some(0);
+            }
+            (None, KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('O')) => {
+                state.show = false;
+            }
+            // Looking at one option's members
+            (Some(i), KeyCode::Up | KeyCode::Char('k')) => {
+                state.member = Some(i.saturating_sub(1));
+            }
+            (Some(i), KeyCode::Down | KeyCode::Char('j')) => {
+                state.member = Some((i + 1).min(member_count.saturating_sub(1)));
+            }
+            (Some(_), KeyCode::Char('d') | KeyCode::Delete) => {
+                self.remove_selected_list_member();
+            }
+            // Opened from the property editor: there's no option list to go back to
+            (Some(_), KeyCode::Esc | KeyCode::Left | KeyCode::Char('h' | 'q'))
+                if state.property.is_some() =>
+            {
+                state.show = false;
+                state.property = None;
+            }
+            (Some(_), KeyCode::Esc | KeyCode::Left | KeyCode::Char('h')) => {
+                state.member = None;
+            }
+            (_, KeyCode::Char('a')) => {
+                self.open_list_member_prompt();
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_move_prompt_input(&mut self, code: KeyCode) -> Result<()> {
+        let state = &mut self.move_prompt;
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                state.selected = state.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                state.selected = (state.selected + 1).min(state.targets.len().saturating_sub(1));
+            }
+            KeyCode::Enter => self.submit_move_prompt()?,
+            KeyCode::Esc | KeyCode::Char('q') => state.show = false,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_template_prompt_input(&mut self, code: KeyCode) -> Result<()> {
+        let state = &mut self.template_prompt;
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                state.selected = state.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                state.selected = (state.selected + 1).min(state.templates.len().saturating_sub(1));
+            }
+            KeyCode::Enter => self.apply_selected_template(),
+            KeyCode::Esc | KeyCode::Char('q') => state.show = false,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_cleanup_input(&mut self, code: KeyCode) -> Result<()> {
+        let count = self.cleanup_candidates().len();
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.cleanup.selected = self.cleanup.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.cleanup.selected = (self.cleanup.selected + 1).min(count.saturating_sub(1));
+            }
+            KeyCode::Char(' ') => {
+                self.toggle_cleanup_mark();
+                self.cleanup.selected = (self.cleanup.selected + 1).min(count.saturating_sub(1));
+            }
+            KeyCode::Char('a') => self.toggle_cleanup_mark_all(),
+            KeyCode::Char('d') | KeyCode::Delete | KeyCode::Enter => {
+                self.confirm_cleanup_removal()?;
+            }
+            KeyCode::Char('u') => self.undo_snapshot(),
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('X') => {
+                self.cleanup.show = false;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_add_as_prompt_input(&mut self, code: KeyCode) -> Result<()> {
+        let entry_type = match code {
+            KeyCode::Char('p') => EntryType::Package,
+            KeyCode::Char('r') => EntryType::Program,
+            KeyCode::Char('s') => EntryType::Service,
+            KeyCode::Esc => {
+                self.add_as_prompt.show = false;
+                self.set_status("Cancelled".to_string());
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+
+        self.add_as_prompt.show = false;
+        let name = std::mem::take(&mut self.add_as_prompt.name);
+        self.add_as(&name, entry_type)
+    }
+
+    fn handle_confirm_prompt_input(&mut self, code: KeyCode) -> Result<()> {
+        let confirmed = match code {
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.confirm_prompt.selected = 0;
+                None
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.confirm_prompt.selected = 1;
+                None
+            }
+            KeyCode::Tab => {
+                self.confirm_prompt.selected = if self.confirm_prompt.selected == 0 {
+                    1
+                } else {
+                    0
+                };
+                None
+            }
+            KeyCode::Char('y') | KeyCode::Char('Y') => Some(true),
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => Some(false),
+            KeyCode::Enter => Some(self.confirm_prompt.selected == 0),
+            _ => None,
+        };
+
+        if let Some(confirmed) = confirmed {
+            self.confirm_prompt.show = false;
+            let action = self.confirm_prompt.action.take();
+            if confirmed {
+                if let Some(action) = action {
+                    self.run_confirmed_action(action)?;
+                }
+            } else {
+                self.set_status("Cancelled".to_string());
+            }
+        }
+        Ok(())
+    }
+
     /// Show description popup for the currently selected entry
     fn show_description_popup(&mut self, list_type: &ListType) {
         let entry = match list_type {
@@ -474,20 +1068,71 @@ impl App {
             ListType::Services => self
                 .service_state
                 .selected()
-                .and_then(|i| self.services.get(i)),
+                .and_then(|i| self.services.get(i))
+                .filter(|e| e.header.is_none()),
             ListType::Packages => self
                 .package_state
                 .selected()
-                .and_then(|i| self.packages.get(i)),
+                .and_then(|i| self.packages.get(i))
+                .filter(|e| e.header.is_none()),
+            ListType::Users => self.user_state.selected().and_then(|i| self.users.get(i)),
         };
 
         if let Some(entry) = entry {
             self.description_popup.name = entry.name.clone();
-            self.description_popup.description = if entry.description.is_empty() {
+            let mut description = if entry.description.is_empty() {
                 "No description available".to_string()
             } else {
                 entry.description.clone()
             };
+
+            // The user's own note from the config comes first
+            let entry_type = match list_type {
+                ListType::Programs => EntryType::Program,
+                ListType::Services => EntryType::Service,
+                ListType::Packages => EntryType::Package,
+                ListType::Users => EntryType::User,
+            };
+            if let Some(note) = self
+                .config
+                .get_entry(&entry.name, &entry_type)
+                .and_then(|e| e.note.as_ref())
+            {
+                description = format!("Note: {}\n\n{}", note, description);
+            }
+
+            // Entries from imported files say where they are
+            if let Some(file) = self
+                .config
+                .get_entry(&entry.name, &entry_type)
+                .map(|e| &e.file)
+                .filter(|file| **file != self.config.path)
+                .map(|file| self.display_path(file))
+            {
+                description = format!("Defined in: {}\n\n{}", file, description);
+            }
+
+            // Add package metadata from the search results, when known
+            if let Some(result) = self
+                .search_results
+                .iter()
+                .find(|r| r.attr_name == entry.name)
+            {
+                let mut metadata = Vec::new();
+                if result.display_name != result.attr_name {
+                    metadata.push(format!("Package name: {}", result.display_name));
+                }
+                if let Some(ref version) = result.version {
+                    metadata.push(format!("Version: {}", version));
+                }
+                if let Some(ref homepage) = result.homepage {
+                    metadata.push(format!("Homepage: {}", homepage));
+                }
+                if !metadata.is_empty() {
+                    description = format!("{}\n\n{}", description, metadata.join("\n"));
+                }
+            }
+            self.description_popup.description = description;
             self.description_popup.scroll_offset = 0; // Reset scroll when opening
             self.description_popup.show = true;
         }
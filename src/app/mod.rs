@@ -5,21 +5,42 @@
 //! - `input`: Event handling (keyboard, mouse)
 //! - `property_editor`: Property editor logic
 //! - `search_handler`: Search processing
+//! - `service_groups`: Collapsible prefix groups in the Services column
+//! - `text_input`: Cursor editing for the one-line text fields
 //! - `ui`: All rendering code
 
+mod diff_preview;
 mod input;
+mod package_sections;
 mod property_editor;
 mod search_handler;
+mod service_groups;
+mod text_input;
 pub mod types;
 pub mod ui;
 
 use anyhow::Result;
 use ratatui::{layout::Rect, widgets::ListState};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 
-use crate::config_parser::{EntryType, NixConfig, SchemaCache};
-use crate::search::{NixSearcher, SearchResult};
+use crate::cli::CliArgs;
+use crate::clipboard;
+use crate::config_parser::{self, EntryType, NixConfig, PropertyType, SchemaBackend, SchemaCache};
+use crate::flake::FlakeHost;
+use crate::git;
+use crate::hooks;
+use crate::search::{NixSearcher, SearchMode, SearchResult};
+use crate::settings::Settings;
 
-use types::{DescriptionPopupState, Focus, ListEntry, PropertyEditorState, RebuildPromptState};
+use types::{
+    AddAsPromptState, ChangeAction, ChangeLog, ChangeLogEntry, CleanupState, ConfirmAction,
+    ConfirmPromptState, DescriptionPopupState, DiffPopupState, Focus, ListEntry, ListOptionsState,
+    MovePromptState, PackageCheck, PropertyEditorState, RebuildPromptState, SnapshotUndo,
+    StatusHistory, TemplatePromptState, TextPromptState,
+};
 
 pub struct App {
     pub config: NixConfig,
@@ -27,16 +48,28 @@ pub struct App {
     pub schema_cache: SchemaCache,
     pub search_query: String,
     pub search_cursor: usize,
+    // Position in the search history while recalling queries, and the query typed before
+    pub search_recall: Option<(usize, String)>,
+    // Whether a search was added to the history since the settings were saved
+    pub search_history_changed: bool,
+    pub search_mode: SearchMode,
     pub focus: Focus,
+    pub previous_focus: Option<Focus>, // Where focus was before, for Ctrl+O
     pub programs: Vec<ListEntry>,
     pub services: Vec<ListEntry>,
     pub packages: Vec<ListEntry>,
+    pub users: Vec<ListEntry>,
     pub program_state: ListState,
     pub service_state: ListState,
     pub package_state: ListState,
+    pub user_state: ListState,
     pub should_quit: bool,
     pub status_message: Option<String>,
     pub is_searching: bool,
+    // A save was requested; the main loop does it, blocking input until it's done
+    pub saving: bool,
+    // Results of the post-save hooks running in the background, one per hook
+    pub hook_receiver: Option<mpsc::Receiver<(String, Result<Option<String>>)>>,
     pub search_results: Vec<SearchResult>,
     pub show_help: bool,
     // Layout areas for mouse handling
@@ -44,6 +77,7 @@ pub struct App {
     pub programs_area: Rect,
     pub services_area: Rect,
     pub packages_area: Rect,
+    pub users_area: Rect,
     // Property editor state
     pub prop_editor: PropertyEditorState,
     // Property editor area for mouse handling
@@ -52,17 +86,127 @@ pub struct App {
     pub rebuild_prompt: RebuildPromptState,
     // Track unsaved changes
     pub is_dirty: bool,
+    // Number of edits since the last save
+    pub unsaved_changes: usize,
     // Description popup state
     pub description_popup: DescriptionPopupState,
+    pub diff_popup: DiffPopupState,
+    // Generic confirmation prompt state
+    pub confirm_prompt: ConfirmPromptState,
+    // "Add as package/program/service" prompt for search results
+    pub add_as_prompt: AddAsPromptState,
+    // Text input for the selected entry (note, rename)
+    pub text_prompt: TextPromptState,
+    // Popup for options that are lists (firewall ports, kernel modules, ...)
+    pub list_options: ListOptionsState,
+    // Choice of the file to move a program/service to
+    pub move_prompt: MovePromptState,
+    pub template_prompt: TemplatePromptState,
+    // Disabled programs/services whose blocks can be removed
+    pub cleanup: CleanupState,
+    // The last edit made in one go, until it's undone
+    pub snapshot_undo: Option<SnapshotUndo>,
+    // Root of the git repository holding the config (only with --git)
+    pub git_repo: Option<PathBuf>,
+    // Whether the pre-existing uncommitted changes were already checked this session
+    pub git_dirty_checked: bool,
+    // Changes applied this session, for the commit-ready summary
+    pub change_log: ChangeLog,
+    // Group the Services column under collapsible prefix headers
+    pub group_services: bool,
+    // Show the Users column; on by default when the config defines users
+    pub show_users: bool,
+    // Prefixes of the service groups that are collapsed
+    pub collapsed_service_groups: HashSet<String>,
+    // Hide service search results that aren't NixOS service modules
+    pub service_modules_only: bool,
+    // M was pressed while the service modules are still being listed
+    pub service_modules_requested: bool,
+    // The saved config is newer than the active system profile
+    pub rebuild_pending: bool,
+    // Skip confirmations for non-destructive actions (--trust or the settings file)
+    pub trust_mode: bool,
+    // Whether mouse events should be captured; the main loop applies changes
+    pub mouse_capture: bool,
+    // Only look at the config: everything that would change it is refused (--read-only)
+    pub read_only: bool,
+    // Commented-out packages checked so far and how many there are, while verifying
+    pub verify_progress: Option<(usize, usize)>,
+    // Commented-out "packages" that aren't in nixpkgs (likely plain comments), hidden
+    pub missing_packages: HashSet<String>,
+    // Packages from the P prompt, added once the check that they exist is done
+    pub package_check: Option<PackageCheck>,
+    // Persistent preferences from the settings file
+    pub settings: Settings,
+    // Which package list new packages are added to
+    pub package_list_target: usize,
+    // Earlier status messages, for the history panel
+    pub status_history: StatusHistory,
+    // Last reference jump (n/N), continued while the selection stays where it landed
+    pub reference_jump: Option<types::ReferenceJump>,
+    // Host of a multi-host flake that schemas and rebuilds are scoped to
+    pub flake_host: Option<FlakeHost>,
 }
 
+/// The NixOS system profile, re-linked by every `nixos-rebuild switch`/`boot`
+const SYSTEM_PROFILE: &str = "/nix/var/nix/profiles/system";
+
 impl App {
-    pub fn new(mut config: NixConfig) -> Self {
-        let searcher = NixSearcher::new();
-        let schema_cache = SchemaCache::new();
+    pub fn new(config: NixConfig, args: &CliArgs) -> Self {
+        let mut searcher = NixSearcher::new();
+        let mut schema_cache = SchemaCache::new();
+
+        // Check in the background that commented-out packages exist in nixpkgs
+        let mut verify_progress = None;
+        if !args.no_verify_packages {
+            let names = config.commented_package_names();
+            if !names.is_empty() {
+                verify_progress = Some((0, names.len()));
+                searcher.start_verifying(names);
+            }
+        }
+
+        let settings = Settings::load();
+        if let Some(nixpkgs) = settings.nixpkgs_reference() {
+            schema_cache.set_nixpkgs(&nixpkgs);
+        }
+
+        let git_repo = if args.git {
+            git::find_repo_root(Path::new(&config.path))
+        } else {
+            None
+        };
 
-        // Verify that disabled packages actually exist in nixpkgs
-        config.verify_packages(&searcher);
+        // Explain once why the lists may be empty, property options missing or caching broken
+        let mut warnings = Vec::new();
+        if let Some(error) = &settings.load_error {
+            warnings.push(error.clone());
+        }
+        if let Some(warning) = config.module_warning() {
+            warnings.push(warning.to_string());
+        }
+        if schema_cache.backend() == SchemaBackend::Unavailable {
+            warnings
+                .push("nix-instantiate not found: property options are unavailable".to_string());
+        } else if let Err(e) = schema_cache.check_nixpkgs() {
+            warnings.push(format!(
+                "nixpkgs from the settings doesn't evaluate ({}): property options are unavailable",
+                e
+            ));
+        }
+        // Both caches live under the same directory, so report it only once
+        if let Some(warning) = schema_cache.cache_warning().or(searcher.cache_warning()) {
+            warnings.push(warning.to_string());
+        }
+        let status_message = if warnings.is_empty() {
+            None
+        } else {
+            Some(warnings.join(" | "))
+        };
+        let mut status_history = StatusHistory::default();
+        if let Some(message) = &status_message {
+            status_history.record(message);
+        }
 
         let mut app = App {
             config,
@@ -70,47 +214,104 @@ impl App {
             schema_cache,
             search_query: String::new(),
             search_cursor: 0,
+            search_recall: None,
+            search_history_changed: false,
+            search_mode: SearchMode::default(),
             focus: Focus::SearchBar,
+            previous_focus: None,
             programs: Vec::new(),
             services: Vec::new(),
             packages: Vec::new(),
+            users: Vec::new(),
             program_state: ListState::default(),
             service_state: ListState::default(),
             package_state: ListState::default(),
+            user_state: ListState::default(),
             should_quit: false,
-            status_message: None,
+            status_message,
             is_searching: false,
+            saving: false,
+            hook_receiver: None,
             search_results: Vec::new(),
             show_help: false,
             search_area: Rect::default(),
             programs_area: Rect::default(),
             services_area: Rect::default(),
             packages_area: Rect::default(),
+            users_area: Rect::default(),
             prop_editor: PropertyEditorState::default(),
             property_list_area: Rect::default(),
             rebuild_prompt: RebuildPromptState::default(),
             is_dirty: false,
+            unsaved_changes: 0,
             description_popup: DescriptionPopupState::default(),
+            diff_popup: DiffPopupState::default(),
+            confirm_prompt: ConfirmPromptState::default(),
+            add_as_prompt: AddAsPromptState::default(),
+            text_prompt: TextPromptState::default(),
+            list_options: ListOptionsState::default(),
+            move_prompt: MovePromptState::default(),
+            template_prompt: TemplatePromptState::default(),
+            cleanup: CleanupState::default(),
+            snapshot_undo: None,
+            git_repo,
+            git_dirty_checked: false,
+            change_log: ChangeLog::default(),
+            group_services: false,
+            show_users: false,
+            service_modules_only: false,
+            service_modules_requested: false,
+            collapsed_service_groups: HashSet::new(),
+            rebuild_pending: false,
+            trust_mode: args.trust || settings.trust_mode,
+            mouse_capture: !args.no_mouse,
+            read_only: args.read_only,
+            verify_progress,
+            missing_packages: HashSet::new(),
+            package_check: None,
+            settings,
+            package_list_target: 0,
+            status_history,
+            reference_jump: None,
+            flake_host: None,
         };
 
+        app.refresh_rebuild_pending();
         app.load_from_config();
+        app.show_users = !app.users.is_empty();
         app
     }
 
+    /// In read-only mode, say that `action` is disabled and return true
+    pub(crate) fn blocked_by_read_only(&mut self, action: &str) -> bool {
+        if self.read_only {
+            self.set_status(format!(
+                "Read-only mode: {} is disabled (restart without --read-only to edit)",
+                action
+            ));
+        }
+        self.read_only
+    }
+
+    /// Record an edit to the config that hasn't been saved yet
+    pub(crate) fn mark_dirty(&mut self) {
+        self.is_dirty = true;
+        self.unsaved_changes += 1;
+    }
+
+    /// Show a message in the status bar and keep it in the history
+    pub fn set_status(&mut self, message: String) {
+        self.status_history.record(&message);
+        self.status_message = Some(message);
+    }
+
     pub fn load_from_config(&mut self) {
         // Load programs from config
         self.programs = self
             .config
             .get_entries_by_type(&EntryType::Program)
             .into_iter()
-            .map(|e| ListEntry {
-                name: e.name.clone(),
-                description: String::new(),
-                enabled: e.enabled,
-                in_config: true,
-                has_extra_config: e.has_extra_config,
-                relevance_order: 0,
-            })
+            .map(ListEntry::from_config)
             .collect();
 
         // Load services from config
@@ -118,35 +319,55 @@ impl App {
             .config
             .get_entries_by_type(&EntryType::Service)
             .into_iter()
-            .map(|e| ListEntry {
-                name: e.name.clone(),
-                description: String::new(),
-                enabled: e.enabled,
-                in_config: true,
-                has_extra_config: e.has_extra_config,
-                relevance_order: 0,
+            .map(ListEntry::from_config)
+            .collect();
+
+        // Load packages from config, noting their list when several are merged
+        let multiple_lists = self.config.package_list_lines().len() > 1;
+        let mut package_entries = self.config.get_entries_by_type(&EntryType::Package);
+        package_entries.retain(|e| e.enabled || !self.missing_packages.contains(&e.name));
+        let mut packages: Vec<_> = package_entries
+            .iter()
+            .map(|e| {
+                (
+                    e.section.clone(),
+                    ListEntry {
+                        list_line: e.package_list.filter(|_| multiple_lists),
+                        ..ListEntry::from_config(e)
+                    },
+                )
             })
             .collect();
+        packages.sort_by(|a, b| a.1.name.cmp(&b.1.name));
 
-        // Load packages from config
-        self.packages = self
+        // Sections in the order they're written in
+        package_entries.sort_by(|a, b| (&a.file, a.text_range.0).cmp(&(&b.file, b.text_range.0)));
+        let mut sections: Vec<String> = Vec::new();
+        for section in package_entries.iter().filter_map(|e| e.section.as_ref()) {
+            if !sections.contains(section) {
+                sections.push(section.clone());
+            }
+        }
+        self.packages = package_sections::with_section_dividers(packages, &sections);
+
+        // Load users from config
+        self.users = self
             .config
-            .get_entries_by_type(&EntryType::Package)
+            .get_entries_by_type(&EntryType::User)
             .into_iter()
-            .map(|e| ListEntry {
-                name: e.name.clone(),
-                description: String::new(),
-                enabled: e.enabled,
-                in_config: true,
-                has_extra_config: false,
-                relevance_order: 0,
-            })
+            .map(ListEntry::from_config)
             .collect();
 
+        // Only show actionable programs/services if the user asked for it
+        if self.settings.hide_disabled {
+            self.programs.retain(|e| e.enabled);
+            self.services.retain(|e| e.enabled);
+        }
+
         // Sort all lists
         self.programs.sort_by(|a, b| a.name.cmp(&b.name));
         self.services.sort_by(|a, b| a.name.cmp(&b.name));
-        self.packages.sort_by(|a, b| a.name.cmp(&b.name));
+        self.users.sort_by(|a, b| a.name.cmp(&b.name));
 
         // Select first item in each list if available
         if !self.programs.is_empty() {
@@ -156,86 +377,212 @@ impl App {
             self.service_state.select(Some(0));
         }
         if !self.packages.is_empty() {
-            self.package_state.select(Some(0));
+            self.package_state
+                .select(Some(self.next_selectable_package(0, 1)));
         }
+        if !self.users.is_empty() {
+            self.user_state.select(Some(0));
+        }
+
+        self.apply_service_grouping();
     }
 
     pub fn save_config(&mut self) -> Result<()> {
+        if self.blocked_by_read_only("saving") {
+            return Ok(());
+        }
+        if self.hook_receiver.is_some() {
+            self.set_status("The post-save hooks are still running".to_string());
+            return Ok(());
+        }
+        // Warn once per session if the repo already had uncommitted changes,
+        // so our save doesn't get mixed in with them unnoticed
+        if let Some(repo) = &self.git_repo {
+            if !self.git_dirty_checked {
+                match git::has_uncommitted_changes(repo) {
+                    Ok(true) => {
+                        return self.request_confirmation(
+                            "The git repository already has uncommitted changes. Save anyway?"
+                                .to_string(),
+                            ConfirmAction::SaveConfig,
+                        );
+                    }
+                    Ok(false) => self.git_dirty_checked = true,
+                    Err(e) => {
+                        self.set_status(format!("Git error: {}", e));
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        self.write_config()
+    }
+
+    /// Write the config to disk without any git checks
+    fn write_config(&mut self) -> Result<()> {
         match self.config.save() {
             Ok(()) => {
                 self.is_dirty = false;
-                self.status_message = Some("Configuration saved!".to_string());
-                // Show rebuild prompt after successful save
-                self.rebuild_prompt.show = true;
-                self.rebuild_prompt.selected = 0;
-                self.rebuild_prompt.pending_rebuild = false;
+                self.unsaved_changes = 0;
+                self.refresh_rebuild_pending();
+                self.set_status(if self.change_log.is_empty() {
+                    "Configuration saved!".to_string()
+                } else {
+                    "Configuration saved! Press C to copy a change summary".to_string()
+                });
+                if !self.start_post_save_hooks() {
+                    self.after_save()?;
+                }
             }
             Err(e) => {
-                self.status_message = Some(format!("Save error: {}", e));
+                self.set_status(format!("Save error: {}", e));
             }
         }
         Ok(())
     }
 
-    pub fn toggle_selected(&mut self, list_type: &types::ListType) -> Result<()> {
-        let (entry_type, idx, name, enabled, in_config) = match list_type {
-            types::ListType::Programs => {
-                let idx = self.program_state.selected();
-                if let Some(idx) = idx {
-                    if idx < self.programs.len() {
-                        let entry = &self.programs[idx];
-                        (
-                            EntryType::Program,
-                            idx,
-                            entry.name.clone(),
-                            entry.enabled,
-                            entry.in_config,
-                        )
-                    } else {
-                        return Ok(());
-                    }
-                } else {
-                    return Ok(());
+    /// Offer to rebuild and to stage the saved files, once the hooks are done
+    fn after_save(&mut self) -> Result<()> {
+        // Show rebuild prompt after successful save
+        self.request_rebuild();
+
+        // Offer to stage the file; this prompt sits on top of the rebuild prompt
+        if self.git_repo.is_some() {
+            let file_name = Path::new(&self.config.path)
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_else(|| self.config.path.clone());
+            self.request_confirmation(
+                format!("Stage {} with git add?", file_name),
+                ConfirmAction::GitAdd,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Start the `post_save_hooks` from the settings file on another thread, in
+    /// order, stopping at the first failure. Returns whether there were any.
+    fn start_post_save_hooks(&mut self) -> bool {
+        if self.settings.post_save_hooks.is_empty() {
+            return false;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        let path = PathBuf::from(&self.config.path);
+        let commands = self.settings.post_save_hooks.clone();
+        std::thread::spawn(move || {
+            for command in commands {
+                let result = hooks::run(&command, &path);
+                let failed = result.is_err();
+                if tx.send((command, result)).is_err() || failed {
+                    break;
                 }
             }
-            types::ListType::Services => {
-                let idx = self.service_state.selected();
-                if let Some(idx) = idx {
-                    if idx < self.services.len() {
-                        let entry = &self.services[idx];
-                        (
-                            EntryType::Service,
-                            idx,
-                            entry.name.clone(),
-                            entry.enabled,
-                            entry.in_config,
-                        )
-                    } else {
-                        return Ok(());
-                    }
-                } else {
-                    return Ok(());
-                }
+        });
+        self.hook_receiver = Some(rx);
+        self.set_status("Running the post-save hooks...".to_string());
+        true
+    }
+
+    /// Report the hooks that finished since the last call (call this regularly).
+    /// Once all are done, picks up the files again if a hook (e.g. a formatter)
+    /// changed them.
+    pub fn poll_post_save_hooks(&mut self) -> Result<()> {
+        let Some(receiver) = &self.hook_receiver else {
+            return Ok(());
+        };
+        let mut results = Vec::new();
+        let done = loop {
+            match receiver.try_recv() {
+                Ok(result) => results.push(result),
+                Err(mpsc::TryRecvError::Empty) => break false,
+                Err(mpsc::TryRecvError::Disconnected) => break true,
             }
-            types::ListType::Packages => {
-                let idx = self.package_state.selected();
-                if let Some(idx) = idx {
-                    if idx < self.packages.len() {
-                        let entry = &self.packages[idx];
-                        (
-                            EntryType::Package,
-                            idx,
-                            entry.name.clone(),
-                            entry.enabled,
-                            entry.in_config,
-                        )
-                    } else {
-                        return Ok(());
+        };
+        for (command, result) in results {
+            match result {
+                Ok(Some(line)) => self.set_status(format!("Hook `{}`: {}", command, line)),
+                Ok(None) => self.set_status(format!("Hook `{}` done", command)),
+                Err(e) => self.set_status(format!("Error: {}", e)),
+            }
+        }
+        if !done {
+            return Ok(());
+        }
+        self.hook_receiver = None;
+
+        let changed: Vec<String> = self
+            .config
+            .modules()
+            .into_iter()
+            .filter(|m| !fs::read_to_string(&m.path).is_ok_and(|c| c == m.content))
+            .map(|m| self.display_path(&m.path))
+            .collect();
+        if !changed.is_empty() {
+            if self.is_dirty {
+                // Reloading would drop the edits made while the hooks ran
+                self.set_status(format!(
+                    "A hook changed {} on disk; saving your edits will overwrite that",
+                    changed.join(", ")
+                ));
+            } else {
+                match NixConfig::load(&self.config.path) {
+                    Ok(config) => {
+                        self.config = config;
+                        self.load_from_config();
+                        self.sync_property_editor();
                     }
-                } else {
-                    return Ok(());
+                    Err(e) => self.set_status(format!("Error reloading after hooks: {}", e)),
                 }
             }
+        }
+        self.after_save()
+    }
+
+    pub fn toggle_selected(&mut self, list_type: &types::ListType) -> Result<()> {
+        let selected = match list_type {
+            types::ListType::Programs => self.program_state.selected(),
+            types::ListType::Services => self.service_state.selected(),
+            types::ListType::Packages => self.package_state.selected(),
+            types::ListType::Users => self.user_state.selected(),
+        };
+
+        if let Some(idx) = selected {
+            let is_header = *list_type == types::ListType::Services
+                && self.services.get(idx).is_some_and(|e| e.header.is_some());
+            let is_divider = *list_type == types::ListType::Packages
+                && self.packages.get(idx).is_some_and(|e| e.header.is_some());
+            if is_header {
+                self.toggle_service_group_at(idx);
+            } else if !is_divider {
+                self.toggle_entry_at(list_type, idx)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Toggle the entry at `idx` in the given list, adding it to the config if needed
+    pub fn toggle_entry_at(&mut self, list_type: &types::ListType, idx: usize) -> Result<()> {
+        if self.blocked_by_read_only("toggling") {
+            return Ok(());
+        }
+        let (entry_type, entries) = match list_type {
+            types::ListType::Programs => (EntryType::Program, &self.programs),
+            types::ListType::Services => (EntryType::Service, &self.services),
+            types::ListType::Packages => (EntryType::Package, &self.packages),
+            types::ListType::Users => (EntryType::User, &self.users),
+        };
+
+        let (name, enabled, in_config, binding) = match entries.get(idx) {
+            Some(entry) => (
+                entry.name.clone(),
+                entry.enabled,
+                entry.in_config,
+                entry.binding.clone(),
+            ),
+            None => return Ok(()),
         };
 
         let new_enabled = !enabled;
@@ -246,37 +593,79 @@ impl App {
                 .config
                 .set_entry_enabled(&name, &entry_type, new_enabled)
             {
-                self.status_message = Some(format!("Error: {}", e));
+                self.set_status(format!("Error: {}", e));
                 return Ok(());
             }
 
-            self.is_dirty = true;
+            self.mark_dirty();
+            self.change_log.record(
+                &name,
+                None,
+                if new_enabled {
+                    ChangeAction::Enable
+                } else {
+                    ChangeAction::Disable
+                },
+            );
 
             // Update the local entry
             match list_type {
                 types::ListType::Programs => self.programs[idx].enabled = new_enabled,
                 types::ListType::Services => self.services[idx].enabled = new_enabled,
                 types::ListType::Packages => self.packages[idx].enabled = new_enabled,
+                types::ListType::Users => self.users[idx].enabled = new_enabled,
             }
 
-            self.status_message = Some(format!(
-                "{} {} {}",
+            // Other entries may share the `let` binding that was just flipped
+            if binding.is_some() {
+                self.sync_enabled_from_config();
+            }
+
+            self.set_status(format!(
+                "{} {} {}{}",
                 if new_enabled { "Enabled" } else { "Disabled" },
                 match entry_type {
                     EntryType::Program => "program",
                     EntryType::Service => "service",
                     EntryType::Package => "package",
+                    EntryType::User => "user",
                 },
-                name
+                name,
+                binding
+                    .map(|b| format!(" (via let binding {})", b))
+                    .unwrap_or_default()
             ));
         } else {
+            // A non-list `environment.systemPackages` can't take another package
+            if entry_type == EntryType::Package {
+                if let Some(line) = self.config.unmanaged_packages_line() {
+                    return self.request_confirmation(
+                        format!(
+                            "{} (line {}) isn't a plain list. Add {} to a new {} module instead?",
+                            self.config.packages_option(),
+                            line,
+                            name,
+                            config_parser::PACKAGES_MODULE
+                        ),
+                        ConfirmAction::AddPackageToModule(name),
+                    );
+                }
+            }
+
             // Add new entry to config
-            if let Err(e) = self.config.add_entry(&name, &entry_type) {
-                self.status_message = Some(format!("Error: {}", e));
+            let added = match entry_type {
+                EntryType::Package => self
+                    .config
+                    .add_package_to_list(&name, self.package_list_target),
+                _ => self.config.add_entry(&name, &entry_type),
+            };
+            if let Err(e) = added {
+                self.set_status(format!("Error: {}", e));
                 return Ok(());
             }
 
-            self.is_dirty = true;
+            self.mark_dirty();
+            self.change_log.record(&name, None, ChangeAction::Add);
 
             // Update the local entry
             match list_type {
@@ -292,14 +681,19 @@ impl App {
                     self.packages[idx].enabled = true;
                     self.packages[idx].in_config = true;
                 }
+                types::ListType::Users => {
+                    self.users[idx].enabled = true;
+                    self.users[idx].in_config = true;
+                }
             }
 
-            self.status_message = Some(format!(
+            self.set_status(format!(
                 "Added {} {}",
                 match entry_type {
                     EntryType::Program => "program",
                     EntryType::Service => "service",
                     EntryType::Package => "package",
+                    EntryType::User => "user",
                 },
                 name
             ));
@@ -307,4 +701,1477 @@ impl App {
 
         Ok(())
     }
+
+    /// Refresh the enabled state of listed programs, services and users from the parsed config
+    fn sync_enabled_from_config(&mut self) {
+        for (entry_type, entries) in [
+            (EntryType::Program, &mut self.programs),
+            (EntryType::Service, &mut self.services),
+            (EntryType::User, &mut self.users),
+        ] {
+            for entry in entries
+                .iter_mut()
+                .filter(|e| e.in_config && e.header.is_none())
+            {
+                if let Some(config_entry) = self.config.get_entry(&entry.name, &entry_type) {
+                    entry.enabled = config_entry.enabled;
+                }
+            }
+        }
+    }
+
+    /// Ask how to add the selected search result, whatever column it was sorted into
+    pub fn open_add_as_prompt(&mut self, list_type: &types::ListType) {
+        if self.blocked_by_read_only("adding") {
+            return;
+        }
+        let (state, entries) = match list_type {
+            types::ListType::Programs => (&self.program_state, &self.programs),
+            types::ListType::Services => (&self.service_state, &self.services),
+            types::ListType::Packages => (&self.package_state, &self.packages),
+            types::ListType::Users => (&self.user_state, &self.users),
+        };
+        let Some(entry) = state
+            .selected()
+            .and_then(|i| entries.get(i))
+            .filter(|e| e.header.is_none())
+        else {
+            return;
+        };
+
+        if entry.in_config {
+            self.set_status(format!("{} is already in the config", entry.name));
+            return;
+        }
+
+        self.add_as_prompt.name = entry.name.clone();
+        self.add_as_prompt.show = true;
+    }
+
+    /// Add `name` to the config as the given kind of entry and select it there
+    pub fn add_as(&mut self, name: &str, entry_type: EntryType) -> Result<()> {
+        if entry_type == EntryType::Package {
+            if let Some(line) = self.config.unmanaged_packages_line() {
+                return self.request_confirmation(
+                    format!(
+                        "{} (line {}) isn't a plain list. Add {} to a new {} module instead?",
+                        self.config.packages_option(),
+                        line,
+                        name,
+                        config_parser::PACKAGES_MODULE
+                    ),
+                    ConfirmAction::AddPackageToModule(name.to_string()),
+                );
+            }
+        }
+
+        let added = match entry_type {
+            EntryType::Package => self
+                .config
+                .add_package_to_list(name, self.package_list_target),
+            _ => self.config.add_entry(name, &entry_type),
+        };
+        if let Err(e) = added {
+            self.set_status(format!("Error: {}", e));
+            return Ok(());
+        }
+
+        self.mark_dirty();
+        self.change_log.record(name, None, ChangeAction::Add);
+
+        // Rebuild the lists so the new entry shows up in its own column
+        if self.search_query.is_empty() {
+            self.load_from_config();
+        } else {
+            self.process_search_results(self.search_results.clone());
+        }
+        self.select_entry(name, &entry_type);
+
+        self.set_status(format!(
+            "Added {} as a {}",
+            name,
+            match entry_type {
+                EntryType::Program => "program",
+                EntryType::Service => "service",
+                EntryType::Package => "package",
+                EntryType::User => "user",
+            }
+        ));
+        Ok(())
+    }
+
+    /// Ask for a list of packages to add at once, e.g. pasted from another config
+    pub fn open_packages_prompt(&mut self) {
+        if self.blocked_by_read_only("adding packages") {
+            return;
+        }
+        self.text_prompt.buffer.clear();
+        self.text_prompt.cursor = 0;
+        self.text_prompt.name.clear();
+        self.text_prompt.entry_type = None;
+        self.text_prompt.kind = types::TextPrompt::Packages;
+        self.text_prompt.show = true;
+    }
+
+    /// Add every package in `input` (separated by whitespace or commas) that exists
+    /// in nixpkgs, as one edit once they're checked in the background. Packages
+    /// commented out in the config are re-enabled.
+    fn add_packages(&mut self, input: &str) {
+        if self.package_check.is_some() {
+            self.set_status(
+                "Still checking the last packages, try again once they're added".to_string(),
+            );
+            return;
+        }
+        let mut names: Vec<String> = Vec::new();
+        for name in input
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .map(|name| name.trim_start_matches("pkgs."))
+            .filter(|name| !name.is_empty())
+        {
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+        if names.is_empty() {
+            return;
+        }
+
+        let (present, names): (Vec<String>, Vec<String>) = names
+            .into_iter()
+            .partition(|name| self.config.get_entry(name, &EntryType::Package).is_some());
+        let (commented, present): (Vec<String>, Vec<String>) =
+            present.into_iter().partition(|name| {
+                self.config
+                    .get_entry(name, &EntryType::Package)
+                    .is_some_and(|e| !e.enabled)
+            });
+
+        let check = PackageCheck {
+            names,
+            present,
+            commented,
+        };
+        if check.names.is_empty() {
+            self.finish_adding_packages(check, Vec::new());
+            return;
+        }
+        self.set_status(format!(
+            "Checking {} package(s) in nixpkgs...",
+            check.names.len()
+        ));
+        self.searcher.start_checking_packages(check.names.clone());
+        self.package_check = Some(check);
+    }
+
+    /// Add the packages of a finished check, given whether each exists, and
+    /// keep the config as it was so it can be undone in one step
+    fn finish_adding_packages(&mut self, check: PackageCheck, exists: Vec<bool>) {
+        let PackageCheck {
+            names,
+            present,
+            commented,
+        } = check;
+        let before = self.config.clone();
+        let change_log = self.change_log.entries.clone();
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        for (name, exists) in names.into_iter().zip(exists) {
+            if !exists {
+                missing.push(name);
+            } else if self.config.get_entry(&name, &EntryType::Package).is_none() {
+                // Not added some other way while the check ran
+                found.push(name);
+            }
+        }
+
+        if !found.is_empty() {
+            if let Err(e) = self
+                .config
+                .add_packages_to_list(&found, self.package_list_target)
+            {
+                self.set_status(format!("Error: {}", e));
+                return;
+            }
+        }
+        let mut enabled = 0;
+        for name in &commented {
+            if self
+                .config
+                .set_entry_enabled(name, &EntryType::Package, true)
+                .is_ok()
+            {
+                self.change_log.record(name, None, ChangeAction::Enable);
+                enabled += 1;
+            }
+        }
+        for name in &found {
+            self.change_log.record(name, None, ChangeAction::Add);
+        }
+        let changed = found.len() + enabled;
+        if changed > 0 {
+            self.mark_dirty();
+            if self.search_query.is_empty() {
+                self.load_from_config();
+            } else {
+                self.process_search_results(self.search_results.clone());
+            }
+            let label = format!("adding {} package(s)", changed);
+            self.keep_snapshot_undo(before, change_log, label);
+        }
+
+        let mut parts = vec![format!("Added {} package(s)", found.len())];
+        if enabled > 0 {
+            parts.push(format!("re-enabled {}", enabled));
+        }
+        if !present.is_empty() {
+            parts.push(format!("already in config: {}", present.join(", ")));
+        }
+        if !missing.is_empty() {
+            parts.push(format!("not found: {}", missing.join(", ")));
+        }
+        if changed > 0 {
+            parts.push("u to undo".to_string());
+        }
+        self.set_status(parts.join(" | "));
+    }
+
+    /// Open the note input for the selected entry, prefilled with its current note
+    pub fn open_note_prompt(&mut self, list_type: &types::ListType) {
+        if self.blocked_by_read_only("editing notes") {
+            return;
+        }
+        let (entry_type, state, entries) = match list_type {
+            types::ListType::Programs => (EntryType::Program, &self.program_state, &self.programs),
+            types::ListType::Services => (EntryType::Service, &self.service_state, &self.services),
+            types::ListType::Packages => (EntryType::Package, &self.package_state, &self.packages),
+            types::ListType::Users => (EntryType::User, &self.user_state, &self.users),
+        };
+        let Some(entry) = state
+            .selected()
+            .and_then(|i| entries.get(i))
+            .filter(|e| e.header.is_none())
+        else {
+            return;
+        };
+
+        let Some(config_entry) = self.config.get_entry(&entry.name, &entry_type) else {
+            self.set_status(format!("Add {} to the config before noting it", entry.name));
+            return;
+        };
+
+        self.text_prompt.buffer = config_entry.note.clone().unwrap_or_default();
+        self.text_prompt.cursor = self.text_prompt.buffer.len();
+        self.text_prompt.name = entry.name.clone();
+        self.text_prompt.entry_type = Some(entry_type);
+        self.text_prompt.kind = types::TextPrompt::Note;
+        self.text_prompt.show = true;
+    }
+
+    /// Open the rename input for the selected program, service or user
+    pub fn open_rename_prompt(&mut self, list_type: &types::ListType) {
+        if self.blocked_by_read_only("renaming") {
+            return;
+        }
+        let (entry_type, state, entries) = match list_type {
+            types::ListType::Programs => (EntryType::Program, &self.program_state, &self.programs),
+            types::ListType::Services => (EntryType::Service, &self.service_state, &self.services),
+            types::ListType::Users => (EntryType::User, &self.user_state, &self.users),
+            types::ListType::Packages => {
+                self.set_status("Packages can't be renamed".to_string());
+                return;
+            }
+        };
+        let Some(entry) = state
+            .selected()
+            .and_then(|i| entries.get(i))
+            .filter(|e| e.header.is_none())
+        else {
+            return;
+        };
+        if !entry.in_config {
+            self.set_status(format!("{} is not in the config", entry.name));
+            return;
+        }
+
+        self.text_prompt.buffer = entry.name.clone();
+        self.text_prompt.cursor = self.text_prompt.buffer.len();
+        self.text_prompt.name = entry.name.clone();
+        self.text_prompt.entry_type = Some(entry_type);
+        self.text_prompt.kind = types::TextPrompt::Rename;
+        self.text_prompt.show = true;
+    }
+
+    /// Move the selected program, service or user to another file of the config: from a
+    /// module shared by several hosts into the host's own module, or the other
+    /// way round. Asks which file when there's more than one to choose from.
+    pub fn open_move_prompt(&mut self, list_type: &types::ListType) -> Result<()> {
+        if self.blocked_by_read_only("moving entries") {
+            return Ok(());
+        }
+        let (entry_type, state, entries) = match list_type {
+            types::ListType::Programs => (EntryType::Program, &self.program_state, &self.programs),
+            types::ListType::Services => (EntryType::Service, &self.service_state, &self.services),
+            types::ListType::Users => (EntryType::User, &self.user_state, &self.users),
+            types::ListType::Packages => {
+                self.set_status("Packages can't be moved".to_string());
+                return Ok(());
+            }
+        };
+        let Some(entry) = state
+            .selected()
+            .and_then(|i| entries.get(i))
+            .filter(|e| e.header.is_none())
+        else {
+            return Ok(());
+        };
+        let name = entry.name.clone();
+        let Some(file) = self
+            .config
+            .get_entry(&name, &entry_type)
+            .map(|e| e.file.clone())
+        else {
+            self.set_status(format!("{} is not in the config", name));
+            return Ok(());
+        };
+
+        // A shared setting moves to this host's file; one of this host's to a
+        // shared module. The generated hardware configuration isn't a target.
+        let targets: Vec<String> = if file != self.config.path {
+            vec![self.config.path.clone()]
+        } else {
+            self.config
+                .modules()
+                .iter()
+                .skip(1)
+                .map(|m| m.path.clone())
+                .filter(|path| !path.ends_with("hardware-configuration.nix"))
+                .collect()
+        };
+
+        match targets.as_slice() {
+            [] => {
+                self.set_status(format!(
+                    "{} doesn't import any other files to move {} to",
+                    self.config.path, name
+                ));
+                Ok(())
+            }
+            [target] => {
+                let target = target.clone();
+                self.confirm_move(name, entry_type, target)
+            }
+            _ => {
+                self.move_prompt = MovePromptState {
+                    show: true,
+                    name,
+                    entry_type: Some(entry_type),
+                    targets,
+                    selected: 0,
+                };
+                Ok(())
+            }
+        }
+    }
+
+    /// Move to the file chosen in the move prompt
+    pub fn submit_move_prompt(&mut self) -> Result<()> {
+        self.move_prompt.show = false;
+        let Some(entry_type) = self.move_prompt.entry_type.take() else {
+            return Ok(());
+        };
+        let Some(target) = self.move_prompt.targets.get(self.move_prompt.selected) else {
+            return Ok(());
+        };
+        let target = target.clone();
+        let name = std::mem::take(&mut self.move_prompt.name);
+        self.confirm_move(name, entry_type, target)
+    }
+
+    /// Ask before moving, saying what it means for the other hosts
+    fn confirm_move(&mut self, name: String, entry_type: EntryType, target: String) -> Result<()> {
+        let from = self
+            .config
+            .get_entry(&name, &entry_type)
+            .map(|e| self.display_path(&e.file))
+            .unwrap_or_default();
+        let effect = if target == self.config.path {
+            "so it only applies to this host"
+        } else {
+            "shared with everything that imports it"
+        };
+        self.request_confirmation(
+            format!(
+                "Move {}.{} from {} to {}, {}?",
+                entry_type.prefix(),
+                name,
+                from,
+                self.display_path(&target),
+                effect
+            ),
+            ConfirmAction::MoveEntry(name, entry_type, target),
+        )
+    }
+
+    /// A loaded file's path relative to the config's directory, where it's below it
+    pub(crate) fn display_path(&self, path: &str) -> String {
+        let dir = Path::new(&self.config.path)
+            .parent()
+            .unwrap_or(Path::new(""));
+        Path::new(path)
+            .strip_prefix(dir)
+            .map_or_else(|_| path.to_string(), |p| p.display().to_string())
+    }
+
+    /// Open the input for the selected program/service's enable expression,
+    /// prefilled with the expression as written in the config
+    pub fn open_enable_prompt(&mut self, list_type: &types::ListType) {
+        if self.blocked_by_read_only("editing enable expressions") {
+            return;
+        }
+        let (entry_type, state, entries) = match list_type {
+            types::ListType::Programs => (EntryType::Program, &self.program_state, &self.programs),
+            types::ListType::Services => (EntryType::Service, &self.service_state, &self.services),
+            types::ListType::Users => (EntryType::User, &self.user_state, &self.users),
+            types::ListType::Packages => {
+                self.set_status("Packages don't have an enable option".to_string());
+                return;
+            }
+        };
+        let Some(entry) = state
+            .selected()
+            .and_then(|i| entries.get(i))
+            .filter(|e| e.header.is_none())
+        else {
+            return;
+        };
+
+        match self.config.enable_expression(&entry.name, &entry_type) {
+            Ok(expr) => {
+                self.text_prompt.buffer = expr;
+                self.text_prompt.cursor = self.text_prompt.buffer.len();
+                self.text_prompt.name = entry.name.clone();
+                self.text_prompt.entry_type = Some(entry_type);
+                self.text_prompt.kind = types::TextPrompt::Enable;
+                self.text_prompt.show = true;
+            }
+            Err(e) => self.set_status(format!("Error: {}", e)),
+        }
+    }
+
+    /// Option path the list options popup is editing
+    pub(crate) fn list_options_path(&self) -> Option<String> {
+        match &self.list_options.property {
+            Some((name, entry_type, prop)) => {
+                Some(format!("{}.{}.{}", entry_type.prefix(), name, prop))
+            }
+            None => config_parser::LIST_OPTIONS
+                .get(self.list_options.option)
+                .map(|path| path.to_string()),
+        }
+    }
+
+    /// Ask for an element to append to the list option selected in the list options popup
+    pub fn open_list_member_prompt(&mut self) {
+        if self.blocked_by_read_only("editing list options") {
+            return;
+        }
+        let Some(path) = self.list_options_path() else {
+            return;
+        };
+        self.text_prompt.buffer.clear();
+        self.text_prompt.cursor = 0;
+        self.text_prompt.name = path;
+        self.text_prompt.entry_type = None;
+        self.text_prompt.kind = types::TextPrompt::ListMember;
+        self.text_prompt.show = true;
+    }
+
+    /// Remove the member selected in the list options popup from its option
+    pub fn remove_selected_list_member(&mut self) {
+        if self.blocked_by_read_only("editing list options") {
+            return;
+        }
+        let Some(path) = self.list_options_path() else {
+            return;
+        };
+        let members = self.config.list_option_members(&path).unwrap_or_default();
+        let Some(member) = self.list_options.member.and_then(|i| members.get(i)) else {
+            return;
+        };
+
+        match self.config.remove_list_member(&path, member) {
+            Ok(()) => {
+                self.mark_dirty();
+                self.set_status(format!("Removed {} from {}", member, path));
+                if self.prop_editor.show {
+                    self.refresh_property_editor();
+                }
+                let remaining = members.len() - 1;
+                self.list_options.member = self
+                    .list_options
+                    .member
+                    .map(|i| i.min(remaining.saturating_sub(1)));
+            }
+            Err(e) => self.set_status(format!("Error: {}", e)),
+        }
+    }
+
+    /// Disabled programs/services that still set options, for the cleanup view
+    pub(crate) fn cleanup_candidates(&self) -> Vec<(String, EntryType)> {
+        [EntryType::Program, EntryType::Service]
+            .iter()
+            .flat_map(|entry_type| self.config.get_entries_by_type(entry_type))
+            .filter(|e| !e.enabled && !e.is_conditional())
+            .filter(|e| e.has_extra_config || !e.properties.is_empty())
+            .map(|e| (e.name.clone(), e.entry_type.clone()))
+            .collect()
+    }
+
+    /// Open the cleanup view of disabled blocks
+    pub fn open_cleanup(&mut self) {
+        self.cleanup.show = true;
+        self.cleanup.selected = 0;
+        self.cleanup.marked.clear();
+    }
+
+    /// Mark or unmark the selected entry in the cleanup view
+    pub fn toggle_cleanup_mark(&mut self) {
+        let candidates = self.cleanup_candidates();
+        let Some(entry) = candidates.get(self.cleanup.selected) else {
+            return;
+        };
+        if let Some(i) = self.cleanup.marked.iter().position(|m| m == entry) {
+            self.cleanup.marked.remove(i);
+        } else {
+            self.cleanup.marked.push(entry.clone());
+        }
+    }
+
+    /// Mark every entry in the cleanup view, or none if they all are
+    pub fn toggle_cleanup_mark_all(&mut self) {
+        let candidates = self.cleanup_candidates();
+        if candidates.iter().all(|c| self.cleanup.marked.contains(c)) {
+            self.cleanup.marked.clear();
+        } else {
+            self.cleanup.marked = candidates;
+        }
+    }
+
+    /// Ask to remove the marked entries, or the selected one if none are marked
+    pub fn confirm_cleanup_removal(&mut self) -> Result<()> {
+        if self.blocked_by_read_only("removing blocks") {
+            return Ok(());
+        }
+        let candidates = self.cleanup_candidates();
+        let entries: Vec<(String, EntryType)> = if self.cleanup.marked.is_empty() {
+            candidates
+                .get(self.cleanup.selected)
+                .cloned()
+                .into_iter()
+                .collect()
+        } else {
+            candidates
+                .into_iter()
+                .filter(|c| self.cleanup.marked.contains(c))
+                .collect()
+        };
+        let message = match entries.as_slice() {
+            [] => return Ok(()),
+            [(name, entry_type)] => format!(
+                "Remove the block of {}.{} from the config?",
+                entry_type.prefix(),
+                name
+            ),
+            _ => format!("Remove the blocks of {} disabled entries?", entries.len()),
+        };
+        self.request_confirmation(message, ConfirmAction::RemoveDisabledBlocks(entries))
+    }
+
+    /// Remove the blocks of disabled entries, keeping the config as it was for undo
+    fn remove_disabled_blocks(&mut self, entries: Vec<(String, EntryType)>) {
+        let before = self.config.clone();
+        let change_log = self.change_log.entries.clone();
+        let mut removed = Vec::new();
+        for (name, entry_type) in entries {
+            match self.config.remove_entry(&name, &entry_type) {
+                Ok(_) => {
+                    self.change_log.record(&name, None, ChangeAction::Remove);
+                    removed.push((name, entry_type));
+                }
+                Err(e) => self.set_status(format!("Error removing {}: {}", name, e)),
+            }
+        }
+        if removed.is_empty() {
+            return;
+        }
+
+        self.mark_dirty();
+        self.load_from_config();
+        self.set_status(format!(
+            "Removed {} disabled block{} (u in the cleanup view to undo)",
+            removed.len(),
+            if removed.len() == 1 { "" } else { "s" }
+        ));
+        let label = format!(
+            "removing {} block{}",
+            removed.len(),
+            if removed.len() == 1 { "" } else { "s" }
+        );
+        self.keep_snapshot_undo(before, change_log, label);
+        self.cleanup.marked.clear();
+        let remaining = self.cleanup_candidates().len();
+        self.cleanup.selected = self.cleanup.selected.min(remaining.saturating_sub(1));
+    }
+
+    /// Keep the state from before an edit made in one go, so `u` can take it back
+    pub(super) fn keep_snapshot_undo(
+        &mut self,
+        config: NixConfig,
+        change_log: Vec<ChangeLogEntry>,
+        label: String,
+    ) {
+        self.snapshot_undo = Some(SnapshotUndo {
+            config,
+            change_log,
+            after: self.file_contents(),
+            label,
+        });
+    }
+
+    /// Take back the last edit made in one go, unless the config changed since
+    pub fn undo_snapshot(&mut self) {
+        let Some(undo) = self.snapshot_undo.take() else {
+            self.set_status("Nothing to undo".to_string());
+            return;
+        };
+        if undo.after != self.file_contents() {
+            self.set_status(format!(
+                "The config was edited since {}, it can't be undone",
+                undo.label
+            ));
+            return;
+        }
+        self.config = undo.config;
+        self.change_log.entries = undo.change_log;
+        self.mark_dirty();
+        if self.prop_editor.show {
+            self.refresh_property_editor();
+        } else {
+            self.load_from_config();
+        }
+        self.set_status(format!("Undid {}", undo.label));
+    }
+
+    /// Content of the config and the files it imports
+    pub(super) fn file_contents(&self) -> Vec<String> {
+        self.config
+            .modules()
+            .iter()
+            .map(|m| m.content.clone())
+            .collect()
+    }
+
+    /// Carry out the text prompt: save the note, do the rename or add the list member
+    pub fn submit_text_prompt(&mut self) -> Result<()> {
+        match self.text_prompt.kind {
+            types::TextPrompt::ListMember => {
+                self.text_prompt.show = false;
+                let path = std::mem::take(&mut self.text_prompt.name);
+                let member = std::mem::take(&mut self.text_prompt.buffer);
+                let result = match &self.list_options.property {
+                    // A property that isn't set yet goes into its entry's block
+                    Some((name, entry_type, prop))
+                        if self.config.list_option_members(&path).is_none()
+                            && !member.trim().is_empty() =>
+                    {
+                        self.config.add_property(
+                            name,
+                            entry_type,
+                            prop,
+                            &format!("[ {} ]", member.trim()),
+                            &PropertyType::List,
+                        )
+                    }
+                    _ => self.config.add_list_member(&path, &member),
+                };
+                match result {
+                    Ok(()) => {
+                        self.mark_dirty();
+                        self.set_status(format!("Added {} to {}", member.trim(), path));
+                        if self.prop_editor.show {
+                            self.refresh_property_editor();
+                        }
+                    }
+                    Err(e) => self.set_status(format!("Error: {}", e)),
+                }
+                Ok(())
+            }
+            types::TextPrompt::Note => {
+                self.save_note();
+                Ok(())
+            }
+            types::TextPrompt::Packages => {
+                self.text_prompt.show = false;
+                let input = std::mem::take(&mut self.text_prompt.buffer);
+                self.add_packages(&input);
+                Ok(())
+            }
+            types::TextPrompt::Enable => {
+                self.text_prompt.show = false;
+                let Some(entry_type) = self.text_prompt.entry_type.take() else {
+                    return Ok(());
+                };
+                let name = std::mem::take(&mut self.text_prompt.name);
+                let expr = std::mem::take(&mut self.text_prompt.buffer);
+
+                match self.config.set_enable_expression(&name, &entry_type, &expr) {
+                    Ok(()) => {
+                        self.mark_dirty();
+                        self.change_log
+                            .record(&name, Some("enable"), ChangeAction::Set);
+                        if self.search_query.is_empty() {
+                            self.load_from_config();
+                        } else {
+                            self.process_search_results(self.search_results.clone());
+                        }
+                        self.select_entry(&name, &entry_type);
+                        self.set_status(format!(
+                            "Set {}.{}.enable = {}",
+                            entry_type.prefix(),
+                            name,
+                            expr.trim().trim_end_matches(';').trim_end()
+                        ));
+                    }
+                    Err(e) => self.set_status(format!("Error: {}", e)),
+                }
+                Ok(())
+            }
+            types::TextPrompt::Rename => {
+                self.text_prompt.show = false;
+                let Some(entry_type) = self.text_prompt.entry_type.take() else {
+                    return Ok(());
+                };
+                let name = std::mem::take(&mut self.text_prompt.name);
+                let new_name = std::mem::take(&mut self.text_prompt.buffer)
+                    .trim()
+                    .to_string();
+                if new_name == name {
+                    return Ok(());
+                }
+
+                // A name without any options is most likely a typo
+                let known = self.schema_cache.backend() == SchemaBackend::Unavailable
+                    || self
+                        .schema_cache
+                        .get_schema(&entry_type, &new_name)
+                        .is_some();
+                if known {
+                    self.rename_entry(&name, &entry_type, &new_name);
+                    Ok(())
+                } else {
+                    self.request_confirmation(
+                        format!(
+                            "No NixOS options found under {}.{}. Rename anyway?",
+                            entry_type.prefix(),
+                            new_name
+                        ),
+                        ConfirmAction::RenameEntry(name, entry_type, new_name),
+                    )
+                }
+            }
+        }
+    }
+
+    /// Rename a program/service in the config and the lists
+    fn rename_entry(&mut self, name: &str, entry_type: &EntryType, new_name: &str) {
+        match self.config.rename_entry(name, entry_type, new_name) {
+            Ok(count) => {
+                self.mark_dirty();
+                self.change_log.record(name, None, ChangeAction::Remove);
+                self.change_log.record(new_name, None, ChangeAction::Add);
+                if self.search_query.is_empty() {
+                    self.load_from_config();
+                } else {
+                    self.process_search_results(self.search_results.clone());
+                }
+                self.select_entry(new_name, entry_type);
+                self.set_status(format!(
+                    "Renamed {}.{} to {} ({} definition{})",
+                    entry_type.prefix(),
+                    name,
+                    new_name,
+                    count,
+                    if count == 1 { "" } else { "s" }
+                ));
+            }
+            Err(e) => self.set_status(format!("Error: {}", e)),
+        }
+    }
+
+    /// Write the note input to the config
+    pub fn save_note(&mut self) {
+        self.text_prompt.show = false;
+        let Some(entry_type) = self.text_prompt.entry_type.take() else {
+            return;
+        };
+        let name = std::mem::take(&mut self.text_prompt.name);
+        let note = std::mem::take(&mut self.text_prompt.buffer);
+
+        match self.config.set_note(&name, &entry_type, &note) {
+            Ok(()) => {
+                self.mark_dirty();
+                self.set_status(if note.trim().is_empty() {
+                    format!("Removed the note on {}", name)
+                } else {
+                    format!("Noted on {}: {}", name, note.trim())
+                });
+            }
+            Err(e) => self.set_status(format!("Error: {}", e)),
+        }
+    }
+
+    /// Choose the next package list as the target for new packages
+    pub fn cycle_package_list_target(&mut self) {
+        let lines = self.config.package_list_lines();
+        if lines.len() < 2 {
+            self.set_status(format!(
+                "Only one {} list in this file",
+                self.config.packages_option()
+            ));
+            return;
+        }
+
+        self.package_list_target = (self.package_list_target + 1) % lines.len();
+        self.set_status(format!(
+            "New packages go to the list on line {} ({}/{})",
+            lines[self.package_list_target],
+            self.package_list_target + 1,
+            lines.len()
+        ));
+    }
+
+    /// Hand the mouse to the terminal for selecting text, or take it back
+    pub fn toggle_mouse_capture(&mut self) {
+        self.mouse_capture = !self.mouse_capture;
+        self.set_status(if self.mouse_capture {
+            "Mouse capture on".to_string()
+        } else {
+            "Mouse capture off: select text with the mouse, F3 to turn it back on".to_string()
+        });
+    }
+
+    /// Flip the persistent setting that shows descriptions inline in the lists
+    pub fn toggle_inline_descriptions(&mut self) {
+        self.settings.inline_descriptions = !self.settings.inline_descriptions;
+        let state = if self.settings.inline_descriptions {
+            "shown inline"
+        } else {
+            "shown on demand (d)"
+        };
+
+        self.set_status(match self.settings.save() {
+            Ok(()) => format!("Descriptions {}", state),
+            Err(e) => format!("Descriptions {} (setting not saved: {})", state, e),
+        });
+    }
+
+    /// Collapse the search bar to one line while it isn't focused, or restore it
+    pub fn toggle_compact_search(&mut self) {
+        self.settings.compact_search = !self.settings.compact_search;
+        let state = if self.settings.compact_search {
+            "collapsed when not in use (/ to search)"
+        } else {
+            "always expanded"
+        };
+
+        self.set_status(match self.settings.save() {
+            Ok(()) => format!("Search bar {}", state),
+            Err(e) => format!("Search bar {} (setting not saved: {})", state, e),
+        });
+    }
+
+    /// Show the diff of the unsaved changes before every Ctrl+S save, or save right away
+    pub fn toggle_preview_before_save(&mut self) {
+        self.settings.preview_before_save = !self.settings.preview_before_save;
+        let state = if self.settings.preview_before_save {
+            "Previewing the diff before saving"
+        } else {
+            "Saving without a preview"
+        };
+
+        self.set_status(match self.settings.save() {
+            Ok(()) => state.to_string(),
+            Err(e) => format!("{} (setting not saved: {})", state, e),
+        });
+    }
+
+    /// Cycle the nixos-rebuild output verbosity and remember it for next time
+    pub fn cycle_rebuild_verbosity(&mut self) {
+        self.settings.rebuild_verbosity = self.settings.rebuild_verbosity.next();
+        if let Err(e) = self.settings.save() {
+            self.set_status(format!("Rebuild verbosity not saved: {}", e));
+        }
+    }
+
+    /// Switch the order of search results (saved as a setting)
+    pub fn cycle_result_sort(&mut self) {
+        self.settings.result_sort = self.settings.result_sort.next();
+        let label = self.settings.result_sort.label();
+
+        if let Err(e) = self.settings.save() {
+            self.set_status(format!(
+                "Search results sorted by {} (setting not saved: {})",
+                label, e
+            ));
+        } else {
+            self.set_status(format!("Search results sorted by {}", label));
+        }
+
+        if !self.search_query.is_empty() {
+            self.process_search_results(self.search_results.clone());
+        }
+    }
+
+    /// Show or hide the Users column
+    pub fn toggle_users_column(&mut self) {
+        self.show_users = !self.show_users;
+        if !self.show_users && self.focus == Focus::Users {
+            self.focus = Focus::Packages;
+        }
+        self.set_status(if self.show_users {
+            format!("Showing {} users", self.users.len())
+        } else {
+            "Users column hidden".to_string()
+        });
+    }
+
+    /// Flip the persistent setting that hides disabled programs/services
+    pub fn toggle_hide_disabled(&mut self) {
+        self.settings.hide_disabled = !self.settings.hide_disabled;
+        let state = if self.settings.hide_disabled {
+            "hidden"
+        } else {
+            "shown"
+        };
+
+        if let Err(e) = self.settings.save() {
+            self.set_status(format!(
+                "Disabled programs/services {} (setting not saved: {})",
+                state, e
+            ));
+        } else if self.search_query.is_empty() {
+            self.set_status(format!("Disabled programs/services {}", state));
+        } else {
+            self.set_status(format!(
+                "Disabled programs/services {} once the search is cleared",
+                state
+            ));
+        }
+
+        if self.search_query.is_empty() {
+            self.load_from_config();
+        }
+    }
+
+    /// Ask for confirmation before enabling/disabling every entry shown in a list.
+    /// Always asked, even in trust mode, as it can disable every login at once.
+    pub fn confirm_toggle_all_visible(&mut self, list_type: &types::ListType) -> Result<()> {
+        if self.blocked_by_read_only("toggling") {
+            return Ok(());
+        }
+        let (label, entries) = match list_type {
+            types::ListType::Programs => ("programs", &self.programs),
+            types::ListType::Services => ("services", &self.services),
+            types::ListType::Packages => ("packages", &self.packages),
+            types::ListType::Users => ("users", &self.users),
+        };
+
+        if entries.is_empty() {
+            self.set_status(format!("No {} to toggle", label));
+            return Ok(());
+        }
+
+        let enable = entries.iter().any(|e| !e.enabled && e.header.is_none());
+        let count = entries
+            .iter()
+            .filter(|e| e.enabled != enable && e.header.is_none())
+            .count();
+        let message = format!(
+            "{} all {} visible {}?",
+            if enable { "Enable" } else { "Disable" },
+            count,
+            label
+        );
+        self.request_confirmation(message, ConfirmAction::ToggleAllVisible(list_type.clone()))
+    }
+
+    /// Enable every entry shown in a list, or disable them all if they are all enabled
+    fn toggle_all_visible(&mut self, list_type: &types::ListType) -> Result<()> {
+        let (label, entries) = match list_type {
+            types::ListType::Programs => ("programs", &self.programs),
+            types::ListType::Services => ("services", &self.services),
+            types::ListType::Packages => ("packages", &self.packages),
+            types::ListType::Users => ("users", &self.users),
+        };
+
+        let enable = entries.iter().any(|e| !e.enabled && e.header.is_none());
+        let targets: Vec<usize> = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.enabled != enable && e.header.is_none())
+            .map(|(idx, _)| idx)
+            .collect();
+
+        let mut changed = 0;
+        let mut failed = 0;
+        for idx in targets {
+            self.toggle_entry_at(list_type, idx)?;
+            let entries = match list_type {
+                types::ListType::Programs => &self.programs,
+                types::ListType::Services => &self.services,
+                types::ListType::Packages => &self.packages,
+                types::ListType::Users => &self.users,
+            };
+            if entries[idx].enabled == enable {
+                changed += 1;
+            } else {
+                failed += 1;
+            }
+        }
+
+        self.set_status(if failed == 0 {
+            format!(
+                "{} {} {}",
+                if enable { "Enabled" } else { "Disabled" },
+                changed,
+                label
+            )
+        } else {
+            format!(
+                "{} {} {}, {} failed",
+                if enable { "Enabled" } else { "Disabled" },
+                changed,
+                label,
+                failed
+            )
+        });
+
+        Ok(())
+    }
+
+    /// Whether an action has to be confirmed first. All prompts go through
+    /// here, so trust mode only ever skips non-destructive ones.
+    fn needs_confirmation(&self, destructive: bool) -> bool {
+        destructive || !self.trust_mode
+    }
+
+    /// Show the confirmation prompt for an action, or run it right away in trust mode
+    pub fn request_confirmation(&mut self, message: String, action: ConfirmAction) -> Result<()> {
+        if !self.needs_confirmation(action.is_destructive()) {
+            return self.run_confirmed_action(action);
+        }
+
+        self.confirm_prompt.show = true;
+        self.confirm_prompt.selected = 0;
+        self.confirm_prompt.message = message;
+        self.confirm_prompt.action = Some(action);
+        Ok(())
+    }
+
+    /// Scope schema evaluation and rebuilds to one host of a flake
+    pub fn set_flake_host(&mut self, host: FlakeHost) {
+        self.schema_cache.set_flake_host(host.clone());
+        self.flake_host = Some(host);
+    }
+
+    /// Arguments for `nixos-rebuild`: the chosen action, the flake host if any, and the verbosity flags
+    pub fn rebuild_args(&self) -> Vec<String> {
+        let mut args = vec![self.rebuild_prompt.mode.subcommand().to_string()];
+        if let Some(host) = &self.flake_host {
+            args.push("--flake".to_string());
+            args.push(host.flake_ref());
+        }
+        args.extend(
+            self.settings
+                .rebuild_verbosity
+                .flags()
+                .iter()
+                .map(|f| f.to_string()),
+        );
+        args
+    }
+
+    /// Offer to run nixos-rebuild, or start it directly in trust mode
+    fn request_rebuild(&mut self) {
+        if self.needs_confirmation(false) {
+            self.rebuild_prompt.show = true;
+            self.rebuild_prompt.selected = 0;
+            self.rebuild_prompt.pending_rebuild = false;
+        } else {
+            self.rebuild_prompt.show = false;
+            self.rebuild_prompt.pending_rebuild = true;
+        }
+    }
+
+    /// Carry out an action after the user confirmed it
+    pub(crate) fn run_confirmed_action(&mut self, action: ConfirmAction) -> Result<()> {
+        match action {
+            ConfirmAction::ToggleAllVisible(list_type) => self.toggle_all_visible(&list_type),
+            ConfirmAction::AddAndEditProperties(name, entry_type) => {
+                self.add_and_edit_properties(name, entry_type)
+            }
+            ConfirmAction::SaveConfig => {
+                self.git_dirty_checked = true;
+                self.write_config()
+            }
+            ConfirmAction::GitAdd => {
+                let Some(repo) = &self.git_repo else {
+                    return Ok(());
+                };
+                if let Err(e) = git::add(repo, &self.repo_files(repo)) {
+                    self.set_status(format!("Git error: {}", e));
+                    return Ok(());
+                }
+                self.set_status("Staged configuration with git add".to_string());
+
+                // Offer to commit right away with the generated summary
+                if let Some(message) = self.change_log.summary() {
+                    self.request_confirmation(
+                        format!("Commit with message \"{}\"?", message),
+                        ConfirmAction::GitCommit(message),
+                    )?;
+                }
+                Ok(())
+            }
+            ConfirmAction::AddPackageToModule(name) => {
+                match self.config.add_package_to_module(&name) {
+                    Ok(path) => {
+                        self.mark_dirty();
+                        self.change_log.record(&name, None, ChangeAction::Add);
+                        if let Some(entry) = self
+                            .packages
+                            .iter_mut()
+                            .find(|e| e.name == name && e.header.is_none())
+                        {
+                            entry.enabled = true;
+                            entry.in_config = true;
+                        }
+                        self.set_status(format!("Added {} to {}", name, path.display()));
+                    }
+                    Err(e) => self.set_status(format!("Error: {}", e)),
+                }
+                Ok(())
+            }
+            ConfirmAction::MoveEntry(name, entry_type, target) => {
+                match self.config.move_entry(&name, &entry_type, &target) {
+                    Ok(()) => {
+                        self.mark_dirty();
+                        self.load_from_config();
+                        self.select_entry(&name, &entry_type);
+                        self.set_status(format!(
+                            "Moved {}.{} to {}",
+                            entry_type.prefix(),
+                            name,
+                            self.display_path(&target)
+                        ));
+                    }
+                    Err(e) => self.set_status(format!("Error: {}", e)),
+                }
+                Ok(())
+            }
+            ConfirmAction::RemoveDisabledBlocks(entries) => {
+                self.remove_disabled_blocks(entries);
+                Ok(())
+            }
+            ConfirmAction::RenameEntry(name, entry_type, new_name) => {
+                self.rename_entry(&name, &entry_type, &new_name);
+                Ok(())
+            }
+            ConfirmAction::ReplaceHomePath(name, entry_type, prop, value) => {
+                match self.config.set_property(&name, &entry_type, &prop, &value) {
+                    Ok(()) => {
+                        self.mark_dirty();
+                        self.set_status(format!(
+                            "Updated {}",
+                            self.assignment_status(&prop, &value)
+                        ));
+                        self.load_from_config();
+                    }
+                    Err(e) => self.set_status(format!("Error saving property: {}", e)),
+                }
+                Ok(())
+            }
+            ConfirmAction::GitCommit(message) => {
+                if let Some(repo) = &self.git_repo {
+                    match git::commit(repo, &self.repo_files(repo), &message) {
+                        Ok(()) => {
+                            self.change_log.clear();
+                            self.set_status(format!("Committed: {}", message));
+                        }
+                        Err(e) => self.set_status(format!("Git error: {}", e)),
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The config and the imported files that are in the git repository
+    fn repo_files(&self, repo: &Path) -> Vec<PathBuf> {
+        self.config
+            .modules()
+            .iter()
+            .map(|m| {
+                let path = PathBuf::from(&m.path);
+                path.canonicalize().unwrap_or(path)
+            })
+            .filter(|path| path.starts_with(repo))
+            .collect()
+    }
+
+    /// Check whether the config file was saved after the system profile was last built
+    pub fn refresh_rebuild_pending(&mut self) {
+        let modified = |path: &Path| {
+            std::fs::symlink_metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+        };
+
+        self.rebuild_pending = match (
+            modified(Path::new(&self.config.path)),
+            modified(Path::new(SYSTEM_PROFILE)),
+        ) {
+            (Some(saved), Some(built)) => saved > built,
+            // Without a system profile (e.g. not NixOS) there's nothing to compare
+            _ => false,
+        };
+    }
+
+    /// Copy the commit-ready summary of this session's changes to the clipboard
+    pub fn copy_change_summary(&mut self) {
+        let Some(summary) = self.change_log.summary() else {
+            self.set_status("No changes to summarize".to_string());
+            return;
+        };
+
+        self.set_status(match clipboard::copy(&summary) {
+            Ok(()) => format!("Copied: {}", summary),
+            Err(e) => format!("Clipboard error: {}", e),
+        });
+    }
+
+    /// Copy the selected entry as nixxed parsed it, as pretty JSON, e.g. for a
+    /// bug report about a misparse
+    pub fn copy_entry_json(&mut self, list_type: &types::ListType) {
+        let (entry_type, state, entries) = match list_type {
+            types::ListType::Programs => (EntryType::Program, &self.program_state, &self.programs),
+            types::ListType::Services => (EntryType::Service, &self.service_state, &self.services),
+            types::ListType::Packages => (EntryType::Package, &self.package_state, &self.packages),
+            types::ListType::Users => (EntryType::User, &self.user_state, &self.users),
+        };
+        let Some(entry) = state
+            .selected()
+            .and_then(|i| entries.get(i))
+            .filter(|e| e.header.is_none())
+        else {
+            return;
+        };
+        let Some(config_entry) = self.config.get_entry(&entry.name, &entry_type) else {
+            self.set_status(format!("{} is not in the config", entry.name));
+            return;
+        };
+
+        let json = match serde_json::to_string_pretty(config_entry) {
+            Ok(json) => json,
+            Err(e) => {
+                self.set_status(format!("Error: {}", e));
+                return;
+            }
+        };
+        let name = config_entry.name.clone();
+        self.set_status(match clipboard::copy(&json) {
+            Ok(()) => format!("Copied {} as JSON", name),
+            Err(e) => format!("Clipboard error: {}", e),
+        });
+    }
+
+    /// List the other places in the config that mention the selected entry
+    pub fn show_references(&mut self, list_type: &types::ListType) {
+        let (entry_type, state, entries) = match list_type {
+            types::ListType::Programs => (EntryType::Program, &self.program_state, &self.programs),
+            types::ListType::Services => (EntryType::Service, &self.service_state, &self.services),
+            types::ListType::Packages => (EntryType::Package, &self.package_state, &self.packages),
+            types::ListType::Users => (EntryType::User, &self.user_state, &self.users),
+        };
+        let Some(entry) = state
+            .selected()
+            .and_then(|i| entries.get(i))
+            .filter(|e| e.header.is_none())
+        else {
+            return;
+        };
+
+        let references = self.config.find_references(&entry.name, &entry_type);
+        self.description_popup.name = format!("References to {}", entry.name);
+        self.description_popup.description = if references.is_empty() {
+            "No other references found in the config".to_string()
+        } else {
+            references
+                .iter()
+                .map(|r| format!("{}: {}", self.reference_location(r), r.text))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        self.description_popup.scroll_offset = 0;
+        self.description_popup.show = true;
+    }
+
+    /// `L12` for a reference in the config itself, `desktop.nix:L12` in an import
+    fn reference_location(&self, reference: &crate::config_parser::Reference) -> String {
+        if reference.file == self.config.path {
+            format!("L{}", reference.line)
+        } else {
+            format!("{}:L{}", self.display_path(&reference.file), reference.line)
+        }
+    }
+
+    /// Move the selection to the next (or previous) place mentioning the selected
+    /// entry, e.g. from a package to the program whose `package` option uses it.
+    /// Repeated jumps walk the same name's references and wrap back to its definition.
+    pub fn jump_to_reference(&mut self, list_type: &types::ListType, forward: bool) {
+        let (entry_type, state, entries) = match list_type {
+            types::ListType::Programs => (EntryType::Program, &self.program_state, &self.programs),
+            types::ListType::Services => (EntryType::Service, &self.service_state, &self.services),
+            types::ListType::Packages => (EntryType::Package, &self.package_state, &self.packages),
+            types::ListType::Users => (EntryType::User, &self.user_state, &self.users),
+        };
+        let selected = state.selected();
+
+        let continuing = self
+            .reference_jump
+            .take()
+            .filter(|j| selected.is_some_and(|s| j.landed == (list_type.clone(), s)));
+        let (name, entry_type, index) = match continuing {
+            Some(jump) => (jump.name, jump.entry_type, jump.index),
+            None => {
+                let Some(entry) = selected
+                    .and_then(|i| entries.get(i))
+                    .filter(|e| e.header.is_none())
+                else {
+                    return;
+                };
+                (entry.name.clone(), entry_type, 0)
+            }
+        };
+
+        let references = self.config.find_references(&name, &entry_type);
+        if references.is_empty() {
+            self.set_status(format!("No other references to {}", name));
+            return;
+        }
+
+        let stops = references.len() + 1;
+        let index = if forward {
+            (index + 1) % stops
+        } else {
+            (index + stops - 1) % stops
+        };
+
+        // Land on the entry whose definition contains the reference (the innermost one)
+        let target = match index {
+            0 => Some((name.clone(), entry_type.clone())),
+            _ => {
+                let reference = &references[index - 1];
+                let position = reference.position;
+                self.config
+                    .modules()
+                    .into_iter()
+                    .flat_map(|m| &m.entries)
+                    .filter(|e| e.file == reference.file)
+                    .filter(|e| e.text_range.0 <= position && position < e.text_range.1)
+                    .min_by_key(|e| e.text_range.1 - e.text_range.0)
+                    .map(|e| (e.name.clone(), e.entry_type.clone()))
+            }
+        };
+        let landed = target
+            .and_then(|(target_name, target_type)| self.select_entry(&target_name, &target_type))
+            .or(selected.map(|s| (list_type.clone(), s)));
+
+        if index == 0 {
+            self.set_status(format!("Back at the definition of {}", name));
+        } else {
+            let reference = &references[index - 1];
+            self.set_status(format!(
+                "Reference {}/{} to {}: {}: {}",
+                index,
+                references.len(),
+                name,
+                self.reference_location(reference),
+                reference.text
+            ));
+        }
+
+        self.reference_jump = landed.map(|landed| types::ReferenceJump {
+            name,
+            entry_type,
+            index,
+            landed,
+        });
+    }
+
+    /// Show the uncommitted git diff of the config file in a popup
+    pub fn show_git_diff(&mut self) {
+        let Some(repo) = &self.git_repo else {
+            self.set_status("Git integration is off (start nixxed with --git)".to_string());
+            return;
+        };
+
+        match git::diff(repo, &self.repo_files(repo)) {
+            Ok(diff) => {
+                self.description_popup.name = "git diff".to_string();
+                self.description_popup.description = if diff.trim().is_empty() {
+                    "No unstaged changes".to_string()
+                } else {
+                    diff
+                };
+                self.description_popup.scroll_offset = 0;
+                self.description_popup.show = true;
+            }
+            Err(e) => {
+                self.set_status(format!("Git error: {}", e));
+            }
+        }
+    }
+
+    /// Show the session's status messages, newest first
+    pub fn show_status_history(&mut self) {
+        self.description_popup.name = "Status history".to_string();
+        self.description_popup.description = if self.status_history.is_empty() {
+            "No messages yet".to_string()
+        } else {
+            self.status_history
+                .newest_first()
+                .map(|(time, message)| {
+                    format!("{:>8}  {}", format_age(time.elapsed().as_secs()), message)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        self.description_popup.scroll_offset = 0;
+        self.description_popup.show = true;
+    }
+}
+
+/// How long ago something happened, in its largest whole unit ("5m ago")
+pub(crate) fn format_age(secs: u64) -> String {
+    if secs < 60 {
+        format!("{}s ago", secs)
+    } else if secs < 3600 {
+        format!("{}m ago", secs / 60)
+    } else if secs < 24 * 3600 {
+        format!("{}h ago", secs / 3600)
+    } else {
+        format!("{}d ago", secs / (24 * 3600))
+    }
 }
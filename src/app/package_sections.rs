@@ -0,0 +1,61 @@
+//! Dividers in the Packages column for `# Development` style comment headers
+//! in the package lists
+
+use crate::app::service_groups::closest_selectable;
+use crate::app::types::{GroupHeader, ListEntry};
+use crate::app::App;
+
+/// Group packages under the section they're listed in, in the order the
+/// sections are written, with a divider row heading each one. Packages
+/// before the first header stay on top without a divider.
+pub(crate) fn with_section_dividers(
+    packages: Vec<(Option<String>, ListEntry)>,
+    sections: &[String],
+) -> Vec<ListEntry> {
+    let mut unsectioned = Vec::new();
+    let mut groups: Vec<(&String, Vec<ListEntry>)> =
+        sections.iter().map(|s| (s, Vec::new())).collect();
+    for (section, entry) in packages {
+        match groups
+            .iter_mut()
+            .find(|(s, _)| Some(*s) == section.as_ref())
+        {
+            Some((_, members)) => members.push(entry),
+            None => unsectioned.push(entry),
+        }
+    }
+
+    let mut rows = unsectioned;
+    for (section, mut members) in groups {
+        if members.is_empty() {
+            continue;
+        }
+        for member in members.iter_mut() {
+            member.grouped = true;
+        }
+        rows.push(ListEntry {
+            name: section.clone(),
+            enabled: members.iter().all(|m| m.enabled),
+            in_config: true,
+            header: Some(GroupHeader {
+                collapsed: false,
+                count: members.len(),
+                members: Vec::new(),
+                divider: true,
+            }),
+            ..Default::default()
+        });
+        rows.append(&mut members);
+    }
+    rows
+}
+
+impl App {
+    /// Find the closest package row from `idx` that isn't a divider, searching
+    /// in `direction` first
+    pub(crate) fn next_selectable_package(&self, idx: usize, direction: i32) -> usize {
+        closest_selectable(self.packages.len(), idx, direction, |i| {
+            self.packages[i].header.is_none()
+        })
+    }
+}
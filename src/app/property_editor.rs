@@ -1,10 +1,15 @@
 use anyhow::Result;
 use crossterm::event::KeyCode;
 
-use crate::app::types::{ListType, PropertyEditState};
+use crate::app::text_input;
+use crate::app::types::{
+    ChangeAction, ConfirmAction, ListType, PropertyEditState, PropertyEditorRegion,
+};
 use crate::app::ui::widgets::apply_look_ahead_scroll;
 use crate::app::App;
-use crate::config_parser::{EntryType, PropertyType};
+use crate::config_parser::{
+    bracket_balance_error, ConfigProperty, EntryType, NixOptionInfo, PropertyType,
+};
 
 impl App {
     /// Open the property editor for the currently selected entry
@@ -24,7 +29,7 @@ impl App {
             }
             ListType::Services => {
                 if let Some(idx) = self.service_state.selected() {
-                    if idx < self.services.len() {
+                    if idx < self.services.len() && self.services[idx].header.is_none() {
                         let entry = &self.services[idx];
                         (EntryType::Service, entry.name.clone(), entry.in_config)
                     } else {
@@ -34,19 +39,94 @@ impl App {
                     return Ok(());
                 }
             }
+            ListType::Users => {
+                if let Some(idx) = self.user_state.selected() {
+                    if idx < self.users.len() {
+                        let entry = &self.users[idx];
+                        (EntryType::User, entry.name.clone(), entry.in_config)
+                    } else {
+                        return Ok(());
+                    }
+                } else {
+                    return Ok(());
+                }
+            }
             ListType::Packages => {
                 // Packages don't have properties to edit
-                self.status_message = Some("Packages don't have editable properties".to_string());
+                self.set_status("Packages don't have editable properties".to_string());
                 return Ok(());
             }
         };
 
         if !in_config {
-            self.status_message =
-                Some("Add entry to config first before editing properties".to_string());
+            let message = format!(
+                "{} is not in the config yet. Enable it and edit its properties?",
+                name
+            );
+            return self.request_confirmation(
+                message,
+                crate::app::types::ConfirmAction::AddAndEditProperties(name, entry_type),
+            );
+        }
+
+        self.open_property_editor_for(name, entry_type);
+        Ok(())
+    }
+
+    /// Add an entry to the config, then open the property editor for it
+    pub(crate) fn add_and_edit_properties(
+        &mut self,
+        name: String,
+        entry_type: EntryType,
+    ) -> Result<()> {
+        if self.blocked_by_read_only("adding") {
             return Ok(());
         }
+        if let Err(e) = self.config.add_entry(&name, &entry_type) {
+            self.set_status(format!("Error: {}", e));
+            return Ok(());
+        }
+
+        self.mark_dirty();
+        self.change_log.record(&name, None, ChangeAction::Add);
 
+        // Update the local entry so the current (possibly search) view stays intact
+        let entries = match entry_type {
+            EntryType::Program => &mut self.programs,
+            EntryType::Service => &mut self.services,
+            EntryType::Package => &mut self.packages,
+            EntryType::User => &mut self.users,
+        };
+        if let Some(entry) = entries
+            .iter_mut()
+            .find(|e| e.name == name && e.header.is_none())
+        {
+            entry.enabled = true;
+            entry.in_config = true;
+        }
+
+        self.set_status(format!(
+            "Added {} {}",
+            match entry_type {
+                EntryType::Program => "program",
+                EntryType::Service => "service",
+                EntryType::Package => "package",
+                EntryType::User => "user",
+            },
+            name
+        ));
+
+        self.open_property_editor_for(name, entry_type);
+
+        // A freshly added entry has no properties yet, so start on the available options
+        if !self.prop_editor.available_options.is_empty() {
+            self.prop_editor.showing_available = true;
+        }
+        Ok(())
+    }
+
+    /// Open the property editor for an entry that exists in the config
+    fn open_property_editor_for(&mut self, name: String, entry_type: EntryType) {
         // Fetch available options from schema
         let configured_props = self
             .config
@@ -60,6 +140,20 @@ impl App {
         self.prop_editor
             .available_options
             .sort_by(|a, b| a.0.cmp(&b.0));
+        let options = self
+            .schema_cache
+            .get_schema(&entry_type, &name)
+            .map(|schema| schema.options)
+            .unwrap_or_default();
+        self.prop_editor.secret_options = options
+            .iter()
+            .filter(|(_, info)| description_mentions_secret(&info.description))
+            .map(|(option, _)| option.clone())
+            .collect();
+        self.prop_editor.option_types = options
+            .into_iter()
+            .map(|(option, info)| (option, info.option_type))
+            .collect();
 
         // Set up property editor state
         self.prop_editor.entry = Some((name, entry_type));
@@ -71,9 +165,87 @@ impl App {
         self.prop_editor.new_value.clear();
         self.prop_editor.show = true;
         self.prop_editor.showing_available = false;
+        self.prop_editor.region = PropertyEditorRegion::List;
+        self.prop_editor.description_scroll = 0;
+        self.prop_editor.filter.clear();
+        self.prop_editor.filtering = false;
         self.focus = crate::app::types::Focus::PropertyEditor;
+    }
 
-        Ok(())
+    /// Indices (into the unfiltered list) of the configured properties or available
+    /// options that match the property filter, in display order
+    pub(crate) fn visible_property_indices(&self) -> Vec<usize> {
+        let filter = &self.prop_editor.filter;
+        if self.prop_editor.showing_available {
+            self.prop_editor
+                .available_options
+                .iter()
+                .enumerate()
+                .filter(|(_, (name, _))| fuzzy_match(name, filter))
+                .map(|(i, _)| i)
+                .collect()
+        } else {
+            self.prop_editor
+                .entry
+                .as_ref()
+                .and_then(|(name, entry_type)| self.config.get_entry(name, entry_type))
+                .map(|e| {
+                    e.properties
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, p)| fuzzy_match(&p.name, filter))
+                        .map(|(i, _)| i)
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+    }
+
+    /// Index (into the unfiltered list) of the selected property list row
+    pub(crate) fn selected_property_index(&self) -> Option<usize> {
+        let selected = self.prop_editor.list_state.selected()?;
+        self.visible_property_indices().get(selected).copied()
+    }
+
+    /// Keep the property list selection within the (filtered) list
+    fn clamp_property_selection(&mut self) {
+        let len = self.visible_property_indices().len();
+        let selected = self.prop_editor.list_state.selected().unwrap_or(0);
+        self.prop_editor
+            .list_state
+            .select(Some(selected.min(len.saturating_sub(1))));
+    }
+
+    /// Handle a key while typing into the property filter
+    fn handle_property_filter_input(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char(c) => self.prop_editor.filter.push(c),
+            KeyCode::Backspace => {
+                self.prop_editor.filter.pop();
+            }
+            KeyCode::Enter => {
+                self.prop_editor.filtering = false;
+                return;
+            }
+            KeyCode::Esc => {
+                self.prop_editor.filter.clear();
+                self.prop_editor.filtering = false;
+            }
+            KeyCode::Up => {
+                self.move_property_selection(-1);
+                return;
+            }
+            KeyCode::Down => {
+                self.move_property_selection(1);
+                return;
+            }
+            _ => return,
+        }
+
+        // The filter changed, start again from the top of the list
+        self.prop_editor.list_state.select(Some(0));
+        *self.prop_editor.list_state.offset_mut() = 0;
+        self.prop_editor.description_scroll = 0;
     }
 
     /// Get the viewport height for the property editor list
@@ -84,16 +256,7 @@ impl App {
 
     /// Move selection in property list by delta with look-ahead scrolling
     pub(crate) fn move_property_selection(&mut self, delta: i32) {
-        let len = if self.prop_editor.showing_available {
-            self.prop_editor.available_options.len()
-        } else {
-            self.prop_editor
-                .entry
-                .as_ref()
-                .and_then(|(name, entry_type)| self.config.get_entry(name, entry_type))
-                .map(|e| e.properties.len())
-                .unwrap_or(0)
-        };
+        let len = self.visible_property_indices().len();
 
         if len == 0 {
             return;
@@ -106,6 +269,7 @@ impl App {
             current.saturating_sub((-delta) as usize)
         };
         self.prop_editor.list_state.select(Some(new));
+        self.prop_editor.description_scroll = 0;
 
         // Apply look-ahead scrolling
         let viewport_height = self.get_property_list_viewport_height();
@@ -125,40 +289,160 @@ impl App {
         );
     }
 
+    /// Move keyboard focus to the next property editor region (list -> input -> description)
+    fn cycle_property_editor_region(&mut self) {
+        let next = self.prop_editor.region.next();
+
+        // Entering the input region with nothing in progress starts adding a property
+        if next == PropertyEditorRegion::Input
+            && self.prop_editor.edit_state.is_none()
+            && !self.prop_editor.adding_new
+        {
+            self.start_adding_property();
+        }
+
+        self.prop_editor.region = next;
+    }
+
+    /// Start manual entry of a new property in the input area
+    fn start_adding_property(&mut self) {
+        if self.blocked_by_read_only("adding properties") {
+            return;
+        }
+        self.prop_editor.adding_new = true;
+        self.prop_editor.editing_name = true;
+        self.prop_editor.new_name.clear();
+        self.prop_editor.new_value.clear();
+        self.prop_editor.new_cursor = 0;
+        self.prop_editor.region = PropertyEditorRegion::Input;
+    }
+
+    /// Catch up with a reparsed config: close the editor if its entry is gone,
+    /// follow the property being edited to its new position (or drop the edit
+    /// if it was removed) and keep the selection in range
+    pub(crate) fn sync_property_editor(&mut self) {
+        if !self.prop_editor.show {
+            return;
+        }
+        let Some((name, entry_type)) = self.prop_editor.entry.clone() else {
+            return;
+        };
+        let Some(entry) = self.config.get_entry(&name, &entry_type) else {
+            self.prop_editor.reset();
+            self.set_status(format!(
+                "{}.{} is no longer in the config, closed its property editor",
+                entry_type.prefix(),
+                name
+            ));
+            return;
+        };
+
+        if let Some(edit_state) = self.prop_editor.edit_state.as_mut() {
+            match entry
+                .properties
+                .iter()
+                .position(|p| p.name == edit_state.property_name)
+            {
+                Some(idx) => edit_state.property_index = idx,
+                None => {
+                    let prop_name = edit_state.property_name.clone();
+                    self.prop_editor.edit_state = None;
+                    self.prop_editor.region = PropertyEditorRegion::List;
+                    self.set_status(format!("{} was removed, edit cancelled", prop_name));
+                }
+            }
+        }
+
+        if self.visible_property_indices().is_empty() {
+            self.prop_editor.list_state.select(None);
+        } else {
+            self.clamp_property_selection();
+        }
+    }
+
     /// Handle keyboard input in the property editor
     pub fn handle_property_editor_input(&mut self, code: KeyCode) -> Result<()> {
-        // If we're editing a property value
-        if let Some(ref mut edit_state) = self.prop_editor.edit_state {
+        // The config may have been reparsed since the last key (e.g. after save hooks)
+        self.sync_property_editor();
+        if !self.prop_editor.show {
+            return Ok(());
+        }
+
+        // Shift+Tab cycles focus between the list, input and description regions
+        if code == KeyCode::BackTab {
+            self.cycle_property_editor_region();
+            return Ok(());
+        }
+
+        // Scrolling the description
+        if self.prop_editor.region == PropertyEditorRegion::Description {
+            let max_scroll = self
+                .prop_editor
+                .description_lines
+                .saturating_sub(self.prop_editor.description_visible);
             match code {
-                KeyCode::Char(c) => {
-                    edit_state.edit_buffer.insert(edit_state.cursor_pos, c);
-                    edit_state.cursor_pos += 1;
-                }
-                KeyCode::Backspace => {
-                    if edit_state.cursor_pos > 0 {
-                        edit_state.cursor_pos -= 1;
-                        edit_state.edit_buffer.remove(edit_state.cursor_pos);
-                    }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    self.prop_editor.description_scroll =
+                        self.prop_editor.description_scroll.saturating_sub(1);
                 }
-                KeyCode::Delete => {
-                    if edit_state.cursor_pos < edit_state.edit_buffer.len() {
-                        edit_state.edit_buffer.remove(edit_state.cursor_pos);
-                    }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    self.prop_editor.description_scroll =
+                        (self.prop_editor.description_scroll + 1).min(max_scroll);
                 }
-                KeyCode::Left => {
-                    edit_state.cursor_pos = edit_state.cursor_pos.saturating_sub(1);
+                KeyCode::PageUp => {
+                    self.prop_editor.description_scroll = self
+                        .prop_editor
+                        .description_scroll
+                        .saturating_sub(self.prop_editor.description_visible.max(1));
                 }
-                KeyCode::Right => {
-                    edit_state.cursor_pos =
-                        (edit_state.cursor_pos + 1).min(edit_state.edit_buffer.len());
+                KeyCode::PageDown => {
+                    self.prop_editor.description_scroll = (self.prop_editor.description_scroll
+                        + self.prop_editor.description_visible.max(1))
+                    .min(max_scroll);
                 }
                 KeyCode::Home => {
-                    edit_state.cursor_pos = 0;
+                    self.prop_editor.description_scroll = 0;
                 }
                 KeyCode::End => {
-                    edit_state.cursor_pos = edit_state.edit_buffer.len();
+                    self.prop_editor.description_scroll = max_scroll;
                 }
+                KeyCode::Esc | KeyCode::Tab => {
+                    self.prop_editor.region = PropertyEditorRegion::List;
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        let in_input_region = self.prop_editor.region == PropertyEditorRegion::Input;
+
+        // If we're editing a property value
+        if let Some(edit_state) = self
+            .prop_editor
+            .edit_state
+            .as_mut()
+            .filter(|_| in_input_region)
+        {
+            match code {
                 KeyCode::Enter => {
+                    // Refuse to write structured values with unbalanced brackets
+                    let property_type = self
+                        .prop_editor
+                        .entry
+                        .as_ref()
+                        .and_then(|(name, etype)| self.config.get_entry(name, etype))
+                        .and_then(|e| e.properties.get(edit_state.property_index))
+                        .map(|p| p.property_type.clone());
+                    if let Some(problem) =
+                        structured_value_error(&edit_state.edit_buffer, property_type.as_ref())
+                    {
+                        self.set_status(format!(
+                            "Unbalanced brackets: {} (fix before saving)",
+                            problem
+                        ));
+                        return Ok(());
+                    }
+
                     // Save the edited property
                     let entry_name = edit_state.entry_name.clone();
                     let entry_type = edit_state.entry_type.clone();
@@ -166,70 +450,61 @@ impl App {
 
                     if let Some((ref name, ref etype)) = self.prop_editor.entry {
                         if let Some(entry) = self.config.get_entry(name, etype) {
-                            if edit_state.property_index < entry.properties.len() {
-                                let prop_name =
-                                    entry.properties[edit_state.property_index].name.clone();
+                            if entry
+                                .properties
+                                .get(edit_state.property_index)
+                                .is_some_and(|p| p.name == edit_state.property_name)
+                            {
+                                let prop_name = edit_state.property_name.clone();
                                 if let Err(e) = self.config.set_property(
                                     &entry_name,
                                     &entry_type,
                                     &prop_name,
                                     &new_value,
                                 ) {
-                                    self.status_message =
-                                        Some(format!("Error saving property: {}", e));
+                                    self.set_status(format!("Error saving property: {}", e));
                                 } else {
-                                    self.is_dirty = true;
-                                    self.status_message =
-                                        Some(format!("Updated {} = {}", prop_name, new_value));
+                                    self.mark_dirty();
+                                    self.change_log.record(
+                                        &entry_name,
+                                        Some(&prop_name),
+                                        ChangeAction::Set,
+                                    );
+                                    self.set_status(format!(
+                                        "Updated {}",
+                                        self.assignment_status(&prop_name, &new_value)
+                                    ));
                                     self.load_from_config();
+                                    self.check_path_value(
+                                        &entry_name,
+                                        &entry_type,
+                                        &prop_name,
+                                        &new_value,
+                                    )?;
                                 }
                             }
                         }
                     }
                     self.prop_editor.edit_state = None;
+                    self.prop_editor.region = PropertyEditorRegion::List;
                 }
                 KeyCode::Esc => {
                     // Cancel editing
                     self.prop_editor.edit_state = None;
+                    self.prop_editor.region = PropertyEditorRegion::List;
                 }
-                _ => {}
+                code => text_input::edit(
+                    &mut edit_state.edit_buffer,
+                    &mut edit_state.cursor_pos,
+                    code,
+                ),
             }
             return Ok(());
         }
 
         // If we're adding a new property
-        if self.prop_editor.adding_new {
+        if self.prop_editor.adding_new && in_input_region {
             match code {
-                KeyCode::Char(c) => {
-                    if self.prop_editor.editing_name {
-                        self.prop_editor
-                            .new_name
-                            .insert(self.prop_editor.new_cursor, c);
-                        self.prop_editor.new_cursor += 1;
-                    } else {
-                        self.prop_editor
-                            .new_value
-                            .insert(self.prop_editor.new_cursor, c);
-                        self.prop_editor.new_cursor += 1;
-                    }
-                }
-                KeyCode::Backspace => {
-                    if self.prop_editor.editing_name {
-                        if self.prop_editor.new_cursor > 0 {
-                            self.prop_editor.new_cursor -= 1;
-                            self.prop_editor
-                                .new_name
-                                .remove(self.prop_editor.new_cursor);
-                        }
-                    } else {
-                        if self.prop_editor.new_cursor > 0 {
-                            self.prop_editor.new_cursor -= 1;
-                            self.prop_editor
-                                .new_value
-                                .remove(self.prop_editor.new_cursor);
-                        }
-                    }
-                }
                 KeyCode::Tab => {
                     // Switch between name and value fields
                     self.prop_editor.editing_name = !self.prop_editor.editing_name;
@@ -240,11 +515,20 @@ impl App {
                     };
                 }
                 KeyCode::Enter => {
+                    if let Some(problem) = structured_value_error(&self.prop_editor.new_value, None)
+                    {
+                        self.set_status(format!(
+                            "Unbalanced brackets: {} (fix before saving)",
+                            problem
+                        ));
+                        return Ok(());
+                    }
+
                     // Save the new property
                     if !self.prop_editor.new_name.is_empty()
                         && !self.prop_editor.new_value.is_empty()
                     {
-                        if let Some((ref name, ref entry_type)) = self.prop_editor.entry {
+                        if let Some((name, entry_type)) = self.prop_editor.entry.clone() {
                             // Determine property type from value
                             let prop_type = if self.prop_editor.new_value == "true"
                                 || self.prop_editor.new_value == "false"
@@ -257,37 +541,63 @@ impl App {
                             };
 
                             if let Err(e) = self.config.add_property(
-                                name,
-                                entry_type,
+                                &name,
+                                &entry_type,
                                 &self.prop_editor.new_name,
                                 &self.prop_editor.new_value,
                                 &prop_type,
                             ) {
-                                self.status_message = Some(format!("Error adding property: {}", e));
+                                self.set_status(format!("Error adding property: {}", e));
                             } else {
-                                self.is_dirty = true;
-                                self.status_message = Some(format!(
-                                    "Added {} = {}",
-                                    self.prop_editor.new_name, self.prop_editor.new_value
+                                self.mark_dirty();
+                                self.change_log.record(
+                                    &name,
+                                    Some(&self.prop_editor.new_name),
+                                    ChangeAction::Set,
+                                );
+                                self.set_status(format!(
+                                    "Added {}",
+                                    self.assignment_status(
+                                        &self.prop_editor.new_name,
+                                        &self.prop_editor.new_value
+                                    )
                                 ));
                                 self.load_from_config();
+                                let prop_name = self.prop_editor.new_name.clone();
+                                let value = self.prop_editor.new_value.clone();
+                                self.check_path_value(&name, &entry_type, &prop_name, &value)?;
                             }
                         }
                     }
                     self.prop_editor.adding_new = false;
                     self.prop_editor.new_name.clear();
                     self.prop_editor.new_value.clear();
+                    self.prop_editor.region = PropertyEditorRegion::List;
                 }
                 KeyCode::Esc => {
                     self.prop_editor.adding_new = false;
                     self.prop_editor.new_name.clear();
                     self.prop_editor.new_value.clear();
+                    self.prop_editor.region = PropertyEditorRegion::List;
+                }
+                code => {
+                    let editor = &mut self.prop_editor;
+                    let field = if editor.editing_name {
+                        &mut editor.new_name
+                    } else {
+                        &mut editor.new_value
+                    };
+                    text_input::edit(field, &mut editor.new_cursor, code);
                 }
-                _ => {}
             }
             return Ok(());
         }
 
+        if self.prop_editor.filtering {
+            self.handle_property_filter_input(code);
+            return Ok(());
+        }
+
         // Normal property list navigation
         match code {
             KeyCode::Up => {
@@ -302,10 +612,9 @@ impl App {
                 self.prop_editor.list_state.select(Some(0));
                 *self.prop_editor.list_state.offset_mut() = 0; // Reset scroll position
                 if self.prop_editor.showing_available {
-                    self.status_message =
-                        Some("Showing available options (not yet configured)".to_string());
+                    self.set_status("Showing available options (not yet configured)".to_string());
                 } else {
-                    self.status_message = Some("Showing configured properties".to_string());
+                    self.set_status("Showing configured properties".to_string());
                 }
             }
             KeyCode::Enter | KeyCode::Char(' ') => {
@@ -325,11 +634,7 @@ impl App {
             }
             KeyCode::Char('a') | KeyCode::Char('n') => {
                 // Add new property (manual entry)
-                self.prop_editor.adding_new = true;
-                self.prop_editor.editing_name = true;
-                self.prop_editor.new_name.clear();
-                self.prop_editor.new_value.clear();
-                self.prop_editor.new_cursor = 0;
+                self.start_adding_property();
             }
             KeyCode::Char('d') | KeyCode::Delete => {
                 if !self.prop_editor.showing_available {
@@ -337,6 +642,41 @@ impl App {
                     self.delete_selected_property()?;
                 }
             }
+            KeyCode::Char('v') => {
+                // Reveal/mask values that look like secrets
+                self.prop_editor.reveal_secrets = !self.prop_editor.reveal_secrets;
+            }
+            KeyCode::Char('O') => {
+                // Add/remove elements of a list option, one at a time
+                self.open_selected_list_property();
+            }
+            KeyCode::Char('t') => {
+                // Hide/show the type column to give names and values more room
+                self.prop_editor.show_types = !self.prop_editor.show_types;
+            }
+            KeyCode::Char('T') => {
+                // Exact schema types (nullability, element types) instead of short labels
+                self.prop_editor.raw_types = !self.prop_editor.raw_types;
+                self.set_status(if self.prop_editor.raw_types {
+                    "Showing schema types as written".to_string()
+                } else {
+                    "Showing short type labels".to_string()
+                });
+            }
+            KeyCode::Char('P') => {
+                // Set several properties at once from a template
+                self.open_template_prompt();
+            }
+            KeyCode::Char('u') => self.undo_snapshot(),
+            KeyCode::Char('/') => {
+                // Filter the list by (fuzzy) property name
+                self.prop_editor.filtering = true;
+            }
+            KeyCode::Esc if !self.prop_editor.filter.is_empty() => {
+                // Clear the filter before closing the editor
+                self.prop_editor.filter.clear();
+                self.clamp_property_selection();
+            }
             KeyCode::Esc | KeyCode::Char('q') => {
                 // Close property editor
                 self.prop_editor.reset();
@@ -350,18 +690,23 @@ impl App {
 
     /// Edit the currently selected property
     fn edit_selected_property(&mut self) -> Result<()> {
+        if self.blocked_by_read_only("editing properties") {
+            return Ok(());
+        }
         if let Some((ref name, ref entry_type)) = self.prop_editor.entry {
             if let Some(entry) = self.config.get_entry(name, entry_type) {
-                if let Some(idx) = self.prop_editor.list_state.selected() {
+                if let Some(idx) = self.selected_property_index() {
                     if idx < entry.properties.len() {
                         let prop = &entry.properties[idx];
                         self.prop_editor.edit_state = Some(PropertyEditState {
                             entry_name: name.clone(),
                             entry_type: entry_type.clone(),
                             property_index: idx,
+                            property_name: prop.name.clone(),
                             edit_buffer: prop.value.clone(),
                             cursor_pos: prop.value.len(),
                         });
+                        self.prop_editor.region = PropertyEditorRegion::Input;
                     }
                 }
             }
@@ -369,55 +714,84 @@ impl App {
         Ok(())
     }
 
+    /// After a path value was written, point out where it resolves to if that
+    /// doesn't exist, and offer to replace a `~/` path, which only works for
+    /// whoever evaluates the config (and not at all in a flake)
+    fn check_path_value(
+        &mut self,
+        name: &str,
+        entry_type: &EntryType,
+        prop: &str,
+        value: &str,
+    ) -> Result<()> {
+        let Some(resolved) = self.config.resolve_path_value(value) else {
+            return Ok(());
+        };
+
+        if value.trim_start().starts_with("~/") {
+            let literal = self.config.path_literal(&resolved);
+            return self.request_confirmation(
+                format!(
+                    "{} depends on the home directory of whoever evaluates the config. Write {} instead?",
+                    value.trim(),
+                    literal
+                ),
+                ConfirmAction::ReplaceHomePath(
+                    name.to_string(),
+                    entry_type.clone(),
+                    prop.to_string(),
+                    literal,
+                ),
+            );
+        }
+
+        if !resolved.exists() {
+            self.set_status(format!(
+                "Updated {}, but {} doesn't exist",
+                self.assignment_status(prop, value),
+                resolved.display()
+            ));
+        }
+        Ok(())
+    }
+
     /// Add the selected available option to the config
     fn add_selected_available_option(&mut self) -> Result<()> {
-        if let Some(idx) = self.prop_editor.list_state.selected() {
+        if self.blocked_by_read_only("adding properties") {
+            return Ok(());
+        }
+        if let Some(idx) = self.selected_property_index() {
             if idx < self.prop_editor.available_options.len() {
                 let (opt_name, opt_info) = self.prop_editor.available_options[idx].clone();
 
-                if let Some((ref name, ref entry_type)) = self.prop_editor.entry {
+                if let Some((name, entry_type)) = self.prop_editor.entry.clone() {
                     // Use schema to get the property type
                     let prop_type =
-                        if let Some(schema) = self.schema_cache.get_schema(entry_type, name) {
+                        if let Some(schema) = self.schema_cache.get_schema(&entry_type, &name) {
                             schema.property_type_for(&opt_name)
                         } else {
                             PropertyType::Expression
                         };
 
                     // Get default value or a sensible default based on type
-                    let default_value = opt_info
-                        .default
-                        .map(|v| match v {
-                            serde_json::Value::Bool(b) => b.to_string(),
-                            serde_json::Value::Number(n) => n.to_string(),
-                            serde_json::Value::String(s) => s,
-                            serde_json::Value::Null => match opt_info.option_type.as_str() {
-                                "boolean" => "false".to_string(),
-                                "string" => "\"\"".to_string(),
-                                "signed integer" | "integer" => "0".to_string(),
-                                _ => "null".to_string(),
-                            },
-                            _ => serde_json::to_string(&v).unwrap_or_else(|_| "null".to_string()),
-                        })
-                        .unwrap_or_else(|| match opt_info.option_type.as_str() {
-                            "boolean" => "false".to_string(),
-                            "string" => "\"\"".to_string(),
-                            "signed integer" | "integer" => "0".to_string(),
-                            _ => "null".to_string(),
-                        });
+                    let default_value = default_value_for_option(&opt_info);
 
                     if let Err(e) = self.config.add_property(
-                        name,
-                        entry_type,
+                        &name,
+                        &entry_type,
                         &opt_name,
                         &default_value,
                         &prop_type,
                     ) {
-                        self.status_message = Some(format!("Error adding property: {}", e));
+                        self.set_status(format!("Error adding property: {}", e));
                     } else {
-                        self.is_dirty = true;
-                        self.status_message =
-                            Some(format!("Added {} = {}", opt_name, default_value));
+                        self.mark_dirty();
+                        self.change_log
+                            .record(&name, Some(&opt_name), ChangeAction::Set);
+                        self.set_status(format!(
+                            "Added {}",
+                            self.assignment_status(&opt_name, &default_value)
+                        ));
                         self.load_from_config();
 
                         // Remove from available options
@@ -425,9 +799,7 @@ impl App {
 
                         // Adjust selection
                         if !self.prop_editor.available_options.is_empty() {
-                            self.prop_editor.list_state.select(Some(
-                                idx.min(self.prop_editor.available_options.len() - 1),
-                            ));
+                            self.clamp_property_selection();
                         } else {
                             // Switch back to configured view
                             self.prop_editor.showing_available = false;
@@ -442,15 +814,17 @@ impl App {
 
     /// Delete the selected property
     fn delete_selected_property(&mut self) -> Result<()> {
+        if self.blocked_by_read_only("deleting properties") {
+            return Ok(());
+        }
         let delete_info = if let Some((ref name, ref entry_type)) = self.prop_editor.entry {
             if let Some(entry) = self.config.get_entry(name, entry_type) {
-                if let Some(idx) = self.prop_editor.list_state.selected() {
+                if let Some(idx) = self.selected_property_index() {
                     if idx < entry.properties.len() {
                         Some((
                             name.clone(),
                             entry_type.clone(),
                             entry.properties[idx].name.clone(),
-                            idx,
                         ))
                     } else {
                         None
@@ -465,42 +839,276 @@ impl App {
             None
         };
 
-        if let Some((name, entry_type, prop_name, idx)) = delete_info {
+        if let Some((name, entry_type, prop_name)) = delete_info {
             if let Err(e) = self.config.delete_property(&name, &entry_type, &prop_name) {
-                self.status_message = Some(format!("Error deleting property: {}", e));
+                self.set_status(format!("Error deleting property: {}", e));
             } else {
-                self.is_dirty = true;
-                self.status_message = Some(format!("Deleted property: {}", prop_name));
-                self.load_from_config();
-
-                // Refresh available options (the deleted one should reappear)
-                let configured_props = self
-                    .config
-                    .get_entry(&name, &entry_type)
-                    .map(|e| e.properties.clone())
-                    .unwrap_or_default();
-                self.prop_editor.available_options =
-                    self.schema_cache
-                        .get_available_options(&entry_type, &name, &configured_props);
-                self.prop_editor
-                    .available_options
-                    .sort_by(|a, b| a.0.cmp(&b.0));
-
-                // Adjust selection
-                let new_len = self
-                    .config
-                    .get_entry(&name, &entry_type)
-                    .map(|e| e.properties.len())
-                    .unwrap_or(0);
-                if new_len > 0 {
-                    self.prop_editor
-                        .list_state
-                        .select(Some(idx.min(new_len - 1)));
-                } else {
-                    self.prop_editor.list_state.select(None);
-                }
+                self.mark_dirty();
+                self.change_log
+                    .record(&name, Some(&prop_name), ChangeAction::Remove);
+                self.set_status(format!("Deleted property: {}", prop_name));
+
+                // The deleted option reappears among the available ones
+                self.refresh_property_editor();
             }
         }
         Ok(())
     }
+
+    /// Offer the templates for the entry being edited
+    fn open_template_prompt(&mut self) {
+        if self.blocked_by_read_only("applying templates") {
+            return;
+        }
+        let Some((name, entry_type)) = &self.prop_editor.entry else {
+            return;
+        };
+        let entry = format!("{}.{}", entry_type.prefix(), name);
+        match crate::templates::for_entry(&entry) {
+            Ok(templates) if templates.is_empty() => {
+                self.set_status(format!("No templates for {}", entry));
+            }
+            Ok(templates) => {
+                self.template_prompt.templates = templates;
+                self.template_prompt.selected = 0;
+                self.template_prompt.show = true;
+            }
+            Err(e) => self.set_status(format!("Error loading templates: {:#}", e)),
+        }
+    }
+
+    /// Set every property of the selected template on the entry being edited.
+    /// Nothing is written unless all values fit the schema and all edits succeed,
+    /// and the whole template is undone in one step.
+    pub fn apply_selected_template(&mut self) {
+        self.template_prompt.show = false;
+        let Some(template) = self
+            .template_prompt
+            .templates
+            .get(self.template_prompt.selected)
+            .cloned()
+        else {
+            return;
+        };
+        let Some((name, entry_type)) = self.prop_editor.entry.clone() else {
+            return;
+        };
+
+        for (property, value) in &template.properties {
+            let problem =
+                crate::templates::value_error(property, value, &self.prop_editor.option_types)
+                    .or_else(|| {
+                        let option_type = self.prop_editor.option_types.get(property);
+                        let property_type = option_type.map(|t| PropertyType::from_option_type(t));
+                        structured_value_error(value, property_type.as_ref())
+                    });
+            if let Some(problem) = problem {
+                self.set_status(format!("Template {}: {}", template.name, problem));
+                return;
+            }
+        }
+
+        let before = self.config.clone();
+        let change_log = self.change_log.entries.clone();
+        for (property, value) in &template.properties {
+            let exists = self
+                .config
+                .get_entry(&name, &entry_type)
+                .is_some_and(|e| e.properties.iter().any(|p| &p.name == property));
+            let result = if exists {
+                self.config
+                    .set_property(&name, &entry_type, property, value)
+            } else {
+                let property_type = self
+                    .prop_editor
+                    .option_types
+                    .get(property)
+                    .map_or(PropertyType::Expression, |t| {
+                        PropertyType::from_option_type(t)
+                    });
+                self.config
+                    .add_property(&name, &entry_type, property, value, &property_type)
+            };
+            if let Err(e) = result {
+                self.config = before;
+                self.change_log.entries = change_log;
+                self.set_status(format!(
+                    "Error applying template {} at {}: {}",
+                    template.name, property, e
+                ));
+                return;
+            }
+            self.change_log
+                .record(&name, Some(property), ChangeAction::Set);
+        }
+
+        self.mark_dirty();
+        self.refresh_property_editor();
+        self.set_status(format!(
+            "Applied template {} ({} propert{}, u to undo)",
+            template.name,
+            template.properties.len(),
+            if template.properties.len() == 1 {
+                "y"
+            } else {
+                "ies"
+            }
+        ));
+        let label = format!("template {}", template.name);
+        self.keep_snapshot_undo(before, change_log, label);
+    }
+
+    /// Reload the lists after the edited entry changed outside of the editor's own
+    /// edits: configured properties and the options still available to add
+    pub(crate) fn refresh_property_editor(&mut self) {
+        self.load_from_config();
+
+        if let Some((name, entry_type)) = self.prop_editor.entry.clone() {
+            let configured_props = self
+                .config
+                .get_entry(&name, &entry_type)
+                .map(|e| e.properties.clone())
+                .unwrap_or_default();
+            self.prop_editor.available_options =
+                self.schema_cache
+                    .get_available_options(&entry_type, &name, &configured_props);
+            self.prop_editor
+                .available_options
+                .sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        // Closes the editor if the entry is gone, otherwise adjusts the selection
+        self.sync_property_editor();
+    }
+
+    /// Open the selected property in the list options popup, if it's a list.
+    /// Works for available options too; the first element added creates it.
+    fn open_selected_list_property(&mut self) {
+        let Some((name, entry_type)) = self.prop_editor.entry.clone() else {
+            return;
+        };
+        let Some(idx) = self.selected_property_index() else {
+            return;
+        };
+
+        let selected = if self.prop_editor.showing_available {
+            self.prop_editor
+                .available_options
+                .get(idx)
+                .map(|(opt, info)| (opt.clone(), info.option_type.starts_with("list of")))
+        } else {
+            self.config
+                .get_entry(&name, &entry_type)
+                .and_then(|e| e.properties.get(idx))
+                .map(|p| {
+                    let is_list = p.property_type == PropertyType::List
+                        || self
+                            .schema_cache
+                            .get_schema(&entry_type, &name)
+                            .is_some_and(|s| s.property_type_for(&p.name) == PropertyType::List);
+                    (p.name.clone(), is_list)
+                })
+        };
+        let Some((prop_name, is_list)) = selected else {
+            return;
+        };
+        if !is_list {
+            self.set_status(format!("{} is not a list", prop_name));
+            return;
+        }
+
+        self.list_options.property = Some((name, entry_type, prop_name));
+        self.list_options.member = Some(0);
+        self.list_options.show = true;
+    }
+}
+
+impl App {
+    /// Whether a configured value should be shown as •••• (display only; editing
+    /// shows the real value). Only strings are masked, so e.g. the bool
+    /// `passwordAuthentication` stays readable.
+    pub(crate) fn is_masked(&self, prop: &ConfigProperty) -> bool {
+        !self.prop_editor.reveal_secrets
+            && prop.property_type == PropertyType::String
+            && self.is_secret(&prop.name)
+    }
+
+    /// Whether the option looks like it holds a secret, by name or by schema description
+    fn is_secret(&self, name: &str) -> bool {
+        is_secret_name(name) || self.prop_editor.secret_options.contains(name)
+    }
+
+    /// `name = value` for the status line, with the value as •••• where it may
+    /// be a secret. The status history is kept, so this ignores the reveal toggle.
+    pub(crate) fn assignment_status(&self, name: &str, value: &str) -> String {
+        let value = value.trim();
+        let literal = matches!(value, "true" | "false") || value.parse::<i64>().is_ok();
+        if self.is_secret(name) && !literal {
+            format!("{} = ••••", name)
+        } else {
+            format!("{} = {}", name, value)
+        }
+    }
+}
+
+/// Option names that usually hold secrets: `password`, `secret`, `token`, or `...key`
+fn is_secret_name(name: &str) -> bool {
+    let last = name.rsplit('.').next().unwrap_or(name).to_lowercase();
+    ["password", "secret", "token"]
+        .iter()
+        .any(|pattern| last.contains(pattern))
+        || last.ends_with("key")
+}
+
+/// Whether a schema description says the option holds a secret
+fn description_mentions_secret(description: &str) -> bool {
+    let description = description.to_lowercase();
+    ["password", "secret", "private key", "api key", "token"]
+        .iter()
+        .any(|pattern| description.contains(pattern))
+}
+
+/// Case-insensitive subsequence match, so "lport" matches "listenPort"
+pub(crate) fn fuzzy_match(text: &str, filter: &str) -> bool {
+    let mut chars = text.chars().flat_map(char::to_lowercase);
+    filter
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|f| chars.any(|c| c == f))
+}
+
+/// Bracket problem in a value that will be written as raw Nix (attribute sets, lists,
+/// parenthesized expressions), if any. Plain strings are quoted on save and aren't checked.
+pub(crate) fn structured_value_error(
+    value: &str,
+    property_type: Option<&PropertyType>,
+) -> Option<String> {
+    let structured = value.trim_start().starts_with(['{', '[', '('])
+        || matches!(
+            property_type,
+            Some(PropertyType::AttrSet | PropertyType::List | PropertyType::Expression)
+        );
+    if structured {
+        bracket_balance_error(value)
+    } else {
+        None
+    }
+}
+
+/// The value written when adding an option: its default, or a sensible value for its type
+pub(crate) fn default_value_for_option(opt_info: &NixOptionInfo) -> String {
+    let type_default = || match opt_info.option_type.as_str() {
+        "boolean" => "false".to_string(),
+        "string" => "\"\"".to_string(),
+        "signed integer" | "integer" => "0".to_string(),
+        _ => "null".to_string(),
+    };
+
+    match &opt_info.default {
+        Some(serde_json::Value::Bool(b)) => b.to_string(),
+        Some(serde_json::Value::Number(n)) => n.to_string(),
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Null) | None => type_default(),
+        Some(v) => serde_json::to_string(v).unwrap_or_else(|_| "null".to_string()),
+    }
 }
@@ -1,10 +1,15 @@
 use anyhow::Result;
 use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
 
 use crate::app::types::ListEntry;
-use crate::app::App;
+use crate::app::{format_age, App};
 use crate::config_parser::EntryType;
-use crate::search::{SearchCategory, SearchMessage, SearchResult};
+use crate::search::{SearchCategory, SearchMessage, SearchMode, SearchResult};
+use crate::settings::ResultSort;
+
+/// How many recent search queries are kept
+const SEARCH_HISTORY_LIMIT: usize = 50;
 
 impl App {
     /// Poll for search results (call this regularly)
@@ -13,48 +18,194 @@ impl App {
             match msg {
                 SearchMessage::Started => {
                     self.is_searching = true;
-                    self.status_message = Some("Searching...".to_string());
+                    self.set_status("Searching...".to_string());
                 }
-                SearchMessage::Completed(results) => {
+                SearchMessage::Completed(results, cached_at) => {
                     self.is_searching = false;
+                    let count = results.len();
                     self.process_search_results(results);
+                    match cached_at {
+                        Some(fetched_at) => self.set_cached_status(count, fetched_at),
+                        None => self.status_message = None,
+                    }
                 }
                 SearchMessage::Error(e) => {
                     self.is_searching = false;
-                    self.status_message = Some(format!("Search error: {}", e));
+                    self.set_status(e);
                 }
             }
         }
     }
 
+    /// Apply results of the startup check of commented-out packages (call this
+    /// regularly). Ones that aren't in nixpkgs are dropped from the lists.
+    pub fn poll_verification(&mut self) {
+        let Some((mut checked, total)) = self.verify_progress else {
+            return;
+        };
+        let mut hidden = false;
+        for (name, exists) in self.searcher.poll_verification() {
+            checked += 1;
+            if exists {
+                continue;
+            }
+            if self.search_query.is_empty() {
+                hidden = true;
+            } else {
+                self.packages
+                    .retain(|e| e.enabled || !e.in_config || e.name != name);
+            }
+            self.missing_packages.insert(name);
+        }
+
+        // Rebuilt, so section dividers and their counts match what's left
+        if hidden {
+            self.load_from_config();
+        }
+
+        // Keep the selection in place, only pulling it back past the end
+        let len = self.packages.len();
+        if let Some(selected) = self.package_state.selected() {
+            self.package_state
+                .select((len > 0).then(|| self.next_selectable_package(selected.min(len - 1), -1)));
+        }
+
+        // Stops early if the checks ended without reporting every name
+        if checked < total && self.searcher.is_verifying() {
+            self.verify_progress = Some((checked, total));
+            return;
+        }
+        self.verify_progress = None;
+        if !self.missing_packages.is_empty() {
+            let count = self.missing_packages.len();
+            self.set_status(format!(
+                "Hid {} commented-out name{} not found in nixpkgs",
+                count,
+                if count == 1 { "" } else { "s" }
+            ));
+        }
+    }
+
+    /// Add the packages from the `P` prompt once the check that they exist is
+    /// done (call this regularly)
+    pub fn poll_package_check(&mut self) {
+        if self.package_check.is_none() {
+            return;
+        }
+        if let Some(exists) = self.searcher.poll_package_check() {
+            if let Some(check) = self.package_check.take() {
+                self.finish_adding_packages(check, exists);
+            }
+        } else if !self.searcher.is_checking_packages() {
+            self.package_check = None;
+            self.set_status("The package check stopped, nothing was added".to_string());
+        }
+    }
+
     pub fn perform_search(&mut self) -> Result<()> {
         if self.search_query.is_empty() {
             self.load_from_config();
             return Ok(());
         }
 
+        self.remember_search();
+
         // Check if we have cached results
-        if let Some(cached) = self.searcher.get_cached(&self.search_query) {
-            self.process_search_results(cached.clone());
+        if let Some((fetched_at, cached)) = self
+            .searcher
+            .get_cached(&self.search_query, self.search_mode)
+            .cloned()
+        {
+            let count = cached.len();
+            self.process_search_results(cached);
+            self.set_cached_status(count, fetched_at);
             return Ok(());
         }
 
         // Start async search
-        self.searcher.start_search(self.search_query.clone());
+        self.searcher
+            .start_search(self.search_query.clone(), self.search_mode);
+        self.is_searching = true;
+        self.set_status("Searching...".to_string());
+
+        Ok(())
+    }
+
+    /// Search again for the current query, ignoring every cached response
+    pub fn refresh_search(&mut self) {
+        if self.search_query.is_empty() {
+            self.set_status("Nothing to refresh without a search query".to_string());
+            return;
+        }
+        self.searcher
+            .refresh(self.search_query.clone(), self.search_mode);
         self.is_searching = true;
-        self.status_message = Some("Searching...".to_string());
+        self.set_status("Refreshing...".to_string());
+    }
+
+    /// Point out that the results shown are cached, and how old they are
+    fn set_cached_status(&mut self, count: usize, fetched_at: SystemTime) {
+        let age = SystemTime::now()
+            .duration_since(fetched_at)
+            .unwrap_or_default();
+        self.set_status(format!(
+            "{} result{} (cached, {}) | F5: Refresh",
+            count,
+            if count == 1 { "" } else { "s" },
+            format_age(age.as_secs())
+        ));
+    }
+
+    /// Add the current query to the search history. It's written out with the
+    /// other settings, at the latest when nixxed exits.
+    fn remember_search(&mut self) {
+        if remember(&mut self.settings.search_history, &self.search_query) {
+            self.search_history_changed = true;
+        }
+    }
 
+    /// Write the search history if it changed since the settings were last saved
+    pub fn save_search_history(&mut self) -> Result<()> {
+        if self.search_history_changed {
+            self.settings.save()?;
+            self.search_history_changed = false;
+        }
         Ok(())
     }
 
-    fn process_search_results(&mut self, results: Vec<SearchResult>) {
+    /// Step through earlier queries like a shell: `older` for Up, newer for Down.
+    /// Returns false if Down wasn't recalling anything, so it can move on as usual.
+    pub(crate) fn recall_search(&mut self, older: bool) -> bool {
+        // Up in the middle of a query first moves to its start
+        if self.search_recall.is_none() && older && self.search_cursor != 0 {
+            self.search_cursor = 0;
+            return true;
+        }
+
+        match step_history(
+            &self.settings.search_history,
+            self.search_recall.take(),
+            &self.search_query,
+            older,
+        ) {
+            Some((recall, query)) => {
+                self.search_recall = recall;
+                self.search_query = query;
+                self.search_cursor = self.search_query.len();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn process_search_results(&mut self, results: Vec<SearchResult>) {
         self.search_results = results;
 
         // Build a map from package name to description for quick lookup
         let descriptions: HashMap<String, String> = self
             .search_results
             .iter()
-            .map(|r| (r.name.clone(), r.description.clone()))
+            .map(|r| (r.attr_name.clone(), r.description.clone()))
             .collect();
 
         // Get current config entries as a set for quick lookup
@@ -81,6 +232,7 @@ impl App {
         self.programs.clear();
         self.services.clear();
         self.packages.clear();
+        self.users.clear();
 
         // Add results from config that match the query
         let query_lower = self.search_query.to_lowercase();
@@ -88,12 +240,9 @@ impl App {
         for entry in self.config.get_entries_by_type(&EntryType::Program) {
             if entry.name.to_lowercase().contains(&query_lower) {
                 self.programs.push(ListEntry {
-                    name: entry.name.clone(),
                     description: descriptions.get(&entry.name).cloned().unwrap_or_default(),
-                    enabled: entry.enabled,
-                    in_config: true,
-                    has_extra_config: entry.has_extra_config,
-                    relevance_order: 0,
+                    already_configured: entry.enabled && !self.change_log.mentions(&entry.name),
+                    ..ListEntry::from_config(entry)
                 });
             }
         }
@@ -101,118 +250,125 @@ impl App {
         for entry in self.config.get_entries_by_type(&EntryType::Service) {
             if entry.name.to_lowercase().contains(&query_lower) {
                 self.services.push(ListEntry {
-                    name: entry.name.clone(),
                     description: descriptions.get(&entry.name).cloned().unwrap_or_default(),
-                    enabled: entry.enabled,
-                    in_config: true,
-                    has_extra_config: entry.has_extra_config,
-                    relevance_order: 0,
+                    already_configured: entry.enabled && !self.change_log.mentions(&entry.name),
+                    ..ListEntry::from_config(entry)
                 });
             }
         }
 
         for entry in self.config.get_entries_by_type(&EntryType::Package) {
-            if entry.name.to_lowercase().contains(&query_lower) {
+            let missing = !entry.enabled && self.missing_packages.contains(&entry.name);
+            if entry.name.to_lowercase().contains(&query_lower) && !missing {
                 self.packages.push(ListEntry {
-                    name: entry.name.clone(),
                     description: descriptions.get(&entry.name).cloned().unwrap_or_default(),
-                    enabled: entry.enabled,
-                    in_config: true,
-                    has_extra_config: false,
-                    relevance_order: 0,
+                    already_configured: entry.enabled && !self.change_log.mentions(&entry.name),
+                    ..ListEntry::from_config(entry)
                 });
             }
         }
 
+        for entry in self.config.get_entries_by_type(&EntryType::User) {
+            if entry.name.to_lowercase().contains(&query_lower) {
+                self.users.push(ListEntry::from_config(entry));
+            }
+        }
+
         // Add search results - each result goes to its category AND to packages
-        // (since every program/service is also installable as a package)
+        // (since every program/service is also installable as a package).
+        // Option search results are NixOS modules, not packages, so they stay in their column.
+        let also_as_package = self.search_mode == SearchMode::Packages;
         for (relevance_order, result) in self.search_results.iter().enumerate() {
             match result.category {
                 SearchCategory::Program => {
                     // Add to programs list if not already there
-                    if !config_programs.contains(&result.name)
-                        && !self.programs.iter().any(|p| p.name == result.name)
+                    if !config_programs.contains(&result.attr_name)
+                        && !self.programs.iter().any(|p| p.name == result.attr_name)
                     {
                         self.programs.push(ListEntry {
-                            name: result.name.clone(),
+                            name: result.attr_name.clone(),
+                            display_name: result.distinct_display_name(),
                             description: result.description.clone(),
-                            enabled: false,
-                            in_config: false,
-                            has_extra_config: false,
                             relevance_order,
+                            ..Default::default()
                         });
                     }
                     // Also add to packages list (programs can be installed as packages too)
-                    if !config_packages.contains(&result.name)
-                        && !self.packages.iter().any(|p| p.name == result.name)
+                    if also_as_package
+                        && !config_packages.contains(&result.attr_name)
+                        && !self.packages.iter().any(|p| p.name == result.attr_name)
                     {
                         self.packages.push(ListEntry {
-                            name: result.name.clone(),
+                            name: result.attr_name.clone(),
+                            display_name: result.distinct_display_name(),
                             description: result.description.clone(),
-                            enabled: false,
-                            in_config: false,
-                            has_extra_config: false,
                             relevance_order,
+                            ..Default::default()
                         });
                     }
                 }
                 SearchCategory::Service => {
                     // Add to services list if not already there
-                    if !config_services.contains(&result.name)
-                        && !self.services.iter().any(|s| s.name == result.name)
+                    if !config_services.contains(&result.attr_name)
+                        && !self.services.iter().any(|s| s.name == result.attr_name)
                     {
                         self.services.push(ListEntry {
-                            name: result.name.clone(),
+                            name: result.attr_name.clone(),
+                            display_name: result.distinct_display_name(),
                             description: result.description.clone(),
-                            enabled: false,
-                            in_config: false,
-                            has_extra_config: false,
                             relevance_order,
+                            ..Default::default()
                         });
                     }
                     // Also add to packages list (services can be installed as packages too)
-                    if !config_packages.contains(&result.name)
-                        && !self.packages.iter().any(|p| p.name == result.name)
+                    if also_as_package
+                        && !config_packages.contains(&result.attr_name)
+                        && !self.packages.iter().any(|p| p.name == result.attr_name)
                     {
                         self.packages.push(ListEntry {
-                            name: result.name.clone(),
+                            name: result.attr_name.clone(),
+                            display_name: result.distinct_display_name(),
                             description: result.description.clone(),
-                            enabled: false,
-                            in_config: false,
-                            has_extra_config: false,
                             relevance_order,
+                            ..Default::default()
                         });
                     }
                 }
                 SearchCategory::Package => {
                     // Only add to packages list
-                    if !config_packages.contains(&result.name)
-                        && !self.packages.iter().any(|p| p.name == result.name)
+                    if !config_packages.contains(&result.attr_name)
+                        && !self.packages.iter().any(|p| p.name == result.attr_name)
                     {
                         self.packages.push(ListEntry {
-                            name: result.name.clone(),
+                            name: result.attr_name.clone(),
+                            display_name: result.distinct_display_name(),
                             description: result.description.clone(),
-                            enabled: false,
-                            in_config: false,
-                            has_extra_config: false,
                             relevance_order,
+                            ..Default::default()
                         });
                     }
                 }
             }
         }
 
-        // Sort lists: config entries first (by name), then search results (by relevance)
+        self.retain_service_modules();
+
+        // Sort lists: config entries first (by name), then search results (by the chosen order)
+        let result_sort = self.settings.result_sort;
         let sort_fn = |a: &ListEntry, b: &ListEntry| match (a.in_config, b.in_config) {
             (true, false) => std::cmp::Ordering::Less,
             (false, true) => std::cmp::Ordering::Greater,
             (true, true) => a.name.cmp(&b.name),
-            (false, false) => a.relevance_order.cmp(&b.relevance_order),
+            (false, false) => match result_sort {
+                ResultSort::Relevance => a.relevance_order.cmp(&b.relevance_order),
+                ResultSort::Name => a.name.cmp(&b.name),
+            },
         };
 
         self.programs.sort_by(sort_fn);
         self.services.sort_by(sort_fn);
         self.packages.sort_by(sort_fn);
+        self.users.sort_by(sort_fn);
 
         // Reset selections
         self.program_state.select(if self.programs.is_empty() {
@@ -230,8 +386,181 @@ impl App {
         } else {
             Some(0)
         });
+        self.user_state
+            .select(if self.users.is_empty() { None } else { Some(0) });
+        self.apply_service_grouping();
 
         let total = self.programs.len() + self.services.len() + self.packages.len();
-        self.status_message = Some(format!("Found {} results", total));
+        self.set_status(if total == 0 {
+            format!("No results for '{}'", self.search_query)
+        } else if self.search_results.is_empty() {
+            format!(
+                "No search results for '{}' ({} matching config entries)",
+                self.search_query, total
+            )
+        } else {
+            format!("Found {} results", total)
+        });
+    }
+
+    /// Toggle hiding service search results that have no `services.<name>` module.
+    /// Until the modules are listed it stays off; `poll_service_modules` turns it on.
+    pub(crate) fn toggle_service_modules_only(&mut self) {
+        if self.service_modules_requested {
+            self.service_modules_requested = false;
+            self.set_status("Showing all service results".to_string());
+            return;
+        }
+        if !self.service_modules_only && self.schema_cache.service_modules().is_none() {
+            if self.schema_cache.load_service_modules() {
+                self.service_modules_requested = true;
+                self.set_status("Listing the NixOS service modules...".to_string());
+            } else {
+                self.set_status("Could not list the NixOS service modules".to_string());
+            }
+            return;
+        }
+        self.service_modules_only = !self.service_modules_only;
+        self.apply_service_modules_only();
+    }
+
+    /// Turn on hiding services without a module once the modules asked for by
+    /// `toggle_service_modules_only` are listed (call this regularly)
+    pub fn poll_service_modules(&mut self) {
+        let Some(loaded) = self.schema_cache.poll_service_modules() else {
+            return;
+        };
+        if !std::mem::take(&mut self.service_modules_requested) {
+            return;
+        }
+        if loaded {
+            self.service_modules_only = true;
+            self.apply_service_modules_only();
+        } else {
+            self.set_status("Could not list the NixOS service modules".to_string());
+        }
+    }
+
+    /// Show the search results again after `service_modules_only` changed
+    fn apply_service_modules_only(&mut self) {
+        if !self.search_query.is_empty() {
+            self.process_search_results(self.search_results.clone());
+        }
+        self.set_status(if self.service_modules_only {
+            "Showing only services with a NixOS module".to_string()
+        } else {
+            "Showing all service results".to_string()
+        });
+    }
+
+    /// Drop service search results whose name isn't a NixOS service module. Entries
+    /// from the config stay, as they're what the user wrote.
+    fn retain_service_modules(&mut self) {
+        if !self.service_modules_only {
+            return;
+        }
+        let Some(modules) = self.schema_cache.service_modules() else {
+            return;
+        };
+        self.services.retain(|e| {
+            let module = e.name.split('.').next().unwrap_or(&e.name);
+            e.in_config || modules.contains(module)
+        });
+    }
+}
+
+/// Add `query` to `history`, moving it to the end if it's already there.
+/// Returns false if nothing changed, e.g. for a blank query.
+fn remember(history: &mut Vec<String>, query: &str) -> bool {
+    let query = query.trim();
+    if query.is_empty() || history.last().is_some_and(|q| q == query) {
+        return false;
+    }
+    history.retain(|q| q != query);
+    history.push(query.to_string());
+    let excess = history.len().saturating_sub(SEARCH_HISTORY_LIMIT);
+    history.drain(..excess);
+    true
+}
+
+/// One Up (`older`) or Down step through `history`. `recall` is the position
+/// being shown and the query typed before recalling started. Returns the new
+/// recall state and the query to show, or None if Down wasn't recalling.
+fn step_history(
+    history: &[String],
+    recall: Option<(usize, String)>,
+    typed: &str,
+    older: bool,
+) -> Option<(Option<(usize, String)>, String)> {
+    let (current, typed) = match recall {
+        Some(recall) => recall,
+        None if older => (history.len(), typed.to_string()),
+        None => return None,
+    };
+
+    let next = if older {
+        current.saturating_sub(1)
+    } else {
+        current + 1
+    };
+    match history.get(next) {
+        Some(query) => Some((Some((next, typed)), query.clone())),
+        // Past the newest query: back to what was typed before recalling
+        None => Some((None, typed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> Vec<String> {
+        vec!["git".to_string(), "vim".to_string()]
+    }
+
+    #[test]
+    fn test_remember_skips_blank_and_repeated_queries() {
+        let mut history = history();
+        assert!(!remember(&mut history, "   "));
+        assert!(!remember(&mut history, " vim "));
+        assert!(remember(&mut history, "git "));
+        assert_eq!(history, ["vim", "git"]);
+    }
+
+    #[test]
+    fn test_step_history() {
+        let history = history();
+
+        // Up from an empty query starts at the newest entry
+        let (recall, query) = step_history(&history, None, "", true).unwrap();
+        assert_eq!(query, "vim");
+        let (recall, query) = step_history(&history, recall, &query, true).unwrap();
+        assert_eq!(query, "git");
+
+        // The oldest entry stays put
+        let (recall, query) = step_history(&history, recall, &query, true).unwrap();
+        assert_eq!(
+            (recall.clone(), query.as_str()),
+            (Some((0, String::new())), "git")
+        );
+
+        // Down past the newest entry goes back to what was typed
+        let (recall, query) = step_history(&history, recall, &query, false).unwrap();
+        assert_eq!(query, "vim");
+        let (recall, query) = step_history(&history, recall, &query, false).unwrap();
+        assert_eq!((recall, query.as_str()), (None, ""));
+
+        let (_, query) = step_history(&history, None, "fire", true).unwrap();
+        let (_, query) =
+            step_history(&history, Some((1, "fire".to_string())), &query, false).unwrap();
+        assert_eq!(query, "fire");
+    }
+
+    #[test]
+    fn test_step_history_down_when_not_recalling() {
+        assert_eq!(step_history(&history(), None, "vim", false), None);
+        // Nothing to recall: Up keeps the query
+        let (recall, query) = step_history(&[], None, "vim", true).unwrap();
+        assert_eq!((recall, query.as_str()), (None, "vim"));
     }
 }
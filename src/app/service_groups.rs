@@ -0,0 +1,196 @@
+//! Grouping of the Services column under collapsible headers by top-level prefix
+//! (e.g. `xserver` for `services.xserver.*`)
+
+use crate::app::types::{GroupHeader, ListEntry};
+use crate::app::App;
+
+/// The top-level prefix a service is grouped under
+fn group_prefix(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+impl App {
+    /// Toggle the grouped rendering mode of the Services column
+    pub(crate) fn toggle_service_grouping(&mut self) {
+        self.group_services = !self.group_services;
+        self.apply_service_grouping();
+        self.set_status(if self.group_services {
+            "Grouping services by prefix".to_string()
+        } else {
+            "Showing services ungrouped".to_string()
+        });
+    }
+
+    /// Rebuild the header rows of the Services column, keeping the selected entry
+    pub(crate) fn apply_service_grouping(&mut self) {
+        let selected_name = self
+            .service_state
+            .selected()
+            .and_then(|i| self.services.get(i))
+            .map(|e| e.name.clone());
+
+        // Flatten any existing groups first
+        let mut flat = Vec::with_capacity(self.services.len());
+        for entry in self.services.drain(..) {
+            match entry.header {
+                Some(header) => flat.extend(header.members),
+                None => flat.push(ListEntry {
+                    grouped: false,
+                    ..entry
+                }),
+            }
+        }
+
+        if !self.group_services {
+            self.services = flat;
+        } else {
+            // Collect groups in order of first appearance to keep the current sort
+            let mut groups: Vec<(String, Vec<ListEntry>)> = Vec::new();
+            for entry in flat {
+                let prefix = group_prefix(&entry.name).to_string();
+                match groups.iter_mut().find(|(p, _)| *p == prefix) {
+                    Some((_, members)) => members.push(entry),
+                    None => groups.push((prefix, vec![entry])),
+                }
+            }
+
+            for (prefix, mut members) in groups {
+                // A group of one doesn't need a header
+                if members.len() == 1 {
+                    self.services.append(&mut members);
+                    continue;
+                }
+
+                for member in members.iter_mut() {
+                    member.grouped = true;
+                }
+                let collapsed = self.collapsed_service_groups.contains(&prefix);
+                let count = members.len();
+                self.services.push(ListEntry {
+                    name: prefix,
+                    enabled: members.iter().all(|m| m.enabled),
+                    in_config: true,
+                    header: Some(GroupHeader {
+                        collapsed,
+                        count,
+                        members: Vec::new(),
+                        divider: false,
+                    }),
+                    ..Default::default()
+                });
+                if collapsed {
+                    if let Some(header) = self.services.last_mut().and_then(|e| e.header.as_mut()) {
+                        header.members = members;
+                    }
+                } else {
+                    self.services.append(&mut members);
+                }
+            }
+        }
+
+        // Restore the selection, falling back to the first selectable row
+        let restored = selected_name.and_then(|name| {
+            self.services
+                .iter()
+                .position(|e| e.name == name && e.header.is_none())
+        });
+        let idx = restored.unwrap_or(0);
+        self.service_state.select(if self.services.is_empty() {
+            None
+        } else {
+            Some(self.next_selectable_service(idx, 1))
+        });
+    }
+
+    /// Whether a row in the Services column can be selected (expanded headers are skipped)
+    fn is_service_row_selectable(&self, idx: usize) -> bool {
+        match self.services.get(idx).and_then(|e| e.header.as_ref()) {
+            Some(header) => header.collapsed,
+            None => true,
+        }
+    }
+
+    /// Find the closest selectable row from `idx`, searching in `direction` first
+    pub(crate) fn next_selectable_service(&self, idx: usize, direction: i32) -> usize {
+        closest_selectable(self.services.len(), idx, direction, |i| {
+            self.is_service_row_selectable(i)
+        })
+    }
+
+    /// Collapse or expand the group of the entry at `idx` (the header itself or a member)
+    pub(crate) fn toggle_service_group_at(&mut self, idx: usize) {
+        let Some(entry) = self.services.get(idx) else {
+            return;
+        };
+        if entry.header.is_none() && !entry.grouped {
+            return;
+        }
+
+        let prefix = group_prefix(&entry.name).to_string();
+        let Some(header_idx) = self
+            .services
+            .iter()
+            .position(|e| e.header.is_some() && e.name == prefix)
+        else {
+            return;
+        };
+
+        let collapsed = self.services[header_idx]
+            .header
+            .as_ref()
+            .is_some_and(|h| h.collapsed);
+
+        if collapsed {
+            // Expand: put the members back below the header
+            let members = self.services[header_idx]
+                .header
+                .as_mut()
+                .map(|h| {
+                    h.collapsed = false;
+                    std::mem::take(&mut h.members)
+                })
+                .unwrap_or_default();
+            self.services
+                .splice(header_idx + 1..header_idx + 1, members);
+            self.collapsed_service_groups.remove(&prefix);
+            self.service_state.select(Some(header_idx + 1));
+        } else {
+            // Collapse: move the members into the header
+            let end = self.services[header_idx + 1..]
+                .iter()
+                .position(|e| !e.grouped)
+                .map(|p| header_idx + 1 + p)
+                .unwrap_or(self.services.len());
+            let members: Vec<ListEntry> = self.services.drain(header_idx + 1..end).collect();
+            if let Some(header) = self.services[header_idx].header.as_mut() {
+                header.collapsed = true;
+                header.members = members;
+            }
+            self.collapsed_service_groups.insert(prefix);
+            self.service_state.select(Some(header_idx));
+        }
+    }
+}
+
+/// The closest row of a list of `len` rows from `idx` that passes `selectable`,
+/// searching in `direction` first
+pub(crate) fn closest_selectable(
+    len: usize,
+    idx: usize,
+    direction: i32,
+    selectable: impl Fn(usize) -> bool,
+) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    let idx = idx.min(len - 1);
+
+    let forward = (idx..len).find(|&i| selectable(i));
+    let backward = (0..=idx).rev().find(|&i| selectable(i));
+    let found = if direction < 0 {
+        backward.or(forward)
+    } else {
+        forward.or(backward)
+    };
+    found.unwrap_or(idx)
+}
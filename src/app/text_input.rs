@@ -0,0 +1,113 @@
+//! Cursor movement and editing shared by the one-line text fields. Cursors are byte
+//! offsets that always sit on a char boundary, so multi-byte input can't split a character.
+
+use crossterm::event::KeyCode;
+
+/// Apply an editing key (characters, Backspace/Delete, Left/Right, Home/End) to
+/// `text`. Other keys are ignored.
+pub fn edit(text: &mut String, cursor: &mut usize, code: KeyCode) {
+    // A cursor set from outside may be past the end or inside a character
+    *cursor = (*cursor).min(text.len());
+    while !text.is_char_boundary(*cursor) {
+        *cursor -= 1;
+    }
+
+    match code {
+        KeyCode::Char(c) => {
+            text.insert(*cursor, c);
+            *cursor += c.len_utf8();
+        }
+        KeyCode::Backspace => {
+            if let Some(c) = text[..*cursor].chars().next_back() {
+                *cursor -= c.len_utf8();
+                text.remove(*cursor);
+            }
+        }
+        KeyCode::Delete => {
+            if *cursor < text.len() {
+                text.remove(*cursor);
+            }
+        }
+        KeyCode::Left => {
+            if let Some(c) = text[..*cursor].chars().next_back() {
+                *cursor -= c.len_utf8();
+            }
+        }
+        KeyCode::Right => {
+            if let Some(c) = text[*cursor..].chars().next() {
+                *cursor += c.len_utf8();
+            }
+        }
+        KeyCode::Home => *cursor = 0,
+        KeyCode::End => *cursor = text.len(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(text: &mut String, cursor: &mut usize, keys: &[KeyCode]) {
+        for &key in keys {
+            edit(text, cursor, key);
+        }
+    }
+
+    #[test]
+    fn test_multibyte_editing() {
+        let mut text = String::new();
+        let mut cursor = 0;
+
+        type_keys(
+            &mut text,
+            &mut cursor,
+            &[
+                KeyCode::Char('c'),
+                KeyCode::Char('a'),
+                KeyCode::Char('f'),
+                KeyCode::Char('é'),
+            ],
+        );
+        assert_eq!(text, "café");
+        assert_eq!(cursor, text.len());
+
+        // Insert before the accented character, then step over it
+        type_keys(&mut text, &mut cursor, &[KeyCode::Left, KeyCode::Char('ß')]);
+        assert_eq!(text, "cafßé");
+        type_keys(
+            &mut text,
+            &mut cursor,
+            &[KeyCode::Right, KeyCode::Char('!')],
+        );
+        assert_eq!(text, "cafßé!");
+
+        // Remove characters of different widths on both sides of the cursor
+        type_keys(&mut text, &mut cursor, &[KeyCode::Left, KeyCode::Backspace]);
+        assert_eq!(text, "cafß!");
+        type_keys(&mut text, &mut cursor, &[KeyCode::Left, KeyCode::Delete]);
+        assert_eq!(text, "caf!");
+
+        type_keys(
+            &mut text,
+            &mut cursor,
+            &[KeyCode::Home, KeyCode::Char('日')],
+        );
+        assert_eq!(text, "日caf!");
+        type_keys(&mut text, &mut cursor, &[KeyCode::End, KeyCode::Backspace]);
+        assert_eq!(text, "日caf");
+    }
+
+    #[test]
+    fn test_cursor_inside_a_character() {
+        // e.g. a cursor set to `len()` of a different string
+        let mut text = "né".to_string();
+        let mut cursor = 2;
+        edit(&mut text, &mut cursor, KeyCode::Char('x'));
+        assert_eq!(text, "nxé");
+
+        let mut cursor = 10;
+        edit(&mut text, &mut cursor, KeyCode::Backspace);
+        assert_eq!(text, "nx");
+    }
+}
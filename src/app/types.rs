@@ -1,5 +1,8 @@
-use crate::config_parser::{EntryType, NixOptionInfo};
+use crate::config_parser::{ConfigEntry, EntryType, NixConfig, NixOptionInfo};
+use crate::templates::Template;
 use ratatui::widgets::ListState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Focus {
@@ -7,6 +10,7 @@ pub enum Focus {
     Programs,
     Services,
     Packages,
+    Users,
     PropertyEditor,
 }
 
@@ -16,16 +20,94 @@ pub struct RebuildPromptState {
     pub show: bool,
     pub selected: usize,       // 0 = Yes, 1 = No
     pub pending_rebuild: bool, // Signal to main loop to run rebuild
+    pub mode: RebuildMode,     // What nixos-rebuild does with the new system (kept for the session)
 }
 
-#[derive(Debug, Clone)]
+/// The `nixos-rebuild` action to run
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum RebuildMode {
+    /// Activate now and make it the boot default
+    #[default]
+    Switch,
+    /// Make it the boot default without activating it
+    Boot,
+    /// Activate now, but boot the previous default next time
+    Test,
+    /// Only show what activating would change
+    DryActivate,
+}
+
+impl RebuildMode {
+    const ALL: [RebuildMode; 4] = [
+        RebuildMode::Switch,
+        RebuildMode::Boot,
+        RebuildMode::Test,
+        RebuildMode::DryActivate,
+    ];
+
+    /// The `nixos-rebuild` subcommand
+    pub fn subcommand(self) -> &'static str {
+        match self {
+            RebuildMode::Switch => "switch",
+            RebuildMode::Boot => "boot",
+            RebuildMode::Test => "test",
+            RebuildMode::DryActivate => "dry-activate",
+        }
+    }
+
+    /// Whether the rebuilt system outlasts a reboot, i.e. the config is now in use
+    pub fn is_lasting(self) -> bool {
+        matches!(self, RebuildMode::Switch | RebuildMode::Boot)
+    }
+
+    /// The next mode, or the previous one with `forward` false, wrapping around
+    pub fn cycle(self, forward: bool) -> Self {
+        let i = Self::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        let len = Self::ALL.len();
+        let next = if forward { i + 1 } else { i + len - 1 };
+        Self::ALL[next % len]
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct ListEntry {
     pub name: String,
-    pub description: String, // Description from search results
+    pub display_name: Option<String>, // Search result's package name, if it differs from the attr name
+    pub description: String,          // Description from search results
     pub enabled: bool,
     pub in_config: bool, // Whether this entry exists in the config file
     pub has_extra_config: bool,
     pub relevance_order: usize, // Order from search results (lower = more relevant)
+    pub header: Option<GroupHeader>, // Set for group header rows (grouped services, package sections)
+    pub grouped: bool,               // Member of a group, drawn indented under its header
+    pub binding: Option<String>, // Variable or expression the enable flag comes from, if it's indirect
+    pub conditional: bool,       // The enable flag can't be read as on/off without evaluating it
+    pub list_line: Option<usize>, // Line of the systemPackages list, if there are several
+    pub already_configured: bool, // Search match enabled in the config before this session's changes
+}
+
+impl ListEntry {
+    /// The row for an entry of the config
+    pub fn from_config(entry: &ConfigEntry) -> Self {
+        ListEntry {
+            name: entry.name.clone(),
+            enabled: entry.enabled,
+            in_config: true,
+            has_extra_config: entry.has_extra_config,
+            binding: entry.enable_source(),
+            conditional: entry.is_conditional(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A collapsible header row grouping entries by their top-level prefix
+#[derive(Debug, Clone)]
+pub struct GroupHeader {
+    pub collapsed: bool,
+    pub count: usize,
+    pub members: Vec<ListEntry>, // Members hidden while the group is collapsed
+    pub divider: bool, // A package list `# Section` comment; not selectable or collapsible
 }
 
 /// State for editing a property value
@@ -34,15 +116,336 @@ pub struct PropertyEditState {
     pub entry_name: String,
     pub entry_type: EntryType,
     pub property_index: usize,
+    /// Name of the edited property, to find it again after the config is reparsed
+    pub property_name: String,
     pub edit_buffer: String,
     pub cursor_pos: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ListType {
     Programs,
     Services,
     Packages,
+    Users,
+}
+
+/// An action that is only carried out once the user confirms it
+#[derive(Debug, Clone)]
+pub enum ConfirmAction {
+    /// Enable (or disable) every entry currently shown in a list
+    ToggleAllVisible(ListType),
+    /// Add an entry that isn't in the config yet and open its property editor
+    AddAndEditProperties(String, EntryType),
+    /// Save even though the git repository already has uncommitted changes
+    SaveConfig,
+    /// Stage the saved config file with `git add`
+    GitAdd,
+    /// Commit the staged config file with the given message
+    GitCommit(String),
+    /// Add a package to a separate module, as the config's package list can't be edited
+    AddPackageToModule(String),
+    /// Rename a program/service (old name, type, new name) that has no known options
+    RenameEntry(String, EntryType, String),
+    /// Rewrite a `~/` path value (entry name, type, property, new value)
+    ReplaceHomePath(String, EntryType, String, String),
+    /// Move a program/service (name, type) to another loaded file (path)
+    MoveEntry(String, EntryType, String),
+    /// Remove the blocks of disabled programs/services listed in the cleanup view
+    RemoveDisabledBlocks(Vec<(String, EntryType)>),
+}
+
+impl ConfirmAction {
+    /// Whether the action does more than a single edit that's easy to take back:
+    /// mass toggles, writing files, git, renames and moves. These are confirmed
+    /// even in trust mode.
+    pub fn is_destructive(&self) -> bool {
+        match self {
+            ConfirmAction::AddAndEditProperties(_, _) => false,
+            ConfirmAction::ToggleAllVisible(_)
+            | ConfirmAction::SaveConfig
+            | ConfirmAction::GitAdd
+            | ConfirmAction::GitCommit(_)
+            | ConfirmAction::AddPackageToModule(_)
+            | ConfirmAction::RenameEntry(..)
+            | ConfirmAction::ReplaceHomePath(..)
+            | ConfirmAction::MoveEntry(..)
+            | ConfirmAction::RemoveDisabledBlocks(_) => true,
+        }
+    }
+}
+
+/// What happened to an entry (or one of its properties) during this session
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeAction {
+    Enable,
+    Disable,
+    Add,
+    Set,
+    Remove,
+}
+
+impl ChangeAction {
+    fn verb(&self) -> &'static str {
+        match self {
+            ChangeAction::Enable => "enable",
+            ChangeAction::Disable => "disable",
+            ChangeAction::Add => "add",
+            ChangeAction::Set => "set",
+            ChangeAction::Remove => "remove",
+        }
+    }
+}
+
+/// A single change in the session's change log
+#[derive(Debug, Clone)]
+pub struct ChangeLogEntry {
+    pub name: String,
+    pub property: Option<String>,
+    pub action: ChangeAction,
+}
+
+/// Where the last reference jump landed, so repeated jumps keep walking the
+/// same name's references even after the selection moved to another entry
+#[derive(Debug, Clone)]
+pub struct ReferenceJump {
+    pub name: String,
+    pub entry_type: EntryType,
+    /// 0 = the entry's own definition, 1.. = its references in file order
+    pub index: usize,
+    /// The list and row the jump selected
+    pub landed: (ListType, usize),
+}
+
+/// How many status messages the history keeps
+const STATUS_HISTORY_LEN: usize = 100;
+
+/// Recent status messages with the time they were shown, oldest first
+#[derive(Debug, Default)]
+pub struct StatusHistory {
+    entries: VecDeque<(Instant, String)>,
+}
+
+impl StatusHistory {
+    /// Add a message, dropping the oldest once the history is full
+    pub fn record(&mut self, message: &str) {
+        if self.entries.len() == STATUS_HISTORY_LEN {
+            self.entries.pop_front();
+        }
+        self.entries
+            .push_back((Instant::now(), message.to_string()));
+    }
+
+    /// Messages with their time, newest first
+    pub fn newest_first(&self) -> impl Iterator<Item = &(Instant, String)> {
+        self.entries.iter().rev()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Changes applied during this session, used to build a commit-ready summary
+#[derive(Debug, Default)]
+pub struct ChangeLog {
+    pub entries: Vec<ChangeLogEntry>,
+}
+
+impl ChangeLog {
+    /// Record a change. A later change to the same entry/property replaces the
+    /// earlier one, and enabling then disabling (or vice versa) cancels out.
+    pub fn record(&mut self, name: &str, property: Option<&str>, action: ChangeAction) {
+        let existing = self
+            .entries
+            .iter()
+            .position(|e| e.name == name && e.property.as_deref() == property);
+
+        if let Some(idx) = existing {
+            let cancels = matches!(
+                (self.entries[idx].action, action),
+                (ChangeAction::Enable, ChangeAction::Disable)
+                    | (ChangeAction::Disable, ChangeAction::Enable)
+            );
+            if cancels {
+                self.entries.remove(idx);
+            } else {
+                self.entries[idx].action = action;
+            }
+            return;
+        }
+
+        self.entries.push(ChangeLogEntry {
+            name: name.to_string(),
+            property: property.map(|p| p.to_string()),
+            action,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether the entry itself (not just one of its properties) was changed
+    pub fn mentions(&self, name: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e.name == name && e.property.is_none())
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Build a one-line summary like "nixxed: enable git, disable nginx, add ripgrep"
+    pub fn summary(&self) -> Option<String> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let parts: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| match &e.property {
+                Some(prop) => format!("{} {}.{}", e.action.verb(), e.name, prop),
+                None => format!("{} {}", e.action.verb(), e.name),
+            })
+            .collect();
+
+        Some(format!("nixxed: {}", parts.join(", ")))
+    }
+}
+
+/// State for a generic yes/no confirmation prompt
+#[derive(Debug, Default)]
+pub struct ConfirmPromptState {
+    pub show: bool,
+    pub selected: usize, // 0 = Yes, 1 = No
+    pub message: String,
+    pub action: Option<ConfirmAction>,
+}
+
+/// State for choosing how a search result is added, overriding its guessed column
+#[derive(Debug, Default)]
+pub struct AddAsPromptState {
+    pub show: bool,
+    pub name: String,
+}
+
+/// State for choosing which file a program/service is moved to, when the
+/// config imports several
+#[derive(Debug, Default)]
+pub struct MovePromptState {
+    pub show: bool,
+    pub name: String,
+    pub entry_type: Option<EntryType>,
+    /// Paths of the files it can be moved to
+    pub targets: Vec<String>,
+    pub selected: usize,
+}
+
+/// What a text input prompt is for
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TextPrompt {
+    /// A note, written as a trailing comment on the entry's line
+    #[default]
+    Note,
+    /// A new name for a program/service
+    Rename,
+    /// An element to append to a list option (`name` holds the option path)
+    ListMember,
+    /// Several package names to add at once
+    Packages,
+    /// The expression a program/service's `enable` is set to
+    Enable,
+}
+
+/// State for the popup editing list-valued options (`config_parser::LIST_OPTIONS`)
+#[derive(Debug, Default)]
+pub struct ListOptionsState {
+    pub show: bool,
+    /// Selected option, as an index into `LIST_OPTIONS`
+    pub option: usize,
+    /// Selected member while looking at one option's members
+    pub member: Option<usize>,
+    /// Property opened from the property editor (entry name, type, property name),
+    /// edited instead of the selected `LIST_OPTIONS` entry
+    pub property: Option<(String, EntryType, String)>,
+}
+
+/// State for the cleanup view, listing disabled programs/services whose
+/// blocks still set options
+#[derive(Debug, Default)]
+pub struct CleanupState {
+    pub show: bool,
+    pub selected: usize,
+    /// Entries marked for removal
+    pub marked: Vec<(String, EntryType)>,
+}
+
+/// Packages from the `P` prompt, waiting for the check that they're in nixpkgs
+#[derive(Debug)]
+pub struct PackageCheck {
+    /// Names not in the config yet, which are being checked
+    pub names: Vec<String>,
+    /// Names already enabled in the config
+    pub present: Vec<String>,
+    /// Names commented out in the config, to re-enable
+    pub commented: Vec<String>,
+}
+
+/// The state before an edit made in one go (removing blocks in the cleanup
+/// view, applying a template), to take it back in one step
+#[derive(Debug)]
+pub struct SnapshotUndo {
+    pub config: NixConfig,
+    pub change_log: Vec<ChangeLogEntry>,
+    /// Content of each file right after the edit; undo is refused once
+    /// anything else was edited
+    pub after: Vec<String>,
+    /// What the edit did, for the status line ("removing 2 blocks")
+    pub label: String,
+}
+
+/// State for picking a property template to apply in the property editor
+#[derive(Debug, Default)]
+pub struct TemplatePromptState {
+    pub show: bool,
+    /// The templates for the entry being edited
+    pub templates: Vec<Template>,
+    pub selected: usize,
+}
+
+/// State for a one-line text input about an entry
+#[derive(Debug, Default)]
+pub struct TextPromptState {
+    pub show: bool,
+    pub kind: TextPrompt,
+    pub name: String,
+    pub entry_type: Option<EntryType>,
+    pub buffer: String,
+    /// Byte offset of the cursor in `buffer`
+    pub cursor: usize,
+}
+
+/// Sub-region of the property editor that currently has keyboard focus
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PropertyEditorRegion {
+    #[default]
+    List,
+    Input,
+    Description,
+}
+
+impl PropertyEditorRegion {
+    /// The region that follows this one when cycling with Shift+Tab
+    pub fn next(&self) -> Self {
+        match self {
+            PropertyEditorRegion::List => PropertyEditorRegion::Input,
+            PropertyEditorRegion::Input => PropertyEditorRegion::Description,
+            PropertyEditorRegion::Description => PropertyEditorRegion::List,
+        }
+    }
 }
 
 /// Property editor state - extracted for cleaner organization
@@ -59,6 +462,43 @@ pub struct PropertyEditorState {
     pub editing_name: bool, // true = editing name, false = editing value
     pub available_options: Vec<(String, NixOptionInfo)>,
     pub showing_available: bool, // Toggle between configured and available
+    pub region: PropertyEditorRegion,
+    pub description_scroll: u16,
+    pub description_lines: u16, // Wrapped line count of the description (set when drawn)
+    pub description_visible: u16, // Visible height of the description (set when drawn)
+    pub filter: String,         // Fuzzy filter shared by the configured and available lists
+    pub filtering: bool,        // true while typing into the filter
+    pub show_types: bool,       // Show the type badge and [type] label (kept across openings)
+    pub raw_types: bool, // Show the schema's full type string, untruncated (kept across openings)
+    pub option_types: HashMap<String, String>, // Schema type of every option of the entry
+    pub secret_options: HashSet<String>, // Options whose schema description mentions secrets
+    pub reveal_secrets: bool, // Show secret-looking values instead of ••••
+}
+
+/// State for the diff of the unsaved changes against the files on disk
+#[derive(Debug, Default)]
+pub struct DiffPopupState {
+    pub show: bool,
+    pub lines: Vec<DiffLine>,
+    pub scroll_offset: u16,
+    pub visible_lines: u16, // Set when drawn
+    pub save_after: bool,   // Opened by Ctrl+S: confirming saves
+}
+
+/// A line of the diff preview
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffLineKind {
+    File, // The file the following changes are in
+    Context,
+    Added,
+    Removed,
+    Gap, // Unchanged lines left out between changes
 }
 
 /// State for showing a description popup
@@ -86,6 +526,17 @@ impl Default for PropertyEditorState {
             editing_name: true,
             available_options: Vec::new(),
             showing_available: false,
+            region: PropertyEditorRegion::List,
+            description_scroll: 0,
+            description_lines: 0,
+            description_visible: 0,
+            filter: String::new(),
+            filtering: false,
+            show_types: true,
+            raw_types: false,
+            option_types: HashMap::new(),
+            secret_options: HashSet::new(),
+            reveal_secrets: false,
         }
     }
 }
@@ -102,5 +553,12 @@ impl PropertyEditorState {
         self.new_cursor = 0;
         self.editing_name = true;
         self.showing_available = false;
+        self.region = PropertyEditorRegion::List;
+        self.description_scroll = 0;
+        self.filter.clear();
+        self.filtering = false;
+        self.secret_options.clear();
+        self.option_types.clear();
+        self.reveal_secrets = false;
     }
 }
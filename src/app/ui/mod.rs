@@ -10,17 +10,22 @@ use ratatui::{
     Frame,
 };
 
-use crate::app::types::Focus;
-use crate::app::ui::widgets::draw_list;
+use crate::app::types::{DiffLineKind, Focus, TextPrompt};
+use crate::app::ui::widgets::{draw_list, fit_hints, Hint, HINT_SEPARATOR};
 use crate::app::App;
+use crate::config_parser::LIST_OPTIONS;
+
+/// Unsaved edits after which the Save hint nudges towards saving
+const SAVE_REMINDER_CHANGES: usize = 15;
 
 impl App {
     pub fn draw(&mut self, frame: &mut Frame) {
         let area = frame.area();
 
-        // Fixed layout: 3 lines for search, 2 lines for help+status at bottom
-        // Rest goes to the scrollable center columns
-        let search_height = 3;
+        // Fixed layout: 3 lines for search (1 when collapsed and not focused),
+        // 2 lines for help+status at bottom. Rest goes to the scrollable center columns
+        let search_collapsed = self.settings.compact_search && self.focus != Focus::SearchBar;
+        let search_height = if search_collapsed { 1 } else { 3 };
         let bottom_height = 2; // One for help, one for status
 
         let margin = if area.width < 40 || area.height < 10 {
@@ -33,7 +38,7 @@ impl App {
             .direction(Direction::Vertical)
             .margin(margin)
             .constraints([
-                Constraint::Length(search_height), // Search bar (always 3)
+                Constraint::Length(search_height), // Search bar (3, or 1 collapsed)
                 Constraint::Min(1),                // Main content (scrollable)
                 Constraint::Length(bottom_height), // Help + Status (always 2)
             ])
@@ -42,7 +47,11 @@ impl App {
         // Save areas for mouse handling
         self.search_area = chunks[0];
 
-        self.draw_search_bar(frame, chunks[0]);
+        if search_collapsed {
+            self.draw_collapsed_search_bar(frame, chunks[0]);
+        } else {
+            self.draw_search_bar(frame, chunks[0]);
+        }
         self.draw_columns(frame, chunks[1]);
         self.draw_bottom_bar(frame, chunks[2]);
 
@@ -61,6 +70,589 @@ impl App {
         if self.description_popup.show {
             self.draw_description_popup(frame);
         }
+
+        if self.diff_popup.show {
+            self.draw_diff_popup(frame);
+        }
+
+        if self.add_as_prompt.show {
+            self.draw_add_as_prompt(frame);
+        }
+
+        if self.list_options.show {
+            self.draw_list_options(frame);
+        }
+
+        if self.cleanup.show {
+            self.draw_cleanup(frame);
+        }
+
+        if self.move_prompt.show {
+            self.draw_move_prompt(frame);
+        }
+
+        if self.template_prompt.show {
+            self.draw_template_prompt(frame);
+        }
+
+        if self.text_prompt.show {
+            self.draw_text_prompt(frame);
+        }
+
+        if self.confirm_prompt.show {
+            self.draw_confirm_prompt(frame);
+        }
+    }
+
+    fn draw_text_prompt(&self, frame: &mut Frame) {
+        let area = frame.area();
+
+        // A pasted package list gets a few lines to wrap over
+        let input_height = if self.text_prompt.kind == TextPrompt::Packages {
+            4
+        } else {
+            1
+        };
+        let popup_width = 60.min(area.width.saturating_sub(4));
+        let popup_height = 4 + input_height;
+        let popup_area = Rect {
+            x: (area.width.saturating_sub(popup_width)) / 2,
+            y: (area.height.saturating_sub(popup_height)) / 2,
+            width: popup_width,
+            height: popup_height,
+        };
+
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(match self.text_prompt.kind {
+                TextPrompt::Note => format!(" Note on {} ", self.text_prompt.name),
+                TextPrompt::Rename => format!(" Rename {} ", self.text_prompt.name),
+                TextPrompt::ListMember => format!(" Add to {} ", self.text_prompt.name),
+                TextPrompt::Packages => " Add Packages ".to_string(),
+                TextPrompt::Enable => format!(" Enable Expression of {} ", self.text_prompt.name),
+            });
+
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(input_height),
+                Constraint::Length(1),
+                Constraint::Min(1),
+            ])
+            .split(inner);
+
+        let prompt = &self.text_prompt;
+        let (lead, help) = match prompt.kind {
+            TextPrompt::Note => (
+                "# ".to_string(),
+                "Enter: Save (empty removes it) | Esc: Cancel",
+            ),
+            TextPrompt::Rename => (
+                format!(
+                    "{}.",
+                    prompt.entry_type.as_ref().map(|t| t.prefix()).unwrap_or("")
+                ),
+                "Enter: Rename | Esc: Cancel",
+            ),
+            TextPrompt::ListMember => (
+                String::new(),
+                "Enter: Add (quote strings: \"kvm-intel\") | Esc: Cancel",
+            ),
+            TextPrompt::Packages => (
+                String::new(),
+                "Names separated by spaces or commas | Enter: Check and add | Esc: Cancel",
+            ),
+            TextPrompt::Enable => (
+                "enable = ".to_string(),
+                "Any Nix expression | Enter: Save | Esc: Cancel",
+            ),
+        };
+        let input = Line::from(vec![
+            Span::styled(lead, Style::default().fg(Color::DarkGray)),
+            Span::raw(&prompt.buffer[..prompt.cursor]),
+            Span::styled("│", Style::default().fg(Color::Yellow)),
+            Span::raw(&prompt.buffer[prompt.cursor..]),
+        ]);
+        frame.render_widget(
+            Paragraph::new(input).wrap(ratatui::widgets::Wrap { trim: false }),
+            chunks[0],
+        );
+
+        let help = Paragraph::new(help).style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[2]);
+    }
+
+    fn draw_list_options(&self, frame: &mut Frame) {
+        let area = frame.area();
+        let state = &self.list_options;
+        let path = self.list_options_path().unwrap_or_default();
+
+        let selected_style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let mut lines: Vec<Line> = Vec::new();
+        let (title, help) = match state.member {
+            None => {
+                for (i, option) in LIST_OPTIONS.iter().enumerate() {
+                    let count = match self.config.list_option_members(option) {
+                        Some(members) => format!("{} set", members.len()),
+                        None => "not set".to_string(),
+                    };
+                    let text = format!(" {:<40} {}", option, count);
+                    lines.push(if i == state.option {
+                        Line::from(Span::styled(text, selected_style))
+                    } else {
+                        Line::from(text)
+                    });
+                }
+                (
+                    " List Options ".to_string(),
+                    "↑/↓: Select | Enter: Members | a: Add | Esc: Close",
+                )
+            }
+            Some(selected) => {
+                let members = self.config.list_option_members(&path).unwrap_or_default();
+                if members.is_empty() {
+                    lines.push(Line::from(Span::styled(
+                        " (empty, a: add)",
+                        Style::default().fg(Color::DarkGray),
+                    )));
+                }
+                for (i, member) in members.iter().enumerate() {
+                    let text = format!(" {}", member);
+                    lines.push(if i == selected {
+                        Line::from(Span::styled(text, selected_style))
+                    } else {
+                        Line::from(text)
+                    });
+                }
+                let help = if state.property.is_some() {
+                    "↑/↓: Select | a: Add | d: Remove | Esc: Close"
+                } else {
+                    "↑/↓: Select | a: Add | d: Remove | Esc: Back"
+                };
+                (format!(" {} ", path), help)
+            }
+        };
+
+        let popup_width = 64.min(area.width.saturating_sub(4));
+        let popup_height = (lines.len() as u16 + 4).min(area.height.saturating_sub(2));
+        let popup_area = Rect {
+            x: (area.width.saturating_sub(popup_width)) / 2,
+            y: (area.height.saturating_sub(popup_height)) / 2,
+            width: popup_width,
+            height: popup_height,
+        };
+
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(title);
+
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(inner);
+
+        // Keep the selected line in view on short terminals
+        let selected_line = state.member.unwrap_or(state.option) as u16;
+        let scroll = selected_line.saturating_sub(chunks[0].height.saturating_sub(1));
+        frame.render_widget(Paragraph::new(lines).scroll((scroll, 0)), chunks[0]);
+
+        let help = Paragraph::new(help).style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[1]);
+    }
+
+    fn draw_move_prompt(&self, frame: &mut Frame) {
+        let area = frame.area();
+        let state = &self.move_prompt;
+        let prefix = state.entry_type.as_ref().map_or("", |t| t.prefix());
+
+        let selected_style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let lines: Vec<Line> = state
+            .targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| {
+                let text = format!(" {}", self.display_path(target));
+                if i == state.selected {
+                    Line::from(Span::styled(text, selected_style))
+                } else {
+                    Line::from(text)
+                }
+            })
+            .collect();
+
+        let popup_width = 64.min(area.width.saturating_sub(4));
+        let popup_height = (lines.len() as u16 + 4).min(area.height.saturating_sub(2));
+        let popup_area = Rect {
+            x: (area.width.saturating_sub(popup_width)) / 2,
+            y: (area.height.saturating_sub(popup_height)) / 2,
+            width: popup_width,
+            height: popup_height,
+        };
+
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(format!(" Move {}.{} to ", prefix, state.name));
+
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(inner);
+
+        let scroll = (state.selected as u16).saturating_sub(chunks[0].height.saturating_sub(1));
+        frame.render_widget(Paragraph::new(lines).scroll((scroll, 0)), chunks[0]);
+
+        let help = Paragraph::new("↑/↓: Select | Enter: Move | Esc: Cancel")
+            .style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[1]);
+    }
+
+    fn draw_template_prompt(&self, frame: &mut Frame) {
+        let area = frame.area();
+        let state = &self.template_prompt;
+        let entry = self
+            .prop_editor
+            .entry
+            .as_ref()
+            .map(|(name, entry_type)| format!("{}.{}", entry_type.prefix(), name))
+            .unwrap_or_default();
+
+        let selected_style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let mut lines: Vec<Line> = Vec::new();
+        let mut selected_line = 0;
+        for (i, template) in state.templates.iter().enumerate() {
+            if i == state.selected {
+                selected_line = lines.len();
+                lines.push(Line::from(Span::styled(
+                    format!(" {}", template.name),
+                    selected_style,
+                )));
+            } else {
+                lines.push(Line::from(format!(" {}", template.name)));
+            }
+            // What it sets, so it can be checked before applying
+            for (property, value) in &template.properties {
+                lines.push(Line::from(Span::styled(
+                    format!("   {} = {}", property, value),
+                    Style::default().fg(Color::DarkGray),
+                )));
+            }
+        }
+
+        let popup_width = 72.min(area.width.saturating_sub(4));
+        let popup_height = (lines.len() as u16 + 4).min(area.height.saturating_sub(2));
+        let popup_area = Rect {
+            x: (area.width.saturating_sub(popup_width)) / 2,
+            y: (area.height.saturating_sub(popup_height)) / 2,
+            width: popup_width,
+            height: popup_height,
+        };
+
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(format!(" Apply a template to {} ", entry));
+
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(inner);
+
+        let scroll = (selected_line as u16).saturating_sub(chunks[0].height.saturating_sub(1));
+        frame.render_widget(Paragraph::new(lines).scroll((scroll, 0)), chunks[0]);
+
+        let help = Paragraph::new("↑/↓: Select | Enter: Apply | Esc: Cancel")
+            .style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[1]);
+    }
+
+    fn draw_cleanup(&self, frame: &mut Frame) {
+        let area = frame.area();
+        let state = &self.cleanup;
+        let candidates = self.cleanup_candidates();
+
+        let selected_style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let mut lines: Vec<Line> = Vec::new();
+        if candidates.is_empty() {
+            lines.push(Line::from(Span::styled(
+                " No disabled entries with options left",
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+        for (i, (name, entry_type)) in candidates.iter().enumerate() {
+            let marked = state
+                .marked
+                .iter()
+                .any(|(n, t)| n == name && t == entry_type);
+            let options = self
+                .config
+                .get_entry(name, entry_type)
+                .map_or(0, |e| e.properties.len());
+            let text = format!(
+                " [{}] {:<40} {} option{}",
+                if marked { "x" } else { " " },
+                format!("{}.{}", entry_type.prefix(), name),
+                options,
+                if options == 1 { "" } else { "s" }
+            );
+            lines.push(if i == state.selected {
+                Line::from(Span::styled(text, selected_style))
+            } else {
+                Line::from(text)
+            });
+        }
+        let help = if self.snapshot_undo.is_some() {
+            "Space: Mark | a: All | d: Remove | u: Undo | Esc: Close"
+        } else {
+            "Space: Mark | a: All | d: Remove | Esc: Close"
+        };
+
+        let popup_width = 64.min(area.width.saturating_sub(4));
+        let popup_height = (lines.len() as u16 + 4).min(area.height.saturating_sub(2));
+        let popup_area = Rect {
+            x: (area.width.saturating_sub(popup_width)) / 2,
+            y: (area.height.saturating_sub(popup_height)) / 2,
+            width: popup_width,
+            height: popup_height,
+        };
+
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(" Disabled Blocks ");
+
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(inner);
+
+        // Keep the selected line in view on short terminals
+        let scroll = (state.selected as u16).saturating_sub(chunks[0].height.saturating_sub(1));
+        frame.render_widget(Paragraph::new(lines).scroll((scroll, 0)), chunks[0]);
+
+        let help = Paragraph::new(help).style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[1]);
+    }
+
+    fn draw_add_as_prompt(&self, frame: &mut Frame) {
+        let area = frame.area();
+
+        let popup_width = 60.min(area.width.saturating_sub(4));
+        let popup_height = 6;
+        let popup_area = Rect {
+            x: (area.width.saturating_sub(popup_width)) / 2,
+            y: (area.height.saturating_sub(popup_height)) / 2,
+            width: popup_width,
+            height: popup_height,
+        };
+
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(" Add As ");
+
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(2),
+                Constraint::Length(1),
+                Constraint::Min(1),
+            ])
+            .split(inner);
+
+        let question = Paragraph::new(format!("Add {} to the config as:", self.add_as_prompt.name))
+            .style(Style::default().fg(Color::White))
+            .wrap(ratatui::widgets::Wrap { trim: true });
+        frame.render_widget(question, chunks[0]);
+
+        let key_style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let choices = Line::from(vec![
+            Span::raw("  "),
+            Span::styled(" p ", key_style),
+            Span::raw(" package   "),
+            Span::styled(" r ", key_style),
+            Span::raw(" program   "),
+            Span::styled(" s ", key_style),
+            Span::raw(" service"),
+        ]);
+        frame.render_widget(Paragraph::new(choices), chunks[1]);
+
+        let help = Paragraph::new("Esc: Cancel").style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[2]);
+    }
+
+    fn draw_confirm_prompt(&self, frame: &mut Frame) {
+        let area = frame.area();
+
+        let popup_width = 60.min(area.width.saturating_sub(4));
+        let popup_height = 7;
+        let popup_x = (area.width.saturating_sub(popup_width)) / 2;
+        let popup_y = (area.height.saturating_sub(popup_height)) / 2;
+
+        let popup_area = Rect {
+            x: popup_x,
+            y: popup_y,
+            width: popup_width,
+            height: popup_height,
+        };
+
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(" Confirm ");
+
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(2),
+                Constraint::Length(1),
+                Constraint::Min(1),
+            ])
+            .split(inner);
+
+        let question = Paragraph::new(self.confirm_prompt.message.as_str())
+            .style(Style::default().fg(Color::White))
+            .wrap(ratatui::widgets::Wrap { trim: true });
+        frame.render_widget(question, chunks[0]);
+
+        let yes_style = if self.confirm_prompt.selected == 0 {
+            Style::default().fg(Color::Black).bg(Color::Green)
+        } else {
+            Style::default().fg(Color::Green)
+        };
+        let no_style = if self.confirm_prompt.selected == 1 {
+            Style::default().fg(Color::Black).bg(Color::Red)
+        } else {
+            Style::default().fg(Color::Red)
+        };
+
+        let buttons = Line::from(vec![
+            Span::raw("  "),
+            Span::styled(" Yes (y) ", yes_style),
+            Span::raw("   "),
+            Span::styled(" No (n) ", no_style),
+            Span::raw("  "),
+        ]);
+        frame.render_widget(Paragraph::new(buttons), chunks[1]);
+
+        let help = Paragraph::new("←/→: Select | Enter: Confirm | Esc: Cancel")
+            .style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[2]);
+    }
+
+    fn draw_diff_popup(&mut self, frame: &mut Frame) {
+        let area = frame.area();
+
+        let popup_width = (area.width * 9 / 10).min(area.width.saturating_sub(4));
+        let popup_height = (area.height * 8 / 10).min(area.height.saturating_sub(4));
+        let popup_area = Rect {
+            x: (area.width.saturating_sub(popup_width)) / 2,
+            y: (area.height.saturating_sub(popup_height)) / 2,
+            width: popup_width,
+            height: popup_height,
+        };
+
+        frame.render_widget(Clear, popup_area);
+
+        let title = if self.diff_popup.save_after {
+            " Save these changes? "
+        } else {
+            " Unsaved changes "
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(title);
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        // Reserve 1 line for the key hints
+        let visible_lines = inner.height.saturating_sub(1);
+        self.diff_popup.visible_lines = visible_lines;
+
+        let lines: Vec<Line> = self
+            .diff_popup
+            .lines
+            .iter()
+            .skip(self.diff_popup.scroll_offset as usize)
+            .take(visible_lines as usize)
+            .map(|line| {
+                let (prefix, style) = match line.kind {
+                    DiffLineKind::File => (
+                        "",
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(ratatui::style::Modifier::BOLD),
+                    ),
+                    DiffLineKind::Gap => ("", Style::default().fg(Color::Cyan)),
+                    DiffLineKind::Context => (" ", Style::default().fg(Color::Gray)),
+                    DiffLineKind::Added => ("+", Style::default().fg(Color::Green)),
+                    DiffLineKind::Removed => ("-", Style::default().fg(Color::Red)),
+                };
+                Line::from(Span::styled(format!("{}{}", prefix, line.text), style))
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines),
+            Rect {
+                height: visible_lines,
+                ..inner
+            },
+        );
+
+        let hint = if self.diff_popup.save_after {
+            "Enter/y Save  Esc/n Cancel  j/k PgUp/PgDn Scroll"
+        } else {
+            "Esc Close  j/k PgUp/PgDn Scroll"
+        };
+        frame.render_widget(
+            Paragraph::new(Line::from(Span::styled(
+                hint,
+                Style::default().fg(Color::DarkGray),
+            ))),
+            Rect {
+                y: inner.y + visible_lines,
+                height: 1,
+                ..inner
+            },
+        );
     }
 
     fn draw_description_popup(&mut self, frame: &mut Frame) {
@@ -148,7 +740,7 @@ impl App {
         let area = frame.area();
 
         let popup_width = 60.min(area.width.saturating_sub(4));
-        let popup_height = 9;
+        let popup_height = 11;
         let popup_x = (area.width.saturating_sub(popup_width)) / 2;
         let popup_y = (area.height.saturating_sub(popup_height)) / 2;
 
@@ -173,19 +765,40 @@ impl App {
             .direction(Direction::Vertical)
             .constraints([
                 Constraint::Length(2),
+                Constraint::Length(1),
+                Constraint::Length(1),
                 Constraint::Length(2),
                 Constraint::Length(1),
                 Constraint::Min(1),
             ])
             .split(inner);
 
-        let question = Paragraph::new("Rebuild the system now?\n(sudo nixos-rebuild switch)")
+        let verbosity = self.settings.rebuild_verbosity;
+        let command = format!("sudo nixos-rebuild {}", self.rebuild_args().join(" "));
+        let question = Paragraph::new(format!("Rebuild the system now?\n({})", command))
             .style(Style::default().fg(Color::White));
         frame.render_widget(question, chunks[0]);
 
+        let mode_line = Line::from(vec![
+            Span::styled("Action: ", Style::default().fg(Color::DarkGray)),
+            Span::styled(
+                self.rebuild_prompt.mode.subcommand(),
+                Style::default().fg(Color::Yellow),
+            ),
+            Span::styled(" (←/→: change)", Style::default().fg(Color::DarkGray)),
+        ]);
+        frame.render_widget(Paragraph::new(mode_line), chunks[1]);
+
+        let verbosity_line = Line::from(vec![
+            Span::styled("Output: ", Style::default().fg(Color::DarkGray)),
+            Span::styled(verbosity.label(), Style::default().fg(Color::Yellow)),
+            Span::styled(" (v: change)", Style::default().fg(Color::DarkGray)),
+        ]);
+        frame.render_widget(Paragraph::new(verbosity_line), chunks[2]);
+
         let info = Paragraph::new("The terminal will show live build output.")
             .style(Style::default().fg(Color::DarkGray));
-        frame.render_widget(info, chunks[1]);
+        frame.render_widget(info, chunks[3]);
 
         let yes_style = if self.rebuild_prompt.selected == 0 {
             Style::default().fg(Color::Black).bg(Color::Green)
@@ -206,11 +819,22 @@ impl App {
             Span::raw("  "),
         ]);
         let buttons_para = Paragraph::new(buttons);
-        frame.render_widget(buttons_para, chunks[2]);
+        frame.render_widget(buttons_para, chunks[4]);
 
-        let help = Paragraph::new("←/→: Select | Enter: Confirm | Esc: Cancel")
-            .style(Style::default().fg(Color::DarkGray));
-        frame.render_widget(help, chunks[3]);
+        let hints = [
+            Hint::new("←/→: Action", "←/→", 1),
+            Hint::new("Tab: Select", "Tab", 1),
+            Hint::new("v: Verbosity", "v: Output", 2),
+            Hint::new("Enter: Confirm", "Enter", 3),
+            Hint::new("Esc: Cancel", "Esc", 3),
+        ];
+        let help_text = fit_hints(&hints, chunks[5].width as usize)
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect::<Vec<_>>()
+            .join(HINT_SEPARATOR);
+        let help = Paragraph::new(help_text).style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, chunks[5]);
     }
 
     fn draw_search_bar(&self, frame: &mut Frame, area: Rect) {
@@ -228,12 +852,18 @@ impl App {
             border::PLAIN
         };
 
-        let title = if area.width > 40 {
-            " Search (Enter to search, Esc to clear) "
+        let mode = self.search_mode.label();
+        let title = if area.width > 60 {
+            format!(
+                " Search {} (Enter to search, Esc to clear, F2: mode) ",
+                mode
+            )
+        } else if area.width > 40 {
+            format!(" Search {} (F2: mode) ", mode)
         } else if area.width > 20 {
-            " Search "
+            " Search ".to_string()
         } else {
-            ""
+            String::new()
         };
 
         let search_block = Block::default()
@@ -257,20 +887,43 @@ impl App {
         frame.render_widget(search_text, area);
     }
 
+    /// One-line search bar shown while it's collapsed and not focused
+    fn draw_collapsed_search_bar(&self, frame: &mut Frame, area: Rect) {
+        let hint = if self.search_query.is_empty() {
+            Span::styled("/ to search", Style::default().fg(Color::DarkGray))
+        } else {
+            Span::raw(self.search_query.clone())
+        };
+        let line = Line::from(vec![
+            Span::styled(
+                format!("Search {}: ", self.search_mode.label()),
+                Style::default().fg(Color::DarkGray),
+            ),
+            hint,
+        ]);
+        frame.render_widget(Paragraph::new(line), area);
+    }
+
     fn draw_columns(&mut self, frame: &mut Frame, area: Rect) {
-        let columns = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([
+        let constraints = if self.show_users {
+            vec![Constraint::Percentage(25); 4]
+        } else {
+            vec![
                 Constraint::Percentage(33),
                 Constraint::Percentage(34),
                 Constraint::Percentage(33),
-            ])
+            ]
+        };
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(constraints)
             .split(area);
 
         // Save column areas for mouse handling
         self.programs_area = columns[0];
         self.services_area = columns[1];
         self.packages_area = columns[2];
+        self.users_area = columns.get(3).copied().unwrap_or_default();
 
         // Draw programs
         draw_list(
@@ -280,6 +933,7 @@ impl App {
             &self.programs,
             &mut self.program_state,
             self.focus == Focus::Programs,
+            self.settings.inline_descriptions,
         );
 
         // Draw services
@@ -290,6 +944,7 @@ impl App {
             &self.services,
             &mut self.service_state,
             self.focus == Focus::Services,
+            self.settings.inline_descriptions,
         );
 
         // Draw packages
@@ -300,7 +955,21 @@ impl App {
             &self.packages,
             &mut self.package_state,
             self.focus == Focus::Packages,
+            self.settings.inline_descriptions,
         );
+
+        // Draw users
+        if self.show_users {
+            draw_list(
+                frame,
+                columns[3],
+                "Users",
+                &self.users,
+                &mut self.user_state,
+                self.focus == Focus::Users,
+                self.settings.inline_descriptions,
+            );
+        }
     }
 
     fn draw_bottom_bar(&self, frame: &mut Frame, area: Rect) {
@@ -323,22 +992,137 @@ impl App {
             help_style
         };
 
-        let help_line = Line::from(vec![
-            Span::styled("F1: Help | Ctrl+S: ", help_style),
-            Span::styled(if self.is_dirty { "Save*" } else { "Save" }, save_style),
-            Span::styled(
-                " | Ctrl+Q: Quit | Tab: Switch | Space: Toggle | e: Edit props",
-                help_style,
+        // Most important bindings survive the longest on narrow terminals
+        let (save, save_full) = match self.unsaved_changes {
+            _ if !self.is_dirty => ("Save".to_string(), "Save".to_string()),
+            0 => ("Save*".to_string(), "Save*".to_string()),
+            n if n >= SAVE_REMINDER_CHANGES => (
+                format!("Save* ({})", n),
+                format!("Save* ({} changes, time to save?)", n),
             ),
-        ]);
-        let help_bar = Paragraph::new(help_line);
+            n => (
+                format!("Save* ({})", n),
+                format!("Save* ({} change{})", n, if n == 1 { "" } else { "s" }),
+            ),
+        };
+        let hints = [
+            Hint::new("F1: Help", "F1", 2),
+            Hint::new(format!("Ctrl+S: {}", save_full), format!("^S: {}", save), 3),
+            Hint::new("Ctrl+Q: Quit", "^Q: Quit", 3),
+            Hint::new("Tab: Switch", "Tab", 1),
+            Hint::new("Space: Toggle", "Spc: Toggle", 2),
+            Hint::new("e: Edit props", "e: Edit", 1),
+        ];
+        const SAVE_HINT: usize = 1;
+
+        let mut spans = Vec::new();
+        for (n, (i, text)) in fit_hints(&hints, lines[0].width as usize)
+            .into_iter()
+            .enumerate()
+        {
+            if n > 0 {
+                spans.push(Span::styled(HINT_SEPARATOR, help_style));
+            }
+            let style = if i == SAVE_HINT {
+                save_style
+            } else {
+                help_style
+            };
+            spans.push(Span::styled(text, style));
+        }
+        let help_bar = Paragraph::new(Line::from(spans));
         frame.render_widget(help_bar, lines[0]);
 
+        // Nothing can be changed, so say so at all times
+        let status_area = if self.read_only {
+            let indicator = " READ-ONLY ";
+            let parts = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Min(0),
+                    Constraint::Length(indicator.len() as u16),
+                ])
+                .split(lines[1]);
+            frame.render_widget(
+                Paragraph::new(indicator).style(
+                    Style::default()
+                        .fg(Color::White)
+                        .bg(Color::Red)
+                        .add_modifier(ratatui::style::Modifier::BOLD),
+                ),
+                parts[1],
+            );
+            parts[0]
+        } else {
+            lines[1]
+        };
+
+        // Reserve the right end of the status line for the pending rebuild indicator
+        let status_area = if self.rebuild_pending {
+            let indicator = " ⟳ Unbuilt changes ";
+            let parts = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Min(0),
+                    Constraint::Length(indicator.chars().count() as u16),
+                ])
+                .split(status_area);
+            let indicator_bar = Paragraph::new(indicator).style(
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Magenta)
+                    .add_modifier(ratatui::style::Modifier::BOLD),
+            );
+            frame.render_widget(indicator_bar, parts[1]);
+            parts[0]
+        } else {
+            status_area
+        };
+
+        // Progress of the background check of commented-out packages
+        let status_area = match self.verify_progress {
+            Some((checked, total)) => {
+                let indicator = format!(" verifying {}/{} ", checked, total);
+                let parts = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Min(0),
+                        Constraint::Length(indicator.len() as u16),
+                    ])
+                    .split(status_area);
+                frame.render_widget(
+                    Paragraph::new(indicator).style(Style::default().fg(Color::DarkGray)),
+                    parts[1],
+                );
+                parts[0]
+            }
+            None => status_area,
+        };
+
+        // A quiet reminder that clicks and scrolling currently go to the terminal
+        let status_area = if self.mouse_capture {
+            status_area
+        } else {
+            let indicator = " mouse off ";
+            let parts = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([
+                    Constraint::Min(0),
+                    Constraint::Length(indicator.len() as u16),
+                ])
+                .split(status_area);
+            frame.render_widget(
+                Paragraph::new(indicator).style(Style::default().fg(Color::DarkGray)),
+                parts[1],
+            );
+            parts[0]
+        };
+
         // Status line (yellow when there's a message, otherwise empty)
         if let Some(ref msg) = self.status_message {
             let status_style = Style::default().fg(Color::Yellow);
             let status_bar = Paragraph::new(msg.as_str()).style(status_style);
-            frame.render_widget(status_bar, lines[1]);
+            frame.render_widget(status_bar, status_area);
         }
     }
 
@@ -358,34 +1142,75 @@ impl App {
             "  Ctrl+Q / Ctrl+C  Quit",
             "  Ctrl+S           Save config",
             "  F1               Toggle help",
+            "  F3               Mouse capture on/off (to select text)",
             "",
             "  Search Bar:",
             "  ──────────────────────────",
             "  Enter            Perform search",
             "  Esc              Clear search",
+            "  ↑/↓              Earlier/later searches",
+            "  F2               Packages/options mode",
+            "  F5               Refresh results, skipping the cache",
             "  Tab / Down       Move to lists",
+            "  Shift+Tab        Move to the last column",
             "",
             "  Lists:",
             "  ──────────────────────────",
             "  Up/Down          Navigate",
             "  Space/Enter      Toggle item",
             "  e                Edit properties",
+            "  a                Add result as package/program/service",
+            "  #                Add/edit a note (config comment)",
+            "  R                Rename program/service/user",
+            "  E                Edit the enable expression",
+            "  O                List options (ports, modules, ...)",
+            "  X                Clean up disabled blocks",
+            "  F                Move between host and shared file",
+            "  P                Add several packages at once",
+            "  u                Undo the last package list, template or cleanup",
+            "  r                Find references",
+            "  n/N              Jump to next/previous reference",
+            "  A                Toggle all visible",
+            "  G                Git diff (--git)",
+            "  V                View the unsaved changes as a diff",
+            "  S                Preview the diff on Ctrl+S on/off",
+            "  C                Copy change summary",
+            "  J                Copy the parsed entry as JSON",
+            "  m                Status message history",
+            "  H                Hide/show disabled entries",
+            "  U                Show/hide the Users column",
+            "  o                Sort results by relevance/name",
+            "  i                Inline descriptions on/off",
+            "  b                Collapse search bar when unused",
+            "  L                Target package list (if several)",
+            "  g                Group services by prefix",
+            "  M                Only services with a NixOS module",
+            "  z                Collapse/expand service group",
             "  Tab              Next column",
             "  Shift+Tab        Previous column",
+            "  Ctrl+O           Back to the last focused area",
+            "  [ / ]            Prev/next column (same position)",
             "  / or Esc         Go to search",
             "",
             "  Property Editor:",
             "  ──────────────────────────",
             "  Tab              Toggle configured/available",
+            "  Shift+Tab        Cycle list/input/description",
             "  e/Enter          Edit/Add property",
             "  a/n              Add property (manual)",
             "  d/Del            Delete property",
+            "  O                Add/remove list elements",
+            "  T                Full schema types",
+            "  P                Apply a template",
+            "  u                Undo the last template, package list or cleanup",
             "  Esc/q            Close editor",
             "",
             "  Legend:",
             "  ──────────────────────────",
             "  [✓]  Enabled     ⚙ Has properties",
             "  [ ]  Disabled    + Not in config",
+            "  [?]  Set by an expression, see E",
+            "  (configured)     Search match already enabled",
             "",
             "  Press any key to close",
         ];
@@ -1,6 +1,7 @@
 use ratatui::{
     layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
+    symbols::border,
     text::{Line, Span},
     widgets::{
         Block, Borders, Clear, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation,
@@ -9,6 +10,8 @@ use ratatui::{
     Frame,
 };
 
+use crate::app::property_editor::{default_value_for_option, structured_value_error};
+use crate::app::types::PropertyEditorRegion;
 use crate::app::ui::widgets::{calculate_scrollbar_position, type_indicator_for_nix_type};
 use crate::app::App;
 use crate::config_parser::PropertyType;
@@ -39,6 +42,7 @@ impl App {
                 crate::config_parser::EntryType::Program => "program",
                 crate::config_parser::EntryType::Service => "service",
                 crate::config_parser::EntryType::Package => "package",
+                crate::config_parser::EntryType::User => "user",
             };
             format!(" Properties: {}.{} ", type_str, name)
         } else {
@@ -74,16 +78,27 @@ impl App {
         self.draw_property_input(frame, chunks[2]);
 
         // Draw help text
-        let help_text = if self.prop_editor.adding_new {
-            "Tab: Switch field | Enter: Save | Esc: Cancel"
-        } else if self.prop_editor.edit_state.is_some() {
-            "Enter: Save | Esc: Cancel"
-        } else if self.prop_editor.showing_available {
-            "Tab: Configured | Enter/Space: Add | Esc/q: Close"
-        } else {
-            "Tab: Available | e/Enter: Edit | a/n: Add | d/Del: Delete | Esc/q: Close"
+        let help_text = match self.prop_editor.region {
+            PropertyEditorRegion::Description => {
+                "↑/↓ PgUp/PgDn: Scroll description | Esc: Back to list | S-Tab: Next region"
+            }
+            PropertyEditorRegion::Input if self.prop_editor.adding_new => {
+                "Tab: Switch field | Enter: Save | Esc: Cancel | S-Tab: Next region"
+            }
+            PropertyEditorRegion::Input if self.prop_editor.edit_state.is_some() => {
+                "Enter: Save | Esc: Cancel | S-Tab: Next region"
+            }
+            _ if self.prop_editor.filtering => {
+                "Type to filter | ↑/↓: Navigate | Enter: Done | Esc: Clear filter"
+            }
+            _ if self.prop_editor.showing_available => {
+                "Tab: Configured | Enter/Space: Add | O: List | P: Template | /: Filter | t/T: Types | v: Secrets | S-Tab: Region | Esc/q: Close"
+            }
+            _ => "Tab: Available | e/Enter: Edit | a/n: Add | d/Del: Delete | O: List | P: Template | /: Filter | t/T: Types | v: Secrets | S-Tab: Region | Esc/q: Close",
         };
-        let help = Paragraph::new(help_text).style(Style::default().fg(Color::DarkGray));
+        let help = Paragraph::new(help_text)
+            .style(Style::default().fg(Color::DarkGray))
+            .wrap(ratatui::widgets::Wrap { trim: true });
         frame.render_widget(help, chunks[3]);
     }
 
@@ -95,18 +110,40 @@ impl App {
         }
     }
 
+    /// Border style and set for a property editor region, highlighting the focused one
+    fn region_border(&self, region: PropertyEditorRegion, color: Color) -> (Style, border::Set) {
+        if self.prop_editor.region == region {
+            (Style::default().fg(Color::Yellow), border::THICK)
+        } else {
+            (Style::default().fg(color), border::PLAIN)
+        }
+    }
+
     /// Draw description of the currently selected property/option
-    fn draw_property_description(&self, frame: &mut Frame, area: Rect) {
+    fn draw_property_description(&mut self, frame: &mut Frame, area: Rect) {
         let description = if self.prop_editor.showing_available {
             // Get description from available options
-            self.prop_editor
-                .list_state
-                .selected()
+            self.selected_property_index()
                 .and_then(|idx| self.prop_editor.available_options.get(idx))
                 .map(|(name, info)| {
+                    // Preview the exact line that adding this option would write
+                    let preview = match self.prop_editor.entry {
+                        Some((ref entry_name, ref entry_type)) => format!(
+                            "Adds: {}.{}.{} = {};",
+                            entry_type.prefix(),
+                            entry_name,
+                            name,
+                            self.config
+                                .format_property_value(&default_value_for_option(info))
+                        ),
+                        None => String::new(),
+                    };
+                    // The list truncates long types (unless T is on), so give the full one here
+                    let preview = format!("{}\nType: {}", preview, info.option_type);
+
                     let desc = info.description.trim();
                     if desc.is_empty() {
-                        format!("{}: No description available", name)
+                        format!("{}\n{}: No description available", preview, name)
                     } else {
                         // Clean up NixOS markdown formatting
                         let clean = desc
@@ -114,7 +151,7 @@ impl App {
                             .replace("{file}", "")
                             .replace("`", "'")
                             .replace('\n', " ");
-                        format!("{}: {}", name, clean)
+                        format!("{}\n{}: {}", preview, name, clean)
                     }
                 })
                 .unwrap_or_else(|| "Select an option to see its description".to_string())
@@ -123,31 +160,53 @@ impl App {
             // or show the property name and value
             if let Some((ref entry_name, ref entry_type)) = self.prop_editor.entry {
                 if let Some(entry) = self.config.get_entry(entry_name, entry_type) {
-                    self.prop_editor
-                        .list_state
-                        .selected()
+                    self.selected_property_index()
                         .and_then(|idx| entry.properties.get(idx))
                         .map(|prop| {
+                            let label = match prop.property_type {
+                                PropertyType::Bool => "boolean",
+                                PropertyType::String => "string",
+                                PropertyType::Int => "integer",
+                                PropertyType::Path => "path",
+                                PropertyType::List => "list",
+                                PropertyType::AttrSet => {
+                                    if prop.name.contains('.') {
+                                        "nested attribute"
+                                    } else {
+                                        "attribute set"
+                                    }
+                                }
+                                PropertyType::Expression => "expression",
+                            };
+                            let type_text = match self.prop_editor.option_types.get(&prop.name) {
+                                Some(option_type) if self.prop_editor.raw_types => option_type,
+                                _ => label,
+                            };
                             // Show property info with type annotation
-                            format!(
+                            let info = format!(
                                 "{} = {} ({})",
                                 prop.name,
-                                prop.value,
-                                match prop.property_type {
-                                    PropertyType::Bool => "boolean",
-                                    PropertyType::String => "string",
-                                    PropertyType::Int => "integer",
-                                    PropertyType::Path => "path",
-                                    PropertyType::List => "list",
-                                    PropertyType::AttrSet =>
-                                        if prop.name.contains('.') {
-                                            "nested attribute"
-                                        } else {
-                                            "attribute set"
-                                        },
-                                    PropertyType::Expression => "expression",
-                                }
-                            )
+                                if self.is_masked(prop) {
+                                    "•••• (v: reveal)"
+                                } else {
+                                    &prop.value
+                                },
+                                type_text
+                            );
+                            // Paths are shown resolved, relative ones against the config's directory
+                            match self.config.resolve_path_value(&prop.value) {
+                                Some(path) if !self.is_masked(prop) => format!(
+                                    "{}\nResolves to: {}{}",
+                                    info,
+                                    path.display(),
+                                    if path.exists() {
+                                        ""
+                                    } else {
+                                        " (doesn't exist)"
+                                    }
+                                ),
+                                _ => info,
+                            }
                         })
                         .unwrap_or_else(|| "Select a property to see details".to_string())
                 } else {
@@ -158,19 +217,32 @@ impl App {
             }
         };
 
+        let (border_style, border_set) =
+            self.region_border(PropertyEditorRegion::Description, Color::DarkGray);
         let block = Block::default()
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::DarkGray))
+            .border_set(border_set)
+            .border_style(border_style)
             .title(" Description ");
 
         // Wrap text to fit in area
         let inner_width = area.width.saturating_sub(2) as usize;
-        let wrapped = textwrap::fill(&description, inner_width);
+        let wrapped = textwrap::fill(&description, inner_width.max(1));
+
+        // Remember the size so the description region can be scrolled
+        self.prop_editor.description_lines = wrapped.lines().count() as u16;
+        self.prop_editor.description_visible = area.height.saturating_sub(2);
+        let max_scroll = self
+            .prop_editor
+            .description_lines
+            .saturating_sub(self.prop_editor.description_visible);
+        self.prop_editor.description_scroll = self.prop_editor.description_scroll.min(max_scroll);
 
         let para = Paragraph::new(wrapped)
             .block(block)
             .style(Style::default().fg(Color::Gray))
-            .wrap(ratatui::widgets::Wrap { trim: true });
+            .wrap(ratatui::widgets::Wrap { trim: true })
+            .scroll((self.prop_editor.description_scroll, 0));
 
         frame.render_widget(para, area);
     }
@@ -185,11 +257,30 @@ impl App {
             Vec::new()
         };
 
+        let visible = self.visible_property_indices();
+
         // Add title block
-        let title = format!(" Configured ({}) - Tab for available ", properties.len());
+        let title = if self.prop_editor.filter.is_empty() && !self.prop_editor.filtering {
+            format!(" Configured ({}) - Tab for available ", properties.len())
+        } else {
+            format!(
+                " Configured ({}/{}) - Filter: {}{} ",
+                visible.len(),
+                properties.len(),
+                self.prop_editor.filter,
+                if self.prop_editor.filtering {
+                    "│"
+                } else {
+                    ""
+                }
+            )
+        };
+        let (border_style, border_set) =
+            self.region_border(PropertyEditorRegion::List, Color::Green);
         let block = Block::default()
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Green))
+            .border_set(border_set)
+            .border_style(border_style)
             .title(title);
         let inner = block.inner(area);
         frame.render_widget(block, area);
@@ -204,16 +295,24 @@ impl App {
                     .fg(Color::DarkGray)
                     .add_modifier(Modifier::ITALIC),
             )]))]
+        } else if visible.is_empty() {
+            vec![ListItem::new(Line::from(vec![Span::styled(
+                "  (no properties match the filter)",
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            )]))]
         } else {
-            properties
+            visible
                 .iter()
                 .enumerate()
-                .map(|(idx, prop)| {
+                .map(|(row, &idx)| {
+                    let prop = &properties[idx];
                     // Determine type indicator - prefer schema info if we have it
                     let (type_indicator, type_label) =
                         self.get_property_type_info(&prop.name, &prop.property_type);
 
-                    let is_selected = self.prop_editor.list_state.selected() == Some(idx);
+                    let is_selected = self.prop_editor.list_state.selected() == Some(row);
                     let is_editing = self
                         .prop_editor
                         .edit_state
@@ -230,6 +329,8 @@ impl App {
                         } else {
                             prop.value.clone()
                         }
+                    } else if self.is_masked(prop) {
+                        "••••".to_string()
                     } else {
                         // Truncate long values
                         if prop.value.len() > 30 {
@@ -249,19 +350,27 @@ impl App {
                         Style::default().fg(Color::Gray)
                     };
 
-                    ListItem::new(Line::from(vec![
-                        Span::styled(
+                    let mut spans = Vec::new();
+                    if self.prop_editor.show_types {
+                        spans.push(Span::styled(
                             format!("{} ", type_indicator),
                             Style::default().fg(Color::Cyan),
-                        ),
-                        Span::styled(format!("{}", prop.name), style.add_modifier(Modifier::BOLD)),
-                        Span::styled(
+                        ));
+                    }
+                    spans.push(Span::styled(
+                        format!("{}", prop.name),
+                        style.add_modifier(Modifier::BOLD),
+                    ));
+                    if self.prop_editor.show_types {
+                        spans.push(Span::styled(
                             format!(" [{}]", type_label),
                             Style::default().fg(Color::DarkGray),
-                        ),
-                        Span::styled(" = ", style),
-                        Span::styled(value_display, style),
-                    ]))
+                        ));
+                    }
+                    spans.push(Span::styled(" = ", style));
+                    spans.push(Span::styled(value_display, style));
+
+                    ListItem::new(Line::from(spans))
                 })
                 .collect()
         };
@@ -312,6 +421,16 @@ impl App {
         prop_name: &str,
         fallback_type: &PropertyType,
     ) -> (&'static str, String) {
+        // The exact schema type, when asked for
+        if self.prop_editor.raw_types {
+            if let Some(option_type) = self.prop_editor.option_types.get(prop_name) {
+                return (
+                    type_indicator_for_nix_type(option_type),
+                    option_type.clone(),
+                );
+            }
+        }
+
         // Try to get info from schema via cached available_options or infer from property type
         // First check if this property appears in available_options (shouldn't, but just in case)
         if let Some((_, info)) = self
@@ -365,14 +484,33 @@ impl App {
     }
 
     fn draw_available_options(&mut self, frame: &mut Frame, area: Rect) {
+        let visible = self.visible_property_indices();
+
         // Add title block
-        let title = format!(
-            " Available ({}) - Tab for configured ",
-            self.prop_editor.available_options.len()
-        );
+        let title = if self.prop_editor.filter.is_empty() && !self.prop_editor.filtering {
+            format!(
+                " Available ({}) - Tab for configured ",
+                self.prop_editor.available_options.len()
+            )
+        } else {
+            format!(
+                " Available ({}/{}) - Filter: {}{} ",
+                visible.len(),
+                self.prop_editor.available_options.len(),
+                self.prop_editor.filter,
+                if self.prop_editor.filtering {
+                    "│"
+                } else {
+                    ""
+                }
+            )
+        };
+        let (border_style, border_set) =
+            self.region_border(PropertyEditorRegion::List, Color::Blue);
         let block = Block::default()
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::Blue))
+            .border_set(border_set)
+            .border_style(border_style)
             .title(title);
         let inner = block.inner(area);
         frame.render_widget(block, area);
@@ -387,15 +525,22 @@ impl App {
                     .fg(Color::DarkGray)
                     .add_modifier(Modifier::ITALIC),
             )]))]
+        } else if visible.is_empty() {
+            vec![ListItem::new(Line::from(vec![Span::styled(
+                "  (no options match the filter)",
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            )]))]
         } else {
-            self.prop_editor
-                .available_options
+            visible
                 .iter()
                 .enumerate()
-                .map(|(idx, (opt_name, opt_info))| {
+                .map(|(row, &idx)| {
+                    let (opt_name, opt_info) = &self.prop_editor.available_options[idx];
                     let type_indicator = type_indicator_for_nix_type(&opt_info.option_type);
 
-                    let is_selected = self.prop_editor.list_state.selected() == Some(idx);
+                    let is_selected = self.prop_editor.list_state.selected() == Some(row);
 
                     // Get default value for display
                     let default_str = opt_info
@@ -416,12 +561,13 @@ impl App {
                         })
                         .unwrap_or_else(|| "—".to_string());
 
-                    // Truncate type for display
-                    let type_display = if opt_info.option_type.len() > 20 {
-                        format!("{}...", &opt_info.option_type[..17])
-                    } else {
-                        opt_info.option_type.clone()
-                    };
+                    // Truncate type for display, unless the full type was asked for
+                    let type_display =
+                        if !self.prop_editor.raw_types && opt_info.option_type.len() > 20 {
+                            format!("{}...", &opt_info.option_type[..17])
+                        } else {
+                            opt_info.option_type.clone()
+                        };
 
                     let style = if is_selected {
                         Style::default().fg(Color::White)
@@ -429,21 +575,29 @@ impl App {
                         Style::default().fg(Color::Gray)
                     };
 
-                    ListItem::new(Line::from(vec![
-                        Span::styled(
+                    let mut spans = Vec::new();
+                    if self.prop_editor.show_types {
+                        spans.push(Span::styled(
                             format!("{} ", type_indicator),
                             Style::default().fg(Color::Blue),
-                        ),
-                        Span::styled(opt_name.clone(), style.add_modifier(Modifier::BOLD)),
-                        Span::styled(
+                        ));
+                    }
+                    spans.push(Span::styled(
+                        opt_name.clone(),
+                        style.add_modifier(Modifier::BOLD),
+                    ));
+                    if self.prop_editor.show_types {
+                        spans.push(Span::styled(
                             format!(" [{}]", type_display),
                             Style::default().fg(Color::DarkGray),
-                        ),
-                        Span::styled(
-                            format!(" = {}", default_str),
-                            Style::default().fg(Color::Cyan),
-                        ),
-                    ]))
+                        ));
+                    }
+                    spans.push(Span::styled(
+                        format!(" = {}", default_str),
+                        Style::default().fg(Color::Cyan),
+                    ));
+
+                    ListItem::new(Line::from(spans))
                 })
                 .collect()
         };
@@ -489,6 +643,8 @@ impl App {
     }
 
     fn draw_property_input(&self, frame: &mut Frame, area: Rect) {
+        let input_focused = self.prop_editor.region == PropertyEditorRegion::Input;
+
         if self.prop_editor.adding_new {
             // Show input fields for new property
             let chunks = Layout::default()
@@ -500,8 +656,8 @@ impl App {
                 ])
                 .split(area);
 
-            // Name field
-            let name_style = if self.prop_editor.editing_name {
+            // Name field (only highlighted while the input region has focus)
+            let name_style = if self.prop_editor.editing_name && input_focused {
                 Style::default().fg(Color::Yellow)
             } else {
                 Style::default().fg(Color::White)
@@ -524,8 +680,11 @@ impl App {
             let eq = Paragraph::new(" = ").style(Style::default().fg(Color::White));
             frame.render_widget(eq, chunks[1]);
 
-            // Value field
-            let value_style = if !self.prop_editor.editing_name {
+            // Value field (red while brackets are unbalanced)
+            let bracket_error = structured_value_error(&self.prop_editor.new_value, None);
+            let value_style = if bracket_error.is_some() {
+                Style::default().fg(Color::Red)
+            } else if !self.prop_editor.editing_name && input_focused {
                 Style::default().fg(Color::Yellow)
             } else {
                 Style::default().fg(Color::White)
@@ -540,10 +699,41 @@ impl App {
             let value_block = Block::default()
                 .borders(Borders::ALL)
                 .border_style(value_style)
-                .title(" Value ");
+                .title(match bracket_error {
+                    Some(problem) => format!(" Value - {} ", problem),
+                    None => " Value ".to_string(),
+                });
             let value_para = Paragraph::new(value_display).block(value_block);
             frame.render_widget(value_para, chunks[2]);
-        } else if self.prop_editor.edit_state.is_none() {
+        } else if let Some(ref edit_state) = self.prop_editor.edit_state {
+            // Live bracket balance check for the value being edited inline
+            let property_type = self
+                .prop_editor
+                .entry
+                .as_ref()
+                .and_then(|(name, etype)| self.config.get_entry(name, etype))
+                .and_then(|e| e.properties.get(edit_state.property_index))
+                .map(|p| p.property_type.clone());
+            let (text, color) =
+                match structured_value_error(&edit_state.edit_buffer, property_type.as_ref()) {
+                    Some(problem) => (
+                        format!("✗ Unbalanced brackets: {} - fix before saving", problem),
+                        Color::Red,
+                    ),
+                    None => (
+                        "Editing value in the list above (Enter: Save, Esc: Cancel)".to_string(),
+                        Color::DarkGray,
+                    ),
+                };
+            let status = Paragraph::new(text)
+                .style(Style::default().fg(color))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(color)),
+                );
+            frame.render_widget(status, area);
+        } else {
             // Show hint when not editing
             let hint = Paragraph::new("Press 'a' or 'n' to add a new property")
                 .style(
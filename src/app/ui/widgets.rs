@@ -211,7 +211,13 @@ pub fn draw_list(
     entries: &[ListEntry],
     state: &mut ListState,
     is_focused: bool,
+    show_descriptions: bool,
 ) {
+    // Nothing fits in a collapsed area, not even the borders
+    if area.width == 0 || area.height == 0 {
+        return;
+    }
+
     let border_style = if is_focused {
         Style::default().fg(Color::Yellow)
     } else {
@@ -225,11 +231,42 @@ pub fn draw_list(
         border::PLAIN
     };
 
-    // Adaptive title based on width
-    let title_text = if area.width > 15 {
-        format!(" {} ({}) ", title, entries.len())
+    // Count real entries, including the members of collapsed groups
+    let mut total = 0;
+    let mut enabled = 0;
+    let mut in_config = 0;
+    for entry in entries {
+        let members = match &entry.header {
+            Some(header) => header.members.as_slice(),
+            None => std::slice::from_ref(entry),
+        };
+        for member in members {
+            total += 1;
+            enabled += member.enabled as usize;
+            in_config += member.in_config as usize;
+        }
+    }
+
+    // Adaptive title based on width: "Services (12/40)", and while searching
+    // "Services (3/5 in config + 35 results)"
+    let short_title = format!(" {} ({}/{}) ", title, enabled, total);
+    let full_title = if in_config == total {
+        short_title.clone()
+    } else {
+        format!(
+            " {} ({}/{} in config + {} results) ",
+            title,
+            enabled,
+            in_config,
+            total - in_config
+        )
+    };
+    let title_text = if area.width as usize > full_title.chars().count() + 2 {
+        full_title
+    } else if area.width > 15 {
+        short_title
     } else if area.width > 8 {
-        format!(" {} ", entries.len())
+        format!(" {} ", total)
     } else {
         String::new()
     };
@@ -244,13 +281,71 @@ pub fn draw_list(
         .iter()
         .enumerate()
         .map(|(i, entry)| {
+            // Group header rows: "▼ xserver (3)", or "▶" when collapsed, and
+            // package section dividers: "── Development (5)"
+            if let Some(header) = &entry.header {
+                let arrow = if header.divider {
+                    "──"
+                } else if header.collapsed {
+                    "▶"
+                } else {
+                    "▼"
+                };
+                let line = Line::from(Span::styled(
+                    format!("{} {} ({})", arrow, entry.name, header.count),
+                    Style::default()
+                        .fg(Color::Cyan)
+                        .add_modifier(Modifier::BOLD),
+                ));
+                // Every row has the same height so clicks and scrolling map to entries
+                return if show_descriptions {
+                    ListItem::new(vec![line, Line::from("")])
+                } else {
+                    ListItem::new(line)
+                };
+            }
+
             let is_selected = state.selected() == Some(i);
-            let checkbox = if entry.enabled { "[✓]" } else { "[ ]" };
+            let checkbox = if entry.conditional {
+                "[?]"
+            } else if entry.enabled {
+                "[✓]"
+            } else {
+                "[ ]"
+            };
             let config_indicator = if entry.in_config { "" } else { " +" };
             let extra_indicator = if entry.has_extra_config { " ⚙" } else { "" };
+            let binding_indicator = entry
+                .binding
+                .as_ref()
+                .map(|b| format!(" = {}", b))
+                .unwrap_or_default();
+            // Search results are shown by package name; the attr name is what gets added
+            let attr_indicator = if entry.display_name.is_some() {
+                format!(" ({})", entry.name)
+            } else {
+                String::new()
+            };
+            let list_indicator = entry
+                .list_line
+                .map(|line| format!(" L{}", line))
+                .unwrap_or_default();
+            // Search matches that were set up before, told apart from ones just enabled
+            let already_configured = entry.already_configured && entry.enabled;
+            let configured_indicator = if already_configured {
+                " (configured)"
+            } else {
+                ""
+            };
 
-            let style = if entry.enabled {
+            let style = if already_configured {
+                Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::DIM)
+            } else if entry.enabled {
                 Style::default().fg(Color::Green)
+            } else if entry.conditional {
+                Style::default().fg(Color::Yellow)
             } else if entry.in_config {
                 Style::default().fg(Color::Red)
             } else if is_selected {
@@ -259,13 +354,43 @@ pub fn draw_list(
                 Style::default().fg(Color::DarkGray)
             };
 
-            ListItem::new(Line::from(vec![
+            let line = Line::from(vec![
+                Span::raw(if entry.grouped { "  " } else { "" }),
                 Span::styled(checkbox, style),
                 Span::raw(" "),
-                Span::styled(&entry.name, style),
+                Span::styled(entry.display_name.as_deref().unwrap_or(&entry.name), style),
+                Span::styled(attr_indicator, Style::default().fg(Color::DarkGray)),
                 Span::styled(config_indicator, Style::default().fg(Color::Cyan)),
+                Span::styled(binding_indicator, Style::default().fg(Color::Yellow)),
+                Span::styled(list_indicator, Style::default().fg(Color::DarkGray)),
                 Span::styled(extra_indicator, Style::default().fg(Color::Magenta)),
-            ]))
+                Span::styled(configured_indicator, Style::default().fg(Color::DarkGray)),
+            ]);
+
+            if !show_descriptions {
+                return ListItem::new(line);
+            }
+
+            // One-line description under the entry, cut to the column width
+            let indent = if entry.grouped { "      " } else { "    " };
+            let max_width = (area.width as usize).saturating_sub(indent.len() + 4);
+            let description = entry.description.lines().next().unwrap_or("");
+            let description = if description.chars().count() > max_width {
+                let cut: String = description
+                    .chars()
+                    .take(max_width.saturating_sub(3))
+                    .collect();
+                format!("{}...", cut)
+            } else {
+                description.to_string()
+            };
+            ListItem::new(vec![
+                line,
+                Line::from(Span::styled(
+                    format!("{}{}", indent, description),
+                    Style::default().fg(Color::DarkGray),
+                )),
+            ])
         })
         .collect();
 
@@ -282,8 +407,9 @@ pub fn draw_list(
     frame.render_stateful_widget(list, area, state);
 
     // Draw scrollbar if there are more items than visible
-    let visible_height = area.height.saturating_sub(2) as usize;
-    if entries.len() > visible_height {
+    let row_height = if show_descriptions { 2 } else { 1 };
+    let visible_height = area.height.saturating_sub(2) as usize / row_height;
+    if visible_height > 0 && entries.len() > visible_height {
         let viewport_start = state.offset();
         let (content_len, position, use_decorators, viewport_for_thumb) =
             calculate_scrollbar_position(viewport_start, entries.len(), visible_height);
@@ -313,6 +439,54 @@ pub fn draw_list(
     }
 }
 
+/// A key binding shown in a one-line help bar
+pub struct Hint {
+    pub full: String,
+    /// Abbreviated form for narrow terminals
+    pub short: String,
+    /// Higher is kept longer when the line has to shrink
+    pub priority: u8,
+}
+
+impl Hint {
+    pub fn new(full: impl Into<String>, short: impl Into<String>, priority: u8) -> Self {
+        Hint {
+            full: full.into(),
+            short: short.into(),
+            priority,
+        }
+    }
+}
+
+/// Separator between hints in a help bar
+pub const HINT_SEPARATOR: &str = " | ";
+
+/// Choose how to show `hints` in `width` columns, keeping their order: the full
+/// texts if they fit, otherwise the abbreviations, dropping the least important
+/// hints until the rest fit. Returns (hint index, text) pairs; the most important
+/// hints are always kept, even if they still get clipped.
+pub fn fit_hints(hints: &[Hint], width: usize) -> Vec<(usize, &str)> {
+    let line_width = |texts: &[(usize, &str)]| {
+        texts.iter().map(|(_, t)| t.chars().count()).sum::<usize>()
+            + HINT_SEPARATOR.len() * texts.len().saturating_sub(1)
+    };
+
+    let full: Vec<(usize, &str)> = hints.iter().map(|h| h.full.as_str()).enumerate().collect();
+    if line_width(&full) <= width {
+        return full;
+    }
+
+    let mut short: Vec<(usize, &str)> =
+        hints.iter().map(|h| h.short.as_str()).enumerate().collect();
+    let top = hints.iter().map(|h| h.priority).max().unwrap_or(0);
+    let mut min_priority = 0;
+    while line_width(&short) > width && min_priority < top {
+        min_priority += 1;
+        short.retain(|(i, _)| hints[*i].priority >= min_priority);
+    }
+    short
+}
+
 /// Get type indicator emoji for a Nix type string
 pub fn type_indicator_for_nix_type(type_str: &str) -> &'static str {
     match type_str {
@@ -326,3 +500,67 @@ pub fn type_indicator_for_nix_type(type_str: &str) -> &'static str {
         _ => "λ",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn entry(name: &str) -> ListEntry {
+        ListEntry {
+            name: name.to_string(),
+            description: "A description".to_string(),
+            enabled: true,
+            in_config: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fit_hints() {
+        let hints = [
+            Hint::new("F1: Help", "F1", 2),
+            Hint::new("Ctrl+Q: Quit", "^Q: Quit", 3),
+            Hint::new("Tab: Switch", "Tab", 1),
+        ];
+        let texts = |width| -> Vec<String> {
+            fit_hints(&hints, width)
+                .into_iter()
+                .map(|(_, t)| t.to_string())
+                .collect()
+        };
+
+        assert_eq!(texts(80), ["F1: Help", "Ctrl+Q: Quit", "Tab: Switch"]);
+        assert_eq!(texts(20), ["F1", "^Q: Quit", "Tab"]);
+        assert_eq!(texts(15), ["F1", "^Q: Quit"]);
+        assert_eq!(texts(3), ["^Q: Quit"]);
+    }
+
+    #[test]
+    fn test_draw_list_in_tiny_areas() {
+        let entries: Vec<ListEntry> = (0..20).map(|i| entry(&format!("pkg{}", i))).collect();
+
+        for height in [0, 1, 2, 3] {
+            for show_descriptions in [false, true] {
+                let mut terminal = Terminal::new(TestBackend::new(30, 5)).unwrap();
+                let mut state = ListState::default();
+                state.select(Some(10));
+                terminal
+                    .draw(|frame| {
+                        let area = Rect::new(0, 0, 30, height);
+                        draw_list(
+                            frame,
+                            area,
+                            "Packages",
+                            &entries,
+                            &mut state,
+                            true,
+                            show_descriptions,
+                        );
+                    })
+                    .unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Export/import of the on-disk schema and search caches, for machines without network access
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory holding both the HTTP cache and the `schemas/` subdirectory
+fn cache_root() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("nixxed")
+}
+
+/// Run `tar` with the given arguments
+fn run_tar(args: &[&std::ffi::OsStr]) -> Result<()> {
+    let output = Command::new("tar")
+        .args(args)
+        .output()
+        .context("Failed to run tar")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("tar failed: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Bundle the cache directory into a gzipped tarball at `archive`
+pub fn export(archive: &Path) -> Result<()> {
+    let root = cache_root();
+    if !root.is_dir() {
+        anyhow::bail!("No cache to export: {} does not exist", root.display());
+    }
+    let parent = root.parent().context("Cache directory has no parent")?;
+
+    run_tar(&[
+        "-czf".as_ref(),
+        archive.as_os_str(),
+        "-C".as_ref(),
+        parent.as_os_str(),
+        "nixxed".as_ref(),
+    ])
+}
+
+/// Unpack a tarball made by `export` into the cache directory. Files get the
+/// current time as their modification time (`-m`), so the imported entries
+/// aren't immediately discarded as expired.
+pub fn import(archive: &Path) -> Result<()> {
+    let root = cache_root();
+    let parent = root.parent().context("Cache directory has no parent")?;
+    fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+
+    run_tar(&[
+        "-xzmf".as_ref(),
+        archive.as_os_str(),
+        "-C".as_ref(),
+        parent.as_os_str(),
+        "nixxed".as_ref(),
+    ])
+}
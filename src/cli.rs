@@ -0,0 +1,111 @@
+//! Command line argument parsing
+
+use anyhow::Result;
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+Usage: nixxed [OPTIONS] [CONFIG]
+
+Arguments:
+  [CONFIG]  Path to the NixOS/home-manager configuration file, or to a flake
+            (its directory or flake.nix) to edit the module of one of its hosts
+
+Options:
+  --git                Git integration: warn about uncommitted changes before
+                       saving, offer to stage the config after saving and
+                       to commit (and push) it after a successful rebuild
+  --no-verify-packages Don't check at startup that commented-out packages exist
+                       in nixpkgs (skips the network calls)
+  --no-mouse           Leave the mouse to the terminal, so text can be
+                       selected and copied (F3 toggles it while running)
+  --host NAME          The flake's nixosConfigurations host to edit and rebuild,
+                       instead of the one matching the hostname
+  --read-only          Only inspect the config: toggling, editing, saving and
+                       rebuilding are disabled
+  --trust              Skip confirmations for non-destructive actions, e.g.
+                       rebuild right after saving (same as trust_mode in
+                       ~/.config/nixxed/settings.json)
+  --export-cache FILE  Bundle the schema and search caches into FILE (.tar.gz)
+                       and exit, e.g. to take them to an offline machine
+  --import-cache FILE  Unpack a bundle made with --export-cache into the cache
+                       directory and exit
+  -h, --help           Print this help";
+
+/// Options given on the command line
+#[derive(Debug, Default, Clone)]
+pub struct CliArgs {
+    /// Explicit path to the configuration file
+    pub config_path: Option<PathBuf>,
+    /// Enable git integration for configs kept in a repository
+    pub git: bool,
+    /// Skip the startup check that commented-out packages exist
+    pub no_verify_packages: bool,
+    /// Skip confirmations for non-destructive actions
+    pub trust: bool,
+    /// Disable everything that would change the config
+    pub read_only: bool,
+    /// Flake host to use instead of detecting or asking for one
+    pub host: Option<String>,
+    /// Start without capturing the mouse
+    pub no_mouse: bool,
+    /// Write the caches to this archive and exit
+    pub export_cache: Option<PathBuf>,
+    /// Unpack this archive into the cache directory and exit
+    pub import_cache: Option<PathBuf>,
+}
+
+impl CliArgs {
+    /// Parse the process arguments, exiting on `--help`
+    pub fn parse() -> Result<Self> {
+        match Self::parse_from(std::env::args().skip(1))? {
+            Some(args) => Ok(args),
+            None => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+        }
+    }
+
+    /// Parse arguments (without the program name). Returns `None` if help was requested.
+    fn parse_from(mut args: impl Iterator<Item = String>) -> Result<Option<Self>> {
+        let mut parsed = CliArgs::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--git" => parsed.git = true,
+                "--no-verify-packages" => parsed.no_verify_packages = true,
+                "--trust" => parsed.trust = true,
+                "--read-only" => parsed.read_only = true,
+                "--no-mouse" => parsed.no_mouse = true,
+                "--host" => {
+                    let Some(name) = args.next() else {
+                        anyhow::bail!("--host needs a host name\n\n{}", USAGE);
+                    };
+                    parsed.host = Some(name);
+                }
+                "--export-cache" | "--import-cache" => {
+                    let Some(file) = args.next() else {
+                        anyhow::bail!("{} needs a file argument\n\n{}", arg, USAGE);
+                    };
+                    if arg == "--export-cache" {
+                        parsed.export_cache = Some(PathBuf::from(file));
+                    } else {
+                        parsed.import_cache = Some(PathBuf::from(file));
+                    }
+                }
+                flag if flag.starts_with('-') => {
+                    anyhow::bail!("Unknown option: {}\n\n{}", flag, USAGE);
+                }
+                path => {
+                    if parsed.config_path.is_some() {
+                        anyhow::bail!("Only one configuration file can be given\n\n{}", USAGE);
+                    }
+                    parsed.config_path = Some(PathBuf::from(path));
+                }
+            }
+        }
+
+        Ok(Some(parsed))
+    }
+}
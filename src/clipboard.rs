@@ -0,0 +1,39 @@
+//! Copying text to the system clipboard via the usual command line tools
+
+use anyhow::Result;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Clipboard tools to try, in order (Wayland, X11, macOS)
+const CLIPBOARD_COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+];
+
+/// Copy `text` to the clipboard using the first available tool
+pub fn copy(text: &str) -> Result<()> {
+    for (cmd, args) in CLIPBOARD_COMMANDS {
+        let child = Command::new(cmd)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        let Ok(mut child) = child else {
+            continue;
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+
+    anyhow::bail!("No clipboard tool found (install wl-clipboard, xclip or xsel)")
+}
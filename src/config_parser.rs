@@ -1,18 +1,21 @@
+use crate::flake::FlakeHost;
 use anyhow::{Context, Result};
 use rnix::{SyntaxKind, SyntaxNode};
 use rowan::ast::AstNode;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::mpsc;
 use std::time::{Duration, SystemTime};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
 pub enum EntryType {
     Program,
     Service,
     Package,
+    User,
 }
 
 impl EntryType {
@@ -22,12 +25,13 @@ impl EntryType {
             EntryType::Program => "programs",
             EntryType::Service => "services",
             EntryType::Package => "packages",
+            EntryType::User => "users.users",
         }
     }
 }
 
 /// The type of a configuration property value
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
 pub enum PropertyType {
     Bool,
     String,
@@ -39,7 +43,7 @@ pub enum PropertyType {
 }
 
 /// A single configuration property within a program/service block
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct ConfigProperty {
     pub name: String,
     pub value: String,
@@ -67,28 +71,79 @@ pub struct NixSchema {
 impl NixSchema {
     /// Convert option type string to PropertyType
     pub fn property_type_for(&self, option_name: &str) -> PropertyType {
-        if let Some(info) = self.options.get(option_name) {
-            match info.option_type.as_str() {
-                "boolean" | "null or boolean" => PropertyType::Bool,
-                "string" | "strings" | "null or string" => PropertyType::String,
-                "signed integer" | "integer" | "null or signed integer" => PropertyType::Int,
-                "path" | "null or path" => PropertyType::Path,
-                s if s.starts_with("list of") => PropertyType::List,
-                s if s.contains("attribute set") => PropertyType::AttrSet,
-                _ => PropertyType::Expression,
-            }
-        } else {
-            PropertyType::Expression
+        match self.options.get(option_name) {
+            Some(info) => PropertyType::from_option_type(&info.option_type),
+            None => PropertyType::Expression,
+        }
+    }
+}
+
+impl PropertyType {
+    /// The kind of value an option of the schema's type description takes
+    pub fn from_option_type(option_type: &str) -> PropertyType {
+        match option_type {
+            "boolean" | "null or boolean" => PropertyType::Bool,
+            "string" | "strings" | "null or string" => PropertyType::String,
+            "signed integer" | "integer" | "null or signed integer" => PropertyType::Int,
+            "path" | "null or path" => PropertyType::Path,
+            s if s.starts_with("list of") => PropertyType::List,
+            s if s.contains("attribute set") => PropertyType::AttrSet,
+            _ => PropertyType::Expression,
         }
     }
 }
 
 const SCHEMA_CACHE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60); // 24 hours
 
+/// Which Nix command is used to evaluate option schemas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchemaBackend {
+    /// Classic `nix-instantiate --eval`
+    NixInstantiate,
+    /// New CLI `nix eval` (when only `nix` is installed)
+    NixEval,
+    /// Neither command is available
+    Unavailable,
+}
+
+impl SchemaBackend {
+    /// Probe which evaluator is installed
+    fn detect() -> Self {
+        let available = |cmd: &str| {
+            Command::new(cmd)
+                .arg("--version")
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false)
+        };
+
+        if available("nix-instantiate") {
+            SchemaBackend::NixInstantiate
+        } else if available("nix") {
+            SchemaBackend::NixEval
+        } else {
+            SchemaBackend::Unavailable
+        }
+    }
+}
+
 /// Cache for NixOS option schemas
 pub struct SchemaCache {
     cache_dir: PathBuf,
     memory_cache: HashMap<String, NixSchema>,
+    backend: SchemaBackend,
+    /// Set if the cache directory couldn't be created
+    cache_warning: Option<String>,
+    /// Evaluate options from this flake host instead of `<nixpkgs/nixos>`
+    flake_host: Option<FlakeHost>,
+    /// Nix expression for the nixpkgs to use without a flake host, from the settings
+    nixpkgs: Option<String>,
+    /// Names of the modules under `services.`, listed on first use
+    service_modules: Option<HashSet<String>>,
+    /// Set once listing the service modules failed, so it isn't retried on every search
+    service_modules_failed: bool,
+    /// Receiver for the service modules being evaluated in the background
+    service_modules_receiver: Option<mpsc::Receiver<Option<HashSet<String>>>>,
 }
 
 impl SchemaCache {
@@ -98,19 +153,221 @@ impl SchemaCache {
             .join("nixxed")
             .join("schemas");
 
-        // Create cache directory if it doesn't exist
-        let _ = fs::create_dir_all(&cache_dir);
+        let cache_warning = crate::search::ensure_cache_dir(&cache_dir);
 
         SchemaCache {
             cache_dir,
             memory_cache: HashMap::new(),
+            backend: SchemaBackend::detect(),
+            cache_warning,
+            flake_host: None,
+            nixpkgs: None,
+            service_modules: None,
+            service_modules_failed: false,
+            service_modules_receiver: None,
+        }
+    }
+
+    /// Read schemas from this nixpkgs (see `nixpkgs_expr`) instead of `<nixpkgs>`.
+    /// A flake host still takes precedence, as it includes the config's own modules.
+    pub fn set_nixpkgs(&mut self, reference: &str) {
+        self.memory_cache.clear();
+        self.service_modules = None;
+        self.service_modules_receiver = None;
+        self.nixpkgs = Some(nixpkgs_expr(reference));
+    }
+
+    /// Check that the configured nixpkgs evaluates to a tree with NixOS in it
+    pub fn check_nixpkgs(&self) -> Result<()> {
+        let Some(nixpkgs) = &self.nixpkgs else {
+            return Ok(());
+        };
+        let expr = format!("builtins.pathExists \"${{{}}}/nixos/default.nix\"", nixpkgs);
+        let output = self
+            .eval_command(&expr)
+            .context("No nix evaluator found")?
+            .output()
+            .context("Failed to run the nix evaluator")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let error = stderr
+                .lines()
+                .find(|l| l.trim_start().starts_with("error:"))
+                .unwrap_or(stderr.trim());
+            anyhow::bail!("{}", error.trim());
+        }
+        if String::from_utf8_lossy(&output.stdout).trim() != "true" {
+            anyhow::bail!("it has no nixos/ directory");
+        }
+        Ok(())
+    }
+
+    /// Command evaluating `expr` to JSON with the detected backend
+    fn eval_command(&self, expr: &str) -> Option<Command> {
+        // builtins.getFlake is behind the flakes feature
+        let uses_flakes = self.flake_host.is_some()
+            || self
+                .nixpkgs
+                .as_deref()
+                .is_some_and(|n| n.contains("builtins.getFlake"));
+
+        match self.backend {
+            SchemaBackend::NixInstantiate => {
+                let mut command = Command::new("nix-instantiate");
+                command.args(["--eval", "--strict", "-E", expr, "--json"]);
+                if uses_flakes {
+                    command.args(["--extra-experimental-features", "flakes"]);
+                }
+                Some(command)
+            }
+            SchemaBackend::NixEval => {
+                let mut command = Command::new("nix");
+                command.args([
+                    "--extra-experimental-features",
+                    "nix-command flakes",
+                    "eval",
+                    "--impure",
+                    "--json",
+                    "--expr",
+                    expr,
+                ]);
+                Some(command)
+            }
+            SchemaBackend::Unavailable => None,
         }
     }
 
+    /// Evaluate schemas from a flake host's configuration from now on
+    pub fn set_flake_host(&mut self, host: FlakeHost) {
+        self.memory_cache.clear();
+        self.service_modules = None;
+        self.service_modules_receiver = None;
+        self.flake_host = Some(host);
+    }
+
+    /// The evaluator detected at startup
+    pub fn backend(&self) -> SchemaBackend {
+        self.backend
+    }
+
+    /// Why schemas aren't being cached on disk, if they aren't
+    pub fn cache_warning(&self) -> Option<&str> {
+        self.cache_warning.as_deref()
+    }
+
     /// Get the cache file path for a program/service
     fn cache_path(&self, entry_type: &EntryType, name: &str) -> PathBuf {
-        self.cache_dir
-            .join(format!("{}.{}.json", entry_type.prefix(), name))
+        self.cache_dir.join(format!(
+            "{}{}.{}.json",
+            self.cache_prefix(),
+            entry_type.prefix(),
+            name
+        ))
+    }
+
+    /// Start of the cache file names, for where the options are read from. Hosts
+    /// can import different modules and another nixpkgs has other options, so
+    /// each gets its own cache entries.
+    fn cache_prefix(&self) -> String {
+        match (&self.flake_host, &self.nixpkgs) {
+            (Some(host), _) => format!("{}@", host.name),
+            (None, Some(nixpkgs)) => {
+                use std::collections::hash_map::DefaultHasher;
+                use std::hash::{Hash, Hasher};
+
+                let mut hasher = DefaultHasher::new();
+                nixpkgs.hash(&mut hasher);
+                format!("nixpkgs-{:08x}@", hasher.finish() as u32)
+            }
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Names of the NixOS modules under `services.` (e.g. `nginx`, `xserver`), once
+    /// `load_service_modules` got them
+    pub fn service_modules(&self) -> Option<&HashSet<String>> {
+        self.service_modules.as_ref()
+    }
+
+    /// Start listing the service modules, if that isn't done or underway. They're
+    /// read from the disk cache like schemas, or evaluated in the background; the
+    /// result arrives through `poll_service_modules`. False if they can't be listed.
+    pub fn load_service_modules(&mut self) -> bool {
+        if self.service_modules.is_some() || self.service_modules_receiver.is_some() {
+            return true;
+        }
+        if self.service_modules_failed {
+            return false;
+        }
+
+        let cache_path = self
+            .cache_dir
+            .join(format!("{}service-modules.json", self.cache_prefix()));
+
+        let fresh = fs::metadata(&cache_path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < SCHEMA_CACHE_MAX_AGE);
+        if fresh {
+            if let Some(names) = fs::read_to_string(&cache_path)
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok())
+            {
+                self.service_modules = Some(names);
+                return true;
+            }
+        }
+
+        let expr = format!("builtins.attrNames {}.options.services", self.nixos_expr());
+        let Some(mut command) = self.eval_command(&expr) else {
+            self.service_modules_failed = true;
+            return false;
+        };
+        let (tx, rx) = mpsc::channel();
+        self.service_modules_receiver = Some(rx);
+        std::thread::spawn(move || {
+            let names = command
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| serde_json::from_slice::<HashSet<String>>(&output.stdout).ok());
+            if let Some(json) = names.as_ref().and_then(|n| serde_json::to_string(n).ok()) {
+                let _ = fs::write(&cache_path, json);
+            }
+            let _ = tx.send(names);
+        });
+        true
+    }
+
+    /// Whether the service modules evaluated in the background arrived since the
+    /// last call: `Some(true)` once they're in, `Some(false)` if listing them failed
+    pub fn poll_service_modules(&mut self) -> Option<bool> {
+        let names = match self.service_modules_receiver.as_ref()?.try_recv() {
+            Ok(names) => names,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        };
+        self.service_modules_receiver = None;
+        self.service_modules_failed = names.is_none();
+        self.service_modules = names;
+        Some(!self.service_modules_failed)
+    }
+
+    /// Nix expression for the evaluated NixOS system the options are read from
+    fn nixos_expr(&self) -> String {
+        match (&self.flake_host, &self.nixpkgs) {
+            (Some(host), _) => host.nixos_expr(),
+            // Without a configuration of its own, `<nixos-config>` isn't needed either
+            (None, Some(nixpkgs)) => {
+                format!(
+                    "(import \"${{{}}}/nixos\" {{ configuration = {{ }}; }})",
+                    nixpkgs
+                )
+            }
+            (None, None) => "(import <nixpkgs/nixos> {})".to_string(),
+        }
     }
 
     /// Fetch schema for a program or service
@@ -154,7 +411,7 @@ impl SchemaCache {
             }
         }
 
-        // Fetch from nix-instantiate
+        // Evaluate with nix-instantiate (or nix eval)
         if let Some(schema) = self.fetch_schema(entry_type, name) {
             // Save to file cache
             if let Ok(json) = serde_json::to_string(&schema.options) {
@@ -168,7 +425,7 @@ impl SchemaCache {
         None
     }
 
-    /// Fetch schema from nix-instantiate
+    /// Fetch schema by evaluating the NixOS options with the detected backend
     fn fetch_schema(&self, entry_type: &EntryType, name: &str) -> Option<NixSchema> {
         if matches!(entry_type, EntryType::Package) {
             return None;
@@ -176,10 +433,16 @@ impl SchemaCache {
         let prefix = entry_type.prefix();
 
         // Build the nix expression to evaluate
+        let nixos = self.nixos_expr();
+        let options = match entry_type {
+            // Every user has the same options, those of the `users.users` submodule
+            EntryType::User => format!("({}.options.{}.type.getSubOptions [])", nixos, prefix),
+            _ => format!("{}.options.{}.{}", nixos, prefix, name),
+        };
         let expr = format!(
             r#"
 let 
-  opts = (import <nixpkgs/nixos> {{}}).options.{}.{};
+  opts = {};
   getInfo = name: opt: {{ 
     type = opt.type.description or "unknown"; 
     default = if builtins.hasAttr "default" opt then opt.default else null;
@@ -187,13 +450,10 @@ let
   }};
 in builtins.mapAttrs getInfo opts
 "#,
-            prefix, name
+            options
         );
 
-        let output = Command::new("nix-instantiate")
-            .args(["--eval", "--strict", "-E", &expr, "--json"])
-            .output()
-            .ok()?;
+        let output = self.eval_command(&expr)?.output().ok()?;
 
         if !output.status.success() {
             return None;
@@ -239,6 +499,13 @@ impl Default for SchemaCache {
     }
 }
 
+/// The title of a `# Development` style comment line heading a group of packages
+fn section_header(line: &str) -> Option<String> {
+    let title = line.trim().strip_prefix('#')?.trim().trim_end_matches(':');
+    let first_char = title.chars().next()?;
+    first_char.is_ascii_uppercase().then(|| title.to_string())
+}
+
 /// Check if a string looks like a valid Nix package name.
 /// Valid names contain only letters, digits, hyphens, and underscores.
 /// They should not start with a digit and should not be empty.
@@ -271,7 +538,7 @@ fn is_valid_package_name(s: &str) -> bool {
     true
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct ConfigEntry {
     pub name: String,
     pub entry_type: EntryType,
@@ -281,6 +548,185 @@ pub struct ConfigEntry {
     pub text_range: (usize, usize),
     /// Properties defined for this entry (excluding 'enable')
     pub properties: Vec<ConfigProperty>,
+    /// Set when `enable` refers to a variable instead of a literal bool
+    pub enable_binding: Option<LetBinding>,
+    /// Text range of the literal `true`/`false` assigned to `enable`
+    pub enable_range: Option<(usize, usize)>,
+    /// `enable` as written when it's an expression rather than a bool or a
+    /// variable, e.g. `cfg.enable`; its state isn't known without evaluating it
+    pub enable_expr: Option<String>,
+    /// For packages: 1-based line of the package list holding it
+    pub package_list: Option<usize>,
+    /// Trailing `# comment` on the entry's first line, e.g. why it was enabled
+    pub note: Option<String>,
+    /// The file the entry is defined in, the loaded config or one it imports
+    pub file: String,
+    /// Condition of a `mkIf cond { ... }` block the entry is set in
+    pub condition: Option<String>,
+    /// For packages: the `# Header` comment the entry sits under in its list
+    pub section: Option<String>,
+}
+
+impl ConfigEntry {
+    /// Whether `enable` can't be read as on/off: an expression, or a variable
+    /// that isn't bound to a plain bool
+    pub fn is_conditional(&self) -> bool {
+        self.enable_expr.is_some()
+            || self.condition.is_some()
+            || self
+                .enable_binding
+                .as_ref()
+                .is_some_and(|b| b.value_range.is_none())
+    }
+
+    /// What `enable` is set to, if it's not a plain bool: the variable or expression
+    pub fn enable_source(&self) -> Option<String> {
+        self.enable_binding
+            .as_ref()
+            .map(|b| b.name.clone())
+            .or_else(|| self.enable_expr.clone())
+            .or_else(|| self.condition.as_ref().map(|c| format!("mkIf {}", c)))
+    }
+}
+
+/// A variable an entry's `enable` refers to, e.g. `enable = enableDesktop;`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LetBinding {
+    pub name: String,
+    /// Text range of the bound `true`/`false`, if it could be resolved to one
+    pub value_range: Option<(usize, usize)>,
+}
+
+/// Options that are configured by what's in a list rather than by an `enable` flag
+pub const LIST_OPTIONS: &[&str] = &[
+    "networking.firewall.allowedTCPPorts",
+    "networking.firewall.allowedUDPPorts",
+    "boot.kernelModules",
+    "boot.initrd.kernelModules",
+    "boot.supportedFilesystems",
+    "services.xserver.videoDrivers",
+    "nix.settings.experimental-features",
+    "nix.settings.trusted-users",
+];
+
+/// Nix expression for a nixpkgs reference from the settings: `<channel>` is used
+/// as is, `flake#input` is one of a flake's inputs and anything else is a path
+fn nixpkgs_expr(reference: &str) -> String {
+    if reference.starts_with('<') {
+        return reference.to_string();
+    }
+    match reference.split_once('#') {
+        Some((flake, input)) => {
+            // Local flakes need the `path:` scheme to be read as they are on disk
+            let flake = if flake.starts_with('/') {
+                format!("path:{}", flake)
+            } else {
+                flake.to_string()
+            };
+            format!("(builtins.getFlake \"{}\").inputs.\"{}\"", flake, input)
+        }
+        None => format!("\"{}\"", reference),
+    }
+}
+
+/// Arguments only a module function takes; a derivation or library file won't
+const MODULE_ARGS: &[&str] = &["config", "pkgs", "modulesPath", "osConfig"];
+
+/// Top-level attributes that only make sense in a NixOS/home-manager module
+const MODULE_ATTRS: &[&str] = &[
+    "imports",
+    "options",
+    "config",
+    "programs",
+    "services",
+    "environment",
+    "boot",
+    "networking",
+    "users",
+    "nix",
+    "nixpkgs",
+    "system",
+    "systemd",
+    "hardware",
+    "fileSystems",
+    "swapDevices",
+    "security",
+    "virtualisation",
+    "i18n",
+    "time",
+    "fonts",
+    "home",
+    "xdg",
+];
+
+/// Options holding package lists, as attribute set and attribute: NixOS'
+/// `environment.systemPackages` and per-user `users.users.<name>.packages`,
+/// and home-manager's `home.packages`. `*` matches any one name.
+const PACKAGE_OPTIONS: &[(&str, &str)] = &[
+    ("environment", "systemPackages"),
+    ("users.users.*", "packages"),
+    ("home", "packages"),
+];
+
+/// Source range of the name in an attribute path starting with the entry's
+/// prefix and every segment of its name, e.g. `git` in `programs.git.enable`,
+/// `alice` in `users.users.alice` or `foo.server` in `services.foo.server.enable`.
+/// A path that goes on into one of the `nested` entries (`foo.server` for `foo`)
+/// belongs to that entry instead.
+fn entry_name_range(
+    path: &SyntaxNode,
+    entry_type: &EntryType,
+    name: &str,
+    nested: &[String],
+) -> Option<(usize, usize)> {
+    let segments: Vec<SyntaxNode> = path.children().collect();
+    let starts_with = |full_name: &str| {
+        let expected: Vec<&str> = entry_type
+            .prefix()
+            .split('.')
+            .chain(full_name.split('.'))
+            .collect();
+        segments.len() >= expected.len()
+            && segments
+                .iter()
+                .zip(&expected)
+                .all(|(segment, part)| segment.text() == *part)
+    };
+    if !starts_with(name) || nested.iter().any(|n| starts_with(n)) {
+        return None;
+    }
+    let first = entry_type.prefix().split('.').count();
+    let last = first + name.split('.').count() - 1;
+    Some((
+        segments[first].text_range().start().into(),
+        segments[last].text_range().end().into(),
+    ))
+}
+
+/// Whether a dotted attribute path matches a pattern like `users.users.*`
+pub(crate) fn path_matches(path: &str, pattern: &str) -> bool {
+    path.split('.').count() == pattern.split('.').count()
+        && path
+            .split('.')
+            .zip(pattern.split('.'))
+            .all(|(part, expected)| expected == "*" || part == expected)
+}
+
+/// Module created next to the config for packages when its own
+/// `environment.systemPackages` isn't a list nixxed can edit
+pub const PACKAGES_MODULE: &str = "nixxed-packages.nix";
+
+/// A place in the config that mentions an entry outside of its own definition
+#[derive(Debug, Clone)]
+pub struct Reference {
+    /// Path of the file it's in, the config or one of its imports
+    pub file: String,
+    /// 1-based line number
+    pub line: usize,
+    /// Byte offset of the mention
+    pub position: usize,
+    /// The trimmed source line
+    pub text: String,
 }
 
 #[derive(Debug, Clone)]
@@ -288,40 +734,170 @@ pub struct NixConfig {
     pub path: String,
     pub content: String,
     pub entries: Vec<ConfigEntry>,
+    /// Modules this file imports by path, each parsed from its own file. Their
+    /// entries are listed with this file's, and edits to them go to their file.
+    pub imports: Vec<NixConfig>,
 }
 
 impl NixConfig {
+    /// The file a config path refers to. Like Nix, a directory stands for the
+    /// `default.nix` inside it.
+    pub fn resolve_path(path: &Path) -> Result<PathBuf> {
+        if !path.is_dir() {
+            return Ok(path.to_path_buf());
+        }
+        let default = path.join("default.nix");
+        if default.is_file() {
+            return Ok(default);
+        }
+
+        let hint = ["configuration.nix", "home.nix", "flake.nix"]
+            .iter()
+            .map(|name| path.join(name))
+            .find(|p| p.is_file())
+            .map(|p| format!("; pass {} instead", p.display()))
+            .unwrap_or_default();
+        anyhow::bail!(
+            "{} is a directory without a default.nix{}",
+            path.display(),
+            hint
+        )
+    }
+
+    /// Load the module a flake's `nixosConfigurations.<host>` is built from.
+    /// The flake usually only lists `./configuration.nix` in the host's
+    /// `modules`; what that imports is loaded along with it.
+    pub fn load_flake(dir: &Path, host: &str) -> Result<Self> {
+        crate::flake::host_modules(dir, host)?
+            .iter()
+            .find_map(|path| Self::load(path).ok())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No module file found for nixosConfigurations.{} in {}; pass the file to edit instead",
+                    host,
+                    dir.join("flake.nix").display()
+                )
+            })
+    }
+
+    /// Load a config file and the files it imports
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path_str = path.as_ref().to_string_lossy().to_string();
-        let content = fs::read_to_string(&path).context("Failed to read NixOS config file")?;
+        let path = Self::resolve_path(path.as_ref())?;
+        let mut config = Self::load_file(&path)?;
+
+        let mut loaded = HashSet::from([path.canonicalize().unwrap_or(path)]);
+        config.load_imports(&mut loaded);
+        Ok(config)
+    }
+
+    /// Load a single config file, without its imports
+    fn load_file(path: &Path) -> Result<Self> {
+        let path_str = path.to_string_lossy().to_string();
+        let content = fs::read_to_string(path).context("Failed to read NixOS config file")?;
 
         let mut config = NixConfig {
             path: path_str,
             content: content.clone(),
             entries: Vec::new(),
+            imports: Vec::new(),
         };
 
         config.parse()?;
         Ok(config)
     }
 
-    /// Verify that disabled packages actually exist in nixpkgs
-    /// Removes any commented entries that don't match real packages
-    pub fn verify_packages(&mut self, searcher: &crate::search::NixSearcher) {
-        self.entries.retain(|entry| {
-            // Keep all enabled entries
-            if entry.enabled {
-                return true;
+    /// Load the files imported by path, recursively. Store paths and
+    /// `<nixpkgs/...>` imports aren't part of the user's config and are skipped,
+    /// as are files that can't be read or are already loaded (import cycles).
+    fn load_imports(&mut self, loaded: &mut HashSet<PathBuf>) {
+        for path in self.imported_paths() {
+            if path.starts_with("/nix/store") {
+                continue;
             }
-
-            // For disabled packages, verify they exist
-            if entry.entry_type == EntryType::Package {
-                return searcher.verify_package_exists(&entry.name);
+            let Ok(path) = Self::resolve_path(&path) else {
+                continue;
+            };
+            let path = path.canonicalize().unwrap_or(path);
+            if !path.is_file() || !loaded.insert(path.clone()) {
+                continue;
             }
+            if let Ok(mut module) = Self::load_file(&path) {
+                module.load_imports(loaded);
+                self.imports.push(module);
+            }
+        }
+    }
 
-            // Keep disabled programs/services (they might be NixOS options)
-            true
-        });
+    /// This file and everything it imports, depth first
+    pub fn modules(&self) -> Vec<&NixConfig> {
+        let mut modules = vec![self];
+        for import in &self.imports {
+            modules.extend(import.modules());
+        }
+        modules
+    }
+
+    /// The module (this file or an import) that defines an entry
+    fn module_with_entry(&self, name: &str, entry_type: &EntryType) -> Option<&NixConfig> {
+        self.modules().into_iter().find(|m| {
+            m.entries
+                .iter()
+                .any(|e| e.name == name && &e.entry_type == entry_type)
+        })
+    }
+
+    fn module_with_entry_mut(
+        &mut self,
+        name: &str,
+        entry_type: &EntryType,
+    ) -> Option<&mut NixConfig> {
+        if self
+            .entries
+            .iter()
+            .any(|e| e.name == name && &e.entry_type == entry_type)
+        {
+            return Some(self);
+        }
+        self.imports
+            .iter_mut()
+            .find_map(|m| m.module_with_entry_mut(name, entry_type))
+    }
+
+    /// The imported module defining an entry this file doesn't, which edits of
+    /// that entry are passed on to
+    fn import_with_entry(&mut self, name: &str, entry_type: &EntryType) -> Option<&mut NixConfig> {
+        if self
+            .entries
+            .iter()
+            .any(|e| e.name == name && &e.entry_type == entry_type)
+        {
+            return None;
+        }
+        self.imports
+            .iter_mut()
+            .find_map(|m| m.module_with_entry_mut(name, entry_type))
+    }
+
+    /// The first module (this file or an import) with entries of a type
+    fn module_with_type_mut(&mut self, entry_type: &EntryType) -> Option<&mut NixConfig> {
+        if self.entries.iter().any(|e| &e.entry_type == entry_type) {
+            return Some(self);
+        }
+        self.imports
+            .iter_mut()
+            .find_map(|m| m.module_with_type_mut(entry_type))
+    }
+
+    /// Names of the commented-out packages, which may just be comments that
+    /// look like package names, so callers can check them against nixpkgs
+    pub fn commented_package_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for entry in self.get_entries_by_type(&EntryType::Package) {
+            if !entry.enabled && !names.contains(&entry.name) {
+                names.push(entry.name.clone());
+            }
+        }
+        names
     }
 
     fn parse(&mut self) -> Result<()> {
@@ -331,11 +907,352 @@ impl NixConfig {
         let root = parse.tree();
 
         self.visit_node(root.syntax());
+        self.attach_notes(root.syntax());
 
         Ok(())
     }
 
-    /// Clear entries and re-parse the content
+    /// Fill in each entry's note from the comment ending its first line
+    fn attach_notes(&mut self, root: &SyntaxNode) {
+        let notes: Vec<Option<String>> = self
+            .entries
+            .iter()
+            .map(|e| {
+                self.trailing_comment_range(root, e.text_range.0)
+                    .map(|(start, end)| {
+                        self.content[start..end]
+                            .trim_start_matches('#')
+                            .trim()
+                            .to_string()
+                    })
+                    .filter(|note| !note.is_empty())
+            })
+            .collect();
+        for (entry, note) in self.entries.iter_mut().zip(notes) {
+            entry.note = note;
+        }
+    }
+
+    /// Range of the `# comment` that ends the line `pos` is on, if it starts after `pos`.
+    /// A commented-out package is itself a comment, so it never has one.
+    fn trailing_comment_range(&self, root: &SyntaxNode, pos: usize) -> Option<(usize, usize)> {
+        let line_end = self.content[pos..]
+            .find('\n')
+            .map(|p| pos + p)
+            .unwrap_or(self.content.len());
+        let line_end = line_end - usize::from(self.content[..line_end].ends_with('\r'));
+        if line_end <= pos {
+            return None;
+        }
+
+        let token = root
+            .token_at_offset(rowan::TextSize::from((line_end - 1) as u32))
+            .left_biased()?;
+        let start: usize = token.text_range().start().into();
+        (token.kind() == SyntaxKind::TOKEN_COMMENT && token.text().starts_with('#') && start > pos)
+            .then_some((start, line_end))
+    }
+
+    /// Rename a program/service: rewrite the name segment of every `prefix.name...`
+    /// attribute path, keeping all properties and formatting. Returns how many
+    /// definitions were renamed.
+    pub fn rename_entry(
+        &mut self,
+        name: &str,
+        entry_type: &EntryType,
+        new_name: &str,
+    ) -> Result<usize> {
+        if let Some(module) = self.import_with_entry(name, entry_type) {
+            return module.rename_entry(name, entry_type, new_name);
+        }
+        if entry_type == &EntryType::Package {
+            anyhow::bail!("Only programs and services can be renamed");
+        }
+        let valid = new_name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && new_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '\''));
+        if !valid {
+            anyhow::bail!("'{}' isn't a valid attribute name", new_name);
+        }
+        if self.get_entry(new_name, entry_type).is_some() {
+            anyhow::bail!(
+                "{}.{} is already in the config",
+                entry_type.prefix(),
+                new_name
+            );
+        }
+
+        let nested = self.nested_entry_names(name, entry_type);
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let mut ranges: Vec<(usize, usize)> = root
+            .syntax()
+            .descendants()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH)
+            .filter_map(|path| entry_name_range(&path, entry_type, name, &nested))
+            .collect();
+        if ranges.is_empty() {
+            anyhow::bail!("{}.{} is not in the config", entry_type.prefix(), name);
+        }
+
+        // Back to front, so earlier offsets stay valid
+        ranges.sort_by_key(|&(start, _)| std::cmp::Reverse(start));
+        for &(start, end) in &ranges {
+            self.content.replace_range(start..end, new_name);
+        }
+
+        self.reparse()?;
+        Ok(ranges.len())
+    }
+
+    /// Full attribute path a binding assigns to, including the paths of the
+    /// attribute sets it's nested in (`networking.firewall = { allowedTCPPorts = ...`)
+    fn full_attrpath(&self, binding: &SyntaxNode) -> String {
+        let mut parts: Vec<String> = binding
+            .ancestors()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .filter_map(|n| n.children().find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH))
+            .map(|path| self.get_attrpath_text(&path))
+            .collect();
+        parts.reverse();
+        parts.join(".")
+    }
+
+    /// The list assigned to the option at `path`, if the config sets one
+    fn find_list_option(&self, root: &SyntaxNode, path: &str) -> Option<SyntaxNode> {
+        root.descendants()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|n| self.full_attrpath(n) == path)
+            .and_then(|n| n.children().last())
+            .and_then(|value| {
+                std::iter::once(value.clone())
+                    .chain(value.descendants())
+                    .find(|n| n.kind() == SyntaxKind::NODE_LIST)
+            })
+    }
+
+    /// Elements of the list option at `path` as written in the config (strings keep
+    /// their quotes). `None` if the option isn't set to a list.
+    pub fn list_option_members(&self, path: &str) -> Option<Vec<String>> {
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let list = self.find_list_option(root.syntax(), path)?;
+        Some(list.children().map(|c| c.text().to_string()).collect())
+    }
+
+    /// Append `member` to the list option at `path`, creating the assignment if
+    /// the config doesn't set it yet. Follows the list's one-line or multi-line layout.
+    pub fn add_list_member(&mut self, path: &str, member: &str) -> Result<()> {
+        let member = member.trim();
+        if member.is_empty() {
+            anyhow::bail!("Nothing to add");
+        }
+
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let unit = self.indent_unit();
+
+        match self.find_list_option(root.syntax(), path) {
+            Some(list) => {
+                if list.children().any(|c| c.text() == member) {
+                    anyhow::bail!("{} already contains {}", path, member);
+                }
+                let start: usize = list.text_range().start().into();
+                let end: usize = list.text_range().end().into();
+                let last_end = list.children().last().map(|c| c.text_range().end().into());
+
+                if self.content[start..end].contains('\n') {
+                    let (pos, indent) = match last_end {
+                        Some(pos) => (pos, self.line_indent_at(pos)),
+                        None => (start + 1, format!("{}{}", self.line_indent_at(start), unit)),
+                    };
+                    self.content
+                        .insert_str(pos, &format!("\n{}{}", indent, member));
+                } else {
+                    match last_end {
+                        Some(pos) => self.content.insert_str(pos, &format!(" {}", member)),
+                        None => self
+                            .content
+                            .replace_range(start..end, &format!("[ {} ]", member)),
+                    }
+                }
+            }
+            None => {
+                let (pos, indent) = self.settings_insert_point()?;
+                self.content
+                    .insert_str(pos, &format!("\n{}{} = [ {} ];\n", indent, path, member));
+            }
+        }
+
+        self.reparse()
+    }
+
+    /// Remove `member` from the list option at `path`. A member on a line of its
+    /// own takes the line with it.
+    pub fn remove_list_member(&mut self, path: &str, member: &str) -> Result<()> {
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let element = self
+            .find_list_option(root.syntax(), path)
+            .and_then(|list| list.children().find(|c| c.text() == member))
+            .with_context(|| format!("{} doesn't contain {}", path, member))?;
+
+        let start: usize = element.text_range().start().into();
+        let end: usize = element.text_range().end().into();
+        let line_start = self.content[..start]
+            .rfind('\n')
+            .map(|p| p + 1)
+            .unwrap_or(0);
+        let line_end = self.content[end..]
+            .find('\n')
+            .map(|p| end + p)
+            .unwrap_or(self.content.len());
+
+        let alone = self.content[line_start..start].trim().is_empty()
+            && self.content[end..line_end].trim().is_empty();
+        if alone && line_end < self.content.len() {
+            self.content.replace_range(line_start..line_end + 1, "");
+        } else {
+            let before = self.content[..start].trim_end_matches([' ', '\t']).len();
+            self.content.replace_range(before..end, "");
+        }
+
+        self.reparse()
+    }
+
+    /// Set the note on an entry's line, replacing an existing one. An empty note removes it.
+    pub fn set_note(&mut self, name: &str, entry_type: &EntryType, note: &str) -> Result<()> {
+        if let Some(module) = self.import_with_entry(name, entry_type) {
+            return module.set_note(name, entry_type, note);
+        }
+        let entry = self
+            .get_entry(name, entry_type)
+            .with_context(|| format!("{} is not in the config", name))?;
+        if entry_type == &EntryType::Package && !entry.enabled {
+            anyhow::bail!("{} is commented out, so it can't have a note", name);
+        }
+        let pos = entry.text_range.0;
+
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let note = note.replace(['\n', '\r'], " ");
+        let note = note.trim();
+
+        match self.trailing_comment_range(root.syntax(), pos) {
+            Some((start, end)) => {
+                // Take the whitespace before the comment along when removing it
+                let start = if note.is_empty() {
+                    self.content[..start].trim_end_matches([' ', '\t']).len()
+                } else {
+                    start
+                };
+                let replacement = if note.is_empty() {
+                    String::new()
+                } else {
+                    format!("# {}", note)
+                };
+                self.content.replace_range(start..end, &replacement);
+            }
+            None if note.is_empty() => return Ok(()),
+            None => {
+                let line_end = self.content[pos..]
+                    .find('\n')
+                    .map(|p| pos + p)
+                    .unwrap_or(self.content.len());
+                let code_end = self.content[..line_end].trim_end().len().max(pos);
+                self.content.insert_str(code_end, &format!(" # {}", note));
+            }
+        }
+
+        self.reparse()
+    }
+
+    /// Range of the value of a program/service's `enable` binding, whether it's
+    /// written as `prefix.name.enable = ...;` or inside the entry's attribute set
+    fn enable_value_range(&self, name: &str, entry_type: &EntryType) -> Result<(usize, usize)> {
+        let entry = self
+            .get_entry(name, entry_type)
+            .filter(|_| entry_type != &EntryType::Package)
+            .with_context(|| format!("{}.{} is not in the config", entry_type.prefix(), name))?;
+        let start = entry.text_range.0;
+
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let node = root
+            .syntax()
+            .descendants()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|n| usize::from(n.text_range().start()) == start)
+            .context("Couldn't find the entry's definition")?;
+        let path = node
+            .children()
+            .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH)
+            .map(|p| self.get_attrpath_text(&p))
+            .unwrap_or_default();
+        let value = node
+            .children()
+            .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH)
+            .context("The entry has no value")?;
+
+        let value = if path.ends_with(".enable") {
+            value
+        } else {
+            entry_block(&value)
+                .map_or(value, |(set, _)| set)
+                .children()
+                .filter(|c| c.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+                .find(|c| {
+                    c.children()
+                        .find(|a| a.kind() == SyntaxKind::NODE_ATTRPATH)
+                        .is_some_and(|a| self.get_attrpath_text(&a) == "enable")
+                })
+                .and_then(|c| c.children().find(|v| v.kind() != SyntaxKind::NODE_ATTRPATH))
+                .with_context(|| format!("{} inherits enable, edit it where it's defined", name))?
+        };
+        Ok((
+            value.text_range().start().into(),
+            value.text_range().end().into(),
+        ))
+    }
+
+    /// The expression a program/service's `enable` is set to, exactly as written
+    /// (e.g. `lib.mkForce true` or `config.services.xserver.enable`)
+    pub fn enable_expression(&self, name: &str, entry_type: &EntryType) -> Result<String> {
+        let module = self.module_with_entry(name, entry_type).unwrap_or(self);
+        let (start, end) = module.enable_value_range(name, entry_type)?;
+        Ok(module.content[start..end].to_string())
+    }
+
+    /// Replace the expression a program/service's `enable` is set to. The
+    /// expression has to parse on its own; it isn't otherwise checked.
+    pub fn set_enable_expression(
+        &mut self,
+        name: &str,
+        entry_type: &EntryType,
+        expr: &str,
+    ) -> Result<()> {
+        if let Some(module) = self.import_with_entry(name, entry_type) {
+            return module.set_enable_expression(name, entry_type, expr);
+        }
+        let expr = expr.trim().trim_end_matches(';').trim_end();
+        if expr.is_empty() {
+            anyhow::bail!("The enable expression can't be empty");
+        }
+        if let Some(error) = rnix::Root::parse(expr).errors().first() {
+            anyhow::bail!("'{}' isn't a valid Nix expression: {}", expr, error);
+        }
+
+        let (start, end) = self.enable_value_range(name, entry_type)?;
+        self.content.replace_range(start..end, expr);
+        self.reparse()
+    }
+
+    /// Clear entries and re-parse the content. Only reads `content`, so it's
+    /// safe to call without an edit; the text changes only through the edit methods.
     fn reparse(&mut self) -> Result<()> {
         self.entries.clear();
         self.parse()
@@ -376,13 +1293,18 @@ impl NixConfig {
             let path_text = self.get_attrpath_text(&attrpath);
             let path_parts: Vec<&str> = path_text.split('.').collect();
 
-            // Check for programs.*.enable pattern
+            // Check for programs.*.enable pattern (also nested, programs.*.*.enable)
             if path_parts.len() >= 3
                 && path_parts[0] == "programs"
                 && path_parts.last() == Some(&"enable")
             {
-                let program_name = path_parts[1].to_string();
-                let enabled = self.get_bool_value(&value);
+                // Keep intermediate segments, so `programs.foo.bar.enable` is `foo.bar`
+                let program_name = path_parts[1..path_parts.len() - 1].join(".");
+                // Any expression can be assigned to `enable`, not just the kinds above
+                let value = node
+                    .children()
+                    .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH);
+                let (enabled, enable_binding) = self.resolve_enable_value(&value);
 
                 self.entries.push(ConfigEntry {
                     name: program_name,
@@ -394,37 +1316,61 @@ impl NixConfig {
                         node.text_range().end().into(),
                     ),
                     properties: Vec::new(),
+                    enable_binding,
+                    enable_range: bool_literal_range(&value),
+                    enable_expr: enable_expression_text(&value),
+                    package_list: None,
+                    note: None,
+                    file: self.path.clone(),
+                    condition: None,
+                    section: None,
                 });
             }
             // Check for programs.* = { enable = ...; } pattern
             else if path_parts.len() == 2 && path_parts[0] == "programs" {
-                if let Some(ref val) = value {
-                    if val.kind() == SyntaxKind::NODE_ATTR_SET {
-                        if let Some((enabled, has_extra, properties)) =
-                            self.check_attr_set_for_enable(val)
-                        {
-                            self.entries.push(ConfigEntry {
-                                name: path_parts[1].to_string(),
-                                entry_type: EntryType::Program,
-                                enabled,
-                                has_extra_config: has_extra,
-                                text_range: (
-                                    node.text_range().start().into(),
-                                    node.text_range().end().into(),
-                                ),
-                                properties,
-                            });
-                        }
+                // The block may be wrapped in `mkIf cond { ... }`
+                let block = node
+                    .children()
+                    .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH)
+                    .and_then(|v| entry_block(&v));
+                if let Some((val, condition)) = block {
+                    if let Some((enabled, has_extra, properties, enable_binding)) =
+                        self.check_attr_set_for_enable(&val)
+                    {
+                        let enable_value = self.enable_value_in_set(&val);
+                        self.entries.push(ConfigEntry {
+                            name: path_parts[1].to_string(),
+                            entry_type: EntryType::Program,
+                            enabled,
+                            has_extra_config: has_extra,
+                            text_range: (
+                                node.text_range().start().into(),
+                                node.text_range().end().into(),
+                            ),
+                            properties,
+                            enable_binding,
+                            enable_range: bool_literal_range(&enable_value),
+                            enable_expr: enable_expression_text(&enable_value),
+                            package_list: None,
+                            note: None,
+                            file: self.path.clone(),
+                            condition,
+                            section: None,
+                        });
                     }
                 }
             }
-            // Check for services.*.enable pattern
+            // Check for services.*.enable pattern (also nested, services.*.*.enable)
             else if path_parts.len() >= 3
                 && path_parts[0] == "services"
                 && path_parts.last() == Some(&"enable")
             {
-                let service_name = path_parts[1].to_string();
-                let enabled = self.get_bool_value(&value);
+                // Keep intermediate segments, so `services.foo.server.enable` is `foo.server`
+                let service_name = path_parts[1..path_parts.len() - 1].join(".");
+                let value = node
+                    .children()
+                    .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH);
+                let (enabled, enable_binding) = self.resolve_enable_value(&value);
 
                 self.entries.push(ConfigEntry {
                     name: service_name,
@@ -436,34 +1382,89 @@ impl NixConfig {
                         node.text_range().end().into(),
                     ),
                     properties: Vec::new(),
+                    enable_binding,
+                    enable_range: bool_literal_range(&value),
+                    enable_expr: enable_expression_text(&value),
+                    package_list: None,
+                    note: None,
+                    file: self.path.clone(),
+                    condition: None,
+                    section: None,
                 });
             }
             // Check for services.* = { enable = ...; } pattern
             else if path_parts.len() == 2 && path_parts[0] == "services" {
-                if let Some(ref val) = value {
-                    if val.kind() == SyntaxKind::NODE_ATTR_SET {
-                        if let Some((enabled, has_extra, properties)) =
-                            self.check_attr_set_for_enable(val)
-                        {
-                            self.entries.push(ConfigEntry {
-                                name: path_parts[1].to_string(),
-                                entry_type: EntryType::Service,
-                                enabled,
-                                has_extra_config: has_extra,
-                                text_range: (
-                                    node.text_range().start().into(),
-                                    node.text_range().end().into(),
-                                ),
-                                properties,
-                            });
-                        }
+                // The block may be wrapped in `mkIf cond { ... }`
+                let block = node
+                    .children()
+                    .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH)
+                    .and_then(|v| entry_block(&v));
+                if let Some((val, condition)) = block {
+                    if let Some((enabled, has_extra, properties, enable_binding)) =
+                        self.check_attr_set_for_enable(&val)
+                    {
+                        let enable_value = self.enable_value_in_set(&val);
+                        self.entries.push(ConfigEntry {
+                            name: path_parts[1].to_string(),
+                            entry_type: EntryType::Service,
+                            enabled,
+                            has_extra_config: has_extra,
+                            text_range: (
+                                node.text_range().start().into(),
+                                node.text_range().end().into(),
+                            ),
+                            properties,
+                            enable_binding,
+                            enable_range: bool_literal_range(&enable_value),
+                            enable_expr: enable_expression_text(&enable_value),
+                            package_list: None,
+                            note: None,
+                            file: self.path.clone(),
+                            condition,
+                            section: None,
+                        });
                     }
                 }
             }
-            // Check for environment.systemPackages
-            else if path_text == "environment.systemPackages" {
-                if let Some(ref val) = value {
-                    self.extract_packages(val);
+            // Check for environment.systemPackages / users.users.*.packages /
+            // home.packages, also in the environment = { systemPackages = ...; } form
+            else if let Some(val) = value
+                .as_ref()
+                .and_then(|v| self.packages_value(&path_text, v))
+            {
+                self.extract_packages(&val);
+            }
+
+            // Check for users.users.* = { ... } pattern. Accounts are created
+            // unless `enable = false`, so there's usually no `enable` at all.
+            if path_matches(&path_text, "users.users.*") {
+                let block = node
+                    .children()
+                    .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH)
+                    .and_then(|v| entry_block(&v));
+                if let Some((val, condition)) = block {
+                    let (enable, properties) = self.read_attr_set(&val);
+                    let (enabled, enable_binding) = enable.unwrap_or((true, None));
+                    let enable_value = self.enable_value_in_set(&val);
+                    self.entries.push(ConfigEntry {
+                        name: path_parts[2].to_string(),
+                        entry_type: EntryType::User,
+                        enabled,
+                        has_extra_config: !properties.is_empty(),
+                        text_range: (
+                            node.text_range().start().into(),
+                            node.text_range().end().into(),
+                        ),
+                        properties,
+                        enable_binding,
+                        enable_range: bool_literal_range(&enable_value),
+                        enable_expr: enable_expression_text(&enable_value),
+                        package_list: None,
+                        note: None,
+                        file: self.path.clone(),
+                        condition,
+                        section: None,
+                    });
                 }
             }
         }
@@ -484,25 +1485,135 @@ impl NixConfig {
         parts.join(".")
     }
 
-    fn get_bool_value(&self, value: &Option<SyntaxNode>) -> bool {
-        if let Some(val) = value {
-            let text = val.text().to_string();
-            text.trim() == "true"
-        } else {
-            false
+    /// Work out the effective bool of an `enable` value. Literals are read
+    /// directly; an identifier is looked up in the enclosing `let` blocks.
+    fn resolve_enable_value(&self, value: &Option<SyntaxNode>) -> (bool, Option<LetBinding>) {
+        let Some(val) = value else {
+            return (false, None);
+        };
+        let val = &strip_priority(val);
+
+        let text = val.text().to_string();
+        match text.trim() {
+            "true" => (true, None),
+            "false" => (false, None),
+            name if val.kind() == SyntaxKind::NODE_IDENT => self.resolve_let_binding(val, name),
+            _ => (false, None),
+        }
+    }
+
+    /// Find the innermost `let` binding of `name` visible from `node`. The
+    /// binding is reported even if its value isn't a plain bool, so the
+    /// entry can still show where its state comes from.
+    fn resolve_let_binding(&self, node: &SyntaxNode, name: &str) -> (bool, Option<LetBinding>) {
+        for let_in in node
+            .ancestors()
+            .filter(|n| n.kind() == SyntaxKind::NODE_LET_IN)
+        {
+            for binding in let_in.children() {
+                if binding.kind() != SyntaxKind::NODE_ATTRPATH_VALUE {
+                    continue;
+                }
+                let bound_name = binding
+                    .children()
+                    .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH)
+                    .map(|ap| self.get_attrpath_text(&ap));
+                if bound_name.as_deref() != Some(name) {
+                    continue;
+                }
+
+                let value = binding
+                    .children()
+                    .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH);
+                let (enabled, value_range) = match value {
+                    Some(v) if matches!(v.text().to_string().trim(), "true" | "false") => (
+                        v.text().to_string().trim() == "true",
+                        Some((v.text_range().start().into(), v.text_range().end().into())),
+                    ),
+                    _ => (false, None),
+                };
+
+                return (
+                    enabled,
+                    Some(LetBinding {
+                        name: name.to_string(),
+                        value_range,
+                    }),
+                );
+            }
         }
+
+        // Bound somewhere we can't see, e.g. a function argument
+        (
+            false,
+            Some(LetBinding {
+                name: name.to_string(),
+                value_range: None,
+            }),
+        )
+    }
+
+    /// Value of an attribute set's `enable = ...;`
+    fn enable_value_in_set(&self, attr_set: &SyntaxNode) -> Option<SyntaxNode> {
+        let enable = attr_set
+            .children()
+            .filter(|c| c.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|c| {
+                c.children()
+                    .find(|a| a.kind() == SyntaxKind::NODE_ATTRPATH)
+                    .is_some_and(|a| self.get_attrpath_text(&a) == "enable")
+            })?;
+        enable
+            .children()
+            .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH)
     }
 
     fn check_attr_set_for_enable(
         &self,
         attr_set: &SyntaxNode,
-    ) -> Option<(bool, bool, Vec<ConfigProperty>)> {
+    ) -> Option<(bool, bool, Vec<ConfigProperty>, Option<LetBinding>)> {
+        let (enable, properties) = self.read_attr_set(attr_set);
+        let (enabled, enable_binding) = enable?;
+        Some((enabled, !properties.is_empty(), properties, enable_binding))
+    }
+
+    /// The `enable` of an entry's attribute set, if it sets one, and its other
+    /// attributes as properties
+    fn read_attr_set(
+        &self,
+        attr_set: &SyntaxNode,
+    ) -> (Option<(bool, Option<LetBinding>)>, Vec<ConfigProperty>) {
         let mut found_enable = false;
         let mut enabled = false;
+        let mut enable_binding = None;
         let mut properties = Vec::new();
 
         for child in attr_set.children() {
-            if child.kind() == SyntaxKind::NODE_ATTRPATH_VALUE {
+            // `inherit enable;` takes the value from the surrounding scope
+            if child.kind() == SyntaxKind::NODE_INHERIT {
+                let inherits_enable = child.children().any(|c| {
+                    c.kind() == SyntaxKind::NODE_IDENT && c.text().to_string().trim() == "enable"
+                });
+                if inherits_enable {
+                    found_enable = true;
+                    (enabled, enable_binding) = if let Some(from) = child
+                        .children()
+                        .find(|c| c.kind() == SyntaxKind::NODE_INHERIT_FROM)
+                    {
+                        let from = from.text().to_string();
+                        let from = from.trim().trim_start_matches('(').trim_end_matches(')');
+                        (
+                            false,
+                            Some(LetBinding {
+                                name: format!("{}.enable", from.trim()),
+                                value_range: None,
+                            }),
+                        )
+                    } else {
+                        self.resolve_let_binding(&child, "enable")
+                    };
+                }
+            } else if child.kind() == SyntaxKind::NODE_ATTRPATH_VALUE {
                 let attrpath = child
                     .children()
                     .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH);
@@ -516,10 +1627,7 @@ impl NixConfig {
 
                     if path_text == "enable" {
                         found_enable = true;
-                        if let Some(val_child) = value_node {
-                            let text = val_child.text().to_string().trim().to_string();
-                            enabled = text == "true";
-                        }
+                        (enabled, enable_binding) = self.resolve_enable_value(&value_node);
                     } else {
                         // Extract this as a property
                         if let Some(val_node) = value_node {
@@ -539,15 +1647,16 @@ impl NixConfig {
             }
         }
 
-        if found_enable {
-            Some((enabled, !properties.is_empty(), properties))
-        } else {
-            None
-        }
+        (
+            found_enable.then_some((enabled, enable_binding)),
+            properties,
+        )
     }
 
     /// Extract value and determine the property type from a value node
     fn extract_property_value(&self, node: &SyntaxNode) -> (String, PropertyType) {
+        // `mkForce` and the like are kept when the value is edited
+        let node = &strip_priority(node);
         let text = node.text().to_string().trim().to_string();
 
         match node.kind() {
@@ -586,27 +1695,67 @@ impl NixConfig {
     }
 
     fn extract_packages(&mut self, node: &SyntaxNode) {
-        // Handle "with pkgs; [ ... ]" pattern
-        if node.kind() == SyntaxKind::NODE_WITH {
-            for child in node.children() {
-                if child.kind() == SyntaxKind::NODE_LIST {
-                    self.extract_packages_from_list(&child);
-                    return;
-                }
-            }
+        for list in self.package_lists(node, 0) {
+            self.extract_packages_from_list(&list);
         }
+    }
 
-        // Handle direct list
-        if node.kind() == SyntaxKind::NODE_LIST {
-            self.extract_packages_from_list(node);
+    /// The lists a package option value is made of: a list, the body of
+    /// `with pkgs; [ ... ]`, both sides of `base ++ [ extra ]`, and a name
+    /// bound with `let` in the same file (followed a few levels deep)
+    fn package_lists(&self, node: &SyntaxNode, depth: usize) -> Vec<SyntaxNode> {
+        match node.kind() {
+            SyntaxKind::NODE_LIST => vec![node.clone()],
+            SyntaxKind::NODE_WITH | SyntaxKind::NODE_PAREN => node
+                .children()
+                .last()
+                .map(|body| self.package_lists(&body, depth))
+                .unwrap_or_default(),
+            SyntaxKind::NODE_BIN_OP
+                if node
+                    .children_with_tokens()
+                    .any(|c| c.kind() == SyntaxKind::TOKEN_CONCAT) =>
+            {
+                node.children()
+                    .flat_map(|side| self.package_lists(&side, depth))
+                    .collect()
+            }
+            SyntaxKind::NODE_IDENT if depth < 8 => self
+                .let_binding(node)
+                .map(|value| self.package_lists(&value, depth + 1))
+                .unwrap_or_default(),
+            _ => Vec::new(),
         }
     }
 
+    /// The value bound to an identifier by the closest enclosing `let ... in`
+    fn let_binding(&self, ident: &SyntaxNode) -> Option<SyntaxNode> {
+        let name = ident.text().to_string();
+        ident
+            .ancestors()
+            .filter(|a| a.kind() == SyntaxKind::NODE_LET_IN)
+            .find_map(|let_in| {
+                let binding = let_in
+                    .children()
+                    .filter(|c| c.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+                    .find(|c| {
+                        c.children()
+                            .find(|a| a.kind() == SyntaxKind::NODE_ATTRPATH)
+                            .is_some_and(|a| self.get_attrpath_text(&a) == name)
+                    })?;
+                binding
+                    .children()
+                    .find(|v| v.kind() != SyntaxKind::NODE_ATTRPATH)
+            })
+    }
+
     fn extract_packages_from_list(&mut self, list_node: &SyntaxNode) {
         // Get the text range of the list to scan for commented packages
         let list_start: usize = list_node.text_range().start().into();
         let list_end: usize = list_node.text_range().end().into();
         let list_text = &self.content[list_start..list_end];
+        let list_line = self.line_number_at(list_start);
+        let first_entry = self.entries.len();
 
         // First, extract active packages from AST
         for child in list_node.children() {
@@ -623,6 +1772,14 @@ impl NixConfig {
                             child.text_range().end().into(),
                         ),
                         properties: Vec::new(),
+                        enable_binding: None,
+                        enable_range: None,
+                        enable_expr: None,
+                        package_list: Some(list_line),
+                        note: None,
+                        file: self.path.clone(),
+                        condition: None,
+                        section: None,
                     });
                 }
                 SyntaxKind::NODE_SELECT => {
@@ -639,6 +1796,14 @@ impl NixConfig {
                             child.text_range().end().into(),
                         ),
                         properties: Vec::new(),
+                        enable_binding: None,
+                        enable_range: None,
+                        enable_expr: None,
+                        package_list: Some(list_line),
+                        note: None,
+                        file: self.path.clone(),
+                        condition: None,
+                        section: None,
                     });
                 }
                 _ => {}
@@ -678,6 +1843,14 @@ impl NixConfig {
                                 has_extra_config: false,
                                 text_range: (abs_start, abs_end),
                                 properties: Vec::new(),
+                                enable_binding: None,
+                                enable_range: None,
+                                enable_expr: None,
+                                package_list: Some(list_line),
+                                note: None,
+                                file: self.path.clone(),
+                                condition: None,
+                                section: None,
                             });
                             break;
                         }
@@ -685,6 +1858,23 @@ impl NixConfig {
                 }
             }
         }
+
+        // Section headers like "# Development" group the packages below them
+        let mut headers = Vec::new();
+        let mut offset = list_start;
+        for line in list_text.split_inclusive('\n') {
+            if let Some(header) = section_header(line) {
+                headers.push((offset, header));
+            }
+            offset += line.len();
+        }
+        for entry in &mut self.entries[first_entry..] {
+            entry.section = headers
+                .iter()
+                .rev()
+                .find(|(pos, _)| *pos < entry.text_range.0)
+                .map(|(_, header)| header.clone());
+        }
     }
 
     pub fn set_entry_enabled(
@@ -693,24 +1883,46 @@ impl NixConfig {
         entry_type: &EntryType,
         enabled: bool,
     ) -> Result<()> {
+        if let Some(module) = self.import_with_entry(name, entry_type) {
+            return module.set_entry_enabled(name, entry_type, enabled);
+        }
         // Find the entry
         let entry_exists = self
             .entries
             .iter()
             .any(|e| e.name == name && &e.entry_type == entry_type);
 
-        if entry_exists {
-            match entry_type {
-                EntryType::Program | EntryType::Service => {
-                    self.toggle_enable_entry(name, entry_type, enabled)?;
-                }
-                EntryType::Package => {
-                    self.toggle_package(name, enabled)?;
+        if !entry_exists {
+            return Ok(());
+        }
+        match entry_type {
+            // Updates the parsed entries in place, as a full reparse after every
+            // toggle is slow on large configs
+            EntryType::Program | EntryType::Service => {
+                self.toggle_enable_entry(name, entry_type, enabled)
+            }
+            EntryType::Package => {
+                self.toggle_package(name, enabled)?;
+                self.reparse()
+            }
+            EntryType::User => {
+                // A user without `enable` is on; turning it off adds one
+                let implicit = self.entries.iter().any(|e| {
+                    e.name == name
+                        && e.entry_type == EntryType::User
+                        && e.enable_range.is_none()
+                        && e.enable_binding.is_none()
+                        && e.enable_expr.is_none()
+                });
+                match (implicit, enabled) {
+                    (true, true) => Ok(()),
+                    (true, false) => {
+                        self.add_property(name, entry_type, "enable", "false", &PropertyType::Bool)
+                    }
+                    (false, _) => self.toggle_enable_entry(name, entry_type, enabled),
                 }
             }
         }
-
-        self.reparse()
     }
 
     fn toggle_enable_entry(
@@ -724,54 +1936,82 @@ impl NixConfig {
         }
         let prefix = entry_type.prefix();
 
-        // Find and replace enable = true/false
-        let patterns = [
-            format!("{}.{}.enable = true", prefix, name),
-            format!("{}.{}.enable = false", prefix, name),
-            format!("{}.{}.enable=true", prefix, name),
-            format!("{}.{}.enable=false", prefix, name),
-        ];
-
-        let replacement = format!("{}.{}.enable = {}", prefix, name, enabled);
-
-        for pattern in &patterns {
-            if self.content.contains(pattern) {
-                self.content = self.content.replace(pattern, &replacement);
-                return Ok(());
-            }
-        }
-
-        // Try to find "enable = true/false" within the block
-        // This is a simplified approach - for complex cases we'd need more sophisticated editing
-        let block_pattern_true = format!("enable = true");
-        let block_pattern_false = format!("enable = false");
-
-        // Find the entry's text range and modify within it
-        if let Some(entry) = self
+        let Some(entry) = self
             .entries
             .iter()
             .find(|e| e.name == name && &e.entry_type == entry_type)
-        {
-            let (start, end) = entry.text_range;
-            let block_text = &self.content[start..end];
+        else {
+            return Ok(());
+        };
 
-            let new_block = if enabled {
-                block_text.replace(&block_pattern_false, &block_pattern_true)
-            } else {
-                block_text.replace(&block_pattern_true, &block_pattern_false)
-            };
+        // Replace the parsed value itself, so spacing like `enable  =  true ;` doesn't matter.
+        // An indirect enable is toggled at its `let` binding, not at the reference.
+        let range = match &entry.enable_binding {
+            Some(binding) => binding.value_range.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{}.{}.enable is set through `{}`, which isn't a plain true/false",
+                    prefix,
+                    name,
+                    binding.name
+                )
+            })?,
+            None => entry.enable_range.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{}.{}.enable isn't a plain true/false, press E to edit its expression",
+                    prefix,
+                    name
+                )
+            })?,
+        };
 
-            self.content = format!(
-                "{}{}{}",
-                &self.content[..start],
-                new_block,
-                &self.content[end..]
-            );
+        self.splice(range, &enabled.to_string());
+        // Everything set through the same value changes with it, e.g. several
+        // entries using one `let` binding
+        for entry in &mut self.entries {
+            let binding_range = entry.enable_binding.as_ref().and_then(|b| b.value_range);
+            if binding_range.map(|r| r.0) == Some(range.0)
+                || entry.enable_range.map(|r| r.0) == Some(range.0)
+            {
+                entry.enabled = enabled;
+            }
         }
-
         Ok(())
     }
 
+    /// Replace a range of the content, moving the parsed positions after it
+    /// so the entries stay valid without a reparse
+    fn splice(&mut self, range: (usize, usize), text: &str) {
+        self.content.replace_range(range.0..range.1, text);
+
+        let old_end = range.1;
+        let new_end = range.0 + text.len();
+        let shift = |pos: &mut usize| {
+            if *pos >= old_end {
+                *pos = *pos - old_end + new_end;
+            }
+        };
+        let shift_range = |r: &mut (usize, usize)| {
+            shift(&mut r.0);
+            shift(&mut r.1);
+        };
+        for entry in &mut self.entries {
+            shift_range(&mut entry.text_range);
+            if let Some(r) = &mut entry.enable_range {
+                shift_range(r);
+            }
+            if let Some(r) = entry
+                .enable_binding
+                .as_mut()
+                .and_then(|b| b.value_range.as_mut())
+            {
+                shift_range(r);
+            }
+            for property in &mut entry.properties {
+                shift_range(&mut property.text_range);
+            }
+        }
+    }
+
     fn toggle_package(&mut self, name: &str, enabled: bool) -> Result<()> {
         if enabled {
             // Uncomment the package
@@ -802,19 +2042,110 @@ impl NixConfig {
     }
 
     pub fn add_entry(&mut self, name: &str, entry_type: &EntryType) -> Result<()> {
+        // Next to the entries of the same type, even if they're all in an imported file
+        if !self.entries.iter().any(|e| &e.entry_type == entry_type) {
+            if let Some(module) = self
+                .imports
+                .iter_mut()
+                .find_map(|m| m.module_with_type_mut(entry_type))
+            {
+                return module.add_entry(name, entry_type);
+            }
+        }
+
         match entry_type {
-            EntryType::Program | EntryType::Service => {
-                let new_line = format!("  {}.{}.enable = true;\n", entry_type.prefix(), name);
+            EntryType::Program | EntryType::Service | EntryType::User => {
+                // Match the indentation of existing entries of this type
+                let indent = self
+                    .entries
+                    .iter()
+                    .filter(|e| &e.entry_type == entry_type)
+                    .map(|e| e.text_range.0)
+                    .min()
+                    .map(|start| self.line_indent_at(start));
+                let indent = match indent {
+                    Some(indent) => indent,
+                    None => self.settings_insert_point()?.1,
+                };
+                let new_line = if entry_type == &EntryType::User {
+                    // A login account, as `users.users.<name>` is mostly used for
+                    let unit = self.indent_unit();
+                    format!(
+                        "{indent}users.users.{} = {{\n{indent}{unit}isNormalUser = true;\n{indent}}};\n",
+                        name
+                    )
+                } else {
+                    format!(
+                        "{}{}.{}.enable = true;\n",
+                        indent,
+                        entry_type.prefix(),
+                        name
+                    )
+                };
                 self.insert_entry_using_ast(&new_line, entry_type)?;
             }
             EntryType::Package => {
-                self.add_package_using_ast(name)?;
+                self.add_package_using_ast(name, 0)?;
             }
         }
 
         self.reparse()
     }
 
+    /// Position of the closing brace of the attribute set the config evaluates to,
+    /// looking through the argument pattern (`{ pkgs, lib ? ..., ... }:`), `let ... in`,
+    /// `with` and parentheses. Braces in the arguments or in comments don't count.
+    fn body_close_position(&self) -> Result<usize> {
+        let parse = rnix::Root::parse(&self.content);
+        let set = body_attr_set(parse.tree().syntax())?;
+        let end: usize = set.text_range().end().into();
+        Ok(end - 1)
+    }
+
+    /// Where new settings go when there's nothing similar to put them next to:
+    /// the end of the config's attribute set or, in modules that declare
+    /// `options`, of its `config = { ... };` block. Returns the position (the
+    /// start of the closing brace's line if the brace is on a line of its own)
+    /// and the indentation for the new lines.
+    fn settings_insert_point(&self) -> Result<(usize, String)> {
+        let parse = rnix::Root::parse(&self.content);
+        let body = body_attr_set(parse.tree().syntax())?;
+        let set = self.config_block(&body).unwrap_or(body);
+
+        let close = usize::from(set.text_range().end()) - 1;
+        let line_start = self.content[..close]
+            .rfind('\n')
+            .map(|p| p + 1)
+            .unwrap_or(0);
+        let pos = if self.content[line_start..close].trim().is_empty() {
+            line_start
+        } else {
+            close
+        };
+        Ok((
+            pos,
+            format!("{}{}", self.line_indent_at(close), self.indent_unit()),
+        ))
+    }
+
+    /// The attribute set assigned to `config` in a module's body (also through
+    /// `mkIf cond { ... }`), which holds the settings when the module declares `options`
+    fn config_block(&self, body: &SyntaxNode) -> Option<SyntaxNode> {
+        let mut node = body
+            .children()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|n| {
+                n.children()
+                    .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH)
+                    .is_some_and(|a| self.get_attrpath_text(&a) == "config")
+            })?
+            .last_child()?;
+        while matches!(node.kind(), SyntaxKind::NODE_APPLY | SyntaxKind::NODE_PAREN) {
+            node = node.last_child()?;
+        }
+        (node.kind() == SyntaxKind::NODE_ATTR_SET).then_some(node)
+    }
+
     /// Use rnix AST to find the correct insertion point for a new entry
     fn insert_entry_using_ast(&mut self, new_line: &str, entry_type: &EntryType) -> Result<()> {
         // Get all entries of this type with their positions
@@ -826,10 +2157,9 @@ impl NixConfig {
             .collect();
 
         if matching_entries.is_empty() {
-            // No existing entries of this type, insert before the final closing brace
-            if let Some(pos) = self.content.rfind('}') {
-                self.content.insert_str(pos, &format!("\n{}", new_line));
-            }
+            // No existing entries of this type, add them at the end of the settings
+            let (pos, _) = self.settings_insert_point()?;
+            self.content.insert_str(pos, &format!("\n{}", new_line));
             return Ok(());
         }
 
@@ -862,34 +2192,86 @@ impl NixConfig {
         Ok(())
     }
 
+    /// Add a package to the `list_index`-th package list
+    /// (see `package_list_lines`), falling back to the first one
+    pub fn add_package_to_list(&mut self, name: &str, list_index: usize) -> Result<()> {
+        self.add_package_using_ast(name, list_index)?;
+        self.reparse()
+    }
+
+    /// Add several packages to the `list_index`-th package list in the order given.
+    /// Either all of them are added or, if one fails, none are.
+    pub fn add_packages_to_list(&mut self, names: &[String], list_index: usize) -> Result<()> {
+        let original = self.content.clone();
+        // Each package goes right after the opening bracket, so add them back to front
+        for name in names.iter().rev() {
+            if let Err(e) = self.add_package_using_ast(name, list_index) {
+                self.content = original;
+                return Err(e);
+            }
+        }
+        self.reparse()
+    }
+
     /// Use rnix AST to find the package list and add a new package
-    fn add_package_using_ast(&mut self, name: &str) -> Result<()> {
+    fn add_package_using_ast(&mut self, name: &str, list_index: usize) -> Result<()> {
         let parse = rnix::Root::parse(&self.content);
         let root = parse.tree();
 
-        // Find environment.systemPackages list
-        if let Some(list_range) = self.find_packages_list(root.syntax()) {
-            // Insert after the opening bracket
-            let insert_pos = list_range.0 + 1;
-            let indent = "\n    ";
-            self.content
-                .insert_str(insert_pos, &format!("{}{}", indent, name));
+        // Find the environment.systemPackages / home.packages lists
+        let unit = self.indent_unit();
+        let lists = self.find_packages_lists(root.syntax());
+
+        if let Some(&list_range) = lists.get(list_index).or(lists.first()) {
+            let line_indent = self.line_indent_at(list_range.0);
+            let inner = &self.content[list_range.0 + 1..list_range.1 - 1];
+            if inner.trim().is_empty() {
+                // Empty list (e.g. `[ ]`): replace the inside so the closing
+                // bracket ends up on its own line, aligned with the statement
+                self.content.replace_range(
+                    list_range.0 + 1..list_range.1 - 1,
+                    &format!("\n{}{}{}\n{}", line_indent, unit, name, line_indent),
+                );
+            } else {
+                // Insert after the opening bracket
+                let insert_pos = list_range.0 + 1;
+                self.content
+                    .insert_str(insert_pos, &format!("\n{}{}{}", line_indent, unit, name));
+            }
+        } else if let Some(line) = self.unmanaged_packages_line() {
+            anyhow::bail!(
+                "{} on line {} isn't a plain list, so packages can't be added to it",
+                self.packages_option(),
+                line
+            );
         } else {
-            // No systemPackages exists, create it before the final closing brace
+            // No package list exists, create it at the end of the settings
+            let (pos, indent) = self.settings_insert_point()?;
             let new_block = format!(
-                "\n  environment.systemPackages = with pkgs; [\n    {}\n  ];\n",
+                "\n{indent}{} = with pkgs; [\n{indent}{unit}{}\n{indent}];\n",
+                self.packages_option(),
                 name
             );
-            if let Some(pos) = self.content.rfind('}') {
-                self.content.insert_str(pos, &new_block);
-            }
+            self.content.insert_str(pos, &new_block);
         }
 
         Ok(())
     }
 
-    /// Find the text range of the package list (the [ ] part)
-    fn find_packages_list(&self, node: &SyntaxNode) -> Option<(usize, usize)> {
+    /// Find the text ranges of all package lists (the [ ] parts), in file order.
+    /// NixOS merges every `environment.systemPackages` definition (home-manager
+    /// every `home.packages` one), so there can be several.
+    fn find_packages_lists(&self, node: &SyntaxNode) -> Vec<(usize, usize)> {
+        self.find_packages_definitions(node)
+            .into_iter()
+            .filter_map(|(_, list)| list)
+            .collect()
+    }
+
+    /// Find every `environment.systemPackages`/`home.packages` definition in file order: its start
+    /// and the range of its list, if the value contains one at all
+    fn find_packages_definitions(&self, node: &SyntaxNode) -> Vec<(usize, Option<(usize, usize)>)> {
+        let mut definitions = Vec::new();
         for child in node.children() {
             if child.kind() == SyntaxKind::NODE_ATTRPATH_VALUE {
                 if let Some(attrpath) = child
@@ -897,22 +2279,264 @@ impl NixConfig {
                     .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH)
                 {
                     let path_text = self.get_attrpath_text(&attrpath);
-                    if path_text == "environment.systemPackages" {
-                        // Found it! Now find the list node
-                        for val_child in child.children() {
-                            if let Some(list_range) = self.find_list_in_node(&val_child) {
-                                return Some(list_range);
-                            }
+                    let value = child
+                        .children()
+                        .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH);
+                    let packages = value.and_then(|v| self.packages_value(&path_text, &v));
+                    // Found one! Now find the list nodes
+                    if let Some(packages) = packages {
+                        let start: usize = child.text_range().start().into();
+                        let lists = self.package_lists(&packages, 0);
+                        if lists.is_empty() {
+                            definitions.push((start, self.find_list_in_node(&packages)));
+                        }
+                        for list in lists {
+                            let range = (
+                                list.text_range().start().into(),
+                                list.text_range().end().into(),
+                            );
+                            definitions.push((start, Some(range)));
                         }
+                        continue;
                     }
                 }
             }
             // Recurse
-            if let Some(range) = self.find_packages_list(&child) {
-                return Some(range);
+            definitions.extend(self.find_packages_definitions(&child));
+        }
+        definitions
+    }
+
+    /// Warning for a file that doesn't look like a NixOS or home-manager module,
+    /// e.g. a derivation or a library file, which would leave the lists empty.
+    /// A module takes `config`/`pkgs` arguments or sets well-known options.
+    pub fn module_warning(&self) -> Option<&'static str> {
+        if self.modules().iter().any(|m| !m.entries.is_empty()) {
+            return None;
+        }
+
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+
+        let mut node = root.syntax().first_child();
+        while let Some(current) = node.clone() {
+            match current.kind() {
+                SyntaxKind::NODE_LAMBDA => {
+                    let takes_module_args = current.first_child().is_some_and(|param| {
+                        param
+                            .descendants()
+                            .filter(|n| n.kind() == SyntaxKind::NODE_IDENT)
+                            .any(|n| MODULE_ARGS.contains(&n.text().to_string().as_str()))
+                    });
+                    if takes_module_args {
+                        return None;
+                    }
+                    node = current.last_child();
+                }
+                SyntaxKind::NODE_LET_IN
+                | SyntaxKind::NODE_WITH
+                | SyntaxKind::NODE_ASSERT
+                | SyntaxKind::NODE_PAREN => node = current.last_child(),
+                _ => break,
             }
         }
-        None
+
+        let sets_module_options = node
+            .filter(|set| set.kind() == SyntaxKind::NODE_ATTR_SET)
+            .is_some_and(|set| {
+                set.children()
+                    .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+                    .filter_map(|n| n.children().find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH))
+                    .any(|path| {
+                        let path = self.get_attrpath_text(&path);
+                        let first = path.split('.').next().unwrap_or_default();
+                        MODULE_ATTRS.contains(&first)
+                    })
+            });
+        if sets_module_options {
+            return None;
+        }
+
+        Some(
+            "This file doesn't look like a NixOS/home-manager module; \
+             open your configuration.nix (or home.nix) instead",
+        )
+    }
+
+    /// Line of a package option definition that isn't a list
+    /// (e.g. `builtins.attrValues myPackages`), if there's no list to add to.
+    /// Adding a second definition next to it would conflict.
+    pub fn unmanaged_packages_line(&self) -> Option<usize> {
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let definitions = self.find_packages_definitions(root.syntax());
+        if definitions.iter().any(|(_, list)| list.is_some()) {
+            return None;
+        }
+        definitions
+            .first()
+            .map(|(start, _)| self.line_number_at(*start))
+    }
+
+    /// Add a package to the separate packages module next to this config,
+    /// creating it if needed, and make sure the config imports it. Like any
+    /// other edit, the module and the import are written by `save`.
+    pub fn add_package_to_module(&mut self, name: &str) -> Result<PathBuf> {
+        let module_path = Path::new(&self.path)
+            .parent()
+            .unwrap_or(Path::new("."))
+            .join(PACKAGES_MODULE);
+        let loaded_path = module_path
+            .canonicalize()
+            .unwrap_or_else(|_| module_path.clone());
+
+        if let Some(module) = self.module_mut(&loaded_path.to_string_lossy()) {
+            module.add_entry(name, &EntryType::Package)?;
+        } else if module_path.exists() {
+            // Left over from an earlier session, but not imported (any more)
+            let mut module = NixConfig::load(&loaded_path)?;
+            module.add_entry(name, &EntryType::Package)?;
+            self.imports.push(module);
+        } else {
+            let unit = self.indent_unit();
+            let mut module = NixConfig {
+                path: module_path.to_string_lossy().to_string(),
+                content: format!(
+                    "{{ pkgs, ... }}:\n{{\n{unit}{} = with pkgs; [\n{unit}{unit}{}\n{unit}];\n}}\n",
+                    self.packages_option(),
+                    name
+                ),
+                entries: Vec::new(),
+                imports: Vec::new(),
+            };
+            module.parse()?;
+            self.imports.push(module);
+        }
+
+        self.add_import(&format!("./{}", PACKAGES_MODULE))?;
+        Ok(module_path)
+    }
+
+    /// The config's `imports = [ ... ];` list
+    fn imports_list(&self, root: &SyntaxNode) -> Option<SyntaxNode> {
+        root.descendants()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|n| {
+                n.children()
+                    .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH)
+                    .is_some_and(|a| self.get_attrpath_text(&a) == "imports")
+            })
+            .and_then(|n| n.children().find(|c| c.kind() == SyntaxKind::NODE_LIST))
+    }
+
+    /// Files the config imports by path (`./hardware-configuration.nix`, `../common`),
+    /// resolved against the config's directory
+    pub fn imported_paths(&self) -> Vec<PathBuf> {
+        let parse = rnix::Root::parse(&self.content);
+        let Some(list) = self.imports_list(parse.tree().syntax()) else {
+            return Vec::new();
+        };
+        list.children()
+            .filter(|c| c.kind() == SyntaxKind::NODE_PATH)
+            .filter_map(|c| self.resolve_path_value(&c.text().to_string()))
+            .collect()
+    }
+
+    /// Add a path to the config's `imports` list, creating the list if needed
+    fn add_import(&mut self, import: &str) -> Result<()> {
+        let imported = self.imported_paths();
+        if self
+            .resolve_path_value(import)
+            .is_some_and(|path| imported.contains(&path))
+        {
+            return Ok(());
+        }
+
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+
+        let unit = self.indent_unit();
+        if let Some(list) = self.imports_list(root.syntax()) {
+            // Insert after the opening bracket
+            let start: usize = list.text_range().start().into();
+            let line_indent = self.line_indent_at(start);
+            self.content
+                .insert_str(start + 1, &format!("\n{}{}{}", line_indent, unit, import));
+        } else {
+            let pos = self.body_close_position()?;
+            self.content
+                .insert_str(pos, &format!("\n{unit}imports = [ {} ];\n", import));
+        }
+
+        self.reparse()
+    }
+
+    /// The package list value of a `path_text = value` binding, for
+    /// `environment.systemPackages = ...`, `home.packages = ...`,
+    /// `users.users.alice.packages = ...` and the nested
+    /// `environment = { systemPackages = ...; }` form
+    fn packages_value(&self, path_text: &str, value: &SyntaxNode) -> Option<SyntaxNode> {
+        PACKAGE_OPTIONS.iter().find_map(|(set, attr)| {
+            if path_matches(path_text, &format!("{}.{}", set, attr)) {
+                Some(value.clone())
+            } else if path_matches(path_text, set) {
+                self.attr_in_set(value, attr)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The value of `attr` inside an attribute set like `environment = { ... }`
+    fn attr_in_set(&self, node: &SyntaxNode, attr: &str) -> Option<SyntaxNode> {
+        if node.kind() != SyntaxKind::NODE_ATTR_SET {
+            return None;
+        }
+        node.children()
+            .filter(|c| c.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|c| {
+                c.children()
+                    .find(|a| a.kind() == SyntaxKind::NODE_ATTRPATH)
+                    .is_some_and(|a| self.get_attrpath_text(&a) == attr)
+            })
+            .and_then(|c| c.children().find(|v| v.kind() != SyntaxKind::NODE_ATTRPATH))
+    }
+
+    /// The option packages are added to: `home.packages` in a home-manager
+    /// config (one whose body sets `home.*` or `home = { ... }`),
+    /// `environment.systemPackages` otherwise. A `home` nested deeper, like
+    /// `users.users.alice.home`, is an ordinary NixOS option.
+    pub fn packages_option(&self) -> &'static str {
+        let parse = rnix::Root::parse(&self.content);
+        let is_home_manager = body_attr_set(parse.tree().syntax()).is_ok_and(|body| {
+            let config = self.config_block(&body);
+            std::iter::once(body)
+                .chain(config)
+                .flat_map(|set| set.children())
+                .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+                .filter_map(|n| n.children().find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH))
+                .any(|a| self.get_attrpath_text(&a).split('.').next() == Some("home"))
+        });
+        if is_home_manager {
+            "home.packages"
+        } else {
+            "environment.systemPackages"
+        }
+    }
+
+    /// 1-based line numbers of every package list, in file order
+    pub fn package_list_lines(&self) -> Vec<usize> {
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        self.find_packages_lists(root.syntax())
+            .into_iter()
+            .map(|(start, _)| self.line_number_at(start))
+            .collect()
+    }
+
+    /// 1-based line number of a byte offset
+    fn line_number_at(&self, pos: usize) -> usize {
+        self.content[..pos].matches('\n').count() + 1
     }
 
     /// Find a NODE_LIST within a node (handles "with pkgs; [ ... ]" pattern)
@@ -931,22 +2555,45 @@ impl NixConfig {
         None
     }
 
+    /// Write the config and its imports back to disk. A file identical to what's
+    /// already there isn't touched at all, so an unedited config never churns.
+    /// The previous version of each file is backed up first; if that fails,
+    /// nothing is written.
     pub fn save(&self) -> Result<()> {
-        fs::write(&self.path, &self.content).context("Failed to save NixOS config file")?;
+        self.save_with_backups_in(&backup_dir())
+    }
+
+    /// `save`, keeping the backups in `backups`
+    fn save_with_backups_in(&self, backups: &Path) -> Result<()> {
+        let changed: Vec<&NixConfig> = self
+            .modules()
+            .into_iter()
+            .filter(|m| !fs::read_to_string(&m.path).is_ok_and(|on_disk| on_disk == m.content))
+            .collect();
+        for module in &changed {
+            backup_file(Path::new(&module.path), backups)?;
+        }
+        for module in changed {
+            fs::write(&module.path, &module.content)
+                .with_context(|| format!("Failed to save {}", module.path))?;
+        }
         Ok(())
     }
 
+    /// Entries of a type from the config and its imports
     pub fn get_entries_by_type(&self, entry_type: &EntryType) -> Vec<&ConfigEntry> {
-        self.entries
-            .iter()
+        self.modules()
+            .into_iter()
+            .flat_map(|m| &m.entries)
             .filter(|e| &e.entry_type == entry_type)
             .collect()
     }
 
-    /// Get an entry by name and type
+    /// Get an entry by name and type, from the config or its imports
     pub fn get_entry(&self, name: &str, entry_type: &EntryType) -> Option<&ConfigEntry> {
-        self.entries
-            .iter()
+        self.modules()
+            .into_iter()
+            .flat_map(|m| &m.entries)
             .find(|e| e.name == name && &e.entry_type == entry_type)
     }
 
@@ -974,9 +2621,22 @@ impl NixConfig {
         property_name: &str,
         new_value: &str,
     ) -> Result<()> {
+        if let Some(module) = self.import_with_entry(entry_name, entry_type) {
+            return module.set_property(entry_name, entry_type, property_name, new_value);
+        }
         let property_range = self.find_property_range(entry_name, entry_type, property_name);
 
         if let Some((start, end)) = property_range {
+            // Under `mkForce` and the like only the value itself is replaced
+            if let Some((inner_start, inner_end)) = self.prioritized_value_range(start) {
+                let formatted_value =
+                    self.format_property_value(new_value.trim_end().trim_end_matches(';'));
+                self.content
+                    .replace_range(inner_start..inner_end, &formatted_value);
+                return self.reparse();
+            }
+
+            let end = self.property_edit_end(start, end);
             // Replace the entire property line
             let old_text = &self.content[start..end];
 
@@ -985,9 +2645,18 @@ impl NixConfig {
             if let Some(eq_pos) = old_text.find('=') {
                 let before_eq = &old_text[..=eq_pos];
                 // Format the new value appropriately
-                let formatted_value = self.format_property_value(new_value);
-                // Make sure to include the semicolon
+                // Exactly one semicolon, whatever the value or the old text ended with
+                let formatted_value =
+                    self.format_property_value(new_value.trim_end().trim_end_matches(';'));
                 let new_text = format!("{} {};", before_eq, formatted_value);
+                // A stray `;` right after the old range would now be a duplicate
+                let end = match self.content[end..]
+                    .trim_start_matches([' ', '\t'])
+                    .strip_prefix(';')
+                {
+                    Some(rest) => self.content.len() - rest.len(),
+                    None => end,
+                };
 
                 self.content = format!(
                     "{}{}{}",
@@ -1003,33 +2672,134 @@ impl NixConfig {
         Ok(())
     }
 
-    /// Add a new property to an entry
-    pub fn add_property(
-        &mut self,
-        entry_name: &str,
-        entry_type: &EntryType,
-        property_name: &str,
-        value: &str,
-        _property_type: &PropertyType,
+    /// Range of the value under a priority wrapper like `mkForce`, for the
+    /// definition starting at `start`
+    fn prioritized_value_range(&self, start: usize) -> Option<(usize, usize)> {
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let value = root
+            .syntax()
+            .descendants()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|n| usize::from(n.text_range().start()) == start)?
+            .children()
+            .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH)?;
+        let inner = strip_priority(&value);
+        (inner.text_range() != value.text_range()).then(|| {
+            (
+                inner.text_range().start().into(),
+                inner.text_range().end().into(),
+            )
+        })
+    }
+
+    /// End of a property's text for replacing it. If a manual edit left it
+    /// without its `;`, the parser may have run on into the next line (e.g.
+    /// `port = 80\n  user` as a function call), so stop at the end of the value's
+    /// first line instead of swallowing the next attribute.
+    fn property_edit_end(&self, start: usize, end: usize) -> usize {
+        let text = &self.content[start..end];
+        let Some(eq_pos) = text.find('=') else {
+            return end;
+        };
+        let value = text[eq_pos + 1..].trim_end();
+        let value = value.strip_suffix(';').unwrap_or(value);
+        // A following line of the form `name = ...` means the next attribute was swallowed
+        let swallowed_binding = value.lines().skip(1).any(|line| {
+            let line = line.trim_start();
+            line.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && line.split_once('=').is_some_and(|(name, rest)| {
+                    !rest.starts_with('=')
+                        && name
+                            .trim_end()
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || "._-'".contains(c))
+                })
+        });
+        if text.trim_end().ends_with(';')
+            && bracket_balance_error(value).is_none()
+            && !swallowed_binding
+        {
+            return end;
+        }
+
+        // Only cut if the first line already holds a complete value
+        match text.find('\n') {
+            Some(newline)
+                if newline > eq_pos
+                    && !text[eq_pos + 1..newline].trim().is_empty()
+                    && bracket_balance_error(&text[eq_pos + 1..newline]).is_none() =>
+            {
+                start + text[..newline].trim_end().len()
+            }
+            _ => end,
+        }
+    }
+
+    /// Add a new property to an entry
+    pub fn add_property(
+        &mut self,
+        entry_name: &str,
+        entry_type: &EntryType,
+        property_name: &str,
+        value: &str,
+        _property_type: &PropertyType,
     ) -> Result<()> {
+        if let Some(module) = self.import_with_entry(entry_name, entry_type) {
+            return module.add_property(
+                entry_name,
+                entry_type,
+                property_name,
+                value,
+                _property_type,
+            );
+        }
         // Find the entry
         let entry = self
             .entries
             .iter()
             .find(|e| e.name == entry_name && &e.entry_type == entry_type);
 
+        // `settings.x` goes inside an existing `settings = { ... }`, as a
+        // second `settings` definition would be a duplicate attribute
+        let parent = entry.and_then(|e| {
+            e.properties
+                .iter()
+                .find(|p| property_name.starts_with(&format!("{}.", p.name)))
+                .map(|p| (p.name.clone(), p.text_range.0))
+        });
+        if let Some((parent, start)) = parent {
+            let path = &property_name[parent.len() + 1..];
+            return self.set_in_attr_set(start, &parent, path, value);
+        }
+
         if let Some(entry) = entry {
             let (start, end) = entry.text_range;
             let entry_text = &self.content[start..end];
 
+            let base = self.line_indent_at(start);
+            let unit = self.indent_unit();
+
             // Check if this is a block style (has braces) or simple enable style
             if entry_text.contains('{') {
                 // Block style: insert before the closing brace
                 if let Some(close_brace_pos) = entry_text.rfind('}') {
-                    let insert_pos = start + close_brace_pos;
+                    let close_pos = start + close_brace_pos;
                     let formatted_value = self.format_property_value(value);
-                    let new_prop = format!("    {} = {};\n  ", property_name, formatted_value);
-                    self.content.insert_str(insert_pos, &new_prop);
+                    let line_start = self.content[..close_pos]
+                        .rfind('\n')
+                        .map(|p| p + 1)
+                        .unwrap_or(0);
+                    if self.content[line_start..close_pos].trim().is_empty() {
+                        // Closing brace on its own line: add a full line above it
+                        let new_prop =
+                            format!("{}{}{} = {};\n", base, unit, property_name, formatted_value);
+                        self.content.insert_str(line_start, &new_prop);
+                    } else {
+                        // One-line block like `{ enable = true; }`
+                        let new_prop = format!("{} = {}; ", property_name, formatted_value);
+                        self.content.insert_str(close_pos, &new_prop);
+                    }
                 }
             } else {
                 // Simple enable style: need to convert to block style
@@ -1040,7 +2810,7 @@ impl NixConfig {
                 let formatted_value = self.format_property_value(value);
                 let enabled = if entry.enabled { "true" } else { "false" };
                 let new_block = format!(
-                    "{}.{} = {{\n    enable = {};\n    {} = {};\n  }};",
+                    "{}.{} = {{\n{base}{unit}enable = {};\n{base}{unit}{} = {};\n{base}}};",
                     entry_type.prefix(),
                     entry_name,
                     enabled,
@@ -1063,6 +2833,96 @@ impl NixConfig {
         Ok(())
     }
 
+    /// Set `path` inside the attribute set bound to `parent` by the definition
+    /// starting at `start`, replacing the value already there or adding it
+    /// before the closing brace
+    fn set_in_attr_set(
+        &mut self,
+        start: usize,
+        parent: &str,
+        path: &str,
+        value: &str,
+    ) -> Result<()> {
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let mut set = root
+            .syntax()
+            .descendants()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .find(|n| usize::from(n.text_range().start()) == start)
+            .and_then(|n| n.children().find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH))
+            .map(|v| strip_priority(&v));
+        let mut parent = parent.to_string();
+        let mut path = path.to_string();
+        let formatted_value = self.format_property_value(value);
+
+        loop {
+            let Some(node) = set.filter(|s| s.kind() == SyntaxKind::NODE_ATTR_SET) else {
+                anyhow::bail!(
+                    "{} is not set to an attribute set to add {} to",
+                    parent,
+                    path
+                );
+            };
+            let binding = node
+                .children()
+                .filter(|c| c.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+                .find_map(|c| {
+                    let attrpath = c
+                        .children()
+                        .find(|a| a.kind() == SyntaxKind::NODE_ATTRPATH)?;
+                    let name = self.get_attrpath_text(&attrpath);
+                    (name == path || path.starts_with(&format!("{}.", name))).then_some((name, c))
+                });
+            match binding {
+                Some((name, binding)) => {
+                    let value_node = binding
+                        .children()
+                        .find(|c| c.kind() != SyntaxKind::NODE_ATTRPATH)
+                        .map(|v| strip_priority(&v));
+                    if name == path {
+                        let Some(value_node) = value_node else {
+                            anyhow::bail!("{}.{} has no value to replace", parent, path);
+                        };
+                        let range = value_node.text_range();
+                        self.content.replace_range(
+                            usize::from(range.start())..usize::from(range.end()),
+                            &formatted_value,
+                        );
+                        return self.reparse();
+                    }
+                    path = path[name.len() + 1..].to_string();
+                    parent = format!("{}.{}", parent, name);
+                    set = value_node;
+                }
+                None => {
+                    let close_pos = usize::from(node.text_range().end()) - 1;
+                    let line_start = self.content[..close_pos].rfind('\n').map_or(0, |p| p + 1);
+                    if self.content[line_start..close_pos].trim().is_empty() {
+                        // Closing brace on its own line: add a full line above it
+                        let new_prop = format!(
+                            "{}{}{} = {};\n",
+                            self.line_indent_at(close_pos),
+                            self.indent_unit(),
+                            path,
+                            formatted_value
+                        );
+                        self.content.insert_str(line_start, &new_prop);
+                    } else {
+                        let space = if self.content[..close_pos].ends_with(' ') {
+                            ""
+                        } else {
+                            " "
+                        };
+                        let new_prop = format!("{}{} = {}; ", space, path, formatted_value);
+                        self.content.insert_str(close_pos, &new_prop);
+                    }
+                    return self.reparse();
+                }
+            }
+        }
+    }
+
     /// Delete a property from an entry
     pub fn delete_property(
         &mut self,
@@ -1070,6 +2930,9 @@ impl NixConfig {
         entry_type: &EntryType,
         property_name: &str,
     ) -> Result<()> {
+        if let Some(module) = self.import_with_entry(entry_name, entry_type) {
+            return module.delete_property(entry_name, entry_type, property_name);
+        }
         let property_range = self.find_property_range(entry_name, entry_type, property_name);
 
         if let Some((start, end)) = property_range {
@@ -1096,8 +2959,312 @@ impl NixConfig {
         Ok(())
     }
 
+    /// Source ranges of a program/service's definitions in this file: its
+    /// block and any `prefix.name.*` lines, in order
+    fn definition_ranges(&self, name: &str, entry_type: &EntryType) -> Vec<(usize, usize)> {
+        let nested = self.nested_entry_names(name, entry_type);
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let mut ranges: Vec<(usize, usize)> = root
+            .syntax()
+            .descendants()
+            .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+            .filter(|node| {
+                node.children()
+                    .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH)
+                    .is_some_and(|path| {
+                        entry_name_range(&path, entry_type, name, &nested).is_some()
+                    })
+            })
+            .map(|node| {
+                (
+                    node.text_range().start().into(),
+                    node.text_range().end().into(),
+                )
+            })
+            .chain(
+                self.entries
+                    .iter()
+                    .filter(|e| e.name == name && &e.entry_type == entry_type)
+                    .map(|e| e.text_range),
+            )
+            .collect();
+
+        // Nested ranges go with the definition around them
+        ranges.sort_by_key(|&(start, end)| (std::cmp::Reverse(start), end));
+        let mut definitions: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in ranges {
+            if definitions.iter().any(|&(s, e)| s <= start && end <= e) {
+                continue;
+            }
+            definitions.retain(|&(s, e)| !(start <= s && e <= end));
+            definitions.push((start, end));
+        }
+        definitions.sort_by_key(|&(start, _)| start);
+        definitions
+    }
+
+    /// Names of the entries nested in this one, like `foo.server` in `foo`
+    fn nested_entry_names(&self, name: &str, entry_type: &EntryType) -> Vec<String> {
+        let prefix = format!("{}.", name);
+        self.entries
+            .iter()
+            .filter(|e| &e.entry_type == entry_type && e.name.starts_with(&prefix))
+            .map(|e| e.name.clone())
+            .collect()
+    }
+
+    /// Remove a program/service from the config: its block and any
+    /// `prefix.name.*` lines. Returns how many definitions were removed.
+    pub fn remove_entry(&mut self, name: &str, entry_type: &EntryType) -> Result<usize> {
+        if let Some(module) = self.import_with_entry(name, entry_type) {
+            return module.remove_entry(name, entry_type);
+        }
+        if entry_type == &EntryType::Package {
+            anyhow::bail!("Only programs and services can be removed as a block");
+        }
+
+        let removed = self.definition_ranges(name, entry_type);
+        if removed.is_empty() {
+            anyhow::bail!("{}.{} is not in the config", entry_type.prefix(), name);
+        }
+
+        // Back to front, so earlier offsets stay valid
+        for &(start, end) in removed.iter().rev() {
+            // Take the whole line when the definition is the only thing on it
+            let line_start = self.content[..start].rfind('\n').map_or(0, |p| p + 1);
+            let line_end = self.content[end..]
+                .find('\n')
+                .map_or(self.content.len(), |p| end + p + 1);
+            let alone = self.content[line_start..start].trim().is_empty()
+                && self.content[end..line_end].trim().is_empty();
+            if alone {
+                // Don't leave two blank lines where the definition was set apart by them
+                let blank_before = self.content[..line_start].ends_with("\n\n");
+                let blank_after = self.content[line_end..].starts_with('\n');
+                let line_end = line_end + usize::from(blank_before && blank_after);
+                self.content.replace_range(line_start..line_end, "");
+            } else {
+                // Along with the spaces separating it from the rest of the line
+                let rest = &self.content[end..line_end];
+                let spaces_after = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+                if rest.trim().is_empty() {
+                    let before = self.content[..start].trim_end_matches([' ', '\t']).len();
+                    self.content.replace_range(before..end, "");
+                } else {
+                    self.content.replace_range(start..end + spaces_after, "");
+                }
+            }
+        }
+
+        self.reparse()?;
+        Ok(removed.len())
+    }
+
+    /// Move a program/service's definitions to another loaded file, e.g. from a
+    /// module shared by several flake hosts into the host's own module
+    pub fn move_entry(&mut self, name: &str, entry_type: &EntryType, to: &str) -> Result<()> {
+        let source = self
+            .module_with_entry(name, entry_type)
+            .with_context(|| format!("{}.{} is not in the config", entry_type.prefix(), name))?;
+        if source.path == to {
+            anyhow::bail!("{}.{} is already in {}", entry_type.prefix(), name, to);
+        }
+        let target = self
+            .modules()
+            .into_iter()
+            .find(|m| m.path == to)
+            .with_context(|| format!("{} isn't part of the loaded config", to))?;
+        if target
+            .entries
+            .iter()
+            .any(|e| e.name == name && &e.entry_type == entry_type)
+        {
+            anyhow::bail!(
+                "{}.{} is also set in {}, merge them by hand",
+                entry_type.prefix(),
+                name,
+                to
+            );
+        }
+
+        // Names from the source file's arguments and `let` that the target lacks
+        let ranges = source.definition_ranges(name, entry_type);
+        let source_root = rnix::Root::parse(&source.content).tree();
+        let target_root = rnix::Root::parse(&target.content).tree();
+        let source_scope = file_scope_names(source_root.syntax());
+        let target_scope = file_scope_names(target_root.syntax());
+        let mut undefined: Vec<String> = source_root
+            .syntax()
+            .descendants()
+            .filter(|n| n.kind() == SyntaxKind::NODE_IDENT && is_variable(n))
+            .filter(|n| {
+                let start = usize::from(n.text_range().start());
+                ranges.iter().any(|&(s, e)| s <= start && start < e)
+            })
+            .map(|n| n.text().to_string())
+            .filter(|n| source_scope.contains(n) && !target_scope.contains(n))
+            .collect();
+        undefined.sort();
+        undefined.dedup();
+        if !undefined.is_empty() {
+            anyhow::bail!(
+                "{}.{} uses {}, which {} doesn't define; move it by hand",
+                entry_type.prefix(),
+                name,
+                undefined.join(", "),
+                to
+            );
+        }
+
+        // Definitions without their own indentation, which the target's replaces
+        let definitions: Vec<String> = ranges
+            .into_iter()
+            .map(|(start, end)| {
+                let indent = source.line_indent_at(start);
+                source.content[start..end]
+                    .lines()
+                    .map(|line| line.strip_prefix(indent.as_str()).unwrap_or(line))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect();
+        let mut source = source.clone();
+        let mut target = target.clone();
+
+        // Both files are edited on copies, so a failure leaves neither changed
+        let (pos, indent) = target.settings_insert_point()?;
+        let text: String = definitions
+            .iter()
+            .map(|definition| {
+                let lines: Vec<String> = definition
+                    .lines()
+                    .map(|line| format!("{}{}", indent, line))
+                    .collect();
+                format!("{}\n", lines.join("\n"))
+            })
+            .collect();
+        // Set apart from what's above, unless that's the start of the set
+        let separator = if target.content[..pos].trim_end().ends_with('{') {
+            ""
+        } else {
+            "\n"
+        };
+        target
+            .content
+            .insert_str(pos, &format!("{}{}", separator, text));
+        target.reparse()?;
+        source.remove_entry(name, entry_type)?;
+
+        // Only this file's own text, as one may be an import of the other
+        for edited in [source, target] {
+            let module = self.module_mut(&edited.path).context("File not loaded")?;
+            module.content = edited.content;
+            module.entries = edited.entries;
+        }
+        Ok(())
+    }
+
+    /// The loaded file (this one or an import) with the given path
+    fn module_mut(&mut self, path: &str) -> Option<&mut NixConfig> {
+        if self.path == path {
+            return Some(self);
+        }
+        self.imports.iter_mut().find_map(|m| m.module_mut(path))
+    }
+
+    /// Find lines that mention `name` outside the entry's own definition, e.g. a
+    /// package used in `programs.foo.package = pkgs.name;`, in the config and its
+    /// imports. Comments are ignored.
+    pub fn find_references(&self, name: &str, entry_type: &EntryType) -> Vec<Reference> {
+        if name.is_empty() {
+            return Vec::new();
+        }
+        self.modules()
+            .into_iter()
+            .flat_map(|m| m.references_in_file(name, entry_type))
+            .collect()
+    }
+
+    /// `find_references` within this file alone
+    fn references_in_file(&self, name: &str, entry_type: &EntryType) -> Vec<Reference> {
+        let own_ranges: Vec<(usize, usize)> = self
+            .entries
+            .iter()
+            .filter(|e| e.name == name && &e.entry_type == entry_type)
+            .map(|e| e.text_range)
+            .collect();
+
+        let parse = rnix::Root::parse(&self.content);
+        let root = parse.tree();
+        let is_ident_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '\'');
+
+        let mut references: Vec<Reference> = Vec::new();
+        for (pos, _) in self.content.match_indices(name) {
+            let end = pos + name.len();
+
+            // Only whole identifiers (or attribute path segments)
+            let before = self.content[..pos].chars().next_back();
+            let after = self.content[end..].chars().next();
+            if before.is_some_and(is_ident_char) || after.is_some_and(is_ident_char) {
+                continue;
+            }
+
+            if own_ranges
+                .iter()
+                .any(|&(start, stop)| pos >= start && end <= stop)
+            {
+                continue;
+            }
+
+            let in_comment = root
+                .syntax()
+                .token_at_offset(rowan::TextSize::from(pos as u32))
+                .right_biased()
+                .is_some_and(|t| t.kind() == SyntaxKind::TOKEN_COMMENT);
+            if in_comment {
+                continue;
+            }
+
+            let line = self.content[..pos].matches('\n').count() + 1;
+            if references.last().is_some_and(|r| r.line == line) {
+                continue;
+            }
+
+            let line_start = self.content[..pos].rfind('\n').map(|p| p + 1).unwrap_or(0);
+            let line_end = self.content[pos..]
+                .find('\n')
+                .map(|p| pos + p)
+                .unwrap_or(self.content.len());
+            references.push(Reference {
+                file: self.path.clone(),
+                line,
+                position: pos,
+                text: self.content[line_start..line_end].trim().to_string(),
+            });
+        }
+
+        references
+    }
+
+    /// The indentation unit for generated lines: from `.editorconfig` if present,
+    /// otherwise detected from the file itself
+    pub fn indent_unit(&self) -> String {
+        crate::editorconfig::indent_unit_for(Path::new(&self.path))
+            .unwrap_or_else(|| detect_indent_unit(&self.content))
+    }
+
+    /// The leading whitespace of the line containing byte offset `pos`
+    fn line_indent_at(&self, pos: usize) -> String {
+        let line_start = self.content[..pos].rfind('\n').map(|p| p + 1).unwrap_or(0);
+        self.content[line_start..]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect()
+    }
+
     /// Format a value appropriately for Nix syntax
-    fn format_property_value(&self, value: &str) -> String {
+    pub fn format_property_value(&self, value: &str) -> String {
         // Check if it's a boolean
         if value == "true" || value == "false" {
             return value.to_string();
@@ -1116,19 +3283,445 @@ impl NixConfig {
         }
 
         // Check if it's a path
-        if value.starts_with('/') || value.starts_with("./") || value.starts_with("~/") {
+        if value.starts_with('/')
+            || value.starts_with("./")
+            || value.starts_with("../")
+            || value.starts_with("~/")
+        {
             return value.to_string();
         }
 
         // Otherwise, treat as string and quote it
         format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
     }
+
+    /// Directory relative path values are resolved against: the config file's
+    fn config_dir(&self) -> PathBuf {
+        let dir = Path::new(&self.path)
+            .parent()
+            .filter(|d| !d.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf())
+    }
+
+    /// The absolute path a path value points to, like Nix resolves it: `./` and
+    /// `../` relative to the config file, `~/` in the home directory. `None` if
+    /// the value isn't a path literal.
+    pub fn resolve_path_value(&self, value: &str) -> Option<PathBuf> {
+        let value = value.trim().trim_end_matches(';').trim_end();
+        if value.contains(char::is_whitespace) {
+            return None;
+        }
+        let path = if value.starts_with('/') {
+            PathBuf::from(value)
+        } else if let Some(rest) = value.strip_prefix("~/") {
+            dirs::home_dir()?.join(rest)
+        } else if value.starts_with("./") || value.starts_with("../") {
+            self.config_dir().join(value)
+        } else {
+            return None;
+        };
+        Some(normalize_path(&path))
+    }
+
+    /// How to write an absolute path as a value: relative to the config file
+    /// if it's inside the config's directory, otherwise as is
+    pub fn path_literal(&self, path: &Path) -> String {
+        match path.strip_prefix(self.config_dir()) {
+            Ok(rest) if !rest.as_os_str().is_empty() => format!("./{}", rest.display()),
+            _ => path.display().to_string(),
+        }
+    }
+}
+
+/// How many backups of each file `backup_file` keeps
+const BACKUP_LIMIT: usize = 10;
+
+/// Where backups of saved files go
+fn backup_dir() -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("nixxed")
+        .join("backups")
+}
+
+/// Copy a file about to be overwritten to `<file>.<unix time>.<nanoseconds>` in
+/// `dir`, named after its whole path so `hosts/a/default.nix` and
+/// `hosts/b/default.nix` don't mix, and drop all but the newest `BACKUP_LIMIT`
+/// backups of it. A file that doesn't exist yet has nothing to back up.
+fn backup_file(path: &Path, dir: &Path) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create the backup directory {}", dir.display()))?;
+
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let name = path
+        .to_string_lossy()
+        .trim_start_matches('/')
+        .replace('/', "%");
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let (secs, mut nanos) = (time.as_secs(), time.subsec_nanos());
+    // Saves within the same instant still keep every backup
+    let mut backup = dir.join(format!("{}.{}.{:09}", name, secs, nanos));
+    while backup.exists() {
+        nanos += 1;
+        backup = dir.join(format!("{}.{}.{:09}", name, secs, nanos));
+    }
+    fs::copy(&path, &backup).with_context(|| {
+        format!(
+            "Failed to back up {} to {}, not saving",
+            path.display(),
+            backup.display()
+        )
+    })?;
+
+    // Timestamps have the same number of digits, so they sort by name
+    let prefix = format!("{}.", name);
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix(&prefix))
+                .is_some_and(|rest| rest.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        })
+        .collect();
+    backups.sort();
+    let excess = backups.len().saturating_sub(BACKUP_LIMIT);
+    for old in &backups[..excess] {
+        let _ = fs::remove_file(old);
+    }
+    Ok(())
+}
+
+/// Remove `.` and `..` components without touching the file system
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// The attribute set the config evaluates to, looking through the argument
+/// pattern, `let ... in`, `with`, `assert` and parentheses
+fn body_attr_set(root: &SyntaxNode) -> Result<SyntaxNode> {
+    let mut node = root.first_child();
+    while let Some(current) = node.clone() {
+        match current.kind() {
+            SyntaxKind::NODE_LAMBDA
+            | SyntaxKind::NODE_LET_IN
+            | SyntaxKind::NODE_WITH
+            | SyntaxKind::NODE_ASSERT
+            | SyntaxKind::NODE_PAREN => node = current.last_child(),
+            _ => break,
+        }
+    }
+
+    match node {
+        Some(set) if set.kind() == SyntaxKind::NODE_ATTR_SET => Ok(set),
+        _ => anyhow::bail!("The config doesn't evaluate to a plain attribute set"),
+    }
+}
+
+/// Names the whole file can use: its function's arguments and the bindings of
+/// `let` blocks around its body
+fn file_scope_names(root: &SyntaxNode) -> HashSet<String> {
+    let first_ident = |node: &SyntaxNode| {
+        node.descendants()
+            .find(|n| n.kind() == SyntaxKind::NODE_IDENT)
+            .map(|n| n.text().to_string())
+    };
+    let mut names = HashSet::new();
+    let mut node = root.first_child();
+    while let Some(current) = node {
+        match current.kind() {
+            SyntaxKind::NODE_LAMBDA => {
+                let param = current.first_child();
+                match param.as_ref().map(|p| p.kind()) {
+                    Some(SyntaxKind::NODE_PATTERN) => names.extend(
+                        param
+                            .iter()
+                            .flat_map(|p| p.children())
+                            .filter_map(|entry| first_ident(&entry)),
+                    ),
+                    Some(SyntaxKind::NODE_IDENT | SyntaxKind::NODE_IDENT_PARAM) => {
+                        names.extend(param.as_ref().and_then(first_ident))
+                    }
+                    _ => {}
+                }
+            }
+            SyntaxKind::NODE_LET_IN => {
+                for binding in current.children() {
+                    match binding.kind() {
+                        SyntaxKind::NODE_ATTRPATH_VALUE => names.extend(first_ident(&binding)),
+                        SyntaxKind::NODE_INHERIT => names.extend(
+                            binding
+                                .children()
+                                .filter(|n| n.kind() == SyntaxKind::NODE_IDENT)
+                                .map(|n| n.text().to_string()),
+                        ),
+                        _ => {}
+                    }
+                }
+            }
+            SyntaxKind::NODE_WITH | SyntaxKind::NODE_ASSERT | SyntaxKind::NODE_PAREN => {}
+            _ => break,
+        }
+        node = current.last_child();
+    }
+    names
+}
+
+/// Whether an identifier refers to a variable, rather than naming an attribute
+/// or a parameter
+fn is_variable(ident: &SyntaxNode) -> bool {
+    let Some(parent) = ident.parent() else {
+        return false;
+    };
+    let first = parent.first_child().as_ref() == Some(ident);
+    match parent.kind() {
+        SyntaxKind::NODE_ATTRPATH
+        | SyntaxKind::NODE_PAT_ENTRY
+        | SyntaxKind::NODE_PAT_BIND
+        | SyntaxKind::NODE_IDENT_PARAM => false,
+        // `a.b.c`: only `a` is a variable
+        SyntaxKind::NODE_SELECT => first,
+        // `x: body`: `x` is the parameter
+        SyntaxKind::NODE_LAMBDA => !first,
+        _ => true,
+    }
+}
+
+/// Name of the function applied in `node`, without a `lib.` prefix
+fn applied_function(node: &SyntaxNode) -> Option<String> {
+    if node.kind() != SyntaxKind::NODE_APPLY {
+        return None;
+    }
+    let function = node.first_child()?.text().to_string();
+    let function = function.trim();
+    Some(
+        function
+            .strip_prefix("lib.")
+            .unwrap_or(function)
+            .to_string(),
+    )
+}
+
+/// The value under `mkDefault`, `mkForce` and `mkOverride n` (also `lib.`
+/// prefixed or in parentheses), which only change the priority of a definition
+fn strip_priority(node: &SyntaxNode) -> SyntaxNode {
+    let mut node = node.clone();
+    loop {
+        let inner = match node.kind() {
+            SyntaxKind::NODE_PAREN => node.first_child(),
+            SyntaxKind::NODE_APPLY => {
+                let function = node.first_child();
+                let is_priority = match applied_function(&node).as_deref() {
+                    Some("mkDefault" | "mkForce") => true,
+                    // `mkOverride 50 x` applies `mkOverride 50` to `x`
+                    _ => function
+                        .as_ref()
+                        .and_then(applied_function)
+                        .is_some_and(|f| f == "mkOverride"),
+                };
+                if is_priority {
+                    node.last_child()
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        match inner {
+            Some(inner) => node = inner,
+            None => return node,
+        }
+    }
+}
+
+/// The attribute set holding a program/service's options: the value itself,
+/// or the set in `mkIf cond { ... }` along with the condition
+fn entry_block(value: &SyntaxNode) -> Option<(SyntaxNode, Option<String>)> {
+    let value = strip_priority(value);
+    if value.kind() == SyntaxKind::NODE_ATTR_SET {
+        return Some((value, None));
+    }
+
+    // `mkIf cond set` applies `mkIf cond` to the set
+    let condition = value.first_child()?;
+    if applied_function(&condition).as_deref() != Some("mkIf") {
+        return None;
+    }
+    let set = strip_priority(&value.last_child()?);
+    let condition = condition.last_child()?.text().to_string();
+    let condition = condition.split_whitespace().collect::<Vec<_>>().join(" ");
+    (set.kind() == SyntaxKind::NODE_ATTR_SET).then_some((set, Some(condition)))
+}
+
+/// An `enable` value that is neither a literal bool nor a plain variable, with
+/// its whitespace collapsed so it fits on one line
+fn enable_expression_text(value: &Option<SyntaxNode>) -> Option<String> {
+    let value = value.as_ref()?;
+    let text = value.text().to_string();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let inner = strip_priority(value);
+    (!matches!(inner.text().to_string().trim(), "true" | "false")
+        && inner.kind() != SyntaxKind::NODE_IDENT)
+        .then_some(text)
+}
+
+/// Range of a value node's literal `true`/`false`, also under `mkForce` and the like
+fn bool_literal_range(value: &Option<SyntaxNode>) -> Option<(usize, usize)> {
+    let value = strip_priority(value.as_ref()?);
+    matches!(value.text().to_string().trim(), "true" | "false").then(|| {
+        (
+            value.text_range().start().into(),
+            value.text_range().end().into(),
+        )
+    })
+}
+
+/// Guess the indentation unit of a Nix file: a tab if most indented lines start
+/// with one, otherwise the smallest space indentation (2 if nothing is indented)
+fn detect_indent_unit(content: &str) -> String {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    let mut smallest = usize::MAX;
+
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        if line.starts_with('\t') {
+            tab_lines += 1;
+        } else {
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            if spaces > 0 {
+                space_lines += 1;
+                smallest = smallest.min(spaces);
+            }
+        }
+    }
+
+    if tab_lines > space_lines {
+        "\t".to_string()
+    } else if space_lines > 0 {
+        " ".repeat(smallest.clamp(1, 8))
+    } else {
+        "  ".to_string()
+    }
+}
+
+/// Check that the brackets, braces and parentheses in a Nix value are balanced,
+/// ignoring string contents and comments. Returns a description of the first problem.
+pub fn bracket_balance_error(text: &str) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut stack: Vec<char> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '"' => {
+                // Skip a "double quoted" string, honouring escapes
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Some("unterminated string".to_string());
+                }
+            }
+            '\'' if chars.get(i + 1) == Some(&'\'') => {
+                // Skip an ''indented'' string; ''' ''$ and ''\ are escapes
+                i += 2;
+                loop {
+                    if i + 1 >= chars.len() {
+                        return Some("unterminated '' string".to_string());
+                    }
+                    if chars[i] == '\'' && chars[i + 1] == '\'' {
+                        if matches!(chars.get(i + 2), Some('\'') | Some('$') | Some('\\')) {
+                            i += 3;
+                            continue;
+                        }
+                        i += 1;
+                        break;
+                    }
+                    i += 1;
+                }
+            }
+            '#' => {
+                // Line comment
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                // Block comment
+                i += 2;
+                while i + 1 < chars.len() && !(chars[i] == '*' && chars[i + 1] == '/') {
+                    i += 1;
+                }
+                if i + 1 >= chars.len() {
+                    return Some("unterminated comment".to_string());
+                }
+                i += 1;
+            }
+            open @ ('(' | '[' | '{') => stack.push(open),
+            close @ (')' | ']' | '}') => {
+                let expected = match close {
+                    ')' => '(',
+                    ']' => '[',
+                    _ => '{',
+                };
+                match stack.pop() {
+                    Some(open) if open == expected => {}
+                    Some(open) => return Some(format!("'{}' closed by '{}'", open, close)),
+                    None => return Some(format!("unexpected '{}'", close)),
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    stack.last().map(|open| {
+        let close = match open {
+            '(' => ')',
+            '[' => ']',
+            _ => '}',
+        };
+        format!("missing '{}'", close)
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A config parsed from `content`, as if read from `test.nix`
+    fn parsed(content: &str) -> NixConfig {
+        let mut config = NixConfig {
+            path: "test.nix".to_string(),
+            content: content.to_string(),
+            entries: Vec::new(),
+            imports: Vec::new(),
+        };
+        config.parse().unwrap();
+        config
+    }
+
     #[test]
     fn test_parse_simple_program() {
         let content = r#"
@@ -1142,6 +3735,7 @@ mod tests {
             path: "test.nix".to_string(),
             content: content.to_string(),
             entries: Vec::new(),
+            imports: Vec::new(),
         };
         config.parse().unwrap();
 
@@ -1164,6 +3758,7 @@ mod tests {
             path: "test.nix".to_string(),
             content: content.to_string(),
             entries: Vec::new(),
+            imports: Vec::new(),
         };
         config.parse().unwrap();
 
@@ -1190,6 +3785,7 @@ mod tests {
             path: "test.nix".to_string(),
             content: content.to_string(),
             entries: Vec::new(),
+            imports: Vec::new(),
         };
         config.parse().unwrap();
 
@@ -1239,6 +3835,7 @@ mod tests {
             path: "test.nix".to_string(),
             content: content.to_string(),
             entries: Vec::new(),
+            imports: Vec::new(),
         };
         config.parse().unwrap();
 
@@ -1270,6 +3867,7 @@ mod tests {
             path: "test.nix".to_string(),
             content: content.to_string(),
             entries: Vec::new(),
+            imports: Vec::new(),
         };
         config.parse().unwrap();
 
@@ -1285,11 +3883,189 @@ mod tests {
     }
 
     #[test]
-    fn test_add_program_inserts_after_first_group() {
-        // Test that new programs are inserted after the first contiguous group,
-        // separated by a blank line from programs elsewhere in the file
-        let content = r#"{ config, pkgs, ... }:
-{
+    fn test_package_sections() {
+        let content = r#"
+{ config, pkgs, ... }:
+{
+  environment.systemPackages = with pkgs; [
+    wget
+    # Development
+    git
+    # rust-analyzer
+    # Editors:
+    vim
+  ];
+}
+"#;
+        let config = parsed(content);
+
+        let section = |name: &str| {
+            config
+                .entries
+                .iter()
+                .find(|e| e.name == name)
+                .unwrap()
+                .section
+                .clone()
+        };
+        assert_eq!(section("wget"), None);
+        assert_eq!(section("git").as_deref(), Some("Development"));
+        assert_eq!(section("rust-analyzer").as_deref(), Some("Development"));
+        assert_eq!(section("vim").as_deref(), Some("Editors"));
+        // Headers aren't mistaken for commented-out packages
+        assert_eq!(config.entries.len(), 4);
+    }
+
+    #[test]
+    fn test_home_manager_packages() {
+        let content = r#"{ pkgs, ... }:
+{
+  home.username = "alice";
+  home.packages = with pkgs; [
+    ripgrep
+    # fd
+  ];
+}
+"#;
+        let mut config = NixConfig {
+            path: "home.nix".to_string(),
+            content: content.to_string(),
+            entries: Vec::new(),
+            imports: Vec::new(),
+        };
+        config.parse().unwrap();
+
+        let packages = config.get_entries_by_type(&EntryType::Package);
+        assert_eq!(packages.len(), 2);
+        assert!(packages.iter().any(|e| e.name == "ripgrep" && e.enabled));
+        assert!(packages.iter().any(|e| e.name == "fd" && !e.enabled));
+
+        config.add_package_to_list("jq", 0).unwrap();
+        assert!(config
+            .content
+            .contains("home.packages = with pkgs; [\n    jq\n    ripgrep"));
+
+        // Without a list, a home-manager config gets `home.packages`
+        let mut config = NixConfig {
+            path: "home.nix".to_string(),
+            content: "{ pkgs, ... }:\n{\n  home = {\n    stateVersion = \"24.05\";\n  };\n}\n"
+                .to_string(),
+            entries: Vec::new(),
+            imports: Vec::new(),
+        };
+        config.parse().unwrap();
+        config.add_package_to_list("jq", 0).unwrap();
+        assert!(config
+            .content
+            .contains("home.packages = with pkgs; [\n    jq\n  ];"));
+        assert!(!config.content.contains("systemPackages"));
+
+        // A NixOS config with a nested `home` option stays a NixOS config
+        let config = parsed(
+            "{ pkgs, ... }:\n{\n  users.users.alice = {\n    home = \"/home/alice\";\n  };\n  services.nextcloud.home = \"/srv/nextcloud\";\n}\n",
+        );
+        assert_eq!(config.packages_option(), "environment.systemPackages");
+    }
+
+    #[test]
+    fn test_user_packages() {
+        let content = r#"{ pkgs, ... }:
+{
+  users.users.alice = {
+    isNormalUser = true;
+    packages = with pkgs; [
+      firefox
+    ];
+  };
+  users.users.bob.packages = [ pkgs.htop ];
+}
+"#;
+        let mut config = parsed(content);
+
+        let packages = config.get_entries_by_type(&EntryType::Package);
+        assert!(packages.iter().any(|e| e.name == "firefox"));
+        assert!(packages.iter().any(|e| e.name == "pkgs.htop"));
+        assert_eq!(config.package_list_lines(), vec![5, 9]);
+
+        // New packages go to the list the file already uses
+        config.add_package_to_list("git", 0).unwrap();
+        assert!(config.content.contains("[\n      git\n      firefox"));
+        assert!(!config.content.contains("systemPackages"));
+    }
+
+    #[test]
+    fn test_let_bound_packages() {
+        let content = r#"{ pkgs, ... }:
+let
+  basePackages = with pkgs; [
+    git
+  ];
+  myPackages = basePackages ++ [ pkgs.vim ];
+in
+{
+  environment.systemPackages = myPackages;
+}
+"#;
+        let mut config = parsed(content);
+
+        let packages = config.get_entries_by_type(&EntryType::Package);
+        assert!(packages.iter().any(|e| e.name == "git"));
+        assert!(packages.iter().any(|e| e.name == "pkgs.vim"));
+        assert_eq!(config.package_list_lines(), vec![3, 6]);
+        assert_eq!(config.unmanaged_packages_line(), None);
+
+        // Packages are added to the bound list instead of a new definition
+        config.add_package_to_list("htop", 0).unwrap();
+        assert!(config.content.contains("[\n    htop\n    git"));
+        assert_eq!(config.content.matches("systemPackages").count(), 1);
+    }
+
+    #[test]
+    fn test_users() {
+        let content = r#"{ pkgs, ... }:
+{
+  users.users.alice = {
+    isNormalUser = true;
+    extraGroups = [ "wheel" ];
+  };
+  users.users.bob = {
+    enable = false;
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        let alice = config.get_entry("alice", &EntryType::User).unwrap();
+        assert!(alice.enabled);
+        assert_eq!(alice.properties.len(), 2);
+        assert!(!config.get_entry("bob", &EntryType::User).unwrap().enabled);
+
+        // A user without `enable` gets one when turned off
+        config
+            .set_entry_enabled("alice", &EntryType::User, false)
+            .unwrap();
+        assert!(config
+            .content
+            .contains("    enable = false;\n  };\n  users.users.bob"));
+        assert!(!config.get_entry("alice", &EntryType::User).unwrap().enabled);
+        config
+            .set_entry_enabled("bob", &EntryType::User, true)
+            .unwrap();
+        assert!(config.get_entry("bob", &EntryType::User).unwrap().enabled);
+
+        config.add_entry("carol", &EntryType::User).unwrap();
+        assert!(config
+            .content
+            .contains("  users.users.carol = {\n    isNormalUser = true;\n  };\n"));
+        assert!(config.get_entry("carol", &EntryType::User).is_some());
+    }
+
+    #[test]
+    fn test_add_program_inserts_after_first_group() {
+        // Test that new programs are inserted after the first contiguous group,
+        // separated by a blank line from programs elsewhere in the file
+        let content = r#"{ config, pkgs, ... }:
+{
   programs.git.enable = true;
   programs.vim.enable = true;
   programs.neovim = {
@@ -1305,6 +4081,7 @@ mod tests {
             path: "test.nix".to_string(),
             content: content.to_string(),
             entries: Vec::new(),
+            imports: Vec::new(),
         };
         config.parse().unwrap();
 
@@ -1329,4 +4106,1293 @@ mod tests {
             "firefox should be before services"
         );
     }
+
+    #[test]
+    fn test_add_package_to_empty_list() {
+        let content = r#"{ config, pkgs, ... }:
+{
+  environment.systemPackages = with pkgs; [ ];
+}
+"#;
+        let mut config = parsed(content);
+
+        config.add_entry("ripgrep", &EntryType::Package).unwrap();
+
+        assert!(
+            config
+                .content
+                .contains("  environment.systemPackages = with pkgs; [\n    ripgrep\n  ];\n"),
+            "unexpected output:\n{}",
+            config.content
+        );
+        assert!(config
+            .entries
+            .iter()
+            .any(|e| e.name == "ripgrep" && e.entry_type == EntryType::Package && e.enabled));
+    }
+
+    #[test]
+    fn test_bracket_balance() {
+        assert_eq!(bracket_balance_error("{ a = [ 1 2 ]; b = (x y); }"), None);
+        assert_eq!(bracket_balance_error(r#"{ a = "}"; b = ''{''; }"#), None);
+        assert_eq!(bracket_balance_error("[ 1 # ]\n 2 ]"), None);
+        assert_eq!(
+            bracket_balance_error("{ a = [ 1 ];"),
+            Some("missing '}'".to_string())
+        );
+        assert_eq!(
+            bracket_balance_error("[ 1 }"),
+            Some("'[' closed by '}'".to_string())
+        );
+        assert_eq!(
+            bracket_balance_error("1 ]"),
+            Some("unexpected ']'".to_string())
+        );
+        assert_eq!(
+            bracket_balance_error(r#"[ "a ]"#),
+            Some("unterminated string".to_string())
+        );
+    }
+
+    #[test]
+    fn test_add_property_uses_detected_indentation() {
+        let content = "{ config, pkgs, ... }:\n{\n\tprograms.git.enable = true;\n}\n";
+        let mut config = parsed(content);
+
+        config
+            .add_property(
+                "git",
+                &EntryType::Program,
+                "lfs.enable",
+                "true",
+                &PropertyType::Bool,
+            )
+            .unwrap();
+
+        assert!(
+            config
+                .content
+                .contains("\tprograms.git = {\n\t\tenable = true;\n\t\tlfs.enable = true;\n\t};"),
+            "unexpected output:\n{}",
+            config.content
+        );
+    }
+
+    #[test]
+    fn test_add_property_inside_existing_attr_set() {
+        let content = r#"{ config, pkgs, ... }:
+{
+  services.openssh = {
+    enable = true;
+    settings = {
+      PasswordAuthentication = false;
+      PermitRootLogin = "yes";
+    };
+    extraConfig = { Foo = { }; };
+    banner = banner;
+  };
+}
+"#;
+        let mut config = parsed(content);
+        let openssh = EntryType::Service;
+
+        config
+            .add_property(
+                "openssh",
+                &openssh,
+                "settings.PermitRootLogin",
+                "no",
+                &PropertyType::String,
+            )
+            .unwrap();
+        config
+            .add_property(
+                "openssh",
+                &openssh,
+                "settings.KbdInteractiveAuthentication",
+                "false",
+                &PropertyType::Bool,
+            )
+            .unwrap();
+        config
+            .add_property(
+                "openssh",
+                &openssh,
+                "extraConfig.Foo.Bar",
+                "1",
+                &PropertyType::Int,
+            )
+            .unwrap();
+
+        assert!(
+            config.content.contains(
+                "    settings = {\n      PasswordAuthentication = false;\n      PermitRootLogin = \"no\";\n      KbdInteractiveAuthentication = false;\n    };\n    extraConfig = { Foo = { Bar = 1; }; };"
+            ),
+            "unexpected output:\n{}",
+            config.content
+        );
+        assert_eq!(config.content.matches("settings").count(), 1);
+        // Not an attribute set in the file, so there's nowhere to put it
+        assert!(config
+            .add_property(
+                "openssh",
+                &openssh,
+                "banner.text",
+                "hi",
+                &PropertyType::String
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_let_bound_enable() {
+        let content = r#"
+let
+  enable = true;
+in
+{ config, pkgs, ... }:
+{
+  programs.fish.enable = enable;
+  services.openssh = {
+    inherit enable;
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        let fish = config.get_entry("fish", &EntryType::Program).unwrap();
+        assert!(fish.enabled);
+        assert_eq!(fish.enable_binding.as_ref().unwrap().name, "enable");
+        let openssh = config.get_entry("openssh", &EntryType::Service).unwrap();
+        assert!(openssh.enabled);
+        assert_eq!(openssh.enable_binding.as_ref().unwrap().name, "enable");
+
+        // Toggling edits the binding, not the reference
+        config
+            .set_entry_enabled("fish", &EntryType::Program, false)
+            .unwrap();
+        assert!(config.content.contains("enable = false;"));
+        assert!(config.content.contains("programs.fish.enable = enable;"));
+        assert!(
+            !config
+                .get_entry("openssh", &EntryType::Service)
+                .unwrap()
+                .enabled
+        );
+    }
+
+    #[test]
+    fn test_multiple_package_lists() {
+        let content = r#"
+{ config, pkgs, ... }:
+{
+  environment.systemPackages = with pkgs; [
+    git
+  ];
+  environment = {
+    systemPackages = with pkgs; [
+      htop
+    ];
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        assert_eq!(config.package_list_lines(), vec![4, 8]);
+        let htop = config.get_entry("htop", &EntryType::Package).unwrap();
+        assert_eq!(htop.package_list, Some(8));
+
+        config.add_package_to_list("ripgrep", 1).unwrap();
+        assert!(config.content.contains("[\n      ripgrep\n      htop"));
+        assert_eq!(config.content.matches("systemPackages").count(), 2);
+    }
+
+    #[test]
+    fn test_set_property_missing_semicolon() {
+        let content = r#"
+{ config, pkgs, ... }:
+{
+  services.nginx = {
+    enable = true;
+    port = 80
+    user = "nginx";
+  };
+  services.caddy = {
+    enable = true;
+    port = 80
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        config
+            .set_property("caddy", &EntryType::Service, "port", "8080;")
+            .unwrap();
+        assert!(
+            config.content.contains("    port = 8080;\n  };"),
+            "unexpected output:\n{}",
+            config.content
+        );
+
+        config
+            .set_property("nginx", &EntryType::Service, "port", "8081")
+            .unwrap();
+        assert!(
+            config
+                .content
+                .contains("    port = 8081;\n    user = \"nginx\";\n"),
+            "unexpected output:\n{}",
+            config.content
+        );
+        assert!(!config.content.contains(";;"));
+        assert!(rnix::Root::parse(&config.content).errors().is_empty());
+    }
+
+    #[test]
+    fn test_toggle_with_irregular_spacing() {
+        let content = r#"
+{ config, pkgs, ... }:
+{
+  programs.git.enable = true ;
+  programs.vim.enable  =  false;
+  services.nginx = {
+    enable=true;
+    user = "nginx";
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        config
+            .set_entry_enabled("git", &EntryType::Program, false)
+            .unwrap();
+        config
+            .set_entry_enabled("vim", &EntryType::Program, true)
+            .unwrap();
+        config
+            .set_entry_enabled("nginx", &EntryType::Service, false)
+            .unwrap();
+
+        assert!(config.content.contains("programs.git.enable = false ;"));
+        assert!(config.content.contains("programs.vim.enable  =  true;"));
+        assert!(config.content.contains("    enable=false;\n"));
+        assert!(
+            !config
+                .get_entry("git", &EntryType::Program)
+                .unwrap()
+                .enabled
+        );
+        assert!(
+            config
+                .get_entry("vim", &EntryType::Program)
+                .unwrap()
+                .enabled
+        );
+        assert!(
+            !config
+                .get_entry("nginx", &EntryType::Service)
+                .unwrap()
+                .enabled
+        );
+    }
+
+    #[test]
+    fn test_toggle_enable_split_across_lines() {
+        let content = r#"{ config, pkgs, ... }:
+{
+  programs.git.enable =
+    true;
+  services.openssh = {
+    enable =
+      false;
+    ports = [ 22 ];
+  };
+}
+"#;
+        let mut config = parsed(content);
+        assert!(
+            config
+                .get_entry("git", &EntryType::Program)
+                .unwrap()
+                .enabled
+        );
+        assert!(
+            !config
+                .get_entry("openssh", &EntryType::Service)
+                .unwrap()
+                .enabled
+        );
+
+        config
+            .set_entry_enabled("git", &EntryType::Program, false)
+            .unwrap();
+        config
+            .set_entry_enabled("openssh", &EntryType::Service, true)
+            .unwrap();
+
+        assert_eq!(
+            config.content,
+            content
+                .replace("enable =\n    true;", "enable =\n    false;")
+                .replace("enable =\n      false;", "enable =\n      true;")
+        );
+        assert!(
+            !config
+                .get_entry("git", &EntryType::Program)
+                .unwrap()
+                .enabled
+        );
+        assert!(
+            config
+                .get_entry("openssh", &EntryType::Service)
+                .unwrap()
+                .enabled
+        );
+    }
+
+    #[test]
+    fn test_toggle_leaves_adjacent_blocks() {
+        let content = r#"{ config, pkgs, ... }:
+{
+  services.foo = {
+    enable = true;
+  };
+  services.bar = {
+    client = { enable = true; };
+    enable = true;
+  };
+  services.baz = { enable = true; };
+}
+"#;
+        let mut config = parsed(content);
+
+        config
+            .set_entry_enabled("bar", &EntryType::Service, false)
+            .unwrap();
+        assert_eq!(
+            config.content,
+            content.replace(
+                "    enable = true;\n  };\n  services.baz",
+                "    enable = false;\n  };\n  services.baz"
+            )
+        );
+
+        config
+            .set_entry_enabled("baz", &EntryType::Service, false)
+            .unwrap();
+        assert!(config
+            .content
+            .contains("services.baz = { enable = false; };"));
+        assert!(
+            config
+                .get_entry("foo", &EntryType::Service)
+                .unwrap()
+                .enabled
+        );
+        assert!(config.content.contains("client = { enable = true; };"));
+    }
+
+    #[test]
+    fn test_non_list_system_packages() {
+        let content = r#"
+{ config, pkgs, ... }:
+{
+  environment.systemPackages = builtins.attrValues myPackages;
+}
+"#;
+        let mut config = parsed(content);
+
+        assert_eq!(config.unmanaged_packages_line(), Some(4));
+        assert!(config.add_entry("ripgrep", &EntryType::Package).is_err());
+        assert_eq!(config.content, content);
+    }
+
+    #[test]
+    fn test_add_packages_to_list() {
+        let content = r#"{ pkgs, ... }:
+{
+  environment.systemPackages = with pkgs; [
+    git
+  ];
+}
+"#;
+        let mut config = parsed(content);
+
+        let names = vec!["ripgrep".to_string(), "fd".to_string()];
+        config.add_packages_to_list(&names, 0).unwrap();
+        assert!(config
+            .content
+            .contains("[\n    ripgrep\n    fd\n    git\n  ];"));
+        assert!(config.get_entry("fd", &EntryType::Package).is_some());
+
+        // Nothing is added when the list can't be edited
+        let content = "{ pkgs, ... }:\n{\n  environment.systemPackages = myPackages;\n}\n";
+        let mut config = parsed(content);
+        assert!(config.add_packages_to_list(&names, 0).is_err());
+        assert_eq!(config.content, content);
+    }
+
+    #[test]
+    fn test_nixpkgs_expr() {
+        assert_eq!(nixpkgs_expr("<nixos-unstable>"), "<nixos-unstable>");
+        assert_eq!(
+            nixpkgs_expr("/etc/nixos#nixpkgs"),
+            "(builtins.getFlake \"path:/etc/nixos\").inputs.\"nixpkgs\""
+        );
+        assert_eq!(
+            nixpkgs_expr("github:me/dotfiles#nixpkgs"),
+            "(builtins.getFlake \"github:me/dotfiles\").inputs.\"nixpkgs\""
+        );
+        assert_eq!(nixpkgs_expr("/srv/nixpkgs"), "\"/srv/nixpkgs\"");
+    }
+
+    #[test]
+    fn test_cache_paths_per_nixpkgs() {
+        let mut cache = SchemaCache {
+            cache_dir: PathBuf::from("/cache"),
+            memory_cache: HashMap::new(),
+            backend: SchemaBackend::Unavailable,
+            cache_warning: None,
+            flake_host: None,
+            nixpkgs: None,
+            service_modules: None,
+            service_modules_failed: false,
+            service_modules_receiver: None,
+        };
+        let path = |cache: &SchemaCache| cache.cache_path(&EntryType::Service, "nginx");
+
+        let default = path(&cache);
+        cache.set_nixpkgs("<nixos-unstable>");
+        let unstable = path(&cache);
+        cache.set_nixpkgs("/srv/nixpkgs");
+        let local = path(&cache);
+        cache.set_nixpkgs("<nixos-unstable>");
+
+        assert_eq!(default, PathBuf::from("/cache/services.nginx.json"));
+        assert_ne!(unstable, default);
+        assert_ne!(local, unstable);
+        assert_eq!(path(&cache), unstable);
+    }
+
+    #[test]
+    fn test_module_warning() {
+        let check = |content: &str| parsed(content).module_warning().is_some();
+
+        // A derivation and a library file
+        assert!(check(
+            "{ lib, stdenv, fetchurl }:\nstdenv.mkDerivation { pname = \"foo\"; version = \"1.0\"; }\n"
+        ));
+        assert!(check("{ lib }:\n{ double = x: x * 2; }\n"));
+
+        // Modules, even without any programs/services/packages
+        assert!(!check("{ config, lib, ... }:\n{ }\n"));
+        assert!(!check(
+            "{ ... }:\n{ imports = [ ./hardware-configuration.nix ]; }\n"
+        ));
+        assert!(!check("{\n  time.timeZone = \"UTC\";\n}\n"));
+    }
+
+    #[test]
+    fn test_insert_with_complex_argument_pattern() {
+        let content = r#"{ config, pkgs, lib ? import <nixpkgs/lib> { }, ... }@args:
+let
+  cfg = { user = "alice"; };
+in
+{
+  networking.hostName = "box";
+}
+# {
+#   services.nginx.enable = true;
+# }
+"#;
+        let mut config = parsed(content);
+
+        config.add_entry("git", &EntryType::Program).unwrap();
+        config.add_entry("ripgrep", &EntryType::Package).unwrap();
+
+        // Both land inside the returned set, before its own closing brace
+        let body_end = config.content.find("}\n# {").unwrap();
+        let git = config.content.find("programs.git.enable = true;").unwrap();
+        let ripgrep = config.content.find("ripgrep").unwrap();
+        assert!(git < body_end && ripgrep < body_end);
+        assert!(config
+            .content
+            .starts_with("{ config, pkgs, lib ? import <nixpkgs/lib> { }, ... }@args:"));
+        assert!(config
+            .content
+            .ends_with("# {\n#   services.nginx.enable = true;\n# }\n"));
+        assert!(config.entries.iter().any(|e| e.name == "git" && e.enabled));
+    }
+
+    #[test]
+    fn test_unedited_save_is_byte_identical() {
+        let fixtures = [
+            "{ config, pkgs, ... }:\n{\n  programs.git.enable = true;\n}\n",
+            "{ pkgs, ... }:\n{\n\tenvironment.systemPackages = with pkgs; [\n\t\tvim   # editor\n\t\t# htop\n\t];\n}",
+            "{ config, pkgs, ... }:\r\n{\r\n  services.openssh = {\r\n    enable = true;  \r\n    ports = [ 22 ];\r\n  };\r\n}\r\n",
+            "{ lib, ... }:\nlet\n  on = true;\nin\n{\n  services.nginx.enable = on;\n  programs.fish.enable = lib.mkDefault false;\n}\n",
+        ];
+
+        let path =
+            std::env::temp_dir().join(format!("nixxed-noop-save-{}.nix", std::process::id()));
+        for fixture in fixtures {
+            fs::write(&path, fixture).unwrap();
+            let mut config = NixConfig::load(&path).unwrap();
+            config.reparse().unwrap();
+            config
+                .save_with_backups_in(&std::env::temp_dir().join("nixxed-noop-backups"))
+                .unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), fixture);
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_entry_notes() {
+        let content = r#"{ config, pkgs, ... }:
+{
+  programs.git.enable = true; # set up for work
+  services.openssh.enable = true;
+  environment.systemPackages = with pkgs; [
+    vim
+    # htop # maybe later
+  ];
+}
+"#;
+        let mut config = parsed(content);
+
+        let note = |config: &NixConfig, name: &str, entry_type: EntryType| {
+            config.get_entry(name, &entry_type).unwrap().note.clone()
+        };
+        assert_eq!(
+            note(&config, "git", EntryType::Program).as_deref(),
+            Some("set up for work")
+        );
+        assert_eq!(note(&config, "htop", EntryType::Package), None);
+
+        config
+            .set_note("git", &EntryType::Program, "personal too")
+            .unwrap();
+        config
+            .set_note("openssh", &EntryType::Service, "remote builds")
+            .unwrap();
+        config
+            .set_note("vim", &EntryType::Package, "editor")
+            .unwrap();
+        assert!(config
+            .content
+            .contains("programs.git.enable = true; # personal too\n"));
+        assert!(config
+            .content
+            .contains("services.openssh.enable = true; # remote builds\n"));
+        assert!(config.content.contains("    vim # editor\n"));
+        assert!(config.set_note("htop", &EntryType::Package, "x").is_err());
+
+        config.set_note("git", &EntryType::Program, "").unwrap();
+        assert!(config.content.contains("programs.git.enable = true;\n"));
+        assert_eq!(note(&config, "git", EntryType::Program), None);
+    }
+
+    #[test]
+    fn test_remove_entry() {
+        let content = r#"{ config, pkgs, ... }:
+{
+  services.foo = {
+    enable = false;
+    port = 8080;
+  };
+  services.foo.openFirewall = true;
+  services.foo.server.enable = true;
+  services.foobar.enable = true;
+  programs.git = { enable = false; lfs.enable = true; }; programs.zsh.enable = true;
+}
+"#;
+        let mut config = parsed(content);
+
+        assert_eq!(config.remove_entry("foo", &EntryType::Service).unwrap(), 2);
+        assert_eq!(config.remove_entry("git", &EntryType::Program).unwrap(), 1);
+        assert_eq!(
+            config.content,
+            "{ config, pkgs, ... }:\n{\n  services.foo.server.enable = true;\n  services.foobar.enable = true;\n  programs.zsh.enable = true;\n}\n"
+        );
+        assert!(config.get_entry("foo", &EntryType::Service).is_none());
+        // The nested entry is its own, and can be removed on its own
+        assert_eq!(
+            config
+                .remove_entry("foo.server", &EntryType::Service)
+                .unwrap(),
+            1
+        );
+        assert!(config.get_entry("foobar", &EntryType::Service).is_some());
+        assert!(config.remove_entry("foo", &EntryType::Service).is_err());
+    }
+
+    #[test]
+    fn test_rename_entry() {
+        let content = r#"{ config, pkgs, ... }:
+{
+  services.foo = {
+    enable = true;
+    port = 8080;
+  };
+  services.foo.openFirewall = true;
+  services.foobar.enable = true;
+}
+"#;
+        let mut config = parsed(content);
+
+        assert_eq!(
+            config
+                .rename_entry("foo", &EntryType::Service, "bar")
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            config.content,
+            content
+                .replace("services.foo.", "services.bar.")
+                .replace("services.foo =", "services.bar =")
+        );
+        assert!(config.get_entry("bar", &EntryType::Service).is_some());
+        assert!(config.get_entry("foobar", &EntryType::Service).is_some());
+
+        assert!(config
+            .rename_entry("bar", &EntryType::Service, "foobar")
+            .is_err());
+        assert!(config
+            .rename_entry("bar", &EntryType::Service, "not valid")
+            .is_err());
+    }
+
+    #[test]
+    fn test_list_option_members() {
+        let content = r#"{ config, pkgs, ... }:
+{
+  networking.firewall.allowedTCPPorts = [ 22 80 ];
+  boot = {
+    kernelModules = [
+      "kvm-intel"
+      "wl"
+    ];
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        let tcp = "networking.firewall.allowedTCPPorts";
+        assert_eq!(config.list_option_members(tcp).unwrap(), ["22", "80"]);
+        assert_eq!(
+            config.list_option_members("boot.kernelModules").unwrap(),
+            ["\"kvm-intel\"", "\"wl\""]
+        );
+        assert_eq!(
+            config.list_option_members("boot.supportedFilesystems"),
+            None
+        );
+
+        config.add_list_member(tcp, "443").unwrap();
+        assert!(config.add_list_member(tcp, "80").is_err());
+        config.remove_list_member(tcp, "22").unwrap();
+        assert!(config.content.contains("allowedTCPPorts = [ 80 443 ];"));
+
+        config
+            .add_list_member("boot.kernelModules", "\"kvm-amd\"")
+            .unwrap();
+        config
+            .remove_list_member("boot.kernelModules", "\"wl\"")
+            .unwrap();
+        assert!(config
+            .content
+            .contains("    kernelModules = [\n      \"kvm-intel\"\n      \"kvm-amd\"\n    ];"));
+
+        config
+            .add_list_member("boot.supportedFilesystems", "\"zfs\"")
+            .unwrap();
+        assert_eq!(
+            config
+                .list_option_members("boot.supportedFilesystems")
+                .unwrap(),
+            ["\"zfs\""]
+        );
+    }
+
+    #[test]
+    fn test_set_enable_expression() {
+        let content = r#"
+{ config, lib, ... }:
+{
+  programs.git.enable = lib.mkForce true;
+  services.nginx = {
+    enable = true;
+    user = "nginx";
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        assert_eq!(
+            config
+                .enable_expression("git", &EntryType::Program)
+                .unwrap(),
+            "lib.mkForce true"
+        );
+        config
+            .set_enable_expression(
+                "nginx",
+                &EntryType::Service,
+                "config.services.caddy.enable == false;",
+            )
+            .unwrap();
+        assert!(config
+            .content
+            .contains("    enable = config.services.caddy.enable == false;\n"));
+        assert!(config
+            .set_enable_expression("git", &EntryType::Program, "if true then")
+            .is_err());
+        assert!(config
+            .content
+            .contains("programs.git.enable = lib.mkForce true;"));
+    }
+
+    #[test]
+    fn test_resolve_directory_path() {
+        let dir = std::env::temp_dir().join(format!("nixxed-dir-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("configuration.nix"), "{ }\n").unwrap();
+
+        let err = NixConfig::resolve_path(&dir).unwrap_err().to_string();
+        assert!(err.contains("without a default.nix"), "{}", err);
+        assert!(err.contains("configuration.nix"), "{}", err);
+
+        fs::write(
+            dir.join("default.nix"),
+            "{ ... }:\n{\n  programs.git.enable = true;\n}\n",
+        )
+        .unwrap();
+        let config = NixConfig::load(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(config.path, dir.join("default.nix").to_string_lossy());
+        assert!(config.get_entry("git", &EntryType::Program).is_some());
+    }
+
+    #[test]
+    fn test_resolve_path_value() {
+        let config = NixConfig {
+            path: "/etc/nixos/configuration.nix".to_string(),
+            content: String::new(),
+            entries: Vec::new(),
+            imports: Vec::new(),
+        };
+
+        assert_eq!(
+            config.resolve_path_value("./secrets/../keys/ssh.pub;"),
+            Some(PathBuf::from("/etc/nixos/keys/ssh.pub"))
+        );
+        assert_eq!(
+            config.resolve_path_value("../shared.nix"),
+            Some(PathBuf::from("/etc/shared.nix"))
+        );
+        assert_eq!(
+            config.resolve_path_value("/var/lib/foo"),
+            Some(PathBuf::from("/var/lib/foo"))
+        );
+        assert_eq!(config.resolve_path_value("\"./not-a-path\""), None);
+        assert_eq!(
+            config.path_literal(Path::new("/etc/nixos/keys/ssh.pub")),
+            "./keys/ssh.pub"
+        );
+        assert_eq!(
+            config.path_literal(Path::new("/var/lib/foo")),
+            "/var/lib/foo"
+        );
+        assert_eq!(
+            config.format_property_value("../shared.nix"),
+            "../shared.nix"
+        );
+    }
+
+    #[test]
+    fn test_nested_enables_keep_their_path() {
+        let content = r#"
+{ config, pkgs, ... }:
+{
+  services.foo.enable = true;
+  services.foo.server.enable = true;
+  services.foo.client.enable = false;
+}
+"#;
+        let mut config = parsed(content);
+
+        let names: Vec<&str> = config
+            .get_entries_by_type(&EntryType::Service)
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(names, ["foo", "foo.server", "foo.client"]);
+
+        config
+            .set_entry_enabled("foo.client", &EntryType::Service, true)
+            .unwrap();
+        assert!(config
+            .content
+            .contains("services.foo.client.enable = true;"));
+        assert!(config
+            .content
+            .contains("services.foo.server.enable = true;"));
+    }
+
+    #[test]
+    fn test_toggle_keeps_comments_and_wrapper() {
+        let content = r#"{ config, lib, ... }:
+{
+  services.openssh = {
+    enable = lib.mkForce   true; # keep
+    ports = [ 22 ];
+  };
+  programs.git.enable /* on */ = true;  # daily driver
+}
+"#;
+        let mut config = parsed(content);
+
+        config
+            .set_entry_enabled("openssh", &EntryType::Service, false)
+            .unwrap();
+        config
+            .set_entry_enabled("git", &EntryType::Program, false)
+            .unwrap();
+        assert_eq!(
+            config.content,
+            content
+                .replace("lib.mkForce   true; # keep", "lib.mkForce   false; # keep")
+                .replace("= true;  # daily driver", "= false;  # daily driver")
+        );
+    }
+
+    #[test]
+    fn test_priority_wrappers_and_mk_if() {
+        let content = r#"{ config, lib, ... }:
+{
+  services.openssh.enable = lib.mkForce true;
+  programs.fish.enable = mkOverride 50 (false);
+  services.nginx = {
+    enable = mkDefault true;
+    user = lib.mkForce "nginx";
+  };
+  services.caddy = lib.mkIf config.my.web {
+    enable = true;
+    email = "me@example.com";
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        let entry = |config: &NixConfig, name: &str, entry_type: EntryType| {
+            config.get_entry(name, &entry_type).unwrap().clone()
+        };
+        assert!(entry(&config, "openssh", EntryType::Service).enabled);
+        assert!(!entry(&config, "fish", EntryType::Program).enabled);
+        let nginx = entry(&config, "nginx", EntryType::Service);
+        assert!(nginx.enabled && !nginx.is_conditional());
+        assert_eq!(nginx.properties[0].value, "nginx");
+        assert_eq!(nginx.properties[0].property_type, PropertyType::String);
+        let caddy = entry(&config, "caddy", EntryType::Service);
+        assert!(caddy.enabled && caddy.is_conditional());
+        assert_eq!(caddy.enable_source().as_deref(), Some("mkIf config.my.web"));
+
+        // Toggling and editing keep the wrappers
+        config
+            .set_entry_enabled("openssh", &EntryType::Service, false)
+            .unwrap();
+        config
+            .set_entry_enabled("fish", &EntryType::Program, true)
+            .unwrap();
+        config
+            .set_entry_enabled("nginx", &EntryType::Service, false)
+            .unwrap();
+        config
+            .set_entry_enabled("caddy", &EntryType::Service, false)
+            .unwrap();
+        config
+            .set_property("nginx", &EntryType::Service, "user", "www")
+            .unwrap();
+        assert_eq!(
+            config.content,
+            content
+                .replace("lib.mkForce true", "lib.mkForce false")
+                .replace("(false)", "(true)")
+                .replace("mkDefault true", "mkDefault false")
+                .replace("{\n    enable = true;", "{\n    enable = false;")
+                .replace("lib.mkForce \"nginx\"", "lib.mkForce \"www\"")
+        );
+        assert!(!entry(&config, "caddy", EntryType::Service).enabled);
+    }
+
+    #[test]
+    fn test_expression_enable_in_block() {
+        let content = r#"
+{ config, lib, ... }:
+let
+  cfg = config.my;
+in
+{
+  programs.git = {
+    enable = cfg.enable;
+    lfs.enable = true;
+  };
+  services.nginx.enable = lib.mkDefault
+    true;
+}
+"#;
+        let mut config = parsed(content);
+
+        let git = config.get_entry("git", &EntryType::Program).unwrap();
+        assert_eq!(git.enable_expr.as_deref(), Some("cfg.enable"));
+        assert!(git.is_conditional());
+        assert_eq!(git.enable_source().as_deref(), Some("cfg.enable"));
+
+        // A priority wrapper doesn't make the value an expression
+        let nginx = config.get_entry("nginx", &EntryType::Service).unwrap();
+        assert!(nginx.enabled && !nginx.is_conditional());
+
+        // Toggling would overwrite the expression, so it's refused
+        assert!(config
+            .set_entry_enabled("git", &EntryType::Program, true)
+            .is_err());
+        assert!(config.content.contains("enable = cfg.enable;"));
+    }
+
+    #[test]
+    fn test_add_entries_inside_config_block() {
+        let content = r#"{ config, lib, pkgs, ... }:
+{
+  options.my.enable = lib.mkEnableOption "my settings";
+
+  config = lib.mkIf config.my.enable {
+    services.openssh.enable = true;
+  };
+}
+"#;
+        let mut config = parsed(content);
+
+        config.add_entry("git", &EntryType::Program).unwrap();
+        config.add_entry("ripgrep", &EntryType::Package).unwrap();
+
+        assert!(config.content.contains(
+            "  config = lib.mkIf config.my.enable {\n    services.openssh.enable = true;\n\n    programs.git.enable = true;\n\n    environment.systemPackages = with pkgs; [\n      ripgrep\n    ];\n  };\n}\n"
+        ));
+        assert!(config.get_entry("git", &EntryType::Program).is_some());
+    }
+
+    #[test]
+    fn test_load_flake_follows_imports() {
+        let dir = std::env::temp_dir().join(format!("nixxed-flake-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("flake.nix"),
+            r#"{
+  inputs.nixpkgs.url = "github:NixOS/nixpkgs/nixos-unstable";
+  outputs = { self, nixpkgs }: {
+    nixosConfigurations = {
+      laptop = nixpkgs.lib.nixosSystem {
+        system = "x86_64-linux";
+        modules = [ ./laptop.nix ];
+      };
+    };
+  };
+}
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("laptop.nix"),
+            "{ ... }:\n{\n  imports = [ ./hardware-configuration.nix ./common.nix ];\n}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("hardware-configuration.nix"), "{ ... }:\n{ }\n").unwrap();
+        fs::write(
+            dir.join("common.nix"),
+            "{ pkgs, ... }:\n{\n  programs.git.enable = true;\n}\n",
+        )
+        .unwrap();
+
+        let config = NixConfig::load_flake(&dir, "laptop");
+        let missing = NixConfig::load_flake(&dir, "desktop");
+        fs::remove_dir_all(&dir).unwrap();
+
+        let config = config.unwrap();
+        assert!(config.path.ends_with("laptop.nix"), "{}", config.path);
+        let git = config.get_entry("git", &EntryType::Program).unwrap();
+        assert!(git.file.ends_with("common.nix"), "{}", git.file);
+        // Without a configuration.nix to fall back to, an unknown host has no module
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_add_package_to_imported_module() {
+        let dir = std::env::temp_dir().join(format!("nixxed-pkg-module-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = "{ pkgs, ... }:\n{\n  imports = [ ./nixxed-packages.nix ];\n}\n";
+        fs::write(dir.join("configuration.nix"), main).unwrap();
+        fs::write(
+            dir.join(PACKAGES_MODULE),
+            "{ pkgs, ... }:\n{\n  environment.systemPackages = with pkgs; [\n    git\n  ];\n}\n",
+        )
+        .unwrap();
+
+        let mut config = NixConfig::load(dir.join("configuration.nix")).unwrap();
+        config.add_package_to_module("ripgrep").unwrap();
+        config.save_with_backups_in(&dir.join("backups")).unwrap();
+        let module = fs::read_to_string(dir.join(PACKAGES_MODULE)).unwrap();
+        let saved_main = fs::read_to_string(dir.join("configuration.nix")).unwrap();
+
+        // A fresh config gets the module only once it's saved
+        let fresh = dir.join("fresh");
+        fs::create_dir_all(&fresh).unwrap();
+        fs::write(
+            fresh.join("configuration.nix"),
+            "{ pkgs, ... }:\n{\n  # Not yet: ./nixxed-packages.nix\n}\n",
+        )
+        .unwrap();
+        let mut config = NixConfig::load(fresh.join("configuration.nix")).unwrap();
+        config.add_package_to_module("ripgrep").unwrap();
+        let written_early = fresh.join(PACKAGES_MODULE).exists();
+        config.save_with_backups_in(&dir.join("backups")).unwrap();
+        let created = fs::read_to_string(fresh.join(PACKAGES_MODULE)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(
+            module.contains("git") && module.contains("ripgrep"),
+            "{}",
+            module
+        );
+        // Already imported, so the config itself is unchanged
+        assert_eq!(saved_main, main);
+        assert!(!written_early);
+        assert!(created.contains("ripgrep"));
+        // Mentioned only in a comment, so the import is still added
+        assert!(config
+            .content
+            .contains("imports = [ ./nixxed-packages.nix ];"));
+        assert!(config
+            .get_entry("ripgrep", &EntryType::Package)
+            .is_some_and(|e| e.enabled));
+    }
+
+    #[test]
+    fn test_entries_from_imported_files() {
+        let dir = std::env::temp_dir().join(format!("nixxed-imports-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main = "{ pkgs, ... }:\n{\n  imports = [\n    <nixpkgs/nixos/modules/installer/scan/not-detected.nix>\n    /nix/store/abc-module.nix\n    ./desktop.nix\n    ./server.nix\n  ];\n\n  programs.zsh.enable = true;\n}\n";
+        let desktop = "{ pkgs, ... }:\n{\n  programs.firefox.enable = true;\n  users.defaultUserShell = pkgs.zsh;\n  environment.systemPackages = with pkgs; [\n    # vim\n  ];\n}\n";
+        fs::write(dir.join("configuration.nix"), main).unwrap();
+        fs::write(dir.join("desktop.nix"), desktop).unwrap();
+        fs::write(
+            dir.join("server.nix"),
+            "{ ... }:\n{\n  imports = [ ./configuration.nix ];\n  services.openssh.enable = true;\n}\n",
+        )
+        .unwrap();
+
+        let mut config = NixConfig::load(dir.join("configuration.nix")).unwrap();
+        let programs = config.get_entries_by_type(&EntryType::Program);
+        let ssh = config.get_entry("openssh", &EntryType::Service).cloned();
+        let firefox = config.get_entry("firefox", &EntryType::Program).cloned();
+        assert_eq!(programs.len(), 2);
+        // The import cycle back to configuration.nix isn't followed
+        assert_eq!(config.imports.len(), 2);
+        assert!(ssh.unwrap().file.ends_with("server.nix"));
+        assert!(firefox.unwrap().file.ends_with("desktop.nix"));
+        // Commented-out packages in imported files get checked too
+        assert_eq!(config.commented_package_names(), vec!["vim".to_string()]);
+        // Mentions in imported files count as references too
+        let references = config.find_references("zsh", &EntryType::Program);
+        assert_eq!(references.len(), 1);
+        assert!(references[0].file.ends_with("desktop.nix"));
+        assert_eq!(references[0].line, 4);
+
+        config
+            .set_entry_enabled("firefox", &EntryType::Program, false)
+            .unwrap();
+        assert_eq!(config.content, main);
+        assert!(config.imports[0]
+            .content
+            .contains("programs.firefox.enable = false;"));
+        let backup_dir = dir.join("backups");
+        config.save_with_backups_in(&backup_dir).unwrap();
+        let saved = fs::read_to_string(dir.join("desktop.nix")).unwrap();
+        // A second save right away keeps the first backup
+        config
+            .set_entry_enabled("firefox", &EntryType::Program, false)
+            .unwrap();
+        config.imports[0].content.push('\n');
+        config.save_with_backups_in(&backup_dir).unwrap();
+        let mut backups: Vec<PathBuf> = fs::read_dir(&backup_dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.path())
+            .collect();
+        backups.sort();
+        let first_backup = fs::read_to_string(&backups[0]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(saved.contains("programs.firefox.enable = false;"));
+        // Only the changed file was backed up, as it was before each save
+        assert_eq!(backups.len(), 2);
+        assert!(backups
+            .iter()
+            .all(|b| b.to_string_lossy().contains("%desktop.nix.")));
+        assert_eq!(first_backup, desktop);
+        assert!(
+            !config
+                .get_entry("firefox", &EntryType::Program)
+                .unwrap()
+                .enabled
+        );
+    }
+
+    #[test]
+    fn test_move_entry_needs_names_the_target_has() {
+        let dir = std::env::temp_dir().join(format!("nixxed-move-scope-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let host = "{ config, lib, pkgs, ... }:\nlet\n  cfg = config.my;\nin\n{\n  imports = [ ./common.nix ];\n\n  services.nginx = {\n    enable = lib.mkDefault cfg.web;\n  };\n  programs.git = { enable = true; package = pkgs.gitFull; };\n  programs.fish.enable = cfg.shell.enable;\n}\n";
+        let common = "{ config, pkgs, ... }:\nlet\n  cfg = config.shared;\nin\n{\n}\n";
+        fs::write(dir.join("laptop.nix"), host).unwrap();
+        fs::write(dir.join("common.nix"), common).unwrap();
+
+        let mut config = NixConfig::load(dir.join("laptop.nix")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let shared = config.imports[0].path.clone();
+
+        // `lib` isn't an argument of common.nix
+        let error = config
+            .move_entry("nginx", &EntryType::Service, &shared)
+            .unwrap_err();
+        assert!(error.to_string().contains("uses lib,"), "{}", error);
+        assert_eq!(config.content, host);
+        assert_eq!(config.imports[0].content, common);
+        // Both files have `pkgs` and a `cfg` (it's up to the user what it means there)
+        config
+            .move_entry("git", &EntryType::Program, &shared)
+            .unwrap();
+        config
+            .move_entry("fish", &EntryType::Program, &shared)
+            .unwrap();
+        assert!(config.imports[0]
+            .content
+            .contains("package = pkgs.gitFull;"));
+        assert!(!config.content.contains("programs.git"));
+    }
+
+    #[test]
+    fn test_move_entry_between_files() {
+        let dir = std::env::temp_dir().join(format!("nixxed-move-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("laptop.nix"),
+            "{ ... }:\n{\n  imports = [ ./common.nix ];\n\n  services.openssh = {\n    enable = true;\n    ports = [ 22 ];\n  };\n  services.openssh.agent.enable = true;\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("common.nix"),
+            "{ ... }:\n{\n  programs.git.enable = true;\n}\n",
+        )
+        .unwrap();
+
+        let mut config = NixConfig::load(dir.join("laptop.nix")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let host = config.path.clone();
+        let shared = config.imports[0].path.clone();
+
+        // A shared setting that should only apply to this host
+        config
+            .move_entry("git", &EntryType::Program, &host)
+            .unwrap();
+        // And a host setting promoted to the shared module
+        config
+            .move_entry("openssh", &EntryType::Service, &shared)
+            .unwrap();
+
+        assert_eq!(
+            config.content,
+            "{ ... }:\n{\n  imports = [ ./common.nix ];\n\n  services.openssh.agent.enable = true;\n\n  programs.git.enable = true;\n}\n"
+        );
+        assert_eq!(
+            config.imports[0].content,
+            "{ ... }:\n{\n  services.openssh = {\n    enable = true;\n    ports = [ 22 ];\n  };\n}\n"
+        );
+        assert_eq!(
+            config.get_entry("git", &EntryType::Program).unwrap().file,
+            host
+        );
+        // The nested entry stays where it was
+        assert_eq!(
+            config
+                .get_entry("openssh.agent", &EntryType::Service)
+                .unwrap()
+                .file,
+            host
+        );
+        assert!(config
+            .move_entry("git", &EntryType::Program, &host)
+            .is_err());
+    }
+
+    /// Per-toggle latency on a large generated config, against the old way of
+    /// reparsing the whole file after each toggle. Not run by default:
+    /// `cargo test --release bench_toggle_large_config -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_toggle_large_config() {
+        let mut content = String::from("{ config, pkgs, ... }:\n{\n");
+        for i in 0..5000 {
+            content.push_str(&format!(
+                "  services.svc{i} = {{\n    enable = true;\n    port = {i};\n  }};\n"
+            ));
+        }
+        content.push_str("}\n");
+
+        // Few toggles, as each reparse takes a while at this size
+        let toggles = 20;
+        let time_toggles = |config: &mut NixConfig, toggle: fn(&mut NixConfig, &str, bool)| {
+            let start = std::time::Instant::now();
+            for i in 0..toggles {
+                toggle(config, &format!("svc{}", i * 250), i % 2 == 1);
+            }
+            start.elapsed() / toggles
+        };
+
+        let mut reparsed = parsed(&content);
+        let per_reparse = time_toggles(&mut reparsed, |config, name, enabled| {
+            config
+                .toggle_enable_entry(name, &EntryType::Service, enabled)
+                .unwrap();
+            config.reparse().unwrap();
+        });
+        let mut config = parsed(&content);
+        let per_toggle = time_toggles(&mut config, |config, name, enabled| {
+            config
+                .set_entry_enabled(name, &EntryType::Service, enabled)
+                .unwrap();
+        });
+        println!(
+            "{} lines, {:?} per toggle, {:?} with a reparse after each",
+            config.content.lines().count(),
+            per_toggle,
+            per_reparse
+        );
+
+        assert_eq!(config.content, reparsed.content);
+        assert!(
+            !config
+                .get_entry("svc0", &EntryType::Service)
+                .unwrap()
+                .enabled
+        );
+        assert!(
+            config
+                .get_entry("svc250", &EntryType::Service)
+                .unwrap()
+                .enabled
+        );
+    }
 }
@@ -0,0 +1,121 @@
+//! Minimal `.editorconfig` support: just enough to pick the indentation for `.nix` files
+
+use std::fs;
+use std::path::Path;
+
+/// Indentation settings collected from `.editorconfig` files
+#[derive(Debug, Default)]
+struct IndentSettings {
+    style: Option<String>, // "space" or "tab"
+    size: Option<usize>,
+}
+
+/// Find the indentation unit (e.g. "  " or "\t") that `.editorconfig` files
+/// prescribe for `file`, walking up from its directory until `root = true`.
+/// Returns `None` if no `.editorconfig` sets an indent style.
+pub fn indent_unit_for(file: &Path) -> Option<String> {
+    let file_name = file.file_name()?.to_string_lossy().to_string();
+    let dir = file.parent()?;
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+
+    // Files closer to the config take precedence, so collect them first and
+    // apply them from the outermost inwards
+    let mut files = Vec::new();
+    for ancestor in dir.ancestors() {
+        let path = ancestor.join(".editorconfig");
+        if let Ok(content) = fs::read_to_string(&path) {
+            let is_root = is_root_file(&content);
+            files.push(content);
+            if is_root {
+                break;
+            }
+        }
+    }
+
+    let mut settings = IndentSettings::default();
+    for content in files.iter().rev() {
+        apply_file(content, &file_name, &mut settings);
+    }
+
+    match settings.style.as_deref() {
+        Some("tab") => Some("\t".to_string()),
+        Some("space") => Some(" ".repeat(settings.size.unwrap_or(2).clamp(1, 8))),
+        _ => None,
+    }
+}
+
+/// Whether the preamble (before any section) contains `root = true`
+fn is_root_file(content: &str) -> bool {
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            return false;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("root") && value.trim().eq_ignore_ascii_case("true")
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Apply the indentation keys of every section in `content` that matches `file_name`
+fn apply_file(content: &str, file_name: &str, settings: &mut IndentSettings) {
+    let mut in_matching_section = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(pattern) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_matching_section = section_matches(pattern, file_name);
+            continue;
+        }
+
+        if !in_matching_section {
+            continue;
+        }
+
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim().to_lowercase();
+            match key.trim().to_lowercase().as_str() {
+                "indent_style" => settings.style = Some(value),
+                "indent_size" => {
+                    if value == "tab" {
+                        settings.style = Some("tab".to_string());
+                    } else if let Ok(size) = value.parse() {
+                        settings.size = Some(size);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Simplified section glob matching: `*`, `*.ext`, `**.ext`, `*.{a,b}` and plain file names
+fn section_matches(pattern: &str, file_name: &str) -> bool {
+    // Only the file name matters for the patterns we support
+    let pattern = pattern.rsplit('/').next().unwrap_or(pattern);
+    let pattern = pattern.trim_start_matches('*');
+
+    if pattern.is_empty() {
+        return true;
+    }
+
+    if let Some(alternatives) = pattern.strip_prefix(".{").and_then(|p| p.strip_suffix('}')) {
+        return alternatives
+            .split(',')
+            .any(|ext| file_name.ends_with(&format!(".{}", ext.trim())));
+    }
+
+    if pattern.starts_with('.') {
+        return file_name.ends_with(pattern);
+    }
+
+    file_name == pattern
+}
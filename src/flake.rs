@@ -0,0 +1,286 @@
+//! Multi-host flakes: finding the flake around the config and picking one of its `nixosConfigurations`
+
+use anyhow::{Context, Result};
+use rnix::{SyntaxKind, SyntaxNode};
+use rowan::ast::AstNode;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// One `nixosConfigurations.<name>` of a flake
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlakeHost {
+    /// Directory holding `flake.nix`
+    pub dir: PathBuf,
+    pub name: String,
+}
+
+impl FlakeHost {
+    /// Flake reference for `nixos-rebuild --flake`, e.g. `/etc/nixos#laptop`
+    pub fn flake_ref(&self) -> String {
+        format!("{}#{}", self.dir.display(), self.name)
+    }
+
+    /// Nix expression for the host's evaluated configuration
+    pub fn nixos_expr(&self) -> String {
+        format!(
+            "(builtins.getFlake \"path:{}\").nixosConfigurations.\"{}\"",
+            self.dir.display(),
+            self.name
+        )
+    }
+}
+
+/// Find the directory with a `flake.nix` that contains `config_path`, walking up
+pub fn find_flake_dir(config_path: &Path) -> Option<PathBuf> {
+    let start = config_path.parent()?;
+    let start = start.canonicalize().unwrap_or_else(|_| start.to_path_buf());
+
+    start
+        .ancestors()
+        .find(|dir| dir.join("flake.nix").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Names of the flake's `nixosConfigurations`
+pub fn list_hosts(dir: &Path) -> Result<Vec<String>> {
+    let output = Command::new("nix")
+        .args([
+            "--extra-experimental-features",
+            "nix-command flakes",
+            "eval",
+            "--json",
+        ])
+        .arg(format!("{}#nixosConfigurations", dir.display()))
+        .args(["--apply", "builtins.attrNames"])
+        .output()
+        .context("Failed to run nix eval")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("nix eval failed: {}", stderr.trim());
+    }
+
+    serde_json::from_slice(&output.stdout).context("Unexpected nix eval output")
+}
+
+/// This machine's hostname, which is usually also its `nixosConfigurations` name
+pub fn hostname() -> Option<String> {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| {
+            Command::new("hostname")
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+        })?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// The host named like this machine, which is almost always the one to rebuild
+pub fn host_for_machine<'a>(hosts: &'a [String], hostname: Option<&str>) -> Option<&'a String> {
+    hostname.and_then(|name| hosts.iter().find(|host| *host == name))
+}
+
+/// Names of the `nixosConfigurations` written out in `flake.nix`, read without
+/// evaluating it. Hosts generated by functions aren't found.
+pub fn declared_hosts(dir: &Path) -> Vec<String> {
+    let Ok(content) = std::fs::read_to_string(dir.join("flake.nix")) else {
+        return Vec::new();
+    };
+    let root = rnix::Root::parse(&content).tree();
+    let mut hosts: Vec<String> = Vec::new();
+    for binding in root
+        .syntax()
+        .descendants()
+        .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+    {
+        let path = full_attr_path(&binding);
+        let host = path
+            .rsplit_once("nixosConfigurations.")
+            .filter(|(before, host)| {
+                (before.is_empty() || before.ends_with('.')) && !host.contains('.')
+            })
+            .map(|(_, host)| host.to_string());
+        if let Some(host) = host.filter(|h| !hosts.contains(h)) {
+            hosts.push(host);
+        }
+    }
+    hosts
+}
+
+/// The flake's directory if `path` names a flake rather than a module: its
+/// `flake.nix`, or a directory with one (and no `default.nix`)
+pub fn flake_target(path: &Path) -> Option<PathBuf> {
+    if path.is_dir() {
+        let has_flake = path.join("flake.nix").is_file() && !path.join("default.nix").is_file();
+        return has_flake.then(|| path.to_path_buf());
+    }
+    if path.file_name()? != "flake.nix" {
+        return None;
+    }
+    let dir = path.parent()?;
+    Some(dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()))
+}
+
+/// Module files listed in `modules = [ ... ]` of the host's `nixosSystem` call,
+/// read from `flake.nix` without evaluating it. Only path literals are returned;
+/// if there are none, `configuration.nix` next to the flake is assumed.
+pub fn host_modules(dir: &Path, host: &str) -> Result<Vec<PathBuf>> {
+    let flake = dir.join("flake.nix");
+    let content = std::fs::read_to_string(&flake)
+        .with_context(|| format!("Failed to read {}", flake.display()))?;
+    let root = rnix::Root::parse(&content).tree();
+
+    let wanted = format!("nixosConfigurations.{}", host);
+    let host_value = root
+        .syntax()
+        .descendants()
+        .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+        .find(|n| {
+            let path = full_attr_path(n);
+            path == wanted || path.ends_with(&format!(".{}", wanted))
+        })
+        .and_then(|n| n.last_child());
+
+    let modules: Vec<PathBuf> = host_value
+        .iter()
+        .flat_map(|value| value.descendants())
+        .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+        .filter(|n| attr_path(n).as_deref() == Some("modules"))
+        .filter_map(|n| n.children().find(|c| c.kind() == SyntaxKind::NODE_LIST))
+        .flat_map(|list| list.children())
+        .filter(|c| c.kind() == SyntaxKind::NODE_PATH)
+        .map(|c| dir.join(c.text().to_string()))
+        .collect();
+
+    if modules.is_empty() {
+        let fallback = dir.join("configuration.nix");
+        if fallback.is_file() {
+            return Ok(vec![fallback]);
+        }
+    }
+    Ok(modules)
+}
+
+/// The attribute path of a binding, e.g. `nixosConfigurations.laptop`
+fn attr_path(binding: &SyntaxNode) -> Option<String> {
+    let attrpath = binding
+        .children()
+        .find(|c| c.kind() == SyntaxKind::NODE_ATTRPATH)?;
+    let parts: Vec<String> = attrpath
+        .children()
+        .map(|part| part.text().to_string().trim_matches('"').to_string())
+        .collect();
+    Some(parts.join("."))
+}
+
+/// The attribute path of a binding including those of the bindings it's nested
+/// in, so `nixosConfigurations = { laptop = ...; }` gives `nixosConfigurations.laptop`
+fn full_attr_path(binding: &SyntaxNode) -> String {
+    let mut parts: Vec<String> = binding
+        .ancestors()
+        .filter(|n| n.kind() == SyntaxKind::NODE_ATTRPATH_VALUE)
+        .filter_map(|n| attr_path(&n))
+        .collect();
+    parts.reverse();
+    parts.join(".")
+}
+
+/// Ask on the terminal which host to edit (before the TUI starts).
+/// Enter picks `last`, if it's one of the hosts, or the first one.
+pub fn pick_host(dir: &Path, hosts: &[String], last: Option<&str>) -> Result<String> {
+    let default = default_choice(hosts, last);
+
+    println!("Hosts in {}:", dir.display());
+    for (i, host) in hosts.iter().enumerate() {
+        let marker = if i == default { " (default)" } else { "" };
+        println!("  {}) {}{}", i + 1, host, marker);
+    }
+
+    loop {
+        print!("Host [{}]: ", default + 1);
+        io::stdout().flush()?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let input = input.trim();
+
+        if input.is_empty() {
+            return Ok(hosts[default].clone());
+        }
+        if let Some(host) = hosts.iter().find(|h| h.as_str() == input) {
+            return Ok(host.clone());
+        }
+        match input.parse::<usize>() {
+            Ok(n) if (1..=hosts.len()).contains(&n) => return Ok(hosts[n - 1].clone()),
+            _ => println!("Enter a number from 1 to {} or a host name", hosts.len()),
+        }
+    }
+}
+
+/// Index of the host Enter picks in `pick_host`: `last`, if it's one of the
+/// hosts, or the first one
+fn default_choice(hosts: &[String], last: Option<&str>) -> usize {
+    last.and_then(|last| hosts.iter().position(|h| h == last))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts() -> Vec<String> {
+        vec![
+            "desktop".to_string(),
+            "laptop".to_string(),
+            "server".to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_host_for_machine() {
+        let hosts = hosts();
+        assert_eq!(host_for_machine(&hosts, Some("laptop")), Some(&hosts[1]));
+        assert_eq!(host_for_machine(&hosts, Some("work")), None);
+        assert_eq!(host_for_machine(&hosts, None), None);
+    }
+
+    #[test]
+    fn test_default_choice_falls_back_to_last_host() {
+        let hosts = hosts();
+        assert_eq!(default_choice(&hosts, Some("server")), 2);
+        // A host that's gone since, or none remembered, defaults to the first
+        assert_eq!(default_choice(&hosts, Some("old")), 0);
+        assert_eq!(default_choice(&hosts, None), 0);
+    }
+
+    #[test]
+    fn test_declared_hosts_and_modules() {
+        let dir = std::env::temp_dir().join(format!("nixxed-flake-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("flake.nix"),
+            r#"{
+  outputs = { nixpkgs, ... }: {
+    nixosConfigurations.laptop = nixpkgs.lib.nixosSystem {
+      modules = [ ./hosts/laptop.nix ./common.nix ];
+    };
+    nixosConfigurations = {
+      "server" = nixpkgs.lib.nixosSystem { modules = [ ./hosts/server.nix ]; };
+    };
+  };
+}
+"#,
+        )
+        .unwrap();
+        let hosts = declared_hosts(&dir);
+        let modules = host_modules(&dir, "server");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(hosts, vec!["laptop".to_string(), "server".to_string()]);
+        assert_eq!(modules.unwrap(), vec![dir.join("./hosts/server.nix")]);
+    }
+}
@@ -0,0 +1,97 @@
+//! Optional git integration for configs that live in a git repository
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Find the root of the git repository containing `path` by walking up for a `.git` entry
+pub fn find_repo_root(path: &Path) -> Option<PathBuf> {
+    let start = if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        path.parent()?.to_path_buf()
+    };
+    let start = start.canonicalize().unwrap_or(start);
+
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Run a git command inside the repository and return its stdout
+fn run_git(repo: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("git {} failed: {}", args.join(" "), stderr.trim());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Check whether the repository has any uncommitted (staged, unstaged or untracked) changes
+pub fn has_uncommitted_changes(repo: &Path) -> Result<bool> {
+    let status = run_git(repo, &["status", "--porcelain"])?;
+    Ok(!status.trim().is_empty())
+}
+
+/// `args` followed by `--` and the files, as git arguments
+fn with_files<'a>(args: &[&'a str], files: &'a [String]) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    args.push("--");
+    args.extend(files.iter().map(String::as_str));
+    args
+}
+
+/// Show the unstaged diff of the given files
+pub fn diff(repo: &Path, files: &[PathBuf]) -> Result<String> {
+    let files = path_strings(files);
+    run_git(repo, &with_files(&["diff"], &files))
+}
+
+/// Stage the given files
+pub fn add(repo: &Path, files: &[PathBuf]) -> Result<()> {
+    let files = path_strings(files);
+    run_git(repo, &with_files(&["add"], &files))?;
+    Ok(())
+}
+
+/// Commit only the given files with `message`, leaving anything else staged untouched
+pub fn commit(repo: &Path, files: &[PathBuf], message: &str) -> Result<()> {
+    let files = path_strings(files);
+    run_git(repo, &with_files(&["commit", "-m", message], &files))?;
+    Ok(())
+}
+
+fn path_strings(files: &[PathBuf]) -> Vec<String> {
+    files
+        .iter()
+        .map(|f| f.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Run a git command with inherited stdio, so its output appears in the terminal
+pub fn run_visible(repo: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .status()
+        .context("Failed to run git")?;
+
+    if !status.success() {
+        anyhow::bail!(
+            "git {} failed with exit code {}",
+            args.first().unwrap_or(&""),
+            status.code().unwrap_or(-1)
+        );
+    }
+    Ok(())
+}
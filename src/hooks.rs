@@ -0,0 +1,182 @@
+//! User-defined commands run after the config is saved (formatters, commits, syncing, ...)
+
+use anyhow::{Context, Result};
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a hook may run before it's stopped
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait for the rest of the output once the hook exited. A process
+/// it started in the background (`git push &`) may keep the output open.
+const OUTPUT_GRACE: Duration = Duration::from_millis(500);
+
+/// Run one hook through `sh -c`. The config path is passed as `$1` and as
+/// `NIXXED_CONFIG`. Returns the last line of output, if there was any.
+pub fn run(command: &str, config_path: &Path) -> Result<Option<String>> {
+    run_with_timeout(command, config_path, TIMEOUT)
+}
+
+fn run_with_timeout(
+    command: &str,
+    config_path: &Path,
+    timeout: Duration,
+) -> Result<Option<String>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .arg("nixxed-hook")
+        .arg(config_path)
+        .env("NIXXED_CONFIG", config_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run hook `{}`", command))?;
+
+    // Read the output on the side, so a hook with a lot of it doesn't block on a full pipe
+    let (tx, rx) = mpsc::channel();
+    forward(child.stdout.take(), Stream::Stdout, tx.clone());
+    forward(child.stderr.take(), Stream::Stderr, tx);
+    let mut output = Output::default();
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("Failed to wait for hook `{}`", command))?
+        {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!(
+                "hook `{}` was stopped after {}s",
+                command,
+                timeout.as_secs_f32()
+            );
+        }
+        match rx.recv_timeout(Duration::from_millis(20)) {
+            Ok(chunk) => output.push(chunk),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            // Both streams closed, only the exit is left to wait for
+            Err(mpsc::RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_millis(20)),
+        }
+    };
+
+    let output_deadline = (Instant::now() + OUTPUT_GRACE).min(deadline);
+    while let Ok(chunk) = rx.recv_timeout(output_deadline.saturating_duration_since(Instant::now()))
+    {
+        output.push(chunk);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    if !status.success() {
+        let code = status.code().unwrap_or(-1);
+        match last_line(&stderr).or_else(|| last_line(&stdout)) {
+            Some(line) => anyhow::bail!("hook `{}` failed ({}): {}", command, code, line),
+            None => anyhow::bail!("hook `{}` failed with exit code {}", command, code),
+        }
+    }
+
+    Ok(last_line(&stdout).or_else(|| last_line(&stderr)))
+}
+
+#[derive(Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Output of a hook collected so far
+#[derive(Default)]
+struct Output {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl Output {
+    fn push(&mut self, (stream, chunk): (Stream, Vec<u8>)) {
+        match stream {
+            Stream::Stdout => self.stdout.extend(chunk),
+            Stream::Stderr => self.stderr.extend(chunk),
+        }
+    }
+}
+
+/// Send what a child writes to `stream` through `tx`, chunk by chunk, from another thread
+fn forward(
+    stream: Option<impl Read + Send + 'static>,
+    kind: Stream,
+    tx: mpsc::Sender<(Stream, Vec<u8>)>,
+) {
+    let Some(mut stream) = stream else {
+        return;
+    };
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        while let Ok(read @ 1..) = stream.read(&mut buffer) {
+            if tx.send((kind, buffer[..read].to_vec())).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn last_line(output: &str) -> Option<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_output_and_arguments() {
+        let config = Path::new("/etc/nixos/configuration.nix");
+        assert_eq!(
+            run("echo formatting; echo \"$1 $NIXXED_CONFIG\"", config).unwrap(),
+            Some("/etc/nixos/configuration.nix /etc/nixos/configuration.nix".to_string())
+        );
+        assert_eq!(run("true", config).unwrap(), None);
+    }
+
+    #[test]
+    fn test_hook_failure() {
+        let error = run("echo ok; echo 'not found' >&2; exit 3", Path::new("c.nix")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "hook `echo ok; echo 'not found' >&2; exit 3` failed (3): not found"
+        );
+    }
+
+    #[test]
+    fn test_hook_timeout() {
+        let started = Instant::now();
+        let error = run_with_timeout("sleep 5", Path::new("c.nix"), Duration::from_millis(200))
+            .unwrap_err();
+        assert!(error.to_string().contains("was stopped"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // A background process holding the output open doesn't hold up the hook
+        let started = Instant::now();
+        let line = run_with_timeout(
+            "sleep 5 & echo pushed",
+            Path::new("c.nix"),
+            Duration::from_secs(3),
+        )
+        .unwrap();
+        assert_eq!(line, Some("pushed".to_string()));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -1,12 +1,25 @@
 mod app;
+mod cache_archive;
+mod cli;
+mod clipboard;
 mod config_parser;
+mod editorconfig;
+mod flake;
+mod git;
+mod hooks;
 mod search;
+mod settings;
+mod templates;
 
 use anyhow::{Context, Result};
+use app::types::RebuildMode;
 use app::App;
+use cli::CliArgs;
 use config_parser::NixConfig;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -19,50 +32,99 @@ use ratatui::{
     Terminal,
 };
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
-    // Find the NixOS configuration file
-    let config_path = find_config_path()?;
+    let args = CliArgs::parse()?;
 
-    println!(
-        "Loading NixOS configuration from: {}",
-        config_path.display()
-    );
+    // Cache transfer for offline machines doesn't need a config or the TUI
+    if let Some(archive) = &args.export_cache {
+        cache_archive::export(archive)?;
+        println!("Exported cache to {}", archive.display());
+        return Ok(());
+    }
+    if let Some(archive) = &args.import_cache {
+        cache_archive::import(archive)?;
+        println!("Imported cache from {}", archive.display());
+        return Ok(());
+    }
 
-    // Load the configuration
-    let config = NixConfig::load(&config_path)?;
+    // Find the NixOS configuration file
+    let config_path = find_config_path(args.config_path.clone())?;
+
+    let (config, flake_host) = match flake::flake_target(&config_path) {
+        // Given a flake, edit the module its host is built from
+        Some(dir) => {
+            let Some(host) = select_flake_host(dir.clone(), args.host.as_deref()) else {
+                anyhow::bail!(
+                    "Could not tell which host of {} to edit; pass --host or its configuration file",
+                    dir.join("flake.nix").display()
+                );
+            };
+            let config = NixConfig::load_flake(&dir, &host.name)?;
+            println!("Loading NixOS configuration from: {}", config.path);
+            (config, Some(host))
+        }
+        None => {
+            println!(
+                "Loading NixOS configuration from: {}",
+                config_path.display()
+            );
+            let config = NixConfig::load(&config_path)?;
+
+            // In a flake with several hosts, ask which one schemas and rebuilds are
+            // for. Without --host, only a flake whose hosts build this file counts,
+            // not just any flake.nix further up.
+            let host = flake::find_flake_dir(&config_path).and_then(|dir| {
+                if args.host.is_some() {
+                    select_flake_host(dir, args.host.as_deref())
+                } else {
+                    let hosts = hosts_building(&dir, &config_path);
+                    choose_flake_host(dir, hosts)
+                }
+            });
+            (config, host)
+        }
+    };
 
     // Setup terminal
+    install_panic_hook();
     enable_raw_mode().context("Failed to enable raw mode")?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)
+    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste)
         .context("Failed to setup terminal")?;
+    if !args.no_mouse {
+        execute!(stdout, EnableMouseCapture).context("Failed to setup terminal")?;
+    }
 
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend).context("Failed to create terminal")?;
 
     // Draw loading screen while initializing
-    draw_loading_screen(
-        &mut terminal,
-        "Initializing...",
-        "Verifying packages and loading schemas",
-    )?;
+    draw_loading_screen(&mut terminal, "Initializing...", "Loading schemas")?;
 
     // Create and run the app
-    let mut app = App::new(config);
+    let mut app = App::new(config, &args);
+    if let Some(host) = flake_host {
+        app.set_flake_host(host);
+    }
     let result = run_app(&mut terminal, &mut app);
+    let history_saved = app.save_search_history();
 
     // Restore terminal
     disable_raw_mode().context("Failed to disable raw mode")?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )
     .context("Failed to restore terminal")?;
     terminal.show_cursor().context("Failed to show cursor")?;
 
+    if let Err(e) = history_saved {
+        eprintln!("Warning: could not save the search history: {}", e);
+    }
     if let Err(e) = result {
         eprintln!("Error: {}", e);
         std::process::exit(1);
@@ -71,8 +133,36 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Restore the terminal before a panic message is printed, so a crash doesn't
+/// leave the shell in raw mode on the alternate screen
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(
+            io::stdout(),
+            LeaveAlternateScreen,
+            DisableMouseCapture,
+            DisableBracketedPaste,
+            crossterm::cursor::Show
+        );
+        default_hook(info);
+    }));
+}
+
 fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App) -> Result<()> {
+    let mut mouse_captured = app.mouse_capture;
     loop {
+        // Apply a mouse capture toggle (F3)
+        if app.mouse_capture != mouse_captured {
+            if app.mouse_capture {
+                execute!(terminal.backend_mut(), EnableMouseCapture)?;
+            } else {
+                execute!(terminal.backend_mut(), DisableMouseCapture)?;
+            }
+            mouse_captured = app.mouse_capture;
+        }
+
         // Check if we need to run nixos-rebuild
         if app.rebuild_prompt.pending_rebuild {
             app.rebuild_prompt.pending_rebuild = false;
@@ -80,8 +170,25 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
             continue;
         }
 
-        // Poll for background search results
+        // Save (and reparse) with "Saving..." on screen
+        if app.saving {
+            terminal.draw(|f| app.draw(f))?;
+            let result = app.save_config();
+            // Input that arrived during the save was meant for the state before it
+            while event::poll(std::time::Duration::ZERO)? {
+                let _ = event::read();
+            }
+            app.saving = false;
+            result?;
+            continue;
+        }
+
+        // Poll for background search, package check, service module and post-save hook results
         app.poll_search();
+        app.poll_verification();
+        app.poll_package_check();
+        app.poll_service_modules();
+        app.poll_post_save_hooks()?;
 
         terminal.draw(|f| app.draw(f))?;
 
@@ -114,62 +221,102 @@ fn run_nixos_rebuild(
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableMouseCapture,
+        DisableBracketedPaste
     )?;
     terminal.show_cursor()?;
 
-    println!("\n\x1b[1;36m═══════════════════════════════════════════════════════════════\x1b[0m");
-    println!("\x1b[1;36m  Running: sudo nixos-rebuild switch\x1b[0m");
-    println!("\x1b[1;36m═══════════════════════════════════════════════════════════════\x1b[0m\n");
-
-    // Run the command with inherited stdio for live output
-    let status = std::process::Command::new("sudo")
-        .args(["nixos-rebuild", "switch"])
-        .stdin(std::process::Stdio::inherit())
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit())
-        .status();
+    let args = app.rebuild_args();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let (success, message) = run_rebuild_command(&args);
+    // What happened afterwards (commit, rollback), shown instead of the rebuild result
+    let mut follow_up: Option<String> = None;
 
-    let (success, message) = match status {
-        Ok(exit_status) => {
-            if exit_status.success() {
-                println!("\n\x1b[1;32m✓ Rebuild completed successfully!\x1b[0m");
-                (true, "Rebuild completed successfully!".to_string())
-            } else {
-                let code = exit_status.code().unwrap_or(-1);
-                println!(
-                    "\n\x1b[1;31m✗ Rebuild failed with exit code {}\x1b[0m",
-                    code
-                );
-                (false, format!("Rebuild failed with exit code {}", code))
-            }
-        }
-        Err(e) => {
-            println!("\n\x1b[1;31m✗ Failed to run nixos-rebuild: {}\x1b[0m", e);
-            (false, format!("Failed to run nixos-rebuild: {}", e))
-        }
+    // After a successful lasting rebuild, offer to commit the config repository (with --git)
+    let commit = if success && app.rebuild_prompt.mode.is_lasting() {
+        pending_git_commit(app)
+    } else {
+        None
     };
+    if let Some((repo, commit_message)) = &commit {
+        println!(
+            "\n\x1b[1m{} has uncommitted changes. Committing runs:\x1b[0m",
+            repo.display()
+        );
+        for step in git_commit_steps(commit_message, false) {
+            println!("  git {}", format_git_args(&step));
+        }
+        println!("  git push \x1b[90m(only with p)\x1b[0m");
+        println!(
+            "\n\x1b[90mPress c + Enter to commit, p + Enter to commit and push, \
+             r + Enter to roll back to the previous generation, \
+             or Enter to return to nixxed...\x1b[0m"
+        );
+    } else {
+        // Offer to switch back to the previous generation, whether the rebuild
+        // failed or the new system just isn't what we wanted
+        println!(
+            "\n\x1b[90mPress r + Enter to roll back to the previous generation, \
+             or Enter to return to nixxed...\x1b[0m"
+        );
+    }
 
-    println!("\n\x1b[90mPress Enter to return to nixxed...\x1b[0m");
-
-    // Wait for user to press Enter
     let mut input = String::new();
     let _ = std::io::stdin().read_line(&mut input);
+    let choice = input.trim().to_ascii_lowercase();
+
+    if let (Some((repo, commit_message)), "c" | "p") = (&commit, choice.as_str()) {
+        follow_up = Some(match run_git_commit(repo, commit_message, choice == "p") {
+            Ok(()) => {
+                app.change_log.clear();
+                format!("System rebuilt and committed: {}", commit_message)
+            }
+            Err(e) => format!("System rebuilt, but {}", e),
+        });
+
+        println!("\n\x1b[90mPress Enter to return to nixxed...\x1b[0m");
+        let mut input = String::new();
+        let _ = std::io::stdin().read_line(&mut input);
+    } else if choice == "r" {
+        let (rollback_success, rollback_message) = run_rebuild_command(&["switch", "--rollback"]);
+        follow_up = Some(if rollback_success {
+            "Rolled back to the previous generation".to_string()
+        } else {
+            rollback_message
+        });
+
+        println!("\n\x1b[90mPress Enter to return to nixxed...\x1b[0m");
+        let mut input = String::new();
+        let _ = std::io::stdin().read_line(&mut input);
+    }
 
     // Re-enter the alternate screen
     enable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         EnterAlternateScreen,
-        EnableMouseCapture
+        EnableBracketedPaste
     )?;
+    if app.mouse_capture {
+        execute!(terminal.backend_mut(), EnableMouseCapture)?;
+    }
     terminal.hide_cursor()?;
     terminal.clear()?;
 
     // Close the rebuild prompt and update status
     app.rebuild_prompt.show = false;
-    app.status_message = Some(if success {
-        "System rebuilt successfully!".to_string()
+    app.refresh_rebuild_pending();
+    app.set_status(if let Some(follow_up) = follow_up {
+        follow_up
+    } else if success {
+        match app.rebuild_prompt.mode {
+            RebuildMode::Switch => "System rebuilt successfully!".to_string(),
+            RebuildMode::Boot => "System rebuilt, active after the next boot".to_string(),
+            RebuildMode::Test => {
+                "System rebuilt for testing, the next boot uses the previous one".to_string()
+            }
+            RebuildMode::DryActivate => "Dry activation done, nothing was changed".to_string(),
+        }
     } else {
         message
     });
@@ -177,21 +324,251 @@ fn run_nixos_rebuild(
     Ok(())
 }
 
-fn find_config_path() -> Result<PathBuf> {
+/// The config repository and commit message, if the repository has changes to commit
+fn pending_git_commit(app: &App) -> Option<(PathBuf, String)> {
+    let repo = app.git_repo.clone()?;
+    if !git::has_uncommitted_changes(&repo).unwrap_or(false) {
+        return None;
+    }
+    let message = app
+        .change_log
+        .summary()
+        .unwrap_or_else(|| "nixxed: update configuration".to_string());
+    Some((repo, message))
+}
+
+/// The git commands (without `git`) that commit everything in the repository
+fn git_commit_steps(message: &str, push: bool) -> Vec<Vec<&str>> {
+    let mut steps = vec![vec!["add", "-A"], vec!["commit", "-m", message]];
+    if push {
+        steps.push(vec!["push"]);
+    }
+    steps
+}
+
+/// Quote arguments containing spaces, for showing a command line
+fn format_git_args(args: &[&str]) -> String {
+    args.iter()
+        .map(|a| {
+            if a.contains(char::is_whitespace) {
+                format!("{:?}", a)
+            } else {
+                a.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Stage, commit and optionally push the whole repository with live output
+fn run_git_commit(repo: &std::path::Path, message: &str, push: bool) -> Result<()> {
+    for step in git_commit_steps(message, push) {
+        println!(
+            "\n\x1b[1;36m  Running: git {}\x1b[0m",
+            format_git_args(&step)
+        );
+        if let Err(e) = git::run_visible(repo, &step) {
+            println!("\n\x1b[1;31m✗ {}\x1b[0m", e);
+            return Err(e);
+        }
+    }
+    println!("\n\x1b[1;32m✓ Committed: {}\x1b[0m", message);
+    Ok(())
+}
+
+/// How long a cancelled rebuild gets to exit on its own before it is killed
+const REBUILD_CANCEL_GRACE: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Run `sudo nixos-rebuild <args>` with inherited stdio, returning (success, status message).
+/// Ctrl+C cancels the rebuild without taking nixxed down with it.
+fn run_rebuild_command(args: &[&str]) -> (bool, String) {
+    let command_line = format!("sudo nixos-rebuild {}", args.join(" "));
+
+    println!("\n\x1b[1;36m═══════════════════════════════════════════════════════════════\x1b[0m");
+    println!("\x1b[1;36m  Running: {}\x1b[0m", command_line);
+    println!("\x1b[90m  Press Ctrl+C to cancel\x1b[0m");
+    println!("\x1b[1;36m═══════════════════════════════════════════════════════════════\x1b[0m\n");
+
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => return (false, format!("Failed to run nixos-rebuild: {}", e)),
+    };
+
+    // Run the command with inherited stdio for live output. The terminal sends
+    // Ctrl+C's SIGINT to the child as well; catching it here keeps nixxed alive
+    // so we can wait for the child to stop and return to the editor.
+    let status = runtime.block_on(async {
+        let mut child = tokio::process::Command::new("sudo")
+            .arg("nixos-rebuild")
+            .args(args)
+            .stdin(std::process::Stdio::inherit())
+            .stdout(std::process::Stdio::inherit())
+            .stderr(std::process::Stdio::inherit())
+            .spawn()?;
+
+        tokio::select! {
+            status = child.wait() => status.map(Some),
+            _ = tokio::signal::ctrl_c() => {
+                if tokio::time::timeout(REBUILD_CANCEL_GRACE, child.wait())
+                    .await
+                    .is_err()
+                {
+                    let _ = child.kill().await;
+                }
+                Ok(None)
+            }
+        }
+    });
+
+    match status {
+        Ok(None) => {
+            println!("\n\x1b[1;33m✗ {} cancelled\x1b[0m", command_line);
+            (false, format!("{} cancelled", command_line))
+        }
+        Ok(Some(exit_status)) => {
+            if exit_status.success() {
+                println!(
+                    "\n\x1b[1;32m✓ {} completed successfully!\x1b[0m",
+                    command_line
+                );
+                (true, format!("{} completed successfully!", command_line))
+            } else {
+                let code = exit_status.code().unwrap_or(-1);
+                println!(
+                    "\n\x1b[1;31m✗ {} failed with exit code {}\x1b[0m",
+                    command_line, code
+                );
+                (
+                    false,
+                    format!("{} failed with exit code {}", command_line, code),
+                )
+            }
+        }
+        Err(e) => {
+            println!("\n\x1b[1;31m✗ Failed to run nixos-rebuild: {}\x1b[0m", e);
+            (false, format!("Failed to run nixos-rebuild: {}", e))
+        }
+    }
+}
+
+/// Pick the `nixosConfigurations` host of the flake in `dir` to work on.
+/// A single host is used directly; with several, the one named like this machine is,
+/// otherwise the user chooses. The host is remembered as the default for next time.
+/// `--host` skips all of that.
+fn select_flake_host(dir: PathBuf, host_override: Option<&str>) -> Option<flake::FlakeHost> {
+    // A host given with --host is trusted as is, even if the hosts can't be listed
+    if let Some(name) = host_override {
+        return Some(flake::FlakeHost {
+            dir,
+            name: name.to_string(),
+        });
+    }
+
+    let hosts = match flake::list_hosts(&dir) {
+        Ok(hosts) => hosts,
+        Err(e) => {
+            eprintln!("Warning: could not list the flake's hosts: {}", e);
+            return None;
+        }
+    };
+    choose_flake_host(dir, hosts)
+}
+
+/// Pick one of `hosts` of the flake in `dir`, as `select_flake_host` describes
+fn choose_flake_host(dir: PathBuf, hosts: Vec<String>) -> Option<flake::FlakeHost> {
+    let name = match hosts.len() {
+        0 => return None,
+        1 => hosts[0].clone(),
+        _ => {
+            let mut settings = settings::Settings::load();
+            let hostname = flake::hostname();
+            let name = match flake::host_for_machine(&hosts, hostname.as_deref()) {
+                Some(name) => {
+                    println!("Using host {} (this machine's hostname)", name);
+                    name.clone()
+                }
+                None => {
+                    if let Some(hostname) = &hostname {
+                        println!("No host in the flake is named {}", hostname);
+                    }
+                    match flake::pick_host(&dir, &hosts, settings.last_flake_host.as_deref()) {
+                        Ok(name) => name,
+                        Err(e) => {
+                            eprintln!("Warning: no host selected: {}", e);
+                            return None;
+                        }
+                    }
+                }
+            };
+            settings.last_flake_host = Some(name.clone());
+            if let Err(e) = settings.save() {
+                eprintln!("Warning: could not remember the host: {}", e);
+            }
+            name
+        }
+    };
+
+    Some(flake::FlakeHost { dir, name })
+}
+
+/// Hosts of the flake in `dir` whose modules are `config_path` or import it,
+/// read from the files without evaluating anything
+fn hosts_building(dir: &Path, config_path: &Path) -> Vec<String> {
+    let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let target = canonical(config_path);
+    flake::declared_hosts(dir)
+        .into_iter()
+        .filter(|host| {
+            flake::host_modules(dir, host)
+                .unwrap_or_default()
+                .iter()
+                .any(|module| {
+                    NixConfig::load(module).is_ok_and(|config| {
+                        config
+                            .modules()
+                            .iter()
+                            .any(|m| canonical(Path::new(&m.path)) == target)
+                    })
+                })
+        })
+        .collect()
+}
+
+fn find_config_path(explicit: Option<PathBuf>) -> Result<PathBuf> {
     // Check command line argument first
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 {
-        let path = PathBuf::from(&args[1]);
+    if let Some(path) = explicit {
         if path.exists() {
-            return Ok(path);
+            // A flake is narrowed down to a module once its host is known
+            if flake::flake_target(&path).is_some() {
+                return Ok(path);
+            }
+            return NixConfig::resolve_path(&path);
         } else {
             anyhow::bail!("Configuration file not found: {}", path.display());
         }
     }
 
-    // Try common NixOS configuration paths
+    // Paths from NIXXED_CONFIG_PATHS or the settings take precedence over the defaults
+    for path in settings::Settings::load().config_search_paths() {
+        if path.exists() && flake::flake_target(&path).is_some() {
+            return Ok(path);
+        }
+        if let Ok(path) = NixConfig::resolve_path(&path) {
+            if path.is_file() {
+                return Ok(path);
+            }
+        }
+    }
+
+    // Try common NixOS configuration paths. On a flake system the flake picks
+    // the host's module; the hardware config is only a last resort.
     let common_paths = [
+        PathBuf::from("/etc/nixos/flake.nix"),
         PathBuf::from("/etc/nixos/configuration.nix"),
+        PathBuf::from("/etc/nixos/default.nix"),
         PathBuf::from("/etc/nixos/hardware-configuration.nix"),
     ];
 
@@ -218,7 +595,8 @@ fn find_config_path() -> Result<PathBuf> {
     anyhow::bail!(
         "Could not find NixOS configuration file. \
          Please specify the path as a command line argument:\n\
-         nixxed /path/to/configuration.nix"
+         nixxed /path/to/configuration.nix\n\
+         or list candidate paths in NIXXED_CONFIG_PATHS (colon separated)"
     )
 }
 
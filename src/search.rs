@@ -2,8 +2,7 @@ use anyhow::{Context, Result};
 use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::thread;
 use std::time::{Duration, SystemTime};
@@ -11,12 +10,26 @@ use std::time::{Duration, SystemTime};
 const CACHE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60); // 1 week
 const API_URL: &str = "https://search.nixos.org/backend/latest-44-nixos-unstable/_search";
 const API_AUTH: &str = "Basic YVdWU0FMWHBadjpYOGdQSG56TDUyd0ZFZWt1eHNmUTljU2g=";
+/// How long a request to the search API may take before it's given up on
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
 
 #[derive(Debug, Clone)]
 pub struct SearchResult {
-    pub name: String,
+    /// Attribute name, which is what goes into the config (e.g. `kdePackages.krdc`)
+    pub attr_name: String,
+    /// Name to show, the package's pname where it has one (e.g. `krdc`)
+    pub display_name: String,
     pub description: String,
     pub category: SearchCategory,
+    pub version: Option<String>,  // Package version (packages only)
+    pub homepage: Option<String>, // First homepage URL (packages only)
+}
+
+impl SearchResult {
+    /// The display name, if it says something the attribute name doesn't
+    pub fn distinct_display_name(&self) -> Option<String> {
+        (self.display_name != self.attr_name).then(|| self.display_name.clone())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -26,6 +39,32 @@ pub enum SearchCategory {
     Package,
 }
 
+/// Which search index the search bar queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SearchMode {
+    /// nixpkgs packages, categorized using the options index
+    #[default]
+    Packages,
+    /// NixOS options (programs.*.enable / services.*.enable)
+    Options,
+}
+
+impl SearchMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SearchMode::Packages => "packages",
+            SearchMode::Options => "options",
+        }
+    }
+
+    pub fn toggled(&self) -> Self {
+        match self {
+            SearchMode::Packages => SearchMode::Options,
+            SearchMode::Options => SearchMode::Packages,
+        }
+    }
+}
+
 /// Response from NixOS search API
 #[derive(Debug, Deserialize)]
 struct ElasticResponse {
@@ -47,13 +86,31 @@ struct ElasticHit {
 struct PackageSource {
     package_attr_name: String,
     #[serde(default)]
-    #[allow(dead_code)]
     package_pname: Option<String>,
     #[serde(default)]
     package_description: Option<String>,
     #[serde(default)]
     #[allow(dead_code)]
     package_programs: Option<Vec<String>>,
+    #[serde(default)]
+    package_pversion: Option<String>,
+    /// Usually a list of URLs, but tolerate a plain string too
+    #[serde(default)]
+    package_homepage: Option<serde_json::Value>,
+}
+
+impl PackageSource {
+    /// The first homepage URL, if any
+    fn homepage(&self) -> Option<String> {
+        match self.package_homepage.as_ref()? {
+            serde_json::Value::String(url) => Some(url.clone()),
+            serde_json::Value::Array(urls) => {
+                urls.iter().find_map(|u| u.as_str()).map(|u| u.to_string())
+            }
+            _ => None,
+        }
+        .filter(|url| !url.is_empty())
+    }
 }
 
 /// Response from NixOS options search API
@@ -80,16 +137,59 @@ struct OptionSource {
     option_description: Option<String>,
 }
 
+/// Error body returned by the search API, e.g. `{"error": {"type": ..., "reason": ...}}`
+#[derive(Debug, Deserialize)]
+struct ElasticErrorResponse {
+    error: serde_json::Value,
+    #[serde(default)]
+    status: Option<u16>,
+}
+
+/// Ways a request to the search API can fail
+#[derive(Debug)]
+pub enum SearchError {
+    /// The service could not be reached at all (DNS, connection, TLS, timeout)
+    Network(String),
+    /// The service rejected our credentials (the built-in token may have expired)
+    Auth(u16),
+    /// The service answered with an error status or error body
+    Service(String),
+    /// The service answered with an empty body
+    EmptyResponse,
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::Network(msg) => write!(f, "Network error: {}", msg),
+            SearchError::Auth(code) => write!(
+                f,
+                "Search service rejected the API credentials (HTTP {}); the built-in token may have expired",
+                code
+            ),
+            SearchError::Service(msg) => write!(f, "Search service error: {}", msg),
+            SearchError::EmptyResponse => {
+                write!(f, "Search service error: empty response from server")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
 /// Message sent from search thread to main thread
 pub enum SearchMessage {
     Started,
-    Completed(Vec<SearchResult>),
+    /// The results, and when they were fetched if they came from the HTTP cache
+    Completed(Vec<SearchResult>, Option<SystemTime>),
     Error(String),
 }
 
 /// HTTP-level cache for API responses
 struct HttpCache {
     cache_dir: PathBuf,
+    /// Ignore cached responses (new ones are still written), to replace stale ones
+    refresh: bool,
 }
 
 impl HttpCache {
@@ -98,10 +198,10 @@ impl HttpCache {
             .unwrap_or_else(|| PathBuf::from("/tmp"))
             .join("nixxed");
 
-        // Create cache directory if it doesn't exist
-        let _ = fs::create_dir_all(&cache_dir);
-
-        HttpCache { cache_dir }
+        HttpCache {
+            cache_dir,
+            refresh: false,
+        }
     }
 
     /// Clean up cache files older than CACHE_MAX_AGE
@@ -135,21 +235,22 @@ impl HttpCache {
         self.cache_dir.join(format!("{:x}.json", hash))
     }
 
+    /// When the cached response was stored, if there's one that's still valid
+    fn cached_at(&self, request_body: &str) -> Option<SystemTime> {
+        if self.refresh {
+            return None;
+        }
+        let modified = fs::metadata(self.cache_key(request_body))
+            .and_then(|m| m.modified())
+            .ok()?;
+        let age = SystemTime::now().duration_since(modified).ok()?;
+        (age <= CACHE_MAX_AGE).then_some(modified)
+    }
+
     /// Try to get a cached response
     fn get(&self, request_body: &str) -> Option<String> {
-        let path = self.cache_key(request_body);
-
-        if let Ok(metadata) = fs::metadata(&path) {
-            // Check if cache is still valid
-            if let Ok(modified) = metadata.modified() {
-                if let Ok(age) = SystemTime::now().duration_since(modified) {
-                    if age <= CACHE_MAX_AGE {
-                        return fs::read_to_string(&path).ok();
-                    }
-                }
-            }
-        }
-        None
+        self.cached_at(request_body)?;
+        fs::read_to_string(self.cache_key(request_body)).ok()
     }
 
     /// Store a response in the cache
@@ -159,20 +260,44 @@ impl HttpCache {
     }
 }
 
+/// Create a cache directory if it doesn't exist. Returns a warning for the UI
+/// if that fails, since every cache read/write would then silently miss.
+pub fn ensure_cache_dir(dir: &Path) -> Option<String> {
+    match fs::create_dir_all(dir) {
+        Ok(()) => None,
+        Err(e) => Some(format!(
+            "cache disabled: cannot create {} ({})",
+            dir.display(),
+            e
+        )),
+    }
+}
+
 pub struct NixSearcher {
-    /// Cache of parsed search results (in-memory)
-    cache: HashMap<String, Vec<SearchResult>>,
+    /// Cache of parsed search results (in-memory) with when they were fetched,
+    /// keyed by mode and query
+    cache: HashMap<(SearchMode, String), (SystemTime, Vec<SearchResult>)>,
     /// HTTP cache for raw API responses
     http_cache: HttpCache,
     /// Receiver for search results
     receiver: Option<mpsc::Receiver<SearchMessage>>,
-    /// Current search query (to match results)
-    current_query: Option<String>,
+    /// Current search mode and query (to match results)
+    current_query: Option<(SearchMode, String)>,
+    /// Set if the HTTP cache directory couldn't be created
+    cache_warning: Option<String>,
+    /// Receiver for package verification results (name, exists)
+    verify_receiver: Option<mpsc::Receiver<(String, bool)>>,
+    /// Receiver for whether each package of a list to add exists, in order
+    check_receiver: Option<mpsc::Receiver<Vec<bool>>>,
 }
 
+/// Requests at most in flight at once when checking a list of packages
+const CHECK_WORKERS: usize = 8;
+
 impl NixSearcher {
     pub fn new() -> Self {
         let http_cache = HttpCache::new();
+        let cache_warning = ensure_cache_dir(&http_cache.cache_dir);
         // Clean up old cache entries on startup
         http_cache.cleanup_old_entries();
 
@@ -181,37 +306,65 @@ impl NixSearcher {
             http_cache,
             receiver: None,
             current_query: None,
+            cache_warning,
+            verify_receiver: None,
+            check_receiver: None,
         }
     }
 
-    /// Start a background search for packages
-    pub fn start_search(&mut self, query: String) {
-        if query.is_empty() {
+    /// Why search responses aren't being cached, if they aren't
+    pub fn cache_warning(&self) -> Option<&str> {
+        self.cache_warning.as_deref()
+    }
+
+    /// Start a background search for packages or options
+    pub fn start_search(&mut self, query: String, mode: SearchMode) {
+        // Check in-memory cache first
+        if self.cache.contains_key(&(mode, query.clone())) {
             return;
         }
+        self.spawn_search(query, mode, false);
+    }
 
-        // Check in-memory cache first
-        if self.cache.contains_key(&query) {
+    /// Search again, bypassing both the in-memory and the HTTP cache
+    pub fn refresh(&mut self, query: String, mode: SearchMode) {
+        self.cache.remove(&(mode, query.clone()));
+        self.spawn_search(query, mode, true);
+    }
+
+    fn spawn_search(&mut self, query: String, mode: SearchMode, refresh: bool) {
+        if query.is_empty() {
             return;
         }
 
         // Create channel for communication
         let (tx, rx) = mpsc::channel();
         self.receiver = Some(rx);
-        self.current_query = Some(query.clone());
+        self.current_query = Some((mode, query.clone()));
 
         // Send started message
         let _ = tx.send(SearchMessage::Started);
 
-        // Clone cache_dir for the thread
-        let cache_dir = self.http_cache.cache_dir.clone();
+        // Give the thread its own HttpCache on the same directory
+        let http_cache = HttpCache {
+            cache_dir: self.http_cache.cache_dir.clone(),
+            refresh,
+        };
 
         // Spawn background thread
         thread::spawn(move || {
-            let results = run_nix_search_cached(&query, &cache_dir);
+            let search_body = match mode {
+                SearchMode::Packages => build_search_body(&query),
+                SearchMode::Options => build_options_search_body(&query),
+            };
+            let cached_at = http_cache.cached_at(&search_body);
+            let results = match mode {
+                SearchMode::Packages => run_nix_search_cached(&query, &http_cache),
+                SearchMode::Options => run_option_search_cached(&query, &http_cache),
+            };
             match results {
                 Ok(results) => {
-                    let _ = tx.send(SearchMessage::Completed(results));
+                    let _ = tx.send(SearchMessage::Completed(results, cached_at));
                 }
                 Err(e) => {
                     let _ = tx.send(SearchMessage::Error(e.to_string()));
@@ -225,13 +378,15 @@ impl NixSearcher {
         if let Some(ref receiver) = self.receiver {
             match receiver.try_recv() {
                 Ok(msg) => {
-                    if let SearchMessage::Completed(ref results) = msg {
+                    if let SearchMessage::Completed(ref results, cached_at) = msg {
                         // Cache the results in memory
                         if let Some(ref query) = self.current_query {
-                            self.cache.insert(query.clone(), results.clone());
+                            let fetched_at = cached_at.unwrap_or_else(SystemTime::now);
+                            self.cache
+                                .insert(query.clone(), (fetched_at, results.clone()));
                         }
                     }
-                    if matches!(msg, SearchMessage::Completed(_) | SearchMessage::Error(_)) {
+                    if matches!(msg, SearchMessage::Completed(..) | SearchMessage::Error(_)) {
                         // Search is done, clear receiver
                         self.receiver = None;
                         self.current_query = None;
@@ -258,10 +413,13 @@ impl NixSearcher {
         self.receiver.is_some()
     }
 
-    /// Get cached results for a query
-    #[allow(dead_code)]
-    pub fn get_cached(&self, query: &str) -> Option<&Vec<SearchResult>> {
-        self.cache.get(query)
+    /// Get cached results for a query, with when they were fetched
+    pub fn get_cached(
+        &self,
+        query: &str,
+        mode: SearchMode,
+    ) -> Option<&(SystemTime, Vec<SearchResult>)> {
+        self.cache.get(&(mode, query.to_string()))
     }
 
     /// Cancel any ongoing search
@@ -271,19 +429,114 @@ impl NixSearcher {
         self.current_query = None;
     }
 
-    /// Verify if a package exists by doing an exact match search
-    /// Returns true if the package exists in nixpkgs
-    pub fn verify_package_exists(&self, package_name: &str) -> bool {
-        let cache_dir = self.http_cache.cache_dir.clone();
+    /// Check in the background, one package after another, whether packages
+    /// exist. Results arrive through `poll_verification`.
+    pub fn start_verifying(&mut self, package_names: Vec<String>) {
+        let (tx, rx) = mpsc::channel();
+        self.verify_receiver = Some(rx);
+        let http_cache = HttpCache {
+            cache_dir: self.http_cache.cache_dir.clone(),
+            refresh: false,
+        };
 
-        // Do a synchronous search for the exact package name
-        if let Ok(results) = run_nix_search_cached(package_name, &cache_dir) {
-            // Check for exact match
-            results.iter().any(|r| r.name == package_name)
-        } else {
-            // If search fails, assume package exists to avoid false negatives
-            true
+        thread::spawn(move || {
+            for name in package_names {
+                let exists = package_exists(&name, &http_cache);
+                if tx.send((name, exists)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Verification results that arrived since the last call (non-blocking)
+    pub fn poll_verification(&mut self) -> Vec<(String, bool)> {
+        let Some(receiver) = &self.verify_receiver else {
+            return Vec::new();
+        };
+        let mut results = Vec::new();
+        loop {
+            match receiver.try_recv() {
+                Ok(result) => results.push(result),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    // The checks are over, finished or not
+                    self.verify_receiver = None;
+                    break;
+                }
+            }
         }
+        results
+    }
+
+    /// Whether a verification started with `start_verifying` is still running
+    pub fn is_verifying(&self) -> bool {
+        self.verify_receiver.is_some()
+    }
+
+    /// Check in the background, a few requests at a time, whether packages
+    /// exist. The answers arrive all together through `poll_package_check`.
+    pub fn start_checking_packages(&mut self, package_names: Vec<String>) {
+        let (tx, rx) = mpsc::channel();
+        self.check_receiver = Some(rx);
+        let http_cache = HttpCache {
+            cache_dir: self.http_cache.cache_dir.clone(),
+            refresh: false,
+        };
+
+        thread::spawn(move || {
+            let queue = std::sync::Mutex::new(package_names.iter().enumerate());
+            // Names whose check didn't finish count as existing, like failed requests
+            let exists = std::sync::Mutex::new(vec![true; package_names.len()]);
+            thread::scope(|scope| {
+                for _ in 0..CHECK_WORKERS.min(package_names.len()) {
+                    scope.spawn(|| {
+                        // The lock is released before each request
+                        while let Some((i, name)) = queue.lock().ok().and_then(|mut q| q.next()) {
+                            let result = package_exists(name, &http_cache);
+                            if let Ok(mut exists) = exists.lock() {
+                                exists[i] = result;
+                            }
+                        }
+                    });
+                }
+            });
+            let _ = tx.send(exists.into_inner().unwrap_or_else(|e| e.into_inner()));
+        });
+    }
+
+    /// Whether each package given to `start_checking_packages` exists, once
+    /// the check is done (non-blocking)
+    pub fn poll_package_check(&mut self) -> Option<Vec<bool>> {
+        let result = match self.check_receiver.as_ref()?.try_recv() {
+            Ok(exists) => Some(exists),
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => None,
+        };
+        self.check_receiver = None;
+        result
+    }
+
+    /// Whether a check started with `start_checking_packages` is still running
+    pub fn is_checking_packages(&self) -> bool {
+        self.check_receiver.is_some()
+    }
+}
+
+/// Look up a package by its exact name
+fn package_exists(package_name: &str, http_cache: &HttpCache) -> bool {
+    // Do a synchronous search for the exact package name. Option lookups
+    // aren't needed to tell whether a package exists, so skip them.
+    let search_body = build_search_body(package_name);
+    let results = fetch_search_response(&search_body, http_cache)
+        .and_then(|response| parse_elastic_response(&response, package_name, &[]));
+
+    match results {
+        // Check for exact match
+        Ok(results) => results.iter().any(|r| r.attr_name == package_name),
+        // If the search fails for any reason (offline, API error, bad response),
+        // assume the package exists to avoid false negatives
+        Err(_) => true,
     }
 }
 
@@ -411,53 +664,25 @@ pub struct NixOption {
 fn fetch_nix_options(query: &str, http_cache: &HttpCache) -> Vec<NixOption> {
     let search_body = build_options_search_body(query);
 
-    let response = if let Some(cached) = http_cache.get(&search_body) {
-        cached
-    } else {
-        let output = match Command::new("curl")
-            .args([
-                "-s",
-                "-X",
-                "POST",
-                API_URL,
-                "-H",
-                "Content-Type: application/json",
-                "-H",
-                &format!("Authorization: {}", API_AUTH),
-                "-d",
-                &search_body,
-            ])
-            .output()
-        {
-            Ok(output) => output,
-            Err(_) => return Vec::new(),
-        };
-
-        let response = String::from_utf8_lossy(&output.stdout).to_string();
-
-        if !response.is_empty() && !response.contains("\"error\"") {
-            http_cache.set(&search_body, &response);
-        }
-
-        response
-    };
-
-    if response.trim().is_empty() {
-        return Vec::new();
+    // Options are only used to refine categorization, so failures are not fatal here
+    match fetch_search_response(&search_body, http_cache) {
+        Ok(response) => parse_options_response(&response),
+        Err(_) => Vec::new(),
     }
-
-    parse_options_response(&response)
 }
 
 /// Parse the options API response and extract programs.*.enable and services.*.enable
 fn parse_options_response(output: &str) -> Vec<NixOption> {
+    parse_options_hits(output).unwrap_or_default()
+}
+
+/// Like `parse_options_response`, but reports malformed responses as errors
+fn parse_options_hits(output: &str) -> Result<Vec<NixOption>> {
     let mut options = Vec::new();
     let mut seen = HashSet::new();
 
-    let response: OptionElasticResponse = match serde_json::from_str(output) {
-        Ok(r) => r,
-        Err(_) => return options,
-    };
+    let response: OptionElasticResponse =
+        serde_json::from_str(output).context("Failed to parse options search response")?;
 
     for hit in response.hits.hits {
         let option_name = &hit.source.option_name;
@@ -498,7 +723,7 @@ fn parse_options_response(output: &str) -> Vec<NixOption> {
         }
     }
 
-    options
+    Ok(options)
 }
 
 /// Strip HTML tags from a string (simple implementation)
@@ -519,53 +744,124 @@ fn strip_html_tags(s: &str) -> String {
     result.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
-fn run_nix_search_cached(query: &str, cache_dir: &PathBuf) -> Result<Vec<SearchResult>> {
+fn run_nix_search_cached(query: &str, http_cache: &HttpCache) -> Result<Vec<SearchResult>> {
     let search_body = build_search_body(query);
 
-    // Create a temporary HttpCache for this thread
-    let http_cache = HttpCache {
-        cache_dir: cache_dir.clone(),
-    };
-
     // Fetch available NixOS options for categorization
-    let available_options = fetch_nix_options(query, &http_cache);
+    let available_options = fetch_nix_options(query, http_cache);
+
+    let response = fetch_search_response(&search_body, http_cache)?;
+
+    parse_elastic_response(&response, query, &available_options)
+}
 
+/// POST a request body to the search API, using the HTTP cache when possible.
+/// Only successful responses are cached; failures are classified as a `SearchError`.
+fn fetch_search_response(search_body: &str, http_cache: &HttpCache) -> Result<String> {
     // Check HTTP cache first
-    let response = if let Some(cached) = http_cache.get(&search_body) {
-        cached
-    } else {
-        // Make the actual HTTP request
-        let output = Command::new("curl")
-            .args([
-                "-s",
-                "-X",
-                "POST",
-                API_URL,
-                "-H",
-                "Content-Type: application/json",
-                "-H",
-                &format!("Authorization: {}", API_AUTH),
-                "-d",
-                &search_body,
-            ])
-            .output()
-            .context("Failed to run curl command")?;
-
-        let response = String::from_utf8_lossy(&output.stdout).to_string();
-
-        // Cache the response
-        if !response.is_empty() && !response.contains("\"error\"") {
-            http_cache.set(&search_body, &response);
-        }
+    if let Some(cached) = http_cache.get(search_body) {
+        return Ok(cached);
+    }
 
-        response
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+    let response = agent
+        .post(API_URL)
+        .set("Content-Type", "application/json")
+        .set("Authorization", API_AUTH)
+        .send_string(search_body);
+
+    // Error statuses still carry a body, which says what went wrong
+    let response = match response {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(ureq::Error::Transport(e)) => return Err(SearchError::Network(e.to_string()).into()),
     };
+    let status = response.status();
+    let body = response
+        .into_string()
+        .map_err(|e| SearchError::Network(format!("failed to read the response: {}", e)))?;
+
+    check_search_response(&body, status)?;
 
-    if response.trim().is_empty() {
-        return Ok(Vec::new());
+    http_cache.set(search_body, &body);
+    Ok(body)
+}
+
+/// Classify an API response as usable or as one of the `SearchError` cases
+fn check_search_response(body: &str, status: u16) -> Result<(), SearchError> {
+    if status == 401 || status == 403 {
+        return Err(SearchError::Auth(status));
     }
 
-    parse_elastic_response(&response, query, &available_options)
+    // Elasticsearch reports failures as a JSON body with an "error" field
+    if let Ok(err) = serde_json::from_str::<ElasticErrorResponse>(body) {
+        if matches!(err.status, Some(401) | Some(403)) {
+            return Err(SearchError::Auth(err.status.unwrap_or(status)));
+        }
+        let reason = match &err.error {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Object(map) => map
+                .get("reason")
+                .or_else(|| map.get("type"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown error")
+                .to_string(),
+            other => other.to_string(),
+        };
+        return Err(SearchError::Service(reason));
+    }
+
+    if status >= 400 {
+        return Err(SearchError::Service(format!("HTTP {}", status)));
+    }
+
+    if body.trim().is_empty() {
+        return Err(SearchError::EmptyResponse);
+    }
+
+    Ok(())
+}
+
+/// Search the NixOS options index directly, turning each module into a program or service
+fn run_option_search_cached(query: &str, http_cache: &HttpCache) -> Result<Vec<SearchResult>> {
+    let search_body = build_options_search_body(query);
+    let response = fetch_search_response(&search_body, http_cache)?;
+    let options = parse_options_hits(&response)?;
+
+    let mut results: Vec<(SearchResult, u32, usize)> = options
+        .into_iter()
+        .enumerate()
+        .map(|(api_order, option)| {
+            let category = if option.prefix == "services" {
+                SearchCategory::Service
+            } else {
+                SearchCategory::Program
+            };
+            let match_score = calculate_match_score(&option.module_name, query);
+            let description = if option.description.is_empty() {
+                format!("{}.{} NixOS module", option.prefix, option.module_name)
+            } else {
+                option.description
+            };
+            (
+                SearchResult {
+                    attr_name: option.module_name.clone(),
+                    display_name: option.module_name,
+                    description,
+                    category,
+                    version: None,
+                    homepage: None,
+                },
+                match_score,
+                api_order,
+            )
+        })
+        .collect();
+
+    // Sort by: match_score (desc), then api_order (asc) for tie-breaking
+    results.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.2.cmp(&b.2)));
+
+    Ok(results.into_iter().map(|(r, _, _)| r).collect())
 }
 
 /// Calculate a match score for local sorting (higher = better match)
@@ -604,9 +900,15 @@ fn parse_elastic_response(
     // First, add all packages from the packages API
     for (api_order, hit) in response.hits.hits.into_iter().enumerate() {
         let source = hit.source;
+        let homepage = source.homepage();
+        let version = source.package_pversion.filter(|v| !v.is_empty());
         // Use package_attr_name for the full attribute path (e.g., kdePackages.krdc)
         // This is needed when adding packages to environment.systemPackages
         let name = source.package_attr_name;
+        let display_name = source
+            .package_pname
+            .filter(|pname| !pname.is_empty())
+            .unwrap_or_else(|| name.clone());
         let description = source.package_description.unwrap_or_default();
 
         // Categorize based on available NixOS options
@@ -618,9 +920,12 @@ fn parse_elastic_response(
         seen_names.insert(name.clone());
         results.push((
             SearchResult {
-                name,
+                attr_name: name,
+                display_name,
                 description,
                 category,
+                version,
+                homepage,
             },
             match_score,
             api_order,
@@ -649,9 +954,12 @@ fn parse_elastic_response(
 
             results.push((
                 SearchResult {
-                    name: option.module_name.clone(),
+                    attr_name: option.module_name.clone(),
+                    display_name: option.module_name.clone(),
                     description,
                     category,
+                    version: None,
+                    homepage: None,
                 },
                 match_score,
                 options_start_order + idx,
@@ -687,3 +995,39 @@ fn categorize_result(name: &str, available_options: &[NixOption]) -> SearchCateg
     // Default to Package (environment.systemPackages)
     SearchCategory::Package
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_parser::{EntryType, NixConfig};
+
+    #[test]
+    fn test_package_attr_name_differs_from_pname() {
+        let response = r#"{"hits": {"hits": [{"_source": {
+            "package_attr_name": "kdePackages.krdc",
+            "package_pname": "krdc",
+            "package_description": "Remote desktop client"
+        }}]}}"#;
+        let results = parse_elastic_response(response, "krdc", &[]).unwrap();
+        assert_eq!(results[0].attr_name, "kdePackages.krdc");
+        assert_eq!(results[0].display_name, "krdc");
+        assert_eq!(results[0].distinct_display_name().as_deref(), Some("krdc"));
+
+        // Adding the result writes the attr name, not the pname
+        let path = std::env::temp_dir().join(format!("nixxed-attr-{}.nix", std::process::id()));
+        fs::write(
+            &path,
+            "{ pkgs, ... }:\n{\n  environment.systemPackages = with pkgs; [\n    git\n  ];\n}\n",
+        )
+        .unwrap();
+        let mut config = NixConfig::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        config
+            .add_entry(&results[0].attr_name, &EntryType::Package)
+            .unwrap();
+        assert!(config.content.contains("    kdePackages.krdc\n"));
+        assert!(config
+            .get_entry("kdePackages.krdc", &EntryType::Package)
+            .is_some());
+    }
+}
@@ -0,0 +1,212 @@
+//! Persistent user preferences, stored as JSON in the user's config directory
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Preferences that survive between sessions
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Skip confirmations for non-destructive actions (rebuild after save, git staging, ...)
+    pub trust_mode: bool,
+    /// Leave disabled programs/services out of the lists (search results still show them)
+    pub hide_disabled: bool,
+    /// Show each entry's description on a second line in the lists
+    pub inline_descriptions: bool,
+    /// Shrink the search bar to a single line while it isn't focused
+    pub compact_search: bool,
+    /// Extra output from nixos-rebuild, chosen in the rebuild prompt
+    pub rebuild_verbosity: RebuildVerbosity,
+    /// Shell commands run in order after every save; the config path is `$1` / `$NIXXED_CONFIG`
+    pub post_save_hooks: Vec<String>,
+    /// The `nixosConfigurations` host picked last time in a multi-host flake
+    pub last_flake_host: Option<String>,
+    /// Config files tried in order before the built-in ones when no path is given.
+    /// `~` and `$VAR` / `${VAR}` are expanded.
+    pub config_paths: Vec<String>,
+    /// nixpkgs to read option schemas from instead of `<nixpkgs>`: a path, a
+    /// `<channel>` or `flake#input` (e.g. `/etc/nixos#nixpkgs`). `~` and `$VAR` are expanded.
+    pub nixpkgs: Option<String>,
+    /// Order of search results that aren't in the config yet
+    pub result_sort: ResultSort,
+    /// Recent search queries, oldest first, recalled with Up in the search bar
+    pub search_history: Vec<String>,
+    /// Show the diff of the unsaved changes on Ctrl+S and save once it's confirmed
+    pub preview_before_save: bool,
+    /// Why the settings file couldn't be parsed, if it couldn't. The defaults are
+    /// used for the session and the file is never saved over.
+    #[serde(skip)]
+    pub load_error: Option<String>,
+}
+
+/// How search results are ordered below the config's own entries. The search
+/// API has no popularity or download counts, so there's no order by those.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultSort {
+    /// The search API's ranking, refined by how closely names match
+    #[default]
+    Relevance,
+    /// Alphabetically by attribute name
+    Name,
+}
+
+impl ResultSort {
+    pub fn label(self) -> &'static str {
+        match self {
+            ResultSort::Relevance => "relevance",
+            ResultSort::Name => "name",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ResultSort::Relevance => ResultSort::Name,
+            ResultSort::Name => ResultSort::Relevance,
+        }
+    }
+}
+
+/// How much nixos-rebuild prints, mostly for diagnosing evaluation errors
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RebuildVerbosity {
+    #[default]
+    Normal,
+    /// Full stack traces for evaluation errors
+    ShowTrace,
+    /// Traces plus verbose logging
+    Verbose,
+}
+
+impl RebuildVerbosity {
+    /// Extra `nixos-rebuild` arguments
+    pub fn flags(self) -> &'static [&'static str] {
+        match self {
+            RebuildVerbosity::Normal => &[],
+            RebuildVerbosity::ShowTrace => &["--show-trace"],
+            RebuildVerbosity::Verbose => &["-v", "--show-trace"],
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RebuildVerbosity::Normal => "normal",
+            RebuildVerbosity::ShowTrace => "--show-trace",
+            RebuildVerbosity::Verbose => "-v --show-trace",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            RebuildVerbosity::Normal => RebuildVerbosity::ShowTrace,
+            RebuildVerbosity::ShowTrace => RebuildVerbosity::Verbose,
+            RebuildVerbosity::Verbose => RebuildVerbosity::Normal,
+        }
+    }
+}
+
+impl Settings {
+    /// `~/.config/nixxed/settings.json` (or the platform equivalent)
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("nixxed").join("settings.json"))
+    }
+
+    /// Load the settings file, falling back to defaults if it's missing or
+    /// unreadable. A file that doesn't parse is kept as it is, see `load_error`.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| Settings {
+            load_error: Some(format!(
+                "{} is invalid ({}), using defaults",
+                path.display(),
+                e
+            )),
+            ..Self::default()
+        })
+    }
+
+    /// Candidate config files to try before the defaults: `NIXXED_CONFIG_PATHS`
+    /// (colon separated) if it's set, otherwise the `config_paths` setting
+    pub fn config_search_paths(&self) -> Vec<PathBuf> {
+        match std::env::var("NIXXED_CONFIG_PATHS") {
+            Ok(paths) => paths
+                .split(':')
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| expand_path(p.trim()))
+                .collect(),
+            Err(_) => self.config_paths.iter().map(|p| expand_path(p)).collect(),
+        }
+    }
+
+    /// The `nixpkgs` setting with `~` and variables expanded
+    pub fn nixpkgs_reference(&self) -> Option<String> {
+        self.nixpkgs
+            .as_deref()
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(|r| expand_path(r).to_string_lossy().into_owned())
+    }
+
+    /// Write the settings file, creating its directory if needed. Refuses to
+    /// replace a file that couldn't be parsed, so a typo doesn't lose it.
+    pub fn save(&self) -> Result<()> {
+        if let Some(error) = &self.load_error {
+            anyhow::bail!("not saving settings: {}", error);
+        }
+        let path = Self::path().context("Could not determine the config directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create settings directory")?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&path, content).context("Failed to write settings file")
+    }
+}
+
+/// Expand a leading `~` to the home directory and `$VAR` / `${VAR}` to the
+/// variable's value. Unset variables expand to nothing, like in the shell.
+fn expand_path(path: &str) -> PathBuf {
+    let mut expanded = String::new();
+    let mut rest = path;
+
+    if rest == "~" || rest.starts_with("~/") {
+        if let Some(home) = dirs::home_dir() {
+            expanded.push_str(&home.to_string_lossy());
+            rest = &rest[1..];
+        }
+    }
+
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => (braced, ""),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            // A lone `$` isn't a variable
+            expanded.push('$');
+        } else {
+            expanded.push_str(&std::env::var(name).unwrap_or_default());
+        }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+
+    PathBuf::from(expanded)
+}
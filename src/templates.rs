@@ -0,0 +1,176 @@
+//! Property templates: named sets of options applied to an entry in one go,
+//! the shipped ones plus the user's own from `~/.config/nixxed/templates.json`
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config_parser::{path_matches, PropertyType};
+
+/// A known-good set of `(property, value)` pairs for one kind of entry. Values
+/// are written like in the property editor: plain strings are quoted, `true`,
+/// numbers, paths, lists and attribute sets are written as they are.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Template {
+    pub name: String,
+    /// The entry it's for as `prefix.name`, e.g. `services.nginx`; `*` matches
+    /// any name, as in `users.users.*`
+    #[serde(rename = "for")]
+    pub applies_to: String,
+    pub properties: Vec<(String, String)>,
+}
+
+impl Template {
+    fn new(name: &str, applies_to: &str, properties: &[(&str, &str)]) -> Self {
+        Template {
+            name: name.to_string(),
+            applies_to: applies_to.to_string(),
+            properties: properties
+                .iter()
+                .map(|(p, v)| (p.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// Templates shipped with nixxed
+fn builtin() -> Vec<Template> {
+    vec![
+        Template::new(
+            "Reverse proxy vhost",
+            "services.nginx",
+            &[
+                ("recommendedProxySettings", "true"),
+                ("recommendedTlsSettings", "true"),
+                ("recommendedGzipSettings", "true"),
+                (
+                    "virtualHosts",
+                    r#"{ "example.com" = { forceSSL = true; enableACME = true; locations."/".proxyPass = "http://127.0.0.1:8080"; }; }"#,
+                ),
+            ],
+        ),
+        Template::new(
+            "Key-only login",
+            "services.openssh",
+            &[
+                ("settings.PasswordAuthentication", "false"),
+                ("settings.KbdInteractiveAuthentication", "false"),
+                ("settings.PermitRootLogin", "no"),
+            ],
+        ),
+        Template::new(
+            "Administrator",
+            "users.users.*",
+            &[
+                ("isNormalUser", "true"),
+                ("extraGroups", r#"[ "wheel" "networkmanager" ]"#),
+            ],
+        ),
+    ]
+}
+
+/// `~/.config/nixxed/templates.json` (or the platform equivalent)
+fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("nixxed").join("templates.json"))
+}
+
+/// The templates for an entry, `prefix.name` like `services.nginx`: the
+/// user's own first, then the shipped ones. A missing templates file is fine,
+/// one that can't be read isn't.
+pub fn for_entry(entry: &str) -> Result<Vec<Template>> {
+    let mut templates = match path().filter(|p| p.exists()) {
+        Some(path) => {
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            serde_json::from_str::<Vec<Template>>(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?
+        }
+        None => Vec::new(),
+    };
+    templates.extend(builtin());
+    templates.retain(|t| path_matches(entry, &t.applies_to));
+    Ok(templates)
+}
+
+/// Why a template value doesn't fit the entry's schema, if it doesn't.
+/// `option_types` maps the entry's options to their schema type; without a
+/// schema nothing can be checked. Nested properties like `settings.Port` are
+/// only checked for their top-level option.
+pub fn value_error(
+    property: &str,
+    value: &str,
+    option_types: &HashMap<String, String>,
+) -> Option<String> {
+    if option_types.is_empty() {
+        return None;
+    }
+    let option_type = match option_types.get(property) {
+        Some(option_type) => option_type,
+        None => {
+            let top_level = property.split('.').next().unwrap_or(property);
+            if option_types.contains_key(top_level) {
+                return None;
+            }
+            return Some(format!("{} isn't an option of this entry", property));
+        }
+    };
+    let fits = match PropertyType::from_option_type(option_type) {
+        PropertyType::Bool => value == "true" || value == "false",
+        PropertyType::Int => value.parse::<i64>().is_ok(),
+        PropertyType::List => value.starts_with('['),
+        PropertyType::AttrSet => value.starts_with('{'),
+        _ => true,
+    };
+    if fits {
+        None
+    } else {
+        Some(format!("{} = {} isn't a {}", property, value, option_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_values() {
+        let names: Vec<String> = builtin()
+            .into_iter()
+            .filter(|t| path_matches("users.users.alice", &t.applies_to))
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(names, vec!["Administrator".to_string()]);
+
+        let option_types: HashMap<String, String> = [
+            ("enable", "boolean"),
+            (
+                "port",
+                "16 bit unsigned integer; between 0 and 65535 (both inclusive)",
+            ),
+            ("settings", "attribute set of anything"),
+            ("extraGroups", "list of string"),
+        ]
+        .into_iter()
+        .map(|(o, t)| (o.to_string(), t.to_string()))
+        .collect();
+
+        assert_eq!(value_error("enable", "true", &option_types), None);
+        assert!(value_error("enable", "yes", &option_types).is_some());
+        assert_eq!(
+            value_error("extraGroups", "[ \"wheel\" ]", &option_types),
+            None
+        );
+        assert!(value_error("extraGroups", "wheel", &option_types).is_some());
+        assert_eq!(
+            value_error("settings.PermitRootLogin", "no", &option_types),
+            None
+        );
+        assert!(value_error("enabel", "true", &option_types).is_some());
+        // Types that aren't understood aren't checked
+        assert_eq!(value_error("port", "22", &option_types), None);
+        // Nor is anything without a schema
+        assert_eq!(value_error("enabel", "true", &HashMap::new()), None);
+    }
+}